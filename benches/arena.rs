@@ -0,0 +1,111 @@
+use criterion::{criterion_group, Criterion};
+use serde::Deserialize;
+use serde_qs::{Config, QsParserArena};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts heap allocations, to compare the arena against the default parser.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Address<'a> {
+    #[serde(borrow)]
+    street: Cow<'a, str>,
+    #[serde(borrow)]
+    city: Cow<'a, str>,
+    postcode: u32,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct User<'a> {
+    #[serde(borrow)]
+    name: Cow<'a, str>,
+    #[serde(borrow)]
+    email: Cow<'a, str>,
+    age: u8,
+    #[serde(borrow)]
+    address: Address<'a>,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Search<'a> {
+    #[serde(borrow)]
+    user: User<'a>,
+    #[serde(borrow)]
+    q: Cow<'a, str>,
+    page: u32,
+}
+
+/// A nested struct whose values are mostly percent-encoded or contain `+`.
+const NESTED: &str = "user[name]=Ada+Lovelace&user[email]=ada%40example.com&user[age]=%33%36\
+    &user[address][street]=12+St+James%27s+Square&user[address][city]=London\
+    &user[address][postcode]=%31%30%30%30&q=analytical+engine&page=%32";
+
+/// The heap allocations made by one call of `f`.
+fn allocations<T>(mut f: impl FnMut() -> T) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    drop(f());
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn arena(c: &mut Criterion) {
+    let config = Config::default();
+    let mut arena = QsParserArena::new();
+    // warm up, so the arena has grown to fit
+    let _ = arena.deserialize_str::<Search>(&config, NESTED).unwrap();
+
+    println!(
+        "heap allocations per nested struct: {} without the arena, {} with it",
+        allocations(|| config.deserialize_str::<Search>(NESTED).unwrap()),
+        allocations(|| {
+            arena
+                .deserialize_str::<Search>(&config, NESTED)
+                .map(|_| ())
+                .unwrap()
+        }),
+    );
+
+    c.bench_function("nested struct", |b| {
+        b.iter(|| config.deserialize_str::<Search>(NESTED).unwrap())
+    });
+    c.bench_function("nested struct with arena", |b| {
+        b.iter(|| {
+            arena
+                .deserialize_str::<Search>(&config, NESTED)
+                .map(|_| ())
+                .unwrap()
+        })
+    });
+}
+
+criterion_group!(benches, arena);
+
+fn main() {
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+}
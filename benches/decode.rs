@@ -0,0 +1,140 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Item {
+    first_name: String,
+    last_name: String,
+    email_address: String,
+    phone_number: String,
+    postal_code: String,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Items {
+    items: Vec<Item>,
+}
+
+/// 1000 structs whose five field names are all percent-encoded.
+fn encoded_field_names() -> String {
+    let fields = [
+        "first%5Fname",
+        "last%5Fname",
+        "email%5Faddress",
+        "phone%5Fnumber",
+        "postal%5Fcode",
+    ];
+    let mut query = String::new();
+    for i in 0..1000 {
+        for field in fields {
+            if !query.is_empty() {
+                query.push('&');
+            }
+            query.push_str(&format!("items[{}][{}]=value{}", i, field, i));
+        }
+    }
+    query
+}
+
+/// A mix of top-level values, nested maps and sequences, 1000 pairs in all.
+fn mixed() -> String {
+    let mut query = String::new();
+    for i in 0..100 {
+        query.push_str(&format!(
+            "q{i}=value+{i}&filter{i}[name]=n{i}&filter{i}[range][min]={i}&filter{i}[range][max]=9\
+             &ids{i}[0]=1&ids{i}[1]=2&ids{i}[2]=3&tags{i}[]=a&tags{i}[]=b&sort{i}=asc&",
+            i = i
+        ));
+    }
+    query.pop();
+    query
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Names {
+    items: Vec<Name>,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Name {
+    name: String,
+}
+
+/// 1000 pairs with percent-encoded keys, of which only 50 are deserialized
+/// into `Names`, the rest being ignored fields or nested within them.
+fn mostly_ignored() -> String {
+    let mut query = String::new();
+    for i in 0..50 {
+        query.push_str(&format!("items[{}][na%6De]=n{}&", i, i));
+    }
+    for i in 0..475 {
+        query.push_str(&format!("ignored%5F{i}=x&other[key%5F{i}]=y&", i = i));
+    }
+    query.pop();
+    query
+}
+
+/// 5000 flat pairs with distinct keys, as sent to bulk endpoints.
+fn many_keys() -> String {
+    let mut query = String::new();
+    for i in 0..5000 {
+        query.push_str(&format!("key{i}={i}&", i = i));
+    }
+    query.pop();
+    query
+}
+
+/// 1000 flat pairs, with brackets and encoded brackets in the values.
+fn flat() -> String {
+    let mut query = String::new();
+    for i in 0..1000 {
+        query.push_str(&format!("key{i}=value+[{i}]%5B&", i = i));
+    }
+    query.pop();
+    query
+}
+
+fn decode(c: &mut Criterion) {
+    let query = encoded_field_names();
+    c.bench_function("vec of structs with encoded field names", |b| {
+        b.iter(|| serde_qs::from_str::<Items>(&query).unwrap())
+    });
+
+    let query = mostly_ignored();
+    c.bench_function("struct ignoring most encoded keys", |b| {
+        b.iter(|| serde_qs::from_str::<Names>(&query).unwrap())
+    });
+
+    let query = mixed();
+    c.bench_function("map of json values", |b| {
+        b.iter(|| serde_qs::from_str::<HashMap<String, serde_json::Value>>(&query).unwrap())
+    });
+
+    let query = flat();
+    let config = serde_qs::Config::default();
+    c.bench_function("classify flat querystring", |b| {
+        b.iter(|| serde_qs::classify(&query, &config))
+    });
+    c.bench_function("parse flat querystring", |b| {
+        b.iter(|| serde_qs::from_str::<HashMap<String, String>>(&query).unwrap())
+    });
+
+    // The capacity hint only pre-sizes the parsed map with `indexmap`.
+    let query = many_keys();
+    let config = serde_qs::Config::default();
+    c.bench_function("parse 5000 keys", |b| {
+        b.iter(|| config.parse_str(&query).unwrap())
+    });
+    let config = config.capacity_hint(5000);
+    c.bench_function("parse 5000 keys with capacity hint", |b| {
+        b.iter(|| config.parse_str(&query).unwrap())
+    });
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
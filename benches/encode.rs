@@ -0,0 +1,70 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Numbers {
+    a0: u8,
+    a1: u16,
+    a2: u32,
+    a3: u64,
+    a4: i8,
+    a5: i16,
+    a6: i32,
+    a7: i64,
+    a8: f32,
+    a9: f64,
+    b0: u8,
+    b1: u16,
+    b2: u32,
+    b3: u64,
+    b4: i8,
+    b5: i16,
+    b6: i32,
+    b7: i64,
+    b8: f32,
+    b9: f64,
+}
+
+#[derive(Serialize)]
+struct Items {
+    items: Vec<Numbers>,
+}
+
+/// 1000 structs of 20 numeric fields each.
+fn numbers() -> Items {
+    let items = (0..1000i64)
+        .map(|i| Numbers {
+            a0: i as u8,
+            a1: i as u16,
+            a2: i as u32 * 1000,
+            a3: i as u64 * 1_000_000,
+            a4: -((i % 100) as i8),
+            a5: -(i as i16),
+            a6: -(i as i32) * 1000,
+            a7: -i * 1_000_000,
+            a8: i as f32 / 8.0,
+            a9: i as f64 / 3.0,
+            b0: i as u8,
+            b1: i as u16,
+            b2: i as u32,
+            b3: i as u64,
+            b4: (i % 100) as i8,
+            b5: i as i16,
+            b6: i as i32,
+            b7: i,
+            b8: i as f32,
+            b9: i as f64,
+        })
+        .collect();
+    Items { items }
+}
+
+fn encode(c: &mut Criterion) {
+    let items = numbers();
+    c.bench_function("vec of structs with numeric fields", |b| {
+        b.iter(|| serde_qs::to_string(&items).unwrap())
+    });
+}
+
+criterion_group!(benches, encode);
+criterion_main!(benches);
@@ -4,6 +4,7 @@
 
 use crate::de::Config as QsConfig;
 use crate::error::Error as QsError;
+use crate::web::{ErrorResponse, PROBLEM_JSON};
 
 #[cfg(feature = "actix3")]
 use actix_web3 as actix_web;
@@ -11,9 +12,11 @@ use actix_web3 as actix_web;
 use actix_web4 as actix_web;
 
 use actix_web::dev::Payload;
-#[cfg(feature = "actix3")]
-use actix_web::HttpResponse;
-use actix_web::{web, Error as ActixError, FromRequest, HttpRequest, ResponseError};
+use actix_web::error::{InternalError, UrlencodedError};
+use actix_web::http::StatusCode;
+use actix_web::{
+    web, Error as ActixError, FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError,
+};
 use futures::future::{ready, FutureExt, LocalBoxFuture, Ready};
 use futures::StreamExt;
 use serde::de;
@@ -121,19 +124,387 @@ where
             .qs_config
             .deserialize_str::<T>(req.query_string())
             .map(|val| Ok(QsQuery(val)))
-            .unwrap_or_else(move |e| {
-                let e = if let Some(error_handler) = &query_config.ehandler {
-                    (error_handler)(e, req)
-                } else {
-                    e.into()
-                };
-
-                Err(e)
-            });
+            .unwrap_or_else(move |e| Err(query_config.handle_error(e, req)));
+        ready(res)
+    }
+}
+
+#[cfg(feature = "validator")]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+/// Extract typed information from the request's query, and validate it.
+///
+/// This is the same as [`QsQuery`], except that `T` is validated with
+/// [`validator::Validate`] once deserialized. A validation error is
+/// rejected like a deserialization error, as a [`QsError::Validation`]
+/// holding the `validator::ValidationErrors`, so it is passed to the
+/// [`QsQueryConfig`] error handler as well.
+///
+/// Enable with the `validator` feature.
+///
+/// ## Example
+///
+/// ```rust
+/// # #[macro_use] extern crate serde_derive;
+/// # #[cfg(feature = "actix4")]
+/// # use actix_web4 as actix_web;
+/// # #[cfg(feature = "actix3")]
+/// # use actix_web3 as actix_web;
+/// use actix_web::{web, App, HttpResponse};
+/// use serde_qs::actix::ValidatedQsQuery;
+/// use validator::Validate;
+///
+/// #[derive(Deserialize, Validate)]
+/// pub struct Page {
+///    #[validate(range(min = 1, max = 100))]
+///    size: u32,
+/// }
+///
+/// // `/items?size=10` is accepted, `/items?size=1000` is rejected.
+/// async fn items(page: ValidatedQsQuery<Page>) -> HttpResponse {
+///     HttpResponse::Ok().body(format!("{} items", page.size))
+/// }
+///
+/// fn main() {
+///     let app = App::new().service(
+///        web::resource("/items")
+///            .route(web::get().to(items)));
+/// }
+/// ```
+pub struct ValidatedQsQuery<T>(T);
+
+#[cfg(feature = "validator")]
+impl<T> ValidatedQsQuery<T> {
+    /// Unwrap into inner T value
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+#[cfg(feature = "validator")]
+impl<T> Deref for ValidatedQsQuery<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+#[cfg(feature = "validator")]
+impl<T> DerefMut for ValidatedQsQuery<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+#[cfg(feature = "validator")]
+impl<T: Debug> Debug for ValidatedQsQuery<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(feature = "validator")]
+impl<T: Display> Display for ValidatedQsQuery<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(feature = "validator")]
+impl<T> FromRequest for ValidatedQsQuery<T>
+where
+    T: de::DeserializeOwned + validator::Validate,
+{
+    type Error = ActixError;
+    type Future = Ready<Result<Self, ActixError>>;
+    #[cfg(feature = "actix3")]
+    type Config = QsQueryConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let query_config = req.app_data::<QsQueryConfig>().unwrap_or(&DEFAULT_CONFIG);
+
+        let res = query_config
+            .qs_config
+            .deserialize_str::<T>(req.query_string())
+            .and_then(|val| match val.validate() {
+                Ok(()) => Ok(ValidatedQsQuery(val)),
+                Err(errors) => Err(QsError::Validation(Box::new(errors))),
+            })
+            .map_err(|e| query_config.handle_error(e, req));
+        ready(res)
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+/// Extract typed information from the request's query, which must be flat.
+///
+/// This is the same as [`QsQuery`], except that the query string is parsed
+/// with a `max_depth` of zero, and any key containing a `[` is rejected
+/// using the configured [`QsQueryConfig`]. This suits handlers whose types
+/// have no nested structs, maps or sequences, so that a client can't send
+/// more structure than expected.
+///
+/// ## Example
+///
+/// ```rust
+/// # #[macro_use] extern crate serde_derive;
+/// # #[cfg(feature = "actix4")]
+/// # use actix_web4 as actix_web;
+/// # #[cfg(feature = "actix3")]
+/// # use actix_web3 as actix_web;
+/// use actix_web::{web, App, HttpResponse};
+/// use serde_qs::actix::FlatQsQuery;
+///
+/// #[derive(Deserialize)]
+/// pub struct Search {
+///    q: String,
+///    page: u32,
+/// }
+///
+/// // `/search?q=rust&page=2` is accepted, `/search?q[]=rust` is rejected.
+/// async fn search(info: FlatQsQuery<Search>) -> HttpResponse {
+///     HttpResponse::Ok().body(format!("{} (page {})", info.q, info.page))
+/// }
+///
+/// fn main() {
+///     let app = App::new().service(
+///        web::resource("/search")
+///            .route(web::get().to(search)));
+/// }
+/// ```
+pub struct FlatQsQuery<T>(T);
+
+impl<T> FlatQsQuery<T> {
+    /// Unwrap into inner T value
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for FlatQsQuery<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for FlatQsQuery<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Debug> Debug for FlatQsQuery<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: Display> Display for FlatQsQuery<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T> FromRequest for FlatQsQuery<T>
+where
+    T: de::DeserializeOwned,
+{
+    type Error = ActixError;
+    type Future = Ready<Result<Self, ActixError>>;
+    #[cfg(feature = "actix3")]
+    type Config = QsQueryConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let query_config = req.app_data::<QsQueryConfig>().unwrap_or(&DEFAULT_CONFIG);
+
+        let res = query_config
+            .qs_config
+            .deserialize_flat_str::<T>(req.query_string())
+            .map(FlatQsQuery)
+            .map_err(|e| query_config.handle_error(e, req));
+        ready(res)
+    }
+}
+
+/// Extract typed information from the request's query, if present.
+///
+/// Yields `None` when the query string is missing or empty, rather than
+/// attempting to deserialize `T` from it. A query string which is present
+/// but malformed is still rejected using the configured [`QsQueryConfig`].
+///
+/// ## Example
+///
+/// ```rust
+/// # #[macro_use] extern crate serde_derive;
+/// # #[cfg(feature = "actix4")]
+/// # use actix_web4 as actix_web;
+/// # #[cfg(feature = "actix3")]
+/// # use actix_web3 as actix_web;
+/// use actix_web::HttpResponse;
+/// use serde_qs::actix::OptionalQsQuery;
+///
+/// #[derive(Deserialize)]
+/// pub struct UsersFilter {
+///    id: Vec<u64>,
+/// }
+///
+/// async fn filter_users(info: OptionalQsQuery<UsersFilter>) -> HttpResponse {
+///     match info.into_inner() {
+///         Some(filter) => HttpResponse::Ok().body(format!("{} users", filter.id.len())),
+///         None => HttpResponse::Ok().body("all users"),
+///     }
+/// }
+/// ```
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub struct OptionalQsQuery<T>(Option<T>);
+
+impl<T> OptionalQsQuery<T> {
+    /// Unwrap into inner `Option<T>` value
+    pub fn into_inner(self) -> Option<T> {
+        self.0
+    }
+}
+
+impl<T> Deref for OptionalQsQuery<T> {
+    type Target = Option<T>;
+
+    fn deref(&self) -> &Option<T> {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for OptionalQsQuery<T> {
+    fn deref_mut(&mut self) -> &mut Option<T> {
+        &mut self.0
+    }
+}
+
+impl<T: Debug> Debug for OptionalQsQuery<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T> FromRequest for OptionalQsQuery<T>
+where
+    T: de::DeserializeOwned,
+{
+    type Error = ActixError;
+    type Future = Ready<Result<Self, ActixError>>;
+    #[cfg(feature = "actix3")]
+    type Config = QsQueryConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(extract_optional_query(req).map(OptionalQsQuery))
+    }
+}
+
+/// Extract typed information from the request's query, falling back to
+/// `T::default()`.
+///
+/// The default is used when the query string is missing or empty, so that
+/// structs with required fields need not mark them all as optional. A query
+/// string which is present but malformed is still rejected using the
+/// configured [`QsQueryConfig`].
+///
+/// ## Example
+///
+/// ```rust
+/// # #[macro_use] extern crate serde_derive;
+/// # #[cfg(feature = "actix4")]
+/// # use actix_web4 as actix_web;
+/// # #[cfg(feature = "actix3")]
+/// # use actix_web3 as actix_web;
+/// use actix_web::HttpResponse;
+/// use serde_qs::actix::QsQueryOrDefault;
+///
+/// #[derive(Default, Deserialize)]
+/// pub struct Pagination {
+///    page: u32,
+///    per_page: u32,
+/// }
+///
+/// // `/users` is the same as `/users?page=0&per_page=0`
+/// async fn list_users(info: QsQueryOrDefault<Pagination>) -> HttpResponse {
+///     HttpResponse::Ok().body(format!("page {} of {}", info.page, info.per_page))
+/// }
+/// ```
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub struct QsQueryOrDefault<T>(T);
+
+impl<T> QsQueryOrDefault<T> {
+    /// Unwrap into inner T value
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for QsQueryOrDefault<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for QsQueryOrDefault<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Debug> Debug for QsQueryOrDefault<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: Display> Display for QsQueryOrDefault<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T> FromRequest for QsQueryOrDefault<T>
+where
+    T: de::DeserializeOwned + Default,
+{
+    type Error = ActixError;
+    type Future = Ready<Result<Self, ActixError>>;
+    #[cfg(feature = "actix3")]
+    type Config = QsQueryConfig;
+
+    #[inline]
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let res = extract_optional_query(req).map(|val| QsQueryOrDefault(val.unwrap_or_default()));
         ready(res)
     }
 }
 
+/// Deserializes the request's query string, or returns `None` when it is
+/// empty.
+fn extract_optional_query<T>(req: &HttpRequest) -> Result<Option<T>, ActixError>
+where
+    T: de::DeserializeOwned,
+{
+    let query_config = req.app_data::<QsQueryConfig>().unwrap_or(&DEFAULT_CONFIG);
+    let query = req.query_string();
+    if query.is_empty() {
+        return Ok(None);
+    }
+
+    query_config
+        .qs_config
+        .deserialize_str::<T>(query)
+        .map(Some)
+        .map_err(|e| query_config.handle_error(e, req))
+}
+
 type ActixErrorHandler = Option<Arc<dyn Fn(QsError, &HttpRequest) -> ActixError + Send + Sync>>;
 
 /// Query extractor configuration
@@ -179,11 +550,13 @@ type ActixErrorHandler = Option<Arc<dyn Fn(QsError, &HttpRequest) -> ActixError
 pub struct QsQueryConfig {
     ehandler: ActixErrorHandler,
     qs_config: QsConfig,
+    problem_details: bool,
 }
 
 static DEFAULT_CONFIG: QsQueryConfig = QsQueryConfig {
     ehandler: None,
     qs_config: crate::de::DEFAULT_CONFIG,
+    problem_details: false,
 };
 
 impl QsQueryConfig {
@@ -201,6 +574,27 @@ impl QsQueryConfig {
         self.qs_config = config;
         self
     }
+
+    /// Respond to errors with an RFC 7807 problem details body, rather than
+    /// plain text. Ignored when a custom error handler is set.
+    pub fn problem_details(mut self, enabled: bool) -> Self {
+        self.problem_details = enabled;
+        self
+    }
+
+    fn handle_error(&self, err: QsError, req: &HttpRequest) -> ActixError {
+        if let Some(error_handler) = &self.ehandler {
+            (error_handler)(err, req)
+        } else if self.problem_details {
+            let problem = ErrorResponse::from_qs_error(&err);
+            let response = HttpResponse::BadRequest()
+                .content_type(PROBLEM_JSON)
+                .body(problem.to_json());
+            InternalError::from_response(err, response).into()
+        } else {
+            err.into()
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord)]
@@ -225,6 +619,7 @@ impl QsQueryConfig {
 /// // Use `QsForm` extractor for Form information.
 /// // Content-Type: application/x-www-form-urlencoded
 /// // The correct request payload for this handler would be `id[]=1124&id[]=88`
+/// // Other content types are rejected, see `QsFormConfig`.
 /// async fn filter_users(info: QsForm<UsersFilter>) -> HttpResponse {
 ///     HttpResponse::Ok().body(
 ///         info.id.iter().map(|i| i.to_string()).collect::<Vec<String>>().join(", ")
@@ -274,31 +669,145 @@ where
         let mut stream = payload.take();
         let req_clone = req.clone();
 
+        let form_config = *req
+            .app_data::<QsFormConfig>()
+            .unwrap_or(&DEFAULT_FORM_CONFIG);
         let query_config: QsQueryConfig = req
             .app_data::<QsQueryConfig>()
             .unwrap_or(&DEFAULT_CONFIG)
             .clone();
         async move {
+            // The body is not read if the request is rejected here.
+            form_config.check_request(&req_clone)?;
+
             let mut bytes = web::BytesMut::new();
 
             while let Some(item) = stream.next().await {
-                bytes.extend_from_slice(&item.unwrap());
+                let item = item?;
+                form_config.check_size(bytes.len() + item.len())?;
+                bytes.extend_from_slice(&item);
             }
 
-            query_config
+            #[cfg(feature = "actix3")]
+            let res = query_config.qs_config.deserialize_bytes::<T>(&bytes);
+            #[cfg(feature = "actix4")]
+            let res = query_config
                 .qs_config
-                .deserialize_bytes::<T>(&bytes)
-                .map(|val| Ok(QsForm(val)))
-                .unwrap_or_else(|e| {
-                    let e = if let Some(error_handler) = &query_config.ehandler {
-                        (error_handler)(e, &req_clone)
-                    } else {
-                        e.into()
-                    };
-
-                    Err(e)
-                })
+                .deserialize_bytes_owned::<T>(bytes.freeze());
+
+            res.map(|val| Ok(QsForm(val)))
+                .unwrap_or_else(|e| Err(query_config.handle_error(e, &req_clone)))
         }
         .boxed_local()
     }
 }
+
+/// Form extractor configuration, for the request itself rather than its
+/// deserialization, which uses the [`QsQueryConfig`].
+///
+/// ```rust
+/// # #[macro_use] extern crate serde_derive;
+/// # #[cfg(feature = "actix4")]
+/// # use actix_web4 as actix_web;
+/// # #[cfg(feature = "actix3")]
+/// # use actix_web3 as actix_web;
+/// use actix_web::{web, App, HttpResponse};
+/// use serde_qs::actix::{QsForm, QsFormConfig};
+///
+/// #[derive(Debug, Deserialize)]
+/// struct Info {
+///     username: String,
+/// }
+///
+/// async fn index(info: QsForm<Info>) -> HttpResponse {
+///     HttpResponse::Ok().body(format!("Welcome {}!", info.username))
+/// }
+///
+/// fn main() {
+/// let form_config = QsFormConfig::default()
+///     .limit(4096)
+///     .require_content_type(true);
+///
+/// let app = App::new().service(
+///         web::resource("/index.html").app_data(form_config)
+///             .route(web::post().to(index))
+///     );
+/// }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct QsFormConfig {
+    allowed_content_types: &'static [&'static str],
+    require_content_type: bool,
+    limit: Option<usize>,
+}
+
+static DEFAULT_FORM_CONFIG: QsFormConfig = QsFormConfig {
+    allowed_content_types: &["application/x-www-form-urlencoded"],
+    require_content_type: false,
+    limit: None,
+};
+
+impl Default for QsFormConfig {
+    fn default() -> Self {
+        DEFAULT_FORM_CONFIG
+    }
+}
+
+impl QsFormConfig {
+    /// Set the media types which are accepted, ignoring case and any
+    /// parameters such as `charset`. Other content types are rejected with
+    /// `415 Unsupported Media Type`, without reading the body.
+    ///
+    /// Default is `application/x-www-form-urlencoded`.
+    pub fn allowed_content_types(mut self, types: &'static [&'static str]) -> Self {
+        self.allowed_content_types = types;
+        self
+    }
+
+    /// Specifies whether a request without a `Content-Type` header is
+    /// rejected, rather than deserialized as a form. Default is false.
+    pub fn require_content_type(mut self, require: bool) -> Self {
+        self.require_content_type = require;
+        self
+    }
+
+    /// Set the maximum size of the body in bytes. Larger bodies are rejected
+    /// with `413 Payload Too Large`. Default is no limit.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Checks the content type, and the content length if it is given,
+    /// before the body is read.
+    fn check_request(&self, req: &HttpRequest) -> Result<(), ActixError> {
+        let content_type = req.content_type();
+        let allowed = if content_type.is_empty() {
+            !self.require_content_type
+        } else {
+            self.allowed_content_types
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(content_type))
+        };
+        if !allowed {
+            let err = UrlencodedError::ContentType;
+            return Err(InternalError::new(err, StatusCode::UNSUPPORTED_MEDIA_TYPE).into());
+        }
+
+        let length = req
+            .headers()
+            .get(actix_web::http::header::CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok()?.parse().ok());
+        match length {
+            Some(length) => Ok(self.check_size(length)?),
+            None => Ok(()),
+        }
+    }
+
+    fn check_size(&self, size: usize) -> Result<(), UrlencodedError> {
+        match self.limit {
+            Some(limit) if size > limit => Err(UrlencodedError::Overflow { size, limit }),
+            _ => Ok(()),
+        }
+    }
+}
@@ -4,14 +4,16 @@
 
 use axum_framework as axum;
 
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use crate::de::Config as QsConfig;
+use crate::de::{Config as QsConfig, Parsed};
 use crate::error::Error as QsError;
+use crate::web::{ErrorResponse, PROBLEM_JSON};
 
 use axum::{
-    extract::{Extension, FromRequestParts},
-    http::StatusCode,
+    extract::{Extension, FromRef, FromRequestParts},
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     BoxError, Error,
 };
@@ -19,6 +21,12 @@ use axum::{
 #[derive(Clone, Copy, Default)]
 /// Extract typed information from from the request's query.
 ///
+/// The query string is parsed once per request. The first extractor keeps an
+/// owned copy of the parsed query in the request extensions, which later
+/// extractors with the same `max_depth` and `strict` settings deserialize
+/// from. Making the copy allocates each key and value borrowed from the query
+/// string, even when there is a single extractor.
+///
 /// ## Example
 ///
 /// ```rust
@@ -83,15 +91,252 @@ where
         let Extension(qs_config) = Extension::<QsQueryConfig>::from_request_parts(parts, state)
             .await
             .unwrap_or_else(|_| Extension(QsQueryConfig::default()));
-        let error_handler = qs_config.error_handler.clone();
-        let config: QsConfig = qs_config.into();
-        let query = parts.uri.query().unwrap_or_default();
-        match config.deserialize_str::<T>(query) {
-            Ok(value) => Ok(QsQuery(value)),
-            Err(err) => match error_handler {
-                Some(handler) => Err((handler)(err)),
-                None => Err(QsQueryRejection::new(err, StatusCode::BAD_REQUEST)),
-            },
+        qs_config.deserialize(parts, false).map(QsQuery)
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+/// Extract typed information from the request's query, which must be flat.
+///
+/// This is the same as [`QsQuery`], except that the query string is parsed
+/// with a `max_depth` of zero, and any key containing a `[` is rejected.
+/// This suits handlers whose types have no nested structs, maps or
+/// sequences, so that a client can't send more structure than expected.
+///
+/// ## Example
+///
+/// ```rust
+/// # extern crate axum_framework as axum;
+/// use serde_qs::axum::FlatQsQuery;
+/// use axum::{response::IntoResponse, routing::get, Router};
+///
+/// #[derive(serde::Deserialize)]
+/// pub struct Search {
+///    q: String,
+///    page: u32,
+/// }
+///
+/// // `/search?q=rust&page=2` is accepted, `/search?q[]=rust` is rejected.
+/// async fn search(FlatQsQuery(search): FlatQsQuery<Search>) -> impl IntoResponse {
+///     format!("{} (page {})", search.q, search.page)
+/// }
+///
+/// fn main() {
+///     let app = Router::<()>::new()
+///         .route("/search", get(search));
+/// }
+/// ```
+pub struct FlatQsQuery<T>(pub T);
+
+impl<T> std::ops::Deref for FlatQsQuery<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: std::fmt::Display> std::fmt::Display for FlatQsQuery<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for FlatQsQuery<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[axum::async_trait]
+impl<T, S> FromRequestParts<S> for FlatQsQuery<T>
+where
+    T: serde::de::DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = QsQueryRejection;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let Extension(qs_config) = Extension::<QsQueryConfig>::from_request_parts(parts, state)
+            .await
+            .unwrap_or_else(|_| Extension(QsQueryConfig::default()));
+        qs_config.deserialize(parts, true).map(FlatQsQuery)
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+/// Extract typed information from from the request's query, using the
+/// [`QsQueryConfig`] from the router's state.
+///
+/// This is the same as [`QsQuery`], except that the config is extracted with
+/// [`FromRef`] rather than from an [`Extension`].
+///
+/// ## Example
+///
+/// ```rust
+/// # extern crate axum_framework as axum;
+/// use serde_qs::axum::{QsQueryConfig, QsQueryWithState};
+/// use axum::{extract::FromRef, response::IntoResponse, routing::get, Router};
+///
+/// #[derive(Clone)]
+/// struct AppState {
+///     qs_config: QsQueryConfig,
+/// }
+///
+/// impl FromRef<AppState> for QsQueryConfig {
+///     fn from_ref(state: &AppState) -> Self {
+///         state.qs_config.clone()
+///     }
+/// }
+///
+/// #[derive(serde::Deserialize)]
+/// pub struct UsersFilter {
+///    id: Vec<u64>,
+/// }
+///
+/// async fn filter_users(
+///     QsQueryWithState(info): QsQueryWithState<UsersFilter>
+/// ) -> impl IntoResponse {
+///     info.id
+///         .iter()
+///         .map(|i| i.to_string())
+///         .collect::<Vec<String>>()
+///         .join(", ")
+/// }
+///
+/// fn main() {
+///     let app: Router = Router::new()
+///         .route("/users", get(filter_users))
+///         .with_state(AppState {
+///             qs_config: QsQueryConfig::new(5, false),
+///         });
+/// }
+/// ```
+pub struct QsQueryWithState<T>(pub T);
+
+impl<T> std::ops::Deref for QsQueryWithState<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: std::fmt::Display> std::fmt::Display for QsQueryWithState<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for QsQueryWithState<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[axum::async_trait]
+impl<T, S> FromRequestParts<S> for QsQueryWithState<T>
+where
+    T: serde::de::DeserializeOwned,
+    S: Send + Sync,
+    QsQueryConfig: FromRef<S>,
+{
+    type Rejection = QsQueryRejection;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        QsQueryConfig::from_ref(state)
+            .deserialize(parts, false)
+            .map(QsQueryWithState)
+    }
+}
+
+#[cfg(feature = "validator")]
+#[derive(Clone, Copy, Default)]
+/// Extract typed information from the request's query, and validate it.
+///
+/// This is the same as [`QsQuery`], except that `T` is validated with
+/// [`validator::Validate`] once deserialized. A validation error is
+/// rejected like a deserialization error, as a [`QsError::Validation`]
+/// holding the `validator::ValidationErrors`, so it is passed to the
+/// [`QsQueryConfig`] error handler as well.
+///
+/// Enable with the `validator` feature.
+///
+/// ## Example
+///
+/// ```rust
+/// # extern crate axum_framework as axum;
+/// use serde_qs::axum::ValidatedQsQuery;
+/// use axum::{response::IntoResponse, routing::get, Router};
+/// use validator::Validate;
+///
+/// #[derive(serde::Deserialize, Validate)]
+/// pub struct Page {
+///    #[validate(range(min = 1, max = 100))]
+///    size: u32,
+/// }
+///
+/// // `/items?size=10` is accepted, `/items?size=1000` is rejected.
+/// async fn items(ValidatedQsQuery(page): ValidatedQsQuery<Page>) -> impl IntoResponse {
+///     format!("{} items", page.size)
+/// }
+///
+/// fn main() {
+///     let app = Router::<()>::new()
+///         .route("/items", get(items));
+/// }
+/// ```
+pub struct ValidatedQsQuery<T>(pub T);
+
+#[cfg(feature = "validator")]
+impl<T> std::ops::Deref for ValidatedQsQuery<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(feature = "validator")]
+impl<T: std::fmt::Display> std::fmt::Display for ValidatedQsQuery<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(feature = "validator")]
+impl<T: std::fmt::Debug> std::fmt::Debug for ValidatedQsQuery<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(feature = "validator")]
+#[axum::async_trait]
+impl<T, S> FromRequestParts<S> for ValidatedQsQuery<T>
+where
+    T: serde::de::DeserializeOwned + validator::Validate,
+    S: Send + Sync,
+{
+    type Rejection = QsQueryRejection;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let Extension(qs_config) = Extension::<QsQueryConfig>::from_request_parts(parts, state)
+            .await
+            .unwrap_or_else(|_| Extension(QsQueryConfig::default()));
+        let value: T = qs_config.deserialize(parts, false)?;
+        match value.validate() {
+            Ok(()) => Ok(ValidatedQsQuery(value)),
+            Err(errors) => Err(qs_config.reject(QsError::Validation(Box::new(errors)))),
         }
     }
 }
@@ -101,6 +346,7 @@ where
 pub struct QsQueryRejection {
     error: axum::Error,
     status: StatusCode,
+    problem: Option<Box<ErrorResponse>>,
 }
 
 impl std::fmt::Display for QsQueryRejection {
@@ -122,13 +368,30 @@ impl QsQueryRejection {
         QsQueryRejection {
             error: Error::new(error),
             status,
+            problem: None,
+        }
+    }
+
+    /// Create new rejection which responds with an RFC 7807 problem details
+    /// body, see [`ErrorResponse`](crate::web::ErrorResponse).
+    pub fn problem_details(error: QsError, status: StatusCode) -> Self {
+        let problem = ErrorResponse::from_qs_error(&error).status(status.as_u16());
+        QsQueryRejection {
+            error: Error::new(error),
+            status,
+            problem: Some(Box::new(problem)),
         }
     }
 }
 
 impl IntoResponse for QsQueryRejection {
     fn into_response(self) -> Response {
-        let mut res = self.to_string().into_response();
+        let mut res = match self.problem {
+            Some(ref problem) => {
+                ([(header::CONTENT_TYPE, PROBLEM_JSON)], problem.to_json()).into_response()
+            }
+            None => self.to_string().into_response(),
+        };
         *res.status_mut() = self.status;
         res
     }
@@ -188,6 +451,7 @@ impl std::error::Error for QsQueryRejection {
 pub struct QsQueryConfig {
     max_depth: usize,
     strict: bool,
+    problem_details: bool,
     error_handler: Option<Arc<dyn Fn(QsError) -> QsQueryRejection + Send + Sync>>,
 }
 
@@ -197,10 +461,18 @@ impl QsQueryConfig {
         Self {
             max_depth,
             strict,
+            problem_details: false,
             error_handler: None,
         }
     }
 
+    /// Respond to errors with an RFC 7807 problem details body, rather than
+    /// plain text. Ignored when a custom error handler is set.
+    pub fn problem_details(mut self, enabled: bool) -> Self {
+        self.problem_details = enabled;
+        self
+    }
+
     /// Set custom error handler
     pub fn error_handler<F>(mut self, f: F) -> Self
     where
@@ -211,6 +483,85 @@ impl QsQueryConfig {
     }
 }
 
+impl QsQueryConfig {
+    /// Deserialize the query string of a request with this config, rejecting
+    /// nested keys if `flat` is set.
+    fn deserialize<T>(
+        &self,
+        parts: &mut axum::http::request::Parts,
+        flat: bool,
+    ) -> Result<T, QsQueryRejection>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let res = if flat {
+            let query = parts.uri.query().unwrap_or_default();
+            QsConfig::new(self.max_depth, self.strict).deserialize_flat_str::<T>(query)
+        } else {
+            self.parse(parts)
+                .and_then(|parsed| parsed.deserialize_borrowed::<T>())
+        };
+        res.map_err(|err| self.reject(err))
+    }
+
+    /// Parses the query string of a request, or reuses the result of an
+    /// earlier extractor on the same request with the same `max_depth` and
+    /// `strict` settings.
+    fn parse(
+        &self,
+        parts: &mut axum::http::request::Parts,
+    ) -> Result<Arc<Parsed<'static>>, QsError> {
+        let query = parts.uri.query().unwrap_or_default();
+        if let Some(cached) = parts.extensions.get::<CachedQuery>() {
+            if cached.max_depth == self.max_depth
+                && cached.strict == self.strict
+                && cached.query == query
+            {
+                return Ok(cached.parsed.clone());
+            }
+        }
+        if let Some(counter) = parts.extensions.get::<ParseCounter>() {
+            let _ = counter.0.fetch_add(1, Ordering::Relaxed);
+        }
+        let config = QsConfig::new(self.max_depth, self.strict);
+        let parsed = Arc::new(config.parse_str(query)?.into_owned());
+        let _ = parts.extensions.insert(CachedQuery {
+            query: query.to_owned(),
+            max_depth: self.max_depth,
+            strict: self.strict,
+            parsed: parsed.clone(),
+        });
+        Ok(parsed)
+    }
+
+    /// Converts an error into a rejection, with the error handler if set.
+    fn reject(&self, err: QsError) -> QsQueryRejection {
+        match self.error_handler {
+            Some(ref handler) => (handler)(err),
+            None if self.problem_details => {
+                QsQueryRejection::problem_details(err, StatusCode::BAD_REQUEST)
+            }
+            None => QsQueryRejection::new(err, StatusCode::BAD_REQUEST),
+        }
+    }
+}
+
+/// A query string parsed by an extractor, kept in the request extensions so
+/// that other extractors on the same request need not parse it again.
+#[derive(Clone)]
+struct CachedQuery {
+    query: String,
+    max_depth: usize,
+    strict: bool,
+    parsed: Arc<Parsed<'static>>,
+}
+
+/// Counts the query strings parsed by the extractors on a request, when put
+/// in its extensions. Only meant for tests.
+#[doc(hidden)]
+#[derive(Clone, Default)]
+pub struct ParseCounter(pub Arc<AtomicUsize>);
+
 impl From<QsQueryConfig> for QsConfig {
     fn from(config: QsQueryConfig) -> Self {
         Self::new(config.max_depth, config.strict)
@@ -222,6 +573,7 @@ impl Default for QsQueryConfig {
         Self {
             max_depth: 5,
             strict: true,
+            problem_details: false,
             error_handler: None,
         }
     }
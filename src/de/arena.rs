@@ -0,0 +1,75 @@
+//! Decoding values into an arena which is reused between querystrings.
+
+use super::Config;
+use crate::error::Result;
+
+use bumpalo::Bump;
+use serde::de;
+
+/// Deserializes querystrings, decoding their values into an arena which is
+/// reset and reused for each one.
+///
+/// Values with percent-encoding or a `+` are decoded into the arena rather
+/// than into a string of their own, so once the arena has grown to fit a
+/// typical querystring they cost no heap allocations. This helps when values
+/// are borrowed, as with `&str` fields, or parsed, as with numbers and enums.
+/// Fields of type `String` still copy their values out of the arena. Maps and
+/// sequences in the parsed querystring are allocated as usual.
+///
+/// The deserialized value may borrow from the arena, so it must be dropped
+/// before the arena is used again.
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # extern crate serde_qs;
+/// use serde_qs::{Config, QsParserArena};
+///
+/// #[derive(Debug, Deserialize, PartialEq)]
+/// struct Query<'a> {
+///     name: &'a str,
+///     page: u32,
+/// }
+///
+/// # fn main() {
+/// let config = Config::default();
+/// let mut arena = QsParserArena::new();
+/// for input in ["name=Ada+Lovelace&page=1", "name=Grace%20Hopper&page=%32"] {
+///     let query: Query = arena.deserialize_str(&config, input).unwrap();
+///     assert!(query.name.contains(' '));
+/// }
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct QsParserArena {
+    bump: Bump,
+}
+
+impl QsParserArena {
+    /// Creates an empty arena, which grows as it is used.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deserializes a querystring from a `&str` using `config`, see
+    /// [`Config::deserialize_str`].
+    pub fn deserialize_str<'a, T: de::Deserialize<'a>>(
+        &'a mut self,
+        config: &Config,
+        input: &'a str,
+    ) -> Result<T> {
+        self.deserialize_bytes(config, input.as_bytes())
+    }
+
+    /// Deserializes a querystring from a `&[u8]` using `config`, see
+    /// [`Config::deserialize_bytes`].
+    pub fn deserialize_bytes<'a, T: de::Deserialize<'a>>(
+        &'a mut self,
+        config: &Config,
+        input: &'a [u8],
+    ) -> Result<T> {
+        self.bump.reset();
+        let mut parser = config.parser(input).with_arena(&self.bump);
+        T::deserialize(parser.as_deserializer()?)
+    }
+}
@@ -36,15 +36,38 @@
 //! `Level` is a flat value it will attempt to deserialize it to a primitive via
 //! `ParsableStringDeserializer`.
 
+#[cfg(feature = "bumpalo")]
+mod arena;
+mod pairs;
 mod parse;
+mod remainder;
+mod shape;
+mod whole_input;
+
+#[cfg(feature = "bumpalo")]
+pub use self::arena::QsParserArena;
+pub use self::pairs::{KeyPath, Pairs, Segment};
+pub use self::remainder::Remainder;
+pub use self::shape::{classify, QueryShape};
+pub use self::whole_input::WholeInput;
 
 use crate::error::*;
+use crate::ser::ValueFilter;
 
 use serde::de;
-use serde::de::IntoDeserializer;
+use serde::de::{IntoDeserializer, Unexpected};
 
 use std::borrow::Cow;
-use std::collections::btree_map::{BTreeMap, Entry, IntoIter};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::hash::{Hash, Hasher};
+use std::iter::{self, Chain};
+use std::mem;
+use std::num::{ParseFloatError, ParseIntError};
+use std::option;
+use std::str::{self, FromStr, ParseBoolError};
+use std::sync::{Arc, Mutex};
 
 /// To override the default serialization parameters, first construct a new
 /// Config.
@@ -58,12 +81,31 @@ use std::collections::btree_map::{BTreeMap, Entry, IntoIter};
 /// In non-strict mode, the deserializer will generally tolerate unexpected
 /// characters.
 ///
-/// A `max_depth` of 0 implies no nesting: the result will be a flat map.
-/// This is mostly useful when the maximum nested depth is known beforehand,
-/// to prevent denial of service attacks by providing incredibly deeply nested
-/// inputs.
+/// The depth of a key is the number of bracketed parts in it, so `a` has a
+/// depth of 0 and `a[b][0]` a depth of 2. When deserializing, the first
+/// `max_depth` parts of a key are nested, and the rest of the key, brackets
+/// included, is kept as a single key. A `max_depth` of 0 implies no nesting:
+/// the result will be a flat map. This is mostly useful when the maximum
+/// nested depth is known beforehand, to prevent denial of service attacks by
+/// providing incredibly deeply nested inputs.
+///
+/// When serializing, a key deeper than `max_depth` is an
+/// [`Error::MaxDepthExceeded`], so anything serialized with a `Config` can be
+/// deserialized with the same `Config`.
 ///
 /// The default value for `max_depth` is 5, and the default mode is `strict=true`.
+/// A `max_depth` above [`MAX_DEPTH_LIMIT`] is treated as that limit, since
+/// each level of nesting is a native call frame when parsing and
+/// deserializing.
+///
+/// A single leading `?` is skipped by default, so that the querystring part
+/// of a URL can be passed with or without it. Use
+/// [`allow_leading_question_mark`](#method.allow_leading_question_mark) to
+/// treat it as part of the first key instead.
+///
+/// A byte order mark and whitespace around the input, such as a trailing
+/// newline, are also removed by default, see
+/// [`trim_input`](#method.trim_input).
 ///
 /// ```
 /// use serde_qs::Config;
@@ -74,6 +116,11 @@ use std::collections::btree_map::{BTreeMap, Entry, IntoIter};
 ///                                          .unwrap();
 /// assert_eq!(map.get("a[b][c]").unwrap(), "1");
 ///
+/// let config = Config::new(1, true);
+/// let map: HashMap<String, HashMap<String, HashMap<String, String>>> =
+///             config.deserialize_str("a[b][c][d]=1").unwrap();
+/// assert_eq!(map.get("a").unwrap().get("b").unwrap().get("[c][d]").unwrap(), "1");
+///
 /// let config = Config::new(10, true);
 /// let map: HashMap<String, HashMap<String, HashMap<String, String>>> =
 ///             config.deserialize_str("a[b][c]=1").unwrap();
@@ -87,13 +134,203 @@ pub struct Config {
     max_depth: usize,
     /// Strict deserializing mode will not tolerate encoded brackets.
     strict: bool,
+    /// Skip a single leading `?` in the input. Default is true.
+    allow_leading_question_mark: bool,
+    /// Trim a byte order mark and whitespace around the input. Default is
+    /// true.
+    trim_input: bool,
+    /// Serialize map entries sorted by key. Default is false.
+    pub(crate) stable_output: bool,
+    /// Top-level fields to serialize first, in order. Default is empty.
+    pub(crate) field_order: &'static [&'static str],
+    /// Called for each pair before it is serialized. Default is `None`.
+    pub(crate) value_filter: Option<ValueFilter>,
+    /// Leave out pairs with an empty value. Default is false.
+    pub(crate) skip_empty_leaves: bool,
+    /// The largest integer key treated as a sequence index. Default is 10000.
+    max_seq_index: usize,
+    /// How to handle integer keys above `max_seq_index`.
+    seq_index_overflow: SeqIndexOverflow,
+    /// Treat zero-padded integer keys as map keys. Default is false.
+    preserve_leading_zero_keys: bool,
+    /// Reject keys containing control characters. Default is false.
+    reject_control_chars: bool,
+    /// Reject `%` not followed by two hex digits. Default is false.
+    strict_percent_decoding: bool,
+    /// Decode encoded brackets in keys, even in strict mode. Default is false.
+    lenient_bracket_decoding: bool,
+    /// Read and write values without percent-encoding. Default is false.
+    pub(crate) assume_preencoded_values: bool,
+    /// Called for each conflicting value while parsing. Default is `None`.
+    on_conflict: Option<fn(ConflictEvent)>,
+    /// Converts decoded keys to UTF-8. Default is `None`.
+    key_transcoder: Option<Transcoder>,
+    /// Converts decoded values to UTF-8. Default is `None`.
+    value_transcoder: Option<Transcoder>,
+    /// Accept a sequence of one element for a scalar. Default is false.
+    scalar_from_single_element_seq: bool,
+    /// Collect the values of a repeated key into a sequence. Default is
+    /// false.
+    repeated_keys_as_seq: bool,
+    /// Deserialize a key without `=` as a `true` bool. Default is false.
+    bare_key_as_true: bool,
+    /// Deserialize an empty value as a `true` bool. Default is false.
+    empty_value_as_true: bool,
+    /// How nested keys are written. Default is `NestingStyle::Brackets`.
+    pub(crate) nesting_style: NestingStyle,
+    /// Serialize keys as flat dotted paths. Default is false.
+    pub(crate) serialize_flat_dotted: bool,
+    /// How to resolve a key given both a value and nested keys.
+    key_conflict_resolution: Conflict,
+    /// The expected number of top-level keys. Default is 0.
+    capacity_hint: usize,
+    /// Keep map keys in the order they were given, with the `indexmap`
+    /// feature. Default is false.
+    preserve_key_order: bool,
+    /// Collect the top-level keys not matched to a field. Default is false.
+    collect_unmatched_keys: bool,
 }
 
+/// The largest `max_depth` which is used, see [`Config`].
+pub const MAX_DEPTH_LIMIT: usize = 256;
+
 pub const DEFAULT_CONFIG: Config = Config {
     max_depth: 5,
     strict: true,
+    allow_leading_question_mark: true,
+    trim_input: true,
+    stable_output: false,
+    field_order: &[],
+    value_filter: None,
+    skip_empty_leaves: false,
+    max_seq_index: 10_000,
+    seq_index_overflow: SeqIndexOverflow::MapKey,
+    preserve_leading_zero_keys: false,
+    reject_control_chars: false,
+    strict_percent_decoding: false,
+    lenient_bracket_decoding: false,
+    assume_preencoded_values: false,
+    on_conflict: None,
+    key_transcoder: None,
+    value_transcoder: None,
+    scalar_from_single_element_seq: false,
+    repeated_keys_as_seq: false,
+    bare_key_as_true: false,
+    empty_value_as_true: false,
+    nesting_style: NestingStyle::Brackets,
+    serialize_flat_dotted: false,
+    key_conflict_resolution: Conflict::Error,
+    capacity_hint: 0,
+    preserve_key_order: false,
+    collect_unmatched_keys: false,
 };
 
+/// A function which converts percent-decoded bytes to UTF-8, see
+/// [`Config::value_transcoder`].
+pub type Transcoder = for<'a> fn(Cow<'a, [u8]>) -> Result<Cow<'a, str>, TranscodeError>;
+
+/// How to handle integer keys which are too large to be sequence indices,
+/// see [`Config::max_seq_index`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeqIndexOverflow {
+    /// Treat the key as a map key. This means it can still be deserialized
+    /// into a map such as `HashMap<u64, T>`, but not into a sequence.
+    MapKey,
+    /// Fail with `Error::SequenceIndexTooLarge`.
+    Error,
+}
+
+/// How to resolve a key which is given both a value and nested keys, such
+/// as `a=1&a[b]=2`, see [`Config::key_conflict_resolution`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Conflict {
+    /// Keep both, so that deserializing the key fails.
+    Error,
+    /// Keep the nested keys, and drop the value.
+    PreferNested,
+    /// Keep the value, and drop the nested keys.
+    PreferFlat,
+}
+
+/// How the parts of a nested key are written, see [`Config::nesting_style`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NestingStyle {
+    /// Nested parts are in brackets, e.g. `user[address][city]`.
+    Brackets,
+    /// Nested parts are separated by dots, e.g. `user.address.city`, as
+    /// with the exploded form expansion of RFC 6570. Literal dots in keys
+    /// are encoded as `%2E`.
+    Dots,
+}
+
+/// A value which was dropped or restructured while parsing, see
+/// [`Config::on_conflict`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConflictEvent {
+    /// What happened to the value.
+    pub kind: ConflictKind,
+    /// The key of the entry which caused the conflict, as given in the input,
+    /// e.g. `a[b]`.
+    pub key: String,
+    /// The byte offset of the entry in the input.
+    pub position: usize,
+}
+
+/// The kinds of [`ConflictEvent`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConflictKind {
+    /// The key was already given a value, e.g. `a=1&a=2`. Deserializing the
+    /// containing map or struct fails.
+    DuplicateKey,
+    /// The key already holds a value of a different shape, e.g. `a=1&a[b]=2`
+    /// or `a[]=1&a[0]=2`. As with duplicate keys, deserializing fails.
+    MismatchedStructure,
+    /// A string key was added to a sequence with integer indices, e.g.
+    /// `a[0]=1&a[x]=2`, so the sequence was turned into a map.
+    PromotedToMap,
+    /// The key was given both a value and nested keys, e.g. `a=1&a[b]=2`,
+    /// and one of them was dropped, see [`Config::key_conflict_resolution`].
+    DroppedValue,
+}
+
+/// Counts of what happened while parsing a querystring, see
+/// [`Config::deserialize_str_with_stats`].
+///
+/// Keys and values are borrowed from the input unless they contain `+` or a
+/// percent-encoding, in which case they are decoded into an owned string, or
+/// for keys, decoded when they are deserialized. Values of keys given
+/// without `=` are not counted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ParseStats {
+    /// Key segments borrowed from the input.
+    pub borrowed_keys: usize,
+    /// Key segments which need decoding, including repeats of the same key.
+    /// These are decoded when they are deserialized, or when parsing if a
+    /// key transcoder is set.
+    pub owned_keys: usize,
+    /// Values borrowed from the input.
+    pub borrowed_values: usize,
+    /// Values which were decoded.
+    pub owned_values: usize,
+    /// Sequences which were turned into maps, see
+    /// [`ConflictKind::PromotedToMap`].
+    pub promoted_sequences: usize,
+    /// Keys which were given more than once, see
+    /// [`ConflictKind::DuplicateKey`]. Deserializing the value of such a
+    /// key fails, even when the key is ignored, so this is zero whenever
+    /// deserializing succeeds.
+    pub duplicate_keys: usize,
+    /// Values or nested keys which were dropped, see
+    /// [`ConflictKind::DroppedValue`].
+    pub dropped_values: usize,
+    /// The top-level keys which were not matched to a named field of the
+    /// deserialized type, which is every key when it is deserialized as a
+    /// map. Only collected with [`Config::collect_unmatched_keys`].
+    pub unmatched_keys: Vec<String>,
+}
+
 impl Default for Config {
     fn default() -> Self {
         DEFAULT_CONFIG
@@ -103,12 +340,751 @@ impl Default for Config {
 impl Config {
     /// Create a new `Config` with the specified `max_depth` and `strict` mode.
     pub fn new(max_depth: usize, strict: bool) -> Self {
-        Self { max_depth, strict }
+        Self {
+            max_depth,
+            strict,
+            ..DEFAULT_CONFIG
+        }
+    }
+
+    /// Specifies whether a single leading `?` is skipped. Default is true.
+    ///
+    /// A literal `?` at the start of the first key can still be provided by
+    /// percent-encoding it as `%3F`.
+    ///
+    /// ```
+    /// use serde_qs::Config;
+    /// use std::collections::HashMap;
+    ///
+    /// let map: HashMap<String, String> = Config::default()
+    ///     .deserialize_str("?a=1")
+    ///     .unwrap();
+    /// assert_eq!(map.get("a").unwrap(), "1");
+    ///
+    /// let map: HashMap<String, String> = Config::default()
+    ///     .allow_leading_question_mark(false)
+    ///     .deserialize_str("?a=1")
+    ///     .unwrap();
+    /// assert_eq!(map.get("?a").unwrap(), "1");
+    /// ```
+    pub fn allow_leading_question_mark(mut self, allow: bool) -> Self {
+        self.allow_leading_question_mark = allow;
+        self
+    }
+
+    /// Specifies whether a leading UTF-8 byte order mark and any ASCII
+    /// whitespace, such as a trailing newline, are removed from the input
+    /// before parsing. Default is true.
+    ///
+    /// Only the ends of the whole input are trimmed, so whitespace within
+    /// keys and values is kept, as is any percent-encoded whitespace at the
+    /// end, e.g. `a=x%20`. A leading `?` is skipped after trimming.
+    ///
+    /// ```
+    /// use serde_qs::Config;
+    /// use std::collections::HashMap;
+    ///
+    /// let map: HashMap<String, String> = Config::default()
+    ///     .deserialize_str("a=1&b=2\r\n")
+    ///     .unwrap();
+    /// assert_eq!(map["b"], "2");
+    ///
+    /// let map: HashMap<String, String> = Config::default()
+    ///     .trim_input(false)
+    ///     .deserialize_str("a=1&b=2\r\n")
+    ///     .unwrap();
+    /// assert_eq!(map["b"], "2\r\n");
+    /// ```
+    pub fn trim_input(mut self, trim: bool) -> Self {
+        self.trim_input = trim;
+        self
+    }
+
+    /// Specifies whether map entries are serialized in sorted key order.
+    /// Default is false, which writes entries in the map's iteration order.
+    ///
+    /// Entries are sorted within each map, so sequence elements keep their
+    /// order and the entries of each element stay adjacent. This is useful
+    /// for deterministic output from maps such as `HashMap`, for example in
+    /// snapshot tests.
+    ///
+    /// ```
+    /// use serde_qs::Config;
+    /// use std::collections::HashMap;
+    ///
+    /// let map: HashMap<_, _> = vec![("b", 2), ("c", 3), ("a", 1)].into_iter().collect();
+    /// let config = Config::default().stable_output(true);
+    /// assert_eq!(config.serialize_string(&map).unwrap(), "a=1&b=2&c=3");
+    /// ```
+    pub fn stable_output(mut self, stable: bool) -> Self {
+        self.stable_output = stable;
+        self
     }
 
-    /// Get maximum depth parameter.
-    fn max_depth(&self) -> usize {
-        self.max_depth
+    /// Specifies top-level fields which are serialized first, in the given
+    /// order. Default is empty.
+    ///
+    /// The serialized pairs are buffered and each is matched on its
+    /// top-level key, so every key nested under a listed field moves with
+    /// it, e.g. `page[size]` for `page`. Unlisted keys follow in their
+    /// usual order, and names which do not appear in the output are ignored.
+    /// This applies equally to fields from `#[serde(flatten)]` and to the
+    /// fields under the prefix given to
+    /// [`serialize_string_with_prefix`](#method.serialize_string_with_prefix).
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde_qs;
+    /// use serde_qs::Config;
+    ///
+    /// #[derive(Serialize)]
+    /// struct Query {
+    ///     q: String,
+    ///     sort: String,
+    ///     api_key: String,
+    /// }
+    ///
+    /// # fn main() {
+    /// let query = Query {
+    ///     q: "rust".to_owned(),
+    ///     sort: "new".to_owned(),
+    ///     api_key: "secret".to_owned(),
+    /// };
+    /// let config = Config::default().field_order(&["api_key", "sort"]);
+    /// assert_eq!(
+    ///     config.serialize_string(&query).unwrap(),
+    ///     "api_key=secret&sort=new&q=rust"
+    /// );
+    /// # }
+    /// ```
+    pub fn field_order(mut self, order: &'static [&'static str]) -> Self {
+        self.field_order = order;
+        self
+    }
+
+    /// Specifies a function which is called with the key path of each pair
+    /// before it is serialized, and can redact or skip its value. Default is
+    /// `None`.
+    ///
+    /// This is useful for logging querystrings without secrets such as API
+    /// keys. Redacted values are written as `***`, so the output still
+    /// parses. Numeric segments of the path are given as
+    /// [`Segment::Index`], whether they are sequence indices or map keys.
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde_qs;
+    /// use serde_qs::{Config, KeyPath, Redactable, Segment};
+    ///
+    /// #[derive(Serialize)]
+    /// struct Query {
+    ///     q: String,
+    ///     api_key: String,
+    /// }
+    ///
+    /// fn redact_secrets(path: &KeyPath, value: &mut Redactable) {
+    ///     if path.segments().last() == Some(&Segment::from("api_key")) {
+    ///         value.redact();
+    ///     }
+    /// }
+    ///
+    /// # fn main() {
+    /// let query = Query {
+    ///     q: "rust".to_owned(),
+    ///     api_key: "secret".to_owned(),
+    /// };
+    /// let config = Config::default().value_filter(redact_secrets);
+    /// assert_eq!(config.serialize_string(&query).unwrap(), "q=rust&api_key=***");
+    /// # }
+    /// ```
+    pub fn value_filter(mut self, filter: ValueFilter) -> Self {
+        self.value_filter = Some(filter);
+        self
+    }
+
+    /// Specifies whether pairs with an empty value, such as `city=` for an
+    /// empty `String`, are left out when serializing. Default is false.
+    ///
+    /// A struct whose values are all empty is left out entirely, key and
+    /// all. Elements of sequences are always written, so that the elements
+    /// which follow keep their indices.
+    ///
+    /// This is not symmetric: a value which was left out is missing when
+    /// deserializing, rather than empty. Fields with `#[serde(default)]` are
+    /// empty again, but an `Option<String>` of `Some("")` becomes `None`, and
+    /// a required `String` field fails to deserialize.
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde_qs;
+    /// use serde_qs::Config;
+    ///
+    /// #[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+    /// struct Address {
+    ///     #[serde(default)]
+    ///     city: String,
+    ///     #[serde(default)]
+    ///     postcode: String,
+    /// }
+    ///
+    /// #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    /// struct Query {
+    ///     name: String,
+    ///     #[serde(default)]
+    ///     address: Address,
+    /// }
+    ///
+    /// # fn main() {
+    /// let query = Query {
+    ///     name: "Acme".to_owned(),
+    ///     address: Address::default(),
+    /// };
+    /// let config = Config::default().skip_empty_leaves(true);
+    /// let encoded = config.serialize_string(&query).unwrap();
+    /// assert_eq!(encoded, "name=Acme");
+    /// assert_eq!(config.deserialize_str::<Query>(&encoded).unwrap(), query);
+    /// # }
+    /// ```
+    pub fn skip_empty_leaves(mut self, skip: bool) -> Self {
+        self.skip_empty_leaves = skip;
+        self
+    }
+
+    /// Specifies the largest integer key which is treated as a sequence
+    /// index, such as `a[10]`. Default is 10000.
+    ///
+    /// Larger keys are handled according to
+    /// [`seq_index_overflow`](#method.seq_index_overflow), which by default
+    /// treats them as map keys.
+    ///
+    /// ```
+    /// use serde_qs::{Config, SeqIndexOverflow};
+    /// use std::collections::HashMap;
+    ///
+    /// let config = Config::default().max_seq_index(100);
+    /// assert!(config.deserialize_str::<HashMap<String, Vec<u8>>>("a[101]=1").is_err());
+    ///
+    /// let map: HashMap<String, HashMap<u32, u8>> = config.deserialize_str("a[101]=1").unwrap();
+    /// assert_eq!(map["a"][&101], 1);
+    ///
+    /// let config = config.seq_index_overflow(SeqIndexOverflow::Error);
+    /// assert!(config.deserialize_str::<HashMap<String, HashMap<u32, u8>>>("a[101]=1").is_err());
+    /// ```
+    pub fn max_seq_index(mut self, max_seq_index: usize) -> Self {
+        self.max_seq_index = max_seq_index;
+        self
+    }
+
+    /// Specifies how integer keys above
+    /// [`max_seq_index`](#method.max_seq_index) are handled. Default is
+    /// [`SeqIndexOverflow::MapKey`].
+    pub fn seq_index_overflow(mut self, overflow: SeqIndexOverflow) -> Self {
+        self.seq_index_overflow = overflow;
+        self
+    }
+
+    /// Specifies how to resolve a key which is given both a value and nested
+    /// keys, such as `a=1&a[b]=2`, in either order and at any depth. Default
+    /// is [`Conflict::Error`], which fails to deserialize the key.
+    ///
+    /// This is useful while clients move from one form of a parameter to the
+    /// other. Each value or set of nested keys which is dropped is reported
+    /// to [`on_conflict`](#method.on_conflict) as a
+    /// [`ConflictKind::DroppedValue`].
+    ///
+    /// ```
+    /// use serde_qs::{Config, Conflict};
+    /// use std::collections::HashMap;
+    ///
+    /// let config = Config::default().key_conflict_resolution(Conflict::PreferNested);
+    /// let map: HashMap<String, HashMap<String, u8>> =
+    ///     config.deserialize_str("a=1&a[b]=2").unwrap();
+    /// assert_eq!(map["a"]["b"], 2);
+    ///
+    /// let config = Config::default().key_conflict_resolution(Conflict::PreferFlat);
+    /// let map: HashMap<String, u8> = config.deserialize_str("a[b]=2&a=1").unwrap();
+    /// assert_eq!(map["a"], 1);
+    /// ```
+    pub fn key_conflict_resolution(mut self, resolution: Conflict) -> Self {
+        self.key_conflict_resolution = resolution;
+        self
+    }
+
+    /// Specifies whether zero-padded integer keys, such as `a[001]`, are kept
+    /// as map keys rather than read as sequence indices. Default is false.
+    ///
+    /// By default `a[001]` and `a[1]` are both index 1, which suits inputs
+    /// that pad their indices but means the two conflict when they appear
+    /// together. With this option the padded key is kept as the string
+    /// `"001"`, so both entries can be deserialized into a map keyed by
+    /// `String`, but padded keys can no longer be deserialized into a
+    /// sequence.
+    ///
+    /// ```
+    /// use serde_qs::Config;
+    /// use std::collections::HashMap;
+    ///
+    /// let input = "a[001]=x&a[1]=y";
+    /// assert!(Config::default()
+    ///     .deserialize_str::<HashMap<String, HashMap<String, String>>>(input)
+    ///     .is_err());
+    ///
+    /// let config = Config::default().preserve_leading_zero_keys(true);
+    /// let map: HashMap<String, HashMap<String, String>> = config.deserialize_str(input).unwrap();
+    /// assert_eq!(map["a"]["001"], "x");
+    /// assert_eq!(map["a"]["1"], "y");
+    /// ```
+    pub fn preserve_leading_zero_keys(mut self, preserve: bool) -> Self {
+        self.preserve_leading_zero_keys = preserve;
+        self
+    }
+
+    /// Specifies whether keys containing ASCII control characters (`0x00` to
+    /// `0x1F` and `0x7F`), either as-is or percent-encoded, are rejected with
+    /// [`Error::InvalidKeyCharacter`]. Default is false.
+    ///
+    /// This keeps such characters out of field names, map keys and error
+    /// messages. Values are not checked.
+    ///
+    /// ```
+    /// use serde_qs::Config;
+    /// use std::collections::HashMap;
+    ///
+    /// let config = Config::default().reject_control_chars(true);
+    /// assert!(config.deserialize_str::<HashMap<String, String>>("a%00=1").is_err());
+    /// assert!(config.deserialize_str::<HashMap<String, String>>("a=%00").is_ok());
+    /// ```
+    pub fn reject_control_chars(mut self, reject: bool) -> Self {
+        self.reject_control_chars = reject;
+        self
+    }
+
+    /// Specifies whether a `%` which is not followed by two hex digits is
+    /// rejected with [`Error::InvalidPercentEncoding`]. Default is false,
+    /// which keeps such sequences as literal text.
+    ///
+    /// This catches the `%uXXXX` escapes of JavaScript's `escape()`, which
+    /// would otherwise be read as the text `%uXXXX`, as well as truncated
+    /// escapes such as a trailing `%2`.
+    ///
+    /// ```
+    /// use serde_qs::Config;
+    /// use std::collections::HashMap;
+    ///
+    /// let map: HashMap<String, String> = Config::default().deserialize_str("a=%u0041").unwrap();
+    /// assert_eq!(map["a"], "%u0041");
+    ///
+    /// let config = Config::default().strict_percent_decoding(true);
+    /// assert!(config.deserialize_str::<HashMap<String, String>>("a=%u0041").is_err());
+    /// ```
+    pub fn strict_percent_decoding(mut self, strict: bool) -> Self {
+        self.strict_percent_decoding = strict;
+        self
+    }
+
+    /// Specifies whether encoded brackets in keys, such as `a%5Bb%5D`, denote
+    /// nested keys even in strict mode. Default is false.
+    ///
+    /// This is the bracket handling of non-strict mode on its own: invalid
+    /// UTF-8 and unexpected characters are still errors. Non-strict mode
+    /// always decodes encoded brackets, regardless of this option.
+    ///
+    /// ```
+    /// use serde_qs::Config;
+    /// use std::collections::HashMap;
+    ///
+    /// let config = Config::default().lenient_bracket_decoding(true);
+    /// let map: HashMap<String, HashMap<String, String>> =
+    ///     config.deserialize_str("a%5Bb%5D=1").unwrap();
+    /// assert_eq!(map["a"]["b"], "1");
+    /// assert!(config.deserialize_str::<HashMap<String, String>>("a=%E9").is_err());
+    /// ```
+    pub fn lenient_bracket_decoding(mut self, lenient: bool) -> Self {
+        self.lenient_bracket_decoding = lenient;
+        self
+    }
+
+    /// Specifies whether values are passed through as they are, without
+    /// being percent-decoded when deserializing or percent-encoded when
+    /// serializing. Default is false.
+    ///
+    /// This is for proxies and middlewares which rewrite a querystring
+    /// without interpreting its values, so that a value such as `50%25`
+    /// is written back unchanged, rather than as `50%2525`. A `+` is kept
+    /// as well. Keys are still decoded and encoded as usual.
+    ///
+    /// Values are written verbatim, so they must already be encoded: a
+    /// value containing `&` or `=` produces a different querystring.
+    ///
+    /// ```
+    /// use serde_qs::Config;
+    /// use std::collections::BTreeMap;
+    ///
+    /// let config = Config::default().assume_preencoded_values(true);
+    /// let map: BTreeMap<String, String> =
+    ///     config.deserialize_str("discount=50%25&q=a+b").unwrap();
+    /// assert_eq!(map["discount"], "50%25");
+    /// assert_eq!(config.serialize_string(&map).unwrap(), "discount=50%25&q=a+b");
+    /// ```
+    pub fn assume_preencoded_values(mut self, preencoded: bool) -> Self {
+        self.assume_preencoded_values = preencoded;
+        self
+    }
+
+    /// Specifies a function which is called for each value that is dropped
+    /// or restructured while parsing, such as a duplicate key. Default is
+    /// `None`.
+    ///
+    /// The resulting errors do not say where in the input the conflict
+    /// arose, and promoting a sequence to a map is not an error at all, so
+    /// this is useful for finding out why a field has an unexpected value.
+    ///
+    /// ```
+    /// use serde_qs::{Config, ConflictEvent, ConflictKind};
+    /// use std::collections::HashMap;
+    ///
+    /// fn log_conflict(event: ConflictEvent) {
+    ///     assert_eq!(event.kind, ConflictKind::DuplicateKey);
+    ///     eprintln!("conflicting value for `{}` at {}", event.key, event.position);
+    /// }
+    ///
+    /// let config = Config::default().on_conflict(log_conflict);
+    /// assert!(config.deserialize_str::<HashMap<String, String>>("a=1&a=2").is_err());
+    /// ```
+    pub fn on_conflict(mut self, on_conflict: fn(ConflictEvent)) -> Self {
+        self.on_conflict = Some(on_conflict);
+        self
+    }
+
+    /// Specifies a function which converts percent-decoded values to UTF-8,
+    /// in place of the UTF-8 check of strict and non-strict mode. Default is
+    /// `None`.
+    ///
+    /// This supports clients which percent-encode a legacy charset such as
+    /// Shift JIS. The function is given the value with `+` replaced and
+    /// percent-encodings decoded, and borrowed from the input where
+    /// possible. A returned [`TranscodeError`] fails parsing with
+    /// [`Error::Transcode`](crate::Error::Transcode), which names the key.
+    ///
+    /// A plain function, rather than a closure, keeps `Config` `Copy`.
+    ///
+    /// ```
+    /// use serde_qs::{Config, TranscodeError};
+    /// use std::borrow::Cow;
+    /// use std::collections::HashMap;
+    ///
+    /// fn latin1(bytes: Cow<[u8]>) -> Result<Cow<str>, TranscodeError> {
+    ///     Ok(bytes.iter().map(|&b| char::from(b)).collect::<String>().into())
+    /// }
+    ///
+    /// let config = Config::default().value_transcoder(latin1);
+    /// let map: HashMap<String, String> = config.deserialize_str("name=Jos%E9").unwrap();
+    /// assert_eq!(map["name"], "José");
+    /// ```
+    pub fn value_transcoder(mut self, transcoder: Transcoder) -> Self {
+        self.value_transcoder = Some(transcoder);
+        self
+    }
+
+    /// Specifies a function which converts percent-decoded keys to UTF-8,
+    /// like [`value_transcoder`](#method.value_transcoder) does for values.
+    /// Default is `None`.
+    ///
+    /// The function is given each segment of a key, e.g. `a` and `b` for
+    /// `a[b]`.
+    pub fn key_transcoder(mut self, transcoder: Transcoder) -> Self {
+        self.key_transcoder = Some(transcoder);
+        self
+    }
+
+    /// Specifies whether a sequence of exactly one element, such as `id[]=5`,
+    /// can be deserialized as a single number, string or bool. Default is
+    /// false.
+    ///
+    /// This accepts clients which always send bracketed keys. A sequence of
+    /// more than one element is still an error.
+    ///
+    /// ```
+    /// use serde_qs::Config;
+    /// use std::collections::HashMap;
+    ///
+    /// let config = Config::default().scalar_from_single_element_seq(true);
+    /// let map: HashMap<String, u64> = config.deserialize_str("id[]=5").unwrap();
+    /// assert_eq!(map["id"], 5);
+    /// assert!(config.deserialize_str::<HashMap<String, u64>>("id[]=5&id[]=6").is_err());
+    /// ```
+    pub fn scalar_from_single_element_seq(mut self, accept: bool) -> Self {
+        self.scalar_from_single_element_seq = accept;
+        self
+    }
+
+    /// Specifies whether the values of a key given more than once without
+    /// brackets, such as `tag=a&tag=b`, are collected into a sequence.
+    /// Default is false, which makes a repeated key an error.
+    ///
+    /// This also lets a key given once, such as `color=red`, be deserialized
+    /// as a sequence of one element, so that a `HashMap<String, Vec<String>>`
+    /// holds any querystring of flat keys. Each time a key is given is one
+    /// element, even without a value, so `flag` and `flag=` are `[""]` and
+    /// `flag&flag=x` is `["", "x"]`, unlike the empty sequence they give
+    /// otherwise, see [Empty values](index.html#empty-values). A key given
+    /// without brackets after indexed ones, as in `a[0]=x&a=y`, is added
+    /// after them.
+    ///
+    /// ```
+    /// use serde_qs::Config;
+    /// use std::collections::HashMap;
+    ///
+    /// let config = Config::default().repeated_keys_as_seq(true);
+    /// let map: HashMap<String, Vec<String>> =
+    ///     config.deserialize_str("tag=a&tag=b&color=red").unwrap();
+    /// assert_eq!(map["tag"], ["a", "b"]);
+    /// assert_eq!(map["color"], ["red"]);
+    ///
+    /// let map: HashMap<String, Vec<String>> = config.deserialize_str("flag").unwrap();
+    /// assert_eq!(map["flag"], [""]);
+    /// ```
+    pub fn repeated_keys_as_seq(mut self, collect: bool) -> Self {
+        self.repeated_keys_as_seq = collect;
+        self
+    }
+
+    /// Specifies whether a key given without `=`, such as `flag` in
+    /// `flag&page=2`, deserializes as a `true` bool. Default is false.
+    ///
+    /// This suits checkbox-style flags, which are left out rather than set
+    /// to false. A missing field still needs `#[serde(default)]` to be
+    /// false, or can be an `Option<bool>`. Since the key is present,
+    /// `Option` fields given as a bare key are `Some`.
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde_qs;
+    /// use serde_qs::Config;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Query {
+    ///     verbose: bool,
+    ///     #[serde(default)]
+    ///     quiet: bool,
+    ///     all: Option<bool>,
+    /// }
+    ///
+    /// # fn main() {
+    /// let config = Config::default().bare_key_as_true(true);
+    /// let query: Query = config.deserialize_str("verbose&all").unwrap();
+    /// assert!(query.verbose);
+    /// assert!(!query.quiet);
+    /// assert_eq!(query.all, Some(true));
+    /// # }
+    /// ```
+    pub fn bare_key_as_true(mut self, bare_key_as_true: bool) -> Self {
+        self.bare_key_as_true = bare_key_as_true;
+        self
+    }
+
+    /// Specifies whether an empty value, such as `flag=`, deserializes as
+    /// a `true` bool. Default is false, which makes it an error.
+    ///
+    /// An empty value for an `Option<bool>` is still `None`, which is how
+    /// `None` is serialized.
+    pub fn empty_value_as_true(mut self, empty_value_as_true: bool) -> Self {
+        self.empty_value_as_true = empty_value_as_true;
+        self
+    }
+
+    /// Specifies whether [`deserialize_str_with_stats`](#method.deserialize_str_with_stats)
+    /// lists the top-level keys which were not matched to a named field, in
+    /// [`ParseStats::unmatched_keys`]. Default is false.
+    ///
+    /// A struct is usually deserialized with its list of fields, and any
+    /// other keys are ignored, or rejected with
+    /// `#[serde(deny_unknown_fields)]`. A struct with a `#[serde(flatten)]`
+    /// field is instead deserialized as a map, without its list of fields,
+    /// so every key is passed through generically and all of them are
+    /// listed.
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde_qs;
+    /// use serde_qs::Config;
+    /// use std::collections::HashMap;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Query {
+    ///     page: u32,
+    /// }
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Flattened {
+    ///     page: u32,
+    ///     #[serde(flatten)]
+    ///     rest: HashMap<String, String>,
+    /// }
+    ///
+    /// # fn main() {
+    /// let config = Config::default().collect_unmatched_keys(true);
+    /// let (_, stats) = config
+    ///     .deserialize_str_with_stats::<Query>("page=2&pgae=3")
+    ///     .unwrap();
+    /// assert_eq!(stats.unmatched_keys, ["pgae"]);
+    ///
+    /// let (_, stats) = config
+    ///     .deserialize_str_with_stats::<Flattened>("page=2&pgae=3")
+    ///     .unwrap();
+    /// assert_eq!(stats.unmatched_keys, ["page", "pgae"]);
+    /// # }
+    /// ```
+    pub fn collect_unmatched_keys(mut self, collect: bool) -> Self {
+        self.collect_unmatched_keys = collect;
+        self
+    }
+
+    /// Specifies the number of top-level keys the input is expected to
+    /// have, so that the parsed map can be allocated up front. Default is 0.
+    ///
+    /// This only has an effect with the `indexmap` feature, as the default
+    /// sorted map cannot be pre-sized. The hint only affects performance: a
+    /// hint which is too small means the map grows as usual, and one which
+    /// is too large wastes memory until the input is deserialized. The hint
+    /// is capped at the length of the input.
+    ///
+    /// ```
+    /// use serde_qs::Config;
+    /// use std::collections::HashMap;
+    ///
+    /// let config = Config::default().capacity_hint(5_000);
+    /// let map: HashMap<String, u32> = config.deserialize_str("a=1&b=2").unwrap();
+    /// assert_eq!(map.len(), 2);
+    /// ```
+    pub fn capacity_hint(mut self, capacity: usize) -> Self {
+        self.capacity_hint = capacity;
+        self
+    }
+
+    /// Specifies whether map keys are deserialized in the order they first
+    /// appear in the querystring, rather than sorted. Default is false.
+    ///
+    /// This only has an effect with the `indexmap` feature. Enabling the
+    /// feature alone does not change the order keys are deserialized in, so
+    /// it does not affect other crates using `serde_qs` in the same build.
+    ///
+    /// ```
+    /// # #[cfg(feature = "indexmap")] {
+    /// use indexmap::IndexMap;
+    /// use serde_qs::Config;
+    ///
+    /// let input = "b=1&a=2";
+    /// let map: IndexMap<String, u8> = Config::default().deserialize_str(input).unwrap();
+    /// assert_eq!(map.keys().collect::<Vec<_>>(), ["a", "b"]);
+    ///
+    /// let config = Config::default().preserve_key_order(true);
+    /// let map: IndexMap<String, u8> = config.deserialize_str(input).unwrap();
+    /// assert_eq!(map.keys().collect::<Vec<_>>(), ["b", "a"]);
+    /// # }
+    /// ```
+    pub fn preserve_key_order(mut self, preserve: bool) -> Self {
+        self.preserve_key_order = preserve;
+        self
+    }
+
+    /// Specifies how the parts of nested keys are written. Default is
+    /// [`NestingStyle::Brackets`].
+    ///
+    /// With [`NestingStyle::Dots`], the serializer writes `a.b.0` rather
+    /// than `a[b][0]`, and encodes dots within keys as `%2E`. The
+    /// deserializer splits keys on dots as well as brackets, so both forms
+    /// are accepted, while `%2E` is kept as a literal dot. Each dot counts
+    /// towards `max_depth` as a bracketed part would.
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde_qs;
+    /// use serde_qs::{Config, NestingStyle};
+    /// use std::collections::HashMap;
+    ///
+    /// #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    /// struct Query {
+    ///     filter: HashMap<String, String>,
+    ///     ids: Vec<u32>,
+    /// }
+    ///
+    /// # fn main() {
+    /// let config = Config::default().nesting_style(NestingStyle::Dots);
+    /// let mut filter = HashMap::new();
+    /// filter.insert("file.name".to_owned(), "a".to_owned());
+    /// let query = Query { filter, ids: vec![1, 2] };
+    ///
+    /// let encoded = config.serialize_string(&query).unwrap();
+    /// assert_eq!(encoded, "filter.file%2Ename=a&ids.0=1&ids.1=2");
+    /// assert_eq!(config.deserialize_str::<Query>(&encoded).unwrap(), query);
+    /// # }
+    /// ```
+    pub fn nesting_style(mut self, nesting_style: NestingStyle) -> Self {
+        self.nesting_style = nesting_style;
+        self
+    }
+
+    /// Specifies whether the serializer writes keys as flat dotted paths,
+    /// such as `a.b.0=1`, for backends like metrics systems which do not
+    /// understand nested keys. Default is false.
+    ///
+    /// Unlike [`NestingStyle::Dots`], a key segment containing a `.` or a
+    /// bracket is an error rather than being encoded, so the output never
+    /// contains brackets, encoded or not. This only affects serialization.
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde_qs;
+    /// use serde_qs::Config;
+    ///
+    /// #[derive(Serialize)]
+    /// struct Request {
+    ///     route: Route,
+    ///     status: Vec<u16>,
+    /// }
+    ///
+    /// #[derive(Serialize)]
+    /// struct Route {
+    ///     method: String,
+    /// }
+    ///
+    /// # fn main() {
+    /// let request = Request {
+    ///     route: Route { method: "GET".to_owned() },
+    ///     status: vec![200],
+    /// };
+    /// let config = Config::default().serialize_flat_dotted(true);
+    /// assert_eq!(
+    ///     config.serialize_string(&request).unwrap(),
+    ///     "route.method=GET&status.0=200"
+    /// );
+    /// # }
+    /// ```
+    pub fn serialize_flat_dotted(mut self, flat_dotted: bool) -> Self {
+        self.serialize_flat_dotted = flat_dotted;
+        self
+    }
+
+    /// The style in which the serializer joins the parts of a key.
+    pub(crate) fn key_style(&self) -> NestingStyle {
+        if self.serialize_flat_dotted {
+            NestingStyle::Dots
+        } else {
+            self.nesting_style
+        }
+    }
+
+    /// Get maximum depth parameter, at most `MAX_DEPTH_LIMIT`.
+    pub(crate) fn max_depth(&self) -> usize {
+        self.max_depth.min(MAX_DEPTH_LIMIT)
     }
 }
 
@@ -127,10 +1103,192 @@ impl Config {
     //     T::deserialize(deser)
     // }
 
+    /// Deserializes a querystring from a `Bytes` buffer using this `Config`,
+    /// consuming the buffer.
+    ///
+    /// The querystring is parsed borrowing from `input`, which is dropped as
+    /// soon as the owned `T` has been produced. This suits request bodies,
+    /// which can be handed over without being copied.
+    ///
+    /// ```
+    /// use serde_qs::Config;
+    /// use std::collections::HashMap;
+    ///
+    /// let body = bytes::Bytes::from_static(b"name=Acme&tags[]=a+b");
+    /// let form: HashMap<String, serde_json::Value> =
+    ///     Config::default().deserialize_bytes_owned(body).unwrap();
+    /// assert_eq!(form["name"], "Acme");
+    /// assert_eq!(form["tags"][0], "a b");
+    /// ```
+    #[cfg(feature = "bytes")]
+    pub fn deserialize_bytes_owned<T: de::DeserializeOwned>(
+        &self,
+        input: bytes::Bytes,
+    ) -> Result<T> {
+        self.deserialize_bytes(&input)
+    }
+
     /// Deserializes a querystring from a `&str` using this `Config`.
     pub fn deserialize_str<'de, T: de::Deserialize<'de>>(&self, input: &'de str) -> Result<T> {
         self.deserialize_bytes(input.as_bytes())
     }
+
+    /// Deserializes one querystring per line of `input` using this `Config`,
+    /// skipping empty lines.
+    ///
+    /// Stops at the first line which fails to deserialize, returning an
+    /// [`Error::AtLine`] with its line number, counting from one. See
+    /// [`Config::deserialize_each_line`] to deserialize every line regardless.
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde_qs;
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// struct Record {
+    ///     id: u32,
+    /// }
+    ///
+    /// # fn main() {
+    /// let config = serde_qs::Config::default();
+    /// let records: Vec<Record> = config.deserialize_lines("id=1\nid=2\n").unwrap();
+    /// assert_eq!(records, vec![Record { id: 1 }, Record { id: 2 }]);
+    /// # }
+    /// ```
+    pub fn deserialize_lines<'de, T: de::Deserialize<'de>>(
+        &self,
+        input: &'de str,
+    ) -> Result<Vec<T>> {
+        self.deserialize_each_line(input).collect()
+    }
+
+    /// Deserializes one querystring per line of `input` using this `Config`,
+    /// skipping empty lines, with a result for each line.
+    ///
+    /// Lines are deserialized as the iterator is advanced. An error is
+    /// returned as an [`Error::AtLine`] with its line number, counting from
+    /// one.
+    pub fn deserialize_each_line<'de, T: de::Deserialize<'de>>(
+        &self,
+        input: &'de str,
+    ) -> impl Iterator<Item = Result<T>> + 'de {
+        let config = *self;
+        input
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty())
+            .map(move |(i, line)| {
+                config.deserialize_str(line).map_err(|e| Error::AtLine {
+                    line: i + 1,
+                    source: Box::new(e),
+                })
+            })
+    }
+
+    /// Deserializes a querystring from a `&str` using this `Config`, along
+    /// with counts of what happened while parsing it.
+    ///
+    /// This can be used to check that a querystring is deserialized without
+    /// copying any keys or values.
+    ///
+    /// ```
+    /// use serde_qs::Config;
+    /// use std::borrow::Cow;
+    /// use std::collections::HashMap;
+    ///
+    /// let (map, stats) = Config::default()
+    ///     .deserialize_str_with_stats::<HashMap<&str, Cow<str>>>("a=1&b=2")
+    ///     .unwrap();
+    /// assert_eq!(map["a"], "1");
+    /// assert_eq!(stats.owned_keys + stats.owned_values, 0);
+    /// ```
+    pub fn deserialize_str_with_stats<'de, T: de::Deserialize<'de>>(
+        &self,
+        input: &'de str,
+    ) -> Result<(T, ParseStats)> {
+        let mut parser = self.parser(input.as_bytes());
+        let mut deserializer = parser.as_deserializer()?;
+        let unmatched_keys = if self.collect_unmatched_keys {
+            Some(Arc::default())
+        } else {
+            None
+        };
+        deserializer.unmatched_keys = unmatched_keys.clone();
+        let value = T::deserialize(deserializer)?;
+        let mut stats = parser.stats;
+        if let Some(keys) = unmatched_keys.and_then(|keys| Arc::try_unwrap(keys).ok()) {
+            stats.unmatched_keys = keys.into_inner().unwrap_or_default();
+        }
+        Ok((value, stats))
+    }
+
+    /// Deserializes a querystring which must not contain nested keys, as
+    /// used by the `FlatQsQuery` extractors.
+    ///
+    /// Any key containing a `[` is rejected, and the rest of the input is
+    /// parsed with a `max_depth` of zero.
+    #[cfg(any(feature = "actix4", feature = "actix3", feature = "axum"))]
+    pub(crate) fn deserialize_flat_str<'de, T: de::Deserialize<'de>>(
+        &self,
+        input: &'de str,
+    ) -> Result<T> {
+        let mut position = 0;
+        for pair in input.split('&') {
+            let key = pair.split('=').next().unwrap_or_default();
+            if let Some(i) = key.find('[') {
+                return Err(Error::parse_err_in(
+                    format!("nested key `{}` in a flat query string", key),
+                    position + i + 1,
+                    input.as_bytes(),
+                ));
+            }
+            position += pair.len() + 1;
+        }
+        Config {
+            max_depth: 0,
+            ..*self
+        }
+        .deserialize_str(input)
+    }
+
+    /// Parses a querystring from a `&str` using this `Config`, so that it
+    /// can be deserialized more than once without being parsed again.
+    ///
+    /// ```
+    /// use serde_qs::Config;
+    /// use std::collections::HashMap;
+    ///
+    /// let parsed = Config::default().parse_str("a=1&b=2").unwrap();
+    /// let map: HashMap<String, u8> = parsed.deserialize().unwrap();
+    /// let keys: HashMap<String, String> = parsed.deserialize().unwrap();
+    /// assert_eq!(map["a"], 1);
+    /// assert_eq!(keys["b"], "2");
+    /// ```
+    pub fn parse_str<'a>(&self, input: &'a str) -> Result<Parsed<'a>> {
+        self.parse_bytes(input.as_bytes())
+    }
+
+    /// Parses a querystring from a `&[u8]` using this `Config`, see
+    /// [`Config::parse_str`].
+    pub fn parse_bytes<'a>(&self, input: &'a [u8]) -> Result<Parsed<'a>> {
+        let deserializer = self.parser(input).as_deserializer()?;
+        Ok(Parsed {
+            map: deserializer.map,
+            options: deserializer.options,
+            input: Cow::Borrowed(input),
+        })
+    }
+
+    fn parser<'a>(&self, input: &'a [u8]) -> parse::Parser<'a> {
+        let mut parser = parse::Parser::new(input, self);
+        if self.trim_input {
+            parser.trim_input();
+        }
+        if self.allow_leading_question_mark {
+            parser.skip_leading_question_mark();
+        }
+        parser
+    }
 }
 
 /// Deserializes a querystring from a `&[u8]`.
@@ -196,68 +1354,748 @@ pub fn from_str<'de, T: de::Deserialize<'de>>(input: &'de str) -> Result<T> {
 ///
 /// Supported top-level outputs are structs and maps.
 pub struct QsDeserializer<'a> {
-    iter: IntoIter<Cow<'a, str>, Level<'a>>,
+    /// The parsed map, until the first entry is deserialized.
+    map: Map<'a>,
+    iter: Option<Entries<'a>>,
+    key: Option<Key<'a>>,
     value: Option<Level<'a>>,
+    options: Options,
+    /// Whether the keys are sequence indices, e.g. for `a[0]=1&a[1]=2`
+    /// deserialized as a map.
+    indices: bool,
+    /// The whole input, for `WholeInput`, which is only set at the top level.
+    input: Option<Cow<'a, [u8]>>,
+    /// Where to record the keys not matched to a field, see
+    /// `Config::collect_unmatched_keys`. Only set at the top level.
+    unmatched_keys: Option<Arc<Mutex<Vec<String>>>>,
+}
+
+/// A parsed querystring, which can be deserialized any number of times.
+///
+/// This is created by [`Config::parse_str`] or [`Config::parse_bytes`].
+/// Each deserialization works on a copy of the parsed map, in which keys and
+/// values are mostly borrowed from the input.
+#[derive(Clone, Debug)]
+pub struct Parsed<'a> {
+    map: Map<'a>,
+    options: Options,
+    input: Cow<'a, [u8]>,
+}
+
+impl<'a> Parsed<'a> {
+    /// Deserializes an instance of `T` from the parsed querystring.
+    pub fn deserialize<T: de::Deserialize<'a>>(&self) -> Result<T> {
+        T::deserialize(QsDeserializer {
+            input: Some(self.input.clone()),
+            ..QsDeserializer::with_map(self.map.clone(), self.options)
+        })
+    }
+
+    /// Copies any keys and values borrowed from the input, so that the
+    /// parsed querystring can outlive it, e.g. to be cached.
+    ///
+    /// ```
+    /// use serde_qs::{Config, Parsed};
+    /// use std::collections::HashMap;
+    ///
+    /// let parsed: Parsed<'static> = {
+    ///     let input = String::from("a[b]=1");
+    ///     Config::default().parse_str(&input).unwrap().into_owned()
+    /// };
+    /// let map: HashMap<String, HashMap<String, u8>> = parsed.deserialize().unwrap();
+    /// assert_eq!(map["a"]["b"], 1);
+    /// ```
+    pub fn into_owned(self) -> Parsed<'static> {
+        Parsed {
+            map: self
+                .map
+                .into_iter()
+                .map(|(k, v)| (k.into_owned(), v.into_owned()))
+                .collect(),
+            options: self.options,
+            input: Cow::Owned(self.input.into_owned()),
+        }
+    }
+
+    /// Deserializes an instance of `T` borrowing from this `Parsed`, rather
+    /// than from the input, so that the values of an owned `Parsed` can
+    /// still be deserialized as borrowed strings.
+    #[cfg(feature = "axum")]
+    pub(crate) fn deserialize_borrowed<'de, T: de::Deserialize<'de>>(&'de self) -> Result<T> {
+        let map = self
+            .map
+            .iter()
+            .map(|(k, v)| (k.reborrow(), v.reborrow()))
+            .collect();
+        T::deserialize(QsDeserializer {
+            input: Some(Cow::Borrowed(&self.input)),
+            ..QsDeserializer::with_map(map, self.options)
+        })
+    }
+
+    /// Returns an iterator over the `(key path, value)` pairs of the parsed
+    /// querystring, see [`Deserializer::iter_pairs`](crate::Deserializer::iter_pairs).
+    pub fn iter_pairs(&self) -> Pairs<'_> {
+        Pairs::new(&self.map)
+    }
+}
+
+/// The parts of a `Config` which apply when deserializing the parsed map.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Options {
+    scalar_from_single_element_seq: bool,
+    /// Whether percent-encoded keys must decode to valid UTF-8.
+    strict: bool,
+    /// Whether a numeric key is a `u64` identifier, set for the keys of a
+    /// sequence.
+    index_key: bool,
+    bare_key_as_true: bool,
+    empty_value_as_true: bool,
+    /// Whether a single value can be deserialized as a sequence, see
+    /// `Config::repeated_keys_as_seq`.
+    seq_from_single_value: bool,
+    #[cfg(feature = "indexmap")]
+    preserve_key_order: bool,
+}
+
+impl From<&Config> for Options {
+    fn from(config: &Config) -> Self {
+        Options {
+            scalar_from_single_element_seq: config.scalar_from_single_element_seq,
+            strict: config.strict,
+            index_key: false,
+            bare_key_as_true: config.bare_key_as_true,
+            empty_value_as_true: config.empty_value_as_true,
+            seq_from_single_value: config.repeated_keys_as_seq,
+            #[cfg(feature = "indexmap")]
+            preserve_key_order: config.preserve_key_order,
+        }
+    }
+}
+
+#[cfg(not(feature = "indexmap"))]
+use std::collections::btree_map as map;
+
+#[cfg(feature = "indexmap")]
+use indexmap::map;
+
+/// The entries of a nested level of a querystring. With the `indexmap`
+/// feature, keys are kept in the order they were first given, otherwise
+/// they are sorted.
+#[cfg(not(feature = "indexmap"))]
+type Map<'a> = map::BTreeMap<Key<'a>, Level<'a>>;
+#[cfg(feature = "indexmap")]
+type Map<'a> = map::IndexMap<Key<'a>, Level<'a>>;
+
+/// Creates a `Map` with room for `capacity` keys, see
+/// `Config::capacity_hint`. A `BTreeMap` cannot be pre-sized.
+#[cfg(not(feature = "indexmap"))]
+fn map_with_capacity<'a>(_capacity: usize) -> Map<'a> {
+    Map::default()
+}
+#[cfg(feature = "indexmap")]
+fn map_with_capacity<'a>(capacity: usize) -> Map<'a> {
+    Map::with_capacity(capacity)
+}
+
+/// Sorts the keys of `level` and of the levels nested in it, as a
+/// `BTreeMap` would, unless `Config::preserve_key_order` is set. This keeps
+/// the `indexmap` feature from changing the order keys are visited in.
+#[cfg(not(feature = "indexmap"))]
+fn sort_keys(_level: &mut Level<'_>, _options: Options) {}
+#[cfg(feature = "indexmap")]
+fn sort_keys(level: &mut Level<'_>, options: Options) {
+    if options.preserve_key_order {
+        return;
+    }
+    match level {
+        Level::Nested(map) => {
+            sort_map(map, options);
+            map.values_mut().for_each(|level| sort_keys(level, options));
+        }
+        Level::OrderedSeq(map) => map.values_mut().for_each(|level| sort_keys(level, options)),
+        Level::Sequence(seq) => seq.iter_mut().for_each(|level| sort_keys(level, options)),
+        _ => {}
+    }
+}
+
+/// Sorts the keys of a single `map`, see `sort_keys`.
+#[cfg(not(feature = "indexmap"))]
+fn sort_map(_map: &mut Map<'_>, _options: Options) {}
+#[cfg(feature = "indexmap")]
+fn sort_map(map: &mut Map<'_>, options: Options) {
+    if !options.preserve_key_order {
+        map.sort_keys();
+    }
+}
+
+/// The entries of a `QsDeserializer`, with any entry to visit first.
+type Entries<'a> = Chain<option::IntoIter<(Key<'a>, Level<'a>)>, map::IntoIter<Key<'a>, Level<'a>>>;
+
+/// A map key.
+///
+/// Percent-encoded keys are kept as they were given, and only decoded when
+/// they are deserialized, so that keys of values nested within ignored
+/// fields are never decoded. Keys are compared by their decoded bytes, so
+/// `na%6De` and `name` are still the same key.
+///
+/// Transcoded keys are shared between all the entries they appear in, so
+/// that `items[0][na%6De]&items[1][na%6De]` only allocates `name` once.
+#[derive(Clone, Debug)]
+enum Key<'a> {
+    Borrowed(&'a str),
+    Shared(Arc<str>),
+    /// A key containing `%` or `+`, as given in the input. It is only owned
+    /// if it is not valid UTF-8 once decoded, see `Key::into_owned`.
+    Encoded(Cow<'a, [u8]>),
+}
+
+impl<'a> From<Cow<'a, str>> for Key<'a> {
+    fn from(key: Cow<'a, str>) -> Self {
+        match key {
+            Cow::Borrowed(key) => Key::Borrowed(key),
+            Cow::Owned(key) => Key::Shared(key.into()),
+        }
+    }
+}
+
+impl<'a> Key<'a> {
+    /// The decoded key, replacing invalid UTF-8 in an encoded key.
+    fn as_str(&self) -> Cow<'_, str> {
+        match self {
+            Key::Borrowed(key) => Cow::Borrowed(key),
+            Key::Shared(key) => Cow::Borrowed(key),
+            Key::Encoded(raw) => match decode_key(raw) {
+                Cow::Borrowed(bytes) => String::from_utf8_lossy(bytes),
+                Cow::Owned(bytes) => Cow::Owned(into_string_lossy(bytes)),
+            },
+        }
+    }
+
+    /// The decoded key, or an error if an encoded key is not valid UTF-8
+    /// in strict mode.
+    fn decode(self, strict: bool) -> Result<Cow<'a, str>> {
+        match self {
+            Key::Borrowed(key) => Ok(Cow::Borrowed(key)),
+            Key::Shared(key) => Ok(Cow::Owned(key.to_string())),
+            Key::Encoded(Cow::Borrowed(raw)) => match decode_key(raw) {
+                Cow::Borrowed(bytes) => Ok(Cow::Borrowed(str::from_utf8(bytes)?)),
+                Cow::Owned(bytes) if strict => Ok(Cow::Owned(String::from_utf8(bytes)?)),
+                Cow::Owned(bytes) => Ok(Cow::Owned(into_string_lossy(bytes))),
+            },
+            Key::Encoded(Cow::Owned(raw)) => {
+                let bytes = decode_key(&raw).into_owned();
+                match strict {
+                    true => Ok(Cow::Owned(String::from_utf8(bytes)?)),
+                    false => Ok(Cow::Owned(into_string_lossy(bytes))),
+                }
+            }
+        }
+    }
+
+    /// Copies a borrowed key, decoding it if it is encoded. A key which is
+    /// not valid UTF-8 is kept encoded, so that it is still rejected in
+    /// strict mode, and only if it is deserialized.
+    fn into_owned(self) -> Key<'static> {
+        match self {
+            Key::Shared(key) => Key::Shared(key),
+            Key::Encoded(raw) => match str::from_utf8(&decode_key(&raw)) {
+                Ok(key) => Key::Shared(key.into()),
+                Err(_) => Key::Encoded(Cow::Owned(raw.into_owned())),
+            },
+            key => Key::Shared(key.as_str().into()),
+        }
+    }
+
+    /// Borrows the key, so that it no longer owns anything.
+    #[cfg(feature = "axum")]
+    fn reborrow(&self) -> Key<'_> {
+        match self {
+            Key::Borrowed(key) => Key::Borrowed(key),
+            Key::Shared(key) => Key::Borrowed(key),
+            Key::Encoded(raw) => Key::Encoded(Cow::Borrowed(raw)),
+        }
+    }
+
+    /// Parses the decoded key, as for `str::parse`.
+    fn parse<F: FromStr>(&self) -> std::result::Result<F, F::Err> {
+        self.with_decoded(|key| String::from_utf8_lossy(key).parse())
+    }
+
+    /// Calls `f` with the bytes of the decoded key. Short encoded keys are
+    /// decoded on the stack, rather than allocating.
+    fn with_decoded<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        let mut buf = [0; KEY_BUFFER_LEN];
+        match self {
+            Key::Encoded(raw) if raw.len() <= buf.len() => {
+                let mut len = 0;
+                for byte in KeyBytes(raw, true) {
+                    buf[len] = byte;
+                    len += 1;
+                }
+                f(&buf[..len])
+            }
+            Key::Encoded(raw) => f(&decode_key(raw)),
+            key => f(key.as_str().as_bytes()),
+        }
+    }
+
+    /// Whether the decoded key contains `byte`.
+    fn contains(&self, byte: u8) -> bool {
+        match self {
+            Key::Encoded(raw) => KeyBytes(raw, true).any(|b| b == byte),
+            key => key.as_str().as_bytes().contains(&byte),
+        }
+    }
+
+    /// The bytes of the decoded key, decoding an encoded key on the fly.
+    fn bytes(&self) -> KeyBytes<'_> {
+        match self {
+            Key::Borrowed(key) => KeyBytes(key.as_bytes(), false),
+            Key::Shared(key) => KeyBytes(key.as_bytes(), false),
+            Key::Encoded(raw) => KeyBytes(raw, true),
+        }
+    }
+}
+
+/// The longest encoded key which is decoded on the stack, see
+/// `Key::with_decoded`.
+const KEY_BUFFER_LEN: usize = 64;
+
+/// Converts `bytes` to a string, replacing invalid UTF-8, without copying
+/// them if they are valid.
+fn into_string_lossy(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+}
+
+/// Replaces `+` with a space and percent-decodes a raw key.
+fn decode_key(raw: &[u8]) -> Cow<'_, [u8]> {
+    match KeyBytes(raw, true).eq(raw.iter().copied()) {
+        true => Cow::Borrowed(raw),
+        false => Cow::Owned(KeyBytes(raw, true).collect()),
+    }
+}
+
+/// An iterator over the bytes of a key, which are percent-decoded, with `+`
+/// replaced by a space, if the second field is set.
+struct KeyBytes<'a>(&'a [u8], bool);
+
+impl Iterator for KeyBytes<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.0.split_first()?;
+        self.0 = rest;
+        if !self.1 {
+            return Some(byte);
+        }
+        match byte {
+            b'+' => Some(b' '),
+            b'%' => {
+                let hex = |i: usize| rest.get(i).and_then(|&b| (b as char).to_digit(16));
+                match (hex(0), hex(1)) {
+                    (Some(high), Some(low)) => {
+                        self.0 = &rest[2..];
+                        Some((high * 16 + low) as u8)
+                    }
+                    _ => Some(b'%'),
+                }
+            }
+            byte => Some(byte),
+        }
+    }
+}
+
+impl PartialEq for Key<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Key<'_> {}
+
+impl Hash for Key<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Hash the decoded bytes, as they are what `Eq` compares
+        match self {
+            Key::Encoded(raw) => decode_key(raw).hash(state),
+            key => key.as_str().as_bytes().hash(state),
+        }
+    }
+}
+
+impl PartialOrd for Key<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Key<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Key::Encoded(_), _) | (_, Key::Encoded(_)) => {
+                let (a, b) = (self.bytes(), other.bytes());
+                // A common prefix without `%` or `+` is the same when decoded,
+                // so only decode the rest.
+                let prefix =
+                    a.0.iter()
+                        .zip(b.0)
+                        .take_while(|&(x, y)| x == y && *x != b'%' && *x != b'+')
+                        .count();
+                KeyBytes(&a.0[prefix..], a.1).cmp(KeyBytes(&b.0[prefix..], b.1))
+            }
+            // Comparing strings is faster than comparing byte by byte
+            _ => self.as_str().cmp(&other.as_str()),
+        }
+    }
 }
 
 #[derive(Debug)]
 enum Level<'a> {
-    Nested(BTreeMap<Cow<'a, str>, Level<'a>>),
+    Nested(Map<'a>),
     OrderedSeq(BTreeMap<usize, Level<'a>>),
     Sequence(Vec<Level<'a>>),
     Flat(Cow<'a, str>),
-    Invalid(String),
+    /// The value of a key given without `=`, e.g. `a` in `a&b=1`, which is
+    /// kept distinct from an empty value given with `=` when deserializing
+    /// a sequence, see `LevelDeserializer`.
+    NoValue,
+    Invalid(Error),
     Uninitialised,
 }
 
+impl Clone for Level<'_> {
+    fn clone(&self) -> Self {
+        match self {
+            Level::Nested(map) => Level::Nested(map.clone()),
+            Level::OrderedSeq(map) => Level::OrderedSeq(map.clone()),
+            Level::Sequence(seq) => Level::Sequence(seq.clone()),
+            Level::Flat(value) => Level::Flat(value.clone()),
+            Level::NoValue => Level::NoValue,
+            Level::Invalid(error) => Level::Invalid(error.duplicate()),
+            Level::Uninitialised => Level::Uninitialised,
+        }
+    }
+}
+
+impl<'a> Level<'a> {
+    /// The value of a `Flat` level, or an empty one for `NoValue`.
+    fn into_value(self) -> Cow<'a, str> {
+        match self {
+            Level::Flat(value) => value,
+            _ => Cow::Borrowed(""),
+        }
+    }
+
+    /// What the level holds, for errors.
+    fn kind(&self) -> &'static str {
+        match self {
+            Level::Nested(_) => "map",
+            Level::OrderedSeq(_) | Level::Sequence(_) => "sequence",
+            Level::Flat(_) | Level::NoValue => "value",
+            Level::Invalid(_) => "invalid",
+            Level::Uninitialised => "uninitialised",
+        }
+    }
+
+    /// Copies any borrowed keys and values, so that the level no longer
+    /// borrows from the input.
+    fn into_owned(self) -> Level<'static> {
+        match self {
+            Level::Nested(map) => Level::Nested(
+                map.into_iter()
+                    .map(|(k, v)| (k.into_owned(), v.into_owned()))
+                    .collect(),
+            ),
+            Level::OrderedSeq(map) => {
+                Level::OrderedSeq(map.into_iter().map(|(i, v)| (i, v.into_owned())).collect())
+            }
+            Level::Sequence(seq) => {
+                Level::Sequence(seq.into_iter().map(Level::into_owned).collect())
+            }
+            Level::Flat(value) => Level::Flat(Cow::Owned(value.into_owned())),
+            Level::NoValue => Level::NoValue,
+            Level::Invalid(e) => Level::Invalid(e),
+            Level::Uninitialised => Level::Uninitialised,
+        }
+    }
+
+    /// Borrows any owned keys and values.
+    #[cfg(feature = "axum")]
+    fn reborrow(&self) -> Level<'_> {
+        match self {
+            Level::Nested(map) => Level::Nested(
+                map.iter()
+                    .map(|(k, v)| (k.reborrow(), v.reborrow()))
+                    .collect(),
+            ),
+            Level::OrderedSeq(map) => {
+                Level::OrderedSeq(map.iter().map(|(i, v)| (*i, v.reborrow())).collect())
+            }
+            Level::Sequence(seq) => Level::Sequence(seq.iter().map(Level::reborrow).collect()),
+            Level::Flat(value) => Level::Flat(Cow::Borrowed(value)),
+            Level::NoValue => Level::NoValue,
+            Level::Invalid(e) => Level::Invalid(e.duplicate()),
+            Level::Uninitialised => Level::Uninitialised,
+        }
+    }
+}
+
+fn is_no_value(level: &Level<'_>) -> bool {
+    matches!(level, Level::NoValue)
+}
+
+/// Whether `level` is a key without a value or with an empty one, which
+/// are the same except when deserializing a sequence.
+fn is_empty_value(level: &Level<'_>) -> bool {
+    match level {
+        Level::Flat(value) => value.is_empty(),
+        Level::NoValue => true,
+        _ => false,
+    }
+}
+
 impl<'a> QsDeserializer<'a> {
-    fn with_map(map: BTreeMap<Cow<'a, str>, Level<'a>>) -> Self {
+    fn with_map(map: Map<'a>, options: Options) -> Self {
         QsDeserializer {
-            iter: map.into_iter(),
+            map,
+            iter: None,
+            key: None,
             value: None,
+            options,
+            indices: false,
+            input: None,
+            unmatched_keys: None,
+        }
+    }
+
+    /// The error for a type which cannot be deserialized at the top level,
+    /// describing the first few keys of the parsed input.
+    fn top_level_error(&self, object: &str, field: &str) -> Error {
+        const SHOWN: usize = 5;
+        let shape = if self.map.is_empty() {
+            "an empty querystring".to_owned()
+        } else {
+            let mut keys: Vec<String> = self
+                .map
+                .iter()
+                .take(SHOWN)
+                .map(|(key, value)| format!("{}: {}", key.as_str(), value.kind()))
+                .collect();
+            if self.map.len() > SHOWN {
+                keys.push("...".to_owned());
+            }
+            format!("a map with keys [{}]", keys.join(", "))
+        };
+        Error::top_level_shape(object, field, &shape)
+    }
+
+    /// Records the keys which are not among `fields`, if asked to by
+    /// `Config::collect_unmatched_keys`. This is done once, when the type
+    /// being deserialized asks for a map or struct.
+    fn record_unmatched_keys(&mut self, fields: &[&str]) {
+        let sink = match self.unmatched_keys.take() {
+            Some(sink) => sink,
+            None => return,
+        };
+        if let Ok(mut unmatched) = sink.lock() {
+            unmatched.extend(
+                self.map
+                    .keys()
+                    .map(|key| key.as_str())
+                    .filter(|key| !fields.contains(&&**key))
+                    .map(Cow::into_owned),
+            );
+        };
+    }
+
+    fn next_entry(&mut self) -> Option<(Key<'a>, Level<'a>)> {
+        let map = &mut self.map;
+        self.iter
+            .get_or_insert_with(|| None.into_iter().chain(mem::take(map)))
+            .next()
+    }
+
+    /// Visits the entry for `field` before any others.
+    ///
+    /// Serde deserializes adjacently tagged enums as a struct whose first
+    /// field is the tag. If the content is visited before the tag, serde
+    /// buffers it with `deserialize_any`, after which strings such as `1`
+    /// can no longer be deserialized as numbers.
+    fn visit_first(&mut self, field: &'static str) {
+        if self.iter.is_none() {
+            let first = self.map.remove_entry(&Key::Borrowed(field));
+            self.iter = Some(first.into_iter().chain(mem::take(&mut self.map)));
         }
     }
 
     /// Returns a new `QsDeserializer<'a>`.
     pub fn with_config(config: &Config, input: &'a [u8]) -> Result<Self> {
-        parse::Parser::new(input, config.max_depth(), config.strict).as_deserializer()
+        config.parser(input).as_deserializer()
     }
 
     pub fn new(input: &'a [u8]) -> Result<Self> {
         Self::with_config(&Config::default(), input)
     }
+
+    /// Returns an iterator over the `(key path, value)` pairs of the parsed
+    /// querystring, without deserializing them to a target type.
+    ///
+    /// Pairs are visited depth-first, in the same order as they would be
+    /// deserialized: map keys are sorted, or in the order they were first
+    /// given with the `indexmap` feature, and sequences are in index order.
+    /// Keys which have conflicting values, such as `a=1&a=2`, are yielded
+    /// with a value of `None`.
+    ///
+    /// Once deserialization has started, no more pairs are yielded.
+    ///
+    /// ```
+    /// let qs = serde_qs::Deserializer::new(b"ids[1]=2&ids[0]=1&user[name]=Acme").unwrap();
+    /// let pairs: Vec<_> = qs
+    ///     .iter_pairs()
+    ///     .map(|(path, value)| format!("{}={}", path, value.unwrap()))
+    ///     .collect();
+    /// assert_eq!(pairs, ["ids[0]=1", "ids[1]=2", "user[name]=Acme"]);
+    /// ```
+    pub fn iter_pairs(&self) -> Pairs<'_> {
+        Pairs::new(&self.map)
+    }
+}
+
+impl QsDeserializer<'static> {
+    /// Returns a `QsDeserializer` for pairs of keys and values which are
+    /// already percent-decoded, such as from `url::Url::query_pairs`.
+    ///
+    /// The keys are nested as they would be in a querystring, following
+    /// `config`, but nothing is decoded again, so a `%` or `+` is kept as it
+    /// is. Since the keys are decoded, any bracket in them denotes a nested
+    /// key, including one which was given as `%5B` or `%5D`.
+    ///
+    /// The keys and values are copied, so the pairs can be dropped before
+    /// deserializing, but only owned types can be deserialized.
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde_qs;
+    /// use serde::Deserialize;
+    /// use serde_qs::{Config, Deserializer};
+    ///
+    /// #[derive(Debug, PartialEq, Deserialize)]
+    /// struct Query {
+    ///     user: User,
+    ///     ids: Vec<u32>,
+    /// }
+    ///
+    /// #[derive(Debug, PartialEq, Deserialize)]
+    /// struct User {
+    ///     name: String,
+    /// }
+    ///
+    /// # fn main() {
+    /// let url = url::Url::parse("https://example.com/?user%5Bname%5D=A+%26+B&ids[]=1&ids[]=2")
+    ///     .unwrap();
+    /// let de = Deserializer::from_pairs(url.query_pairs(), &Config::default()).unwrap();
+    /// let query = Query::deserialize(de).unwrap();
+    /// assert_eq!(query.user.name, "A & B");
+    /// assert_eq!(query.ids, [1, 2]);
+    /// # }
+    /// ```
+    pub fn from_pairs<I, K, V>(pairs: I, config: &Config) -> Result<Self>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let pairs: Vec<(K, V)> = pairs.into_iter().collect();
+        let mut root = Level::Nested(Map::default());
+        for (key, value) in &pairs {
+            parse::Parser::new(key.as_ref().as_bytes(), config)
+                .parse_pair(Cow::Borrowed(value.as_ref()), &mut root)?;
+        }
+        sort_keys(&mut root, config.into());
+        Ok(match root.into_owned() {
+            Level::Nested(map) => QsDeserializer::with_map(map, config.into()),
+            _ => QsDeserializer::with_map(Map::default(), config.into()),
+        })
+    }
+}
+
+macro_rules! top_level_scalar {
+    ($($method:ident => $object:expr,)*) => {
+        $(
+            /// Throws an error.
+            ///
+            /// Scalars are not supported at the top level.
+            fn $method<V>(self, _visitor: V) -> Result<V::Value>
+            where
+                V: de::Visitor<'de>,
+            {
+                Err(self.top_level_error($object, "value: T"))
+            }
+        )*
+    };
 }
 
 impl<'de> de::Deserializer<'de> for QsDeserializer<'de> {
     type Error = Error;
 
-    fn deserialize_any<V>(mut self, visitor: V) -> Result<V::Value>
+    /// Visits the parsed map, or a unit for an empty querystring.
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        if self.iter.next().is_none() {
+        if self.iter.is_none() && self.map.is_empty() {
             return visitor.visit_unit();
         }
 
-        Err(Error::top_level("primitive"))
+        self.deserialize_map(visitor)
     }
 
-    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_map<V>(mut self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
+        self.record_unmatched_keys(&[]);
         visitor.visit_map(self)
     }
 
     fn deserialize_struct<V>(
-        self,
+        mut self,
         _name: &'static str,
-        _fields: &'static [&'static str],
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
+        if self.iter.is_none() {
+            // A field such as `01` is parsed as the index 1, so look for it
+            // under that key if there is no field named `1`.
+            for field in fields.iter().filter(|f| is_integer(f)) {
+                let key = Key::Borrowed(field);
+                if self.map.contains_key(&key) {
+                    continue;
+                }
+                let canonical = match field.parse::<usize>() {
+                    Ok(index) if integer_field(fields, index) == Some(*field) => {
+                        Key::Shared(index.to_string().into())
+                    }
+                    _ => continue,
+                };
+                #[cfg(feature = "indexmap")]
+                let value = self.map.shift_remove(&canonical);
+                #[cfg(not(feature = "indexmap"))]
+                let value = self.map.remove(&canonical);
+                if let Some(value) = value {
+                    let _ = self.map.insert(key, value);
+                }
+            }
+        }
+        self.record_unmatched_keys(fields);
+        if let Some(field) = fields.first() {
+            self.visit_first(field);
+        }
         self.deserialize_map(visitor)
     }
 
@@ -268,13 +2106,19 @@ impl<'de> de::Deserializer<'de> for QsDeserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        Err(Error::top_level("sequence"))
+        Err(self.top_level_error("a sequence", "items: Vec<T>"))
     }
 
-    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    fn deserialize_newtype_struct<V>(mut self, name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
+        if name == whole_input::NAME {
+            return match self.input.take() {
+                Some(input) => visitor.visit_seq(whole_input::WholeInputAccess::new(input, self)?),
+                None => Err(whole_input::not_top_level()),
+            };
+        }
         self.deserialize_map(visitor)
     }
 
@@ -285,7 +2129,7 @@ impl<'de> de::Deserializer<'de> for QsDeserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        Err(Error::top_level("tuple"))
+        Err(self.top_level_error("a tuple", "items: (A, B)"))
     }
 
     /// Throws an error.
@@ -293,47 +2137,68 @@ impl<'de> de::Deserializer<'de> for QsDeserializer<'de> {
     /// TupleStructs are not supported at the top level.
     fn deserialize_tuple_struct<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         _len: usize,
         _visitor: V,
     ) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(Error::top_level("tuple struct"))
+        Err(self.top_level_error("a tuple struct", &format!("value: {}", name)))
     }
 
     fn deserialize_enum<V>(
-        self,
+        mut self,
         _name: &'static str,
-        _variants: &'static [&'static str],
+        variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
+        // The variant is the first key, so prefer a key naming a variant over
+        // any siblings which sort before it.
+        let found: Vec<&'static str> = variants
+            .iter()
+            .copied()
+            .filter(|v| self.map.contains_key(&Key::Borrowed(v)))
+            .collect();
+        match found[..] {
+            [] => {}
+            [variant] => self.visit_first(variant),
+            _ => {
+                return Err(Error::MultipleVariants {
+                    found: found.iter().map(|v| v.to_string()).collect(),
+                })
+            }
+        }
         visitor.visit_enum(self)
     }
 
+    top_level_scalar! {
+        deserialize_bool => "a bool",
+        deserialize_u8 => "an integer",
+        deserialize_u16 => "an integer",
+        deserialize_u32 => "an integer",
+        deserialize_u64 => "an integer",
+        deserialize_u128 => "an integer",
+        deserialize_i8 => "an integer",
+        deserialize_i16 => "an integer",
+        deserialize_i32 => "an integer",
+        deserialize_i64 => "an integer",
+        deserialize_i128 => "an integer",
+        deserialize_f32 => "a float",
+        deserialize_f64 => "a float",
+        deserialize_char => "a char",
+        deserialize_str => "a string",
+        deserialize_string => "a string",
+        deserialize_bytes => "bytes",
+        deserialize_byte_buf => "bytes",
+    }
+
     forward_to_deserialize_any! {
-        bool
-        u8
-        u16
-        u32
-        u64
-        i8
-        i16
-        i32
-        i64
-        f32
-        f64
-        char
-        str
-        string
         unit
         option
-        bytes
-        byte_buf
         unit_struct
         identifier
         ignored_any
@@ -347,20 +2212,21 @@ impl<'de> de::MapAccess<'de> for QsDeserializer<'de> {
     where
         K: de::DeserializeSeed<'de>,
     {
-        if let Some((key, value)) = self.iter.next() {
+        if let Some((key, value)) = self.next_entry() {
+            self.key = Some(key.clone());
             self.value = Some(value);
-            let has_bracket = key.contains('[');
-            seed.deserialize(ParsableStringDeserializer(key))
-                .map(Some)
-                .map_err(|e| {
-                    if has_bracket {
-                        de::Error::custom(
-                            format!("{}\nInvalid field contains an encoded bracket -- did you mean to use non-strict mode?\n  https://docs.rs/serde_qs/latest/serde_qs/#strict-vs-non-strict-modes", e,)
-                        )
-                    } else {
-                        e
-                    }
-                })
+            let options = Options {
+                index_key: self.indices,
+                ..self.options
+            };
+            if key.contains(b'[') {
+                let bracketed_key = key.as_str().into_owned();
+                seed.deserialize(KeyDeserializer(key, options))
+                    .map(Some)
+                    .map_err(|e| Error::encoded_bracket(bracketed_key, e))
+            } else {
+                seed.deserialize(KeyDeserializer(key, options)).map(Some)
+            }
         } else {
             Ok(None)
         }
@@ -371,13 +2237,31 @@ impl<'de> de::MapAccess<'de> for QsDeserializer<'de> {
         V: de::DeserializeSeed<'de>,
     {
         if let Some(v) = self.value.take() {
-            seed.deserialize(LevelDeserializer(v))
+            let flat = matches!(v, Level::Flat(_) | Level::NoValue);
+            seed.deserialize(LevelDeserializer(v, self.options))
+                .map_err(|e| match self.key {
+                    Some(ref key) => value_error(&key.as_str(), flat, e),
+                    None => e,
+                })
         } else {
             Err(de::Error::custom(
                 "Somehow the map was empty after a non-empty key was returned",
             ))
         }
     }
+
+    /// The number of remaining entries, so that maps can be allocated up
+    /// front. For a struct, this counts the entries in the input, not its
+    /// fields, which may be absent.
+    fn size_hint(&self) -> Option<usize> {
+        match self.iter {
+            Some(ref iter) => match iter.size_hint() {
+                (lower, Some(upper)) if lower == upper => Some(upper),
+                _ => None,
+            },
+            None => Some(self.map.len()),
+        }
+    }
 }
 
 impl<'de> de::EnumAccess<'de> for QsDeserializer<'de> {
@@ -388,9 +2272,9 @@ impl<'de> de::EnumAccess<'de> for QsDeserializer<'de> {
     where
         V: de::DeserializeSeed<'de>,
     {
-        if let Some((key, value)) = self.iter.next() {
+        if let Some((key, value)) = self.next_entry() {
             self.value = Some(value);
-            Ok((seed.deserialize(ParsableStringDeserializer(key))?, self))
+            Ok((seed.deserialize(KeyDeserializer(key, self.options))?, self))
         } else {
             Err(de::Error::custom("No more values"))
         }
@@ -408,7 +2292,7 @@ impl<'de> de::VariantAccess<'de> for QsDeserializer<'de> {
         T: de::DeserializeSeed<'de>,
     {
         if let Some(value) = self.value {
-            seed.deserialize(LevelDeserializer(value))
+            seed.deserialize(LevelDeserializer(value, self.options))
         } else {
             Err(de::Error::custom("no value to deserialize"))
         }
@@ -418,7 +2302,7 @@ impl<'de> de::VariantAccess<'de> for QsDeserializer<'de> {
         V: de::Visitor<'de>,
     {
         if let Some(value) = self.value {
-            de::Deserializer::deserialize_seq(LevelDeserializer(value), visitor)
+            de::Deserializer::deserialize_seq(LevelDeserializer(value, self.options), visitor)
         } else {
             Err(de::Error::custom("no value to deserialize"))
         }
@@ -428,7 +2312,7 @@ impl<'de> de::VariantAccess<'de> for QsDeserializer<'de> {
         V: de::Visitor<'de>,
     {
         if let Some(value) = self.value {
-            de::Deserializer::deserialize_map(LevelDeserializer(value), visitor)
+            de::Deserializer::deserialize_map(LevelDeserializer(value, self.options), visitor)
         } else {
             Err(de::Error::custom("no value to deserialize"))
         }
@@ -444,14 +2328,16 @@ impl<'de> de::EnumAccess<'de> for LevelDeserializer<'de> {
         V: de::DeserializeSeed<'de>,
     {
         match self.0 {
-            Level::Flat(x) => Ok((
-                seed.deserialize(ParsableStringDeserializer(x))?,
-                LevelDeserializer(Level::Invalid(
-                    "this value can only \
-                     deserialize to a \
-                     UnitVariant"
-                        .to_string(),
-                )),
+            level @ (Level::Flat(_) | Level::NoValue) => Ok((
+                seed.deserialize(ParsableStringDeserializer(level.into_value()))?,
+                LevelDeserializer(
+                    Level::Invalid(de::Error::custom(
+                        "this value can only \
+                         deserialize to a \
+                         UnitVariant",
+                    )),
+                    self.1,
+                ),
             )),
             _ => Err(de::Error::custom(
                 "this value can only deserialize to a \
@@ -487,31 +2373,197 @@ impl<'de> de::VariantAccess<'de> for LevelDeserializer<'de> {
     }
 }
 
-struct LevelSeq<'a, I: Iterator<Item = Level<'a>>>(I);
+/// The keys of a map, as a sequence.
+struct KeySeq<I>(I, Options);
+
+impl<'de, I: Iterator<Item = Key<'de>>> de::SeqAccess<'de> for KeySeq<I> {
+    type Error = Error;
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        match self.0.next() {
+            Some(key) => seed.deserialize(KeyDeserializer(key, self.1)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        match self.0.size_hint() {
+            (lower, Some(upper)) if lower == upper => Some(upper),
+            _ => None,
+        }
+    }
+}
+
+struct LevelSeq<'a, I: Iterator<Item = (usize, Level<'a>)>>(I, Options);
 
-impl<'de, I: Iterator<Item = Level<'de>>> de::SeqAccess<'de> for LevelSeq<'de, I> {
+impl<'de, I: Iterator<Item = (usize, Level<'de>)>> de::SeqAccess<'de> for LevelSeq<'de, I> {
     type Error = Error;
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
     where
         T: de::DeserializeSeed<'de>,
     {
-        if let Some(v) = self.0.next() {
-            seed.deserialize(LevelDeserializer(v)).map(Some)
+        if let Some((index, level)) = self.0.next() {
+            seed.deserialize(SeqElementDeserializer {
+                index,
+                level,
+                options: self.1,
+            })
+            .map(Some)
         } else {
             Ok(None)
         }
     }
+
+    /// The number of elements is known up front, since sparse indices
+    /// (e.g. `a[0]=1&a[9]=2`) are compacted rather than padded.
+    fn size_hint(&self) -> Option<usize> {
+        match self.0.size_hint() {
+            (lower, Some(upper)) if lower == upper => Some(upper),
+            _ => None,
+        }
+    }
+}
+
+/// Formats sequence indices for an error, e.g. `index 1` or `indices 1, 2`.
+fn list_indices(indices: impl Iterator<Item = usize>) -> String {
+    let indices: Vec<String> = indices.map(|i| i.to_string()).collect();
+    let noun = if indices.len() == 1 {
+        "index"
+    } else {
+        "indices"
+    };
+    format!("{} {}", noun, indices.join(", "))
+}
+
+/// Whether a struct field name is made of digits, so that it may have been
+/// parsed as a sequence index.
+fn is_integer(field: &str) -> bool {
+    !field.is_empty() && field.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// The field which `index` was parsed from, preferring a field spelled the
+/// same as the index over one with leading zeros.
+fn integer_field(fields: &'static [&'static str], index: usize) -> Option<&'static str> {
+    let mut found = None;
+    for field in fields.iter().filter(|f| is_integer(f)) {
+        if field.parse() == Ok(index) {
+            if field.len() == 1 || !field.starts_with('0') {
+                return Some(field);
+            }
+            found = found.or(Some(*field));
+        }
+    }
+    found
+}
+
+/// Adds `key` to the key path of an error for a value: either the error came
+/// from parsing a flat value, or it already has the key path of a nested one.
+fn value_error(key: &str, flat: bool, error: Error) -> Error {
+    match error {
+        Error::InvalidValue { .. } => Error::invalid_value(key, error),
+        error if flat => Error::invalid_value(key, error),
+        // Raised by the visitor of a nested value, e.g. a missing field
+        error @ Error::Custom(_) => Error::invalid_value(key, error),
+        error => error,
+    }
+}
+
+/// Deserializes a single element of a sequence, so that errors can refer to
+/// the index of the element.
+struct SeqElementDeserializer<'a> {
+    index: usize,
+    level: Level<'a>,
+    options: Options,
+}
+
+macro_rules! forward_to_level_deserializer {
+    ($($method:ident($($arg:ident: $ty:ty),*),)*) => {
+        $(
+            fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value>
+            where
+                V: de::Visitor<'de>,
+            {
+                let index = self.index;
+                let flat = matches!(self.level, Level::Flat(_) | Level::NoValue);
+                LevelDeserializer(self.level, self.options)
+                    .$method($($arg,)* visitor)
+                    .map_err(|e| value_error(&index.to_string(), flat, e))
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for SeqElementDeserializer<'de> {
+    type Error = Error;
+
+    /// Unit variants are the only enums which can be parsed from a flat
+    /// value, so an empty value can never be valid.
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        let index = self.index.to_string();
+        match self.level {
+            ref level if is_empty_value(level) => Err(Error::invalid_value(
+                &index,
+                de::Error::custom(format!("missing value for enum {}", name)),
+            )),
+            level => LevelDeserializer(level, self.options)
+                .deserialize_enum(name, variants, visitor)
+                .map_err(|e| Error::invalid_value(&index, e)),
+        }
+    }
+
+    forward_to_level_deserializer! {
+        deserialize_any(),
+        deserialize_bool(),
+        deserialize_i8(),
+        deserialize_i16(),
+        deserialize_i32(),
+        deserialize_i64(),
+        deserialize_i128(),
+        deserialize_u8(),
+        deserialize_u16(),
+        deserialize_u32(),
+        deserialize_u64(),
+        deserialize_u128(),
+        deserialize_f32(),
+        deserialize_f64(),
+        deserialize_char(),
+        deserialize_str(),
+        deserialize_string(),
+        deserialize_bytes(),
+        deserialize_byte_buf(),
+        deserialize_option(),
+        deserialize_unit(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_newtype_struct(name: &'static str),
+        deserialize_seq(),
+        deserialize_tuple(len: usize),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_map(),
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]),
+        deserialize_identifier(),
+        deserialize_ignored_any(),
+    }
 }
 
-struct LevelDeserializer<'a>(Level<'a>);
+struct LevelDeserializer<'a>(Level<'a>, Options);
 
 macro_rules! deserialize_primitive {
-    ($ty:ident, $method:ident, $visit_method:ident) => {
+    ($ty:ident, $method:ident, $visit_method:ident $(, $prepare:ident)?) => {
         fn $method<V>(self, visitor: V) -> Result<V::Value>
         where
             V: de::Visitor<'de>,
         {
-            match self.0 {
+            match self$(.$prepare())?.into_scalar(stringify!($ty))?.0 {
                 Level::Nested(_) => Err(de::Error::custom(format!(
                     "Expected: {:?}, got a Map",
                     stringify!($ty)
@@ -524,8 +2576,10 @@ macro_rules! deserialize_primitive {
                     "Expected: {:?}, got a Sequence",
                     stringify!($ty)
                 ))),
-                Level::Flat(x) => ParsableStringDeserializer(x).$method(visitor),
-                Level::Invalid(e) => Err(de::Error::custom(e)),
+                level @ (Level::Flat(_) | Level::NoValue) => {
+                    ParsableStringDeserializer(level.into_value()).$method(visitor)
+                }
+                Level::Invalid(e) => Err(e),
                 Level::Uninitialised => Err(de::Error::custom(
                     "attempted to deserialize unitialised value",
                 )),
@@ -535,15 +2589,124 @@ macro_rules! deserialize_primitive {
 }
 
 impl<'a> LevelDeserializer<'a> {
+    /// Replaces a missing or empty value with `true`, for a bool with
+    /// `Config::bare_key_as_true` or `Config::empty_value_as_true`.
+    fn into_bool(self) -> Self {
+        let options = self.1;
+        let is_true = match self.0 {
+            Level::NoValue => options.bare_key_as_true,
+            Level::Flat(ref x) if x.is_empty() => options.empty_value_as_true,
+            _ => false,
+        };
+        if is_true {
+            LevelDeserializer(Level::Flat(Cow::Borrowed("true")), options)
+        } else {
+            self
+        }
+    }
+
+    /// Takes the element of a sequence of one element, for a scalar with
+    /// `Config::scalar_from_single_element_seq`.
+    fn into_scalar(self, expected: &str) -> Result<Self> {
+        if !self.1.scalar_from_single_element_seq {
+            return Ok(self);
+        }
+        let len = match self.0 {
+            Level::OrderedSeq(ref map) => map.len(),
+            Level::Sequence(ref seq) => seq.len(),
+            _ => return Ok(self),
+        };
+        if len != 1 {
+            return Err(de::Error::custom(format!(
+                "expected a single {}, got a sequence of {} elements",
+                expected, len
+            )));
+        }
+        let level = match self.0 {
+            Level::OrderedSeq(map) => map.into_values().next(),
+            Level::Sequence(seq) => seq.into_iter().next(),
+            _ => None,
+        };
+        Ok(LevelDeserializer(
+            level.unwrap_or(Level::Uninitialised),
+            self.1,
+        ))
+    }
+
+    /// Deserializes a tuple, tuple struct or fixed-size array of `len`
+    /// elements. Serde's tuple visitors ignore any trailing elements, and
+    /// report missing ones without saying which, so check the length here.
+    ///
+    /// Errors describe the value as the visitor does, e.g. "an array of
+    /// length 2" or "a tuple of size 2", or as tuple struct `name`.
+    ///
+    /// Sparse indices are compacted, so `a[0]=1&a[2]=3` is a pair, but a
+    /// triple is missing index 1.
+    fn deserialize_sized<V>(self, len: usize, name: Option<&str>, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'a>,
+    {
+        let (found, problem) = match self.0 {
+            Level::OrderedSeq(ref map) if map.len() < len => {
+                let missing = (0..len).filter(|i| !map.contains_key(i));
+                (map.len(), format!("missing {}", list_indices(missing)))
+            }
+            Level::OrderedSeq(ref map) if map.len() > len => {
+                let unexpected = map.keys().skip(len).copied();
+                (
+                    map.len(),
+                    format!("unexpected {}", list_indices(unexpected)),
+                )
+            }
+            Level::Sequence(ref seq) if seq.len() < len => (
+                seq.len(),
+                format!("missing {}", list_indices(seq.len()..len)),
+            ),
+            Level::Sequence(ref seq) if seq.len() > len => (
+                seq.len(),
+                format!("unexpected {}", list_indices(len..seq.len())),
+            ),
+            _ => return de::Deserializer::deserialize_any(self, visitor),
+        };
+        let expected = match name {
+            Some(name) => format!("tuple struct {} of size {}", name, len),
+            None => (&visitor as &dyn de::Expected).to_string(),
+        };
+        Err(Error::unkeyed_value(de::Error::custom(format!(
+            "invalid length {}, expected {}; {}",
+            found, expected, problem
+        ))))
+    }
+
+    /// The error for a value which should have been empty, showing what
+    /// was given instead.
+    fn not_unit(self, expected: &str) -> Error {
+        let unexpected = match self.0 {
+            Level::Flat(ref x) => Unexpected::Str(x),
+            Level::NoValue => Unexpected::Str(""),
+            Level::Nested(_) => Unexpected::Map,
+            Level::OrderedSeq(_) | Level::Sequence(_) => Unexpected::Seq,
+            Level::Invalid(e) => return e,
+            Level::Uninitialised => Unexpected::Other("nothing"),
+        };
+        Error::unkeyed_value(de::Error::invalid_type(unexpected, &expected))
+    }
+
     fn into_deserializer(self) -> Result<QsDeserializer<'a>> {
         match self.0 {
-            Level::Nested(map) => Ok(QsDeserializer::with_map(map)),
-            Level::OrderedSeq(map) => Ok(QsDeserializer::with_map(
-                map.into_iter()
-                    .map(|(k, v)| (Cow::Owned(k.to_string()), v))
-                    .collect(),
-            )),
-            Level::Invalid(e) => Err(de::Error::custom(e)),
+            Level::Nested(map) => Ok(QsDeserializer::with_map(map, self.1)),
+            Level::OrderedSeq(map) => {
+                let mut map = map
+                    .into_iter()
+                    .map(|(k, v)| (Key::Shared(k.to_string().into()), v))
+                    .collect();
+                sort_map(&mut map, self.1);
+                Ok(QsDeserializer {
+                    indices: true,
+                    ..QsDeserializer::with_map(map, self.1)
+                })
+            }
+            Level::Invalid(e) => Err(e),
             l => Err(de::Error::custom(format!(
                 "could not convert {:?} to \
                  QsDeserializer<'a>",
@@ -562,13 +2725,16 @@ impl<'de> de::Deserializer<'de> for LevelDeserializer<'de> {
     {
         match self.0 {
             Level::Nested(_) => self.into_deserializer()?.deserialize_map(visitor),
-            Level::OrderedSeq(map) => visitor.visit_seq(LevelSeq(map.into_values())),
-            Level::Sequence(seq) => visitor.visit_seq(LevelSeq(seq.into_iter())),
+            Level::OrderedSeq(map) => visitor.visit_seq(LevelSeq(map.into_iter(), self.1)),
+            Level::Sequence(seq) => {
+                visitor.visit_seq(LevelSeq(seq.into_iter().enumerate(), self.1))
+            }
             Level::Flat(x) => match x {
                 Cow::Owned(s) => visitor.visit_string(s),
                 Cow::Borrowed(s) => visitor.visit_borrowed_str(s),
             },
-            Level::Invalid(e) => Err(de::Error::custom(e)),
+            Level::NoValue => visitor.visit_borrowed_str(""),
+            Level::Invalid(e) => Err(e),
             Level::Uninitialised => Err(de::Error::custom(
                 "attempted to deserialize unitialised \
                  value",
@@ -581,7 +2747,8 @@ impl<'de> de::Deserializer<'de> for LevelDeserializer<'de> {
         V: de::Visitor<'de>,
     {
         match self.0 {
-            Level::Flat(ref x) if x == "" => visitor.visit_none(),
+            Level::NoValue if self.1.bare_key_as_true => visitor.visit_some(self),
+            ref level if is_empty_value(level) => visitor.visit_none(),
             _ => visitor.visit_some(self),
         }
     }
@@ -591,8 +2758,19 @@ impl<'de> de::Deserializer<'de> for LevelDeserializer<'de> {
         V: de::Visitor<'de>,
     {
         match self.0 {
-            Level::Flat(ref x) if x == "" => visitor.visit_unit(),
-            _ => Err(de::Error::custom("expected unit".to_owned())),
+            ref level if is_empty_value(level) => visitor.visit_unit(),
+            _ => Err(self.not_unit("unit")),
+        }
+    }
+
+    /// Unit structs are serialized as an empty value, like `()`.
+    fn deserialize_unit_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self.0 {
+            ref level if is_empty_value(level) => visitor.visit_unit(),
+            _ => Err(self.not_unit(&format!("unit struct {}", name))),
         }
     }
 
@@ -607,9 +2785,9 @@ impl<'de> de::Deserializer<'de> for LevelDeserializer<'de> {
     {
         match self.0 {
             Level::Nested(map) => {
-                QsDeserializer::with_map(map).deserialize_enum(name, variants, visitor)
+                QsDeserializer::with_map(map, self.1).deserialize_enum(name, variants, visitor)
             }
-            Level::Flat(_) => visitor.visit_enum(self),
+            Level::Flat(_) | Level::NoValue => visitor.visit_enum(self),
             x => Err(de::Error::custom(format!(
                 "{:?} does not appear to be \
                  an enum",
@@ -618,20 +2796,51 @@ impl<'de> de::Deserializer<'de> for LevelDeserializer<'de> {
         }
     }
 
-    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
         match self.0 {
-            Level::Nested(_) => self.into_deserializer()?.deserialize_map(visitor),
-            Level::OrderedSeq(map) => visitor.visit_seq(LevelSeq(map.into_values())),
-            Level::Sequence(seq) => visitor.visit_seq(LevelSeq(seq.into_iter())),
-            Level::Flat(_) => {
+            // A key given once, with `Config::repeated_keys_as_seq`, even
+            // without a value, as when it is repeated.
+            level @ (Level::Flat(_) | Level::NoValue) if self.1.seq_from_single_value => {
+                visitor.visit_seq(LevelSeq(iter::once((0, level)), self.1))
+            }
+            // An empty value, e.g. `a=`, is an empty sequence.
+            ref level if is_empty_value(level) => {
+                visitor.visit_seq(LevelSeq(iter::empty(), self.1))
+            }
+            // Keys without values, e.g. `ids[3]&ids[5]`, are a set of keys.
+            Level::Nested(map) if map.values().all(is_no_value) => {
+                visitor.visit_seq(KeySeq(map.into_keys(), self.1))
+            }
+            Level::OrderedSeq(map) if map.values().all(is_no_value) => {
+                let keys = map.into_keys().map(|i| Key::Shared(i.to_string().into()));
+                visitor.visit_seq(KeySeq(keys, self.1))
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    /// Newtypes defer to their inner values, as when serializing, so
+    /// `Wrap(Vec<u8>)` is given as `w[0]=1`.
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        if name == whole_input::NAME {
+            return Err(whole_input::not_top_level());
+        }
+        match self.0 {
+            Level::Nested(_) | Level::OrderedSeq(_) | Level::Sequence(_) => {
+                visitor.visit_newtype_struct(self)
+            }
+            Level::Flat(_) | Level::NoValue => {
                 // For a newtype_struct, attempt to deserialize a flat value as a
                 // single element sequence.
-                visitor.visit_seq(LevelSeq(vec![self.0].into_iter()))
+                visitor.visit_seq(LevelSeq(vec![(0, self.0)].into_iter(), self.1))
             }
-            Level::Invalid(e) => Err(de::Error::custom(e)),
+            Level::Invalid(e) => Err(e),
             Level::Uninitialised => Err(de::Error::custom(
                 "attempted to deserialize unitialised \
                  value",
@@ -648,36 +2857,114 @@ impl<'de> de::Deserializer<'de> for LevelDeserializer<'de> {
     {
         match self.0 {
             Level::OrderedSeq(_) => self.into_deserializer()?.deserialize_map(visitor),
+            // An empty value, e.g. `a=`, is an empty map.
+            ref level if is_empty_value(level) => {
+                visitor.visit_map(QsDeserializer::with_map(Map::default(), self.1))
+            }
             _ => self.deserialize_any(visitor),
         }
     }
 
-    deserialize_primitive!(bool, deserialize_bool, visit_bool);
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self.0 {
+            Level::Nested(_) => self
+                .into_deserializer()?
+                .deserialize_struct(name, fields, visitor),
+            // Fields named like integers, e.g. `#[serde(rename = "0")]`, were
+            // parsed as sequence indices, so match them up by name rather
+            // than visiting the values in index order.
+            Level::OrderedSeq(seq) if fields.iter().any(|f| is_integer(f)) => {
+                let mut map = seq
+                    .into_iter()
+                    .map(|(index, value)| {
+                        let key = match integer_field(fields, index) {
+                            Some(field) => Key::Borrowed(field),
+                            None => Key::Shared(index.to_string().into()),
+                        };
+                        (key, value)
+                    })
+                    .collect();
+                sort_map(&mut map, self.1);
+                QsDeserializer::with_map(map, self.1).deserialize_struct(name, fields, visitor)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    deserialize_primitive!(bool, deserialize_bool, visit_bool, into_bool);
     deserialize_primitive!(i8, deserialize_i8, visit_i8);
     deserialize_primitive!(i16, deserialize_i16, visit_i16);
     deserialize_primitive!(i32, deserialize_i32, visit_i32);
     deserialize_primitive!(i64, deserialize_i64, visit_i64);
+    deserialize_primitive!(i128, deserialize_i128, visit_i128);
     deserialize_primitive!(u8, deserialize_u8, visit_u8);
     deserialize_primitive!(u16, deserialize_u16, visit_u16);
     deserialize_primitive!(u32, deserialize_u32, visit_u32);
     deserialize_primitive!(u64, deserialize_u64, visit_u64);
+    deserialize_primitive!(u128, deserialize_u128, visit_u128);
     deserialize_primitive!(f32, deserialize_f32, visit_f32);
     deserialize_primitive!(f64, deserialize_f64, visit_f64);
 
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_sized(len, None, visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_sized(len, Some(name), visitor)
+    }
+
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.into_scalar("char")?.deserialize_any(visitor)
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.into_scalar("string")?.deserialize_any(visitor)
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.into_scalar("string")?.deserialize_any(visitor)
+    }
+
     forward_to_deserialize_any! {
-        char
-        str
-        string
+        // char
+        // str
+        // string
         bytes
         byte_buf
-        unit_struct
+        // unit_struct
         // newtype_struct
-        tuple_struct
-        struct
+        // struct
         identifier
-        tuple
         ignored_any
-        seq
+        // seq
         // map
     }
 }
@@ -688,14 +2975,40 @@ macro_rules! forward_parsable_to_deserialize_any {
             fn $meth<V>(self, visitor: V) -> Result<V::Value> where V: de::Visitor<'de> {
                 match self.0.parse::<$ty>() {
                     Ok(val) => val.into_deserializer().$meth(visitor),
-                    Err(e) => Err(de::Error::custom(e))
+                    Err(e) => Err(e.into_error())
                 }
             }
         )*
     }
 }
 
-struct ParsableStringDeserializer<'a>(Cow<'a, str>);
+/// Converts the error from parsing a primitive, keeping a `ParseIntError`
+/// as the source of the resulting error.
+trait IntoError: Display + Sized {
+    fn into_error(self) -> Error {
+        de::Error::custom(self)
+    }
+}
+
+impl IntoError for ParseIntError {
+    fn into_error(self) -> Error {
+        Error::ParseInt(self)
+    }
+}
+
+impl IntoError for ParseFloatError {}
+
+impl IntoError for ParseBoolError {}
+
+pub(crate) struct ParsableStringDeserializer<'a>(pub(crate) Cow<'a, str>);
+
+impl<'de> IntoDeserializer<'de, Error> for ParsableStringDeserializer<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
 
 impl<'de> de::Deserializer<'de> for ParsableStringDeserializer<'de> {
     type Error = Error;
@@ -704,7 +3017,12 @@ impl<'de> de::Deserializer<'de> for ParsableStringDeserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        self.0.into_deserializer().deserialize_any(visitor)
+        // Unlike `CowStrDeserializer`, hand out borrowed strings with the
+        // lifetime of the input.
+        match self.0 {
+            Cow::Owned(s) => visitor.visit_string(s),
+            Cow::Borrowed(s) => visitor.visit_borrowed_str(s),
+        }
     }
 
     fn deserialize_enum<V>(
@@ -716,14 +3034,24 @@ impl<'de> de::Deserializer<'de> for ParsableStringDeserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_enum(LevelDeserializer(Level::Flat(self.0)))
+        visitor.visit_enum(LevelDeserializer(Level::Flat(self.0), Options::default()))
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        if self.0.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
     }
 
     forward_to_deserialize_any! {
         map
         struct
         seq
-        option
         char
         str
         string
@@ -744,10 +3072,111 @@ impl<'de> de::Deserializer<'de> for ParsableStringDeserializer<'de> {
         u16 => deserialize_u16,
         u32 => deserialize_u32,
         u64 => deserialize_u64,
+        u128 => deserialize_u128,
+        i8 => deserialize_i8,
+        i16 => deserialize_i16,
+        i32 => deserialize_i32,
+        i64 => deserialize_i64,
+        i128 => deserialize_i128,
+        f32 => deserialize_f32,
+        f64 => deserialize_f64,
+    }
+}
+
+/// Deserializes a map key like `ParsableStringDeserializer`, but without
+/// copying keys which are shared.
+struct KeyDeserializer<'a>(Key<'a>, Options);
+
+impl<'de> de::Deserializer<'de> for KeyDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self.0 {
+            Key::Borrowed(key) => {
+                ParsableStringDeserializer(Cow::Borrowed(key)).deserialize_any(visitor)
+            }
+            Key::Shared(key) => visitor.visit_str(&key),
+            // Too long to decode on the stack, so hand over the decoded copy
+            Key::Encoded(ref raw) if raw.len() > KEY_BUFFER_LEN => {
+                ParsableStringDeserializer(self.0.decode(self.1.strict)?).deserialize_any(visitor)
+            }
+            ref key => key.with_decoded(|bytes| match str::from_utf8(bytes) {
+                Ok(key) => visitor.visit_str(key),
+                Err(_) if !self.1.strict => {
+                    visitor.visit_string(String::from_utf8_lossy(bytes).into_owned())
+                }
+                Err(e) => Err(e.into()),
+            }),
+        }
+    }
+
+    /// Visits a sequence index as a `u64`, so that it can identify a field
+    /// or variant by its position, and any other key as a string.
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        if self.1.index_key {
+            if let Ok(index) = self.0.parse() {
+                return visitor.visit_u64(index);
+            }
+        }
+        self.deserialize_any(visitor)
+    }
+
+    /// Ignores the key without decoding it.
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        ParsableStringDeserializer(self.0.decode(self.1.strict)?)
+            .deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        map
+        struct
+        seq
+        option
+        char
+        str
+        string
+        unit
+        bytes
+        byte_buf
+        unit_struct
+        newtype_struct
+        tuple_struct
+        tuple
+    }
+
+    forward_parsable_to_deserialize_any! {
+        bool => deserialize_bool,
+        u8 => deserialize_u8,
+        u16 => deserialize_u16,
+        u32 => deserialize_u32,
+        u64 => deserialize_u64,
+        u128 => deserialize_u128,
         i8 => deserialize_i8,
         i16 => deserialize_i16,
         i32 => deserialize_i32,
         i64 => deserialize_i64,
+        i128 => deserialize_i128,
         f32 => deserialize_f32,
         f64 => deserialize_f64,
     }
@@ -0,0 +1,222 @@
+//! Iterating over a parsed querystring without a target type.
+
+use super::{decode_key, map, Key, Level, Map, NestingStyle};
+use crate::utils::encode_key;
+
+use std::borrow::Cow;
+use std::collections::btree_map;
+use std::fmt;
+use std::iter::Enumerate;
+use std::slice;
+
+/// A single segment of a [`KeyPath`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Segment<'a> {
+    /// A decoded map key or struct field, e.g. `name` in `user[name]`.
+    ///
+    /// Keys are borrowed from the input, unless they needed decoding.
+    Key(Cow<'a, str>),
+    /// A sequence index, e.g. `0` in `ids[0]`.
+    ///
+    /// Elements of unindexed sequences such as `ids[]=1&ids[]=2` are numbered
+    /// in the order they appear.
+    Index(usize),
+}
+
+impl<'a> From<&'a str> for Segment<'a> {
+    fn from(key: &'a str) -> Self {
+        Segment::Key(Cow::Borrowed(key))
+    }
+}
+
+impl From<String> for Segment<'_> {
+    fn from(key: String) -> Self {
+        Segment::Key(Cow::Owned(key))
+    }
+}
+
+impl From<usize> for Segment<'_> {
+    fn from(index: usize) -> Self {
+        Segment::Index(index)
+    }
+}
+
+/// The path to a value in a querystring, e.g. `user[ids][0]`.
+///
+/// The `Display` implementation renders the path as a querystring key, with
+/// the first segment bare and the rest in brackets. Segments are not
+/// re-encoded, use [`encode`](#method.encode) for that.
+///
+/// A path can also be built to write a single value, see
+/// [`Config::serialize_pair`](crate::Config::serialize_pair).
+///
+/// ```
+/// use serde_qs::KeyPath;
+///
+/// let mut path = KeyPath::new(["filter", "a&b"]);
+/// path.push(0);
+/// assert_eq!(path.to_string(), "filter[a&b][0]");
+/// assert_eq!(path.encode(), "filter[a%26b][0]");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyPath<'a> {
+    segments: Vec<Segment<'a>>,
+}
+
+impl<'a> KeyPath<'a> {
+    /// Creates a path from its segments, outermost first.
+    pub fn new<I>(segments: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Segment<'a>>,
+    {
+        KeyPath {
+            segments: segments.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Appends a segment to the path.
+    pub fn push(&mut self, segment: impl Into<Segment<'a>>) {
+        self.segments.push(segment.into());
+    }
+
+    /// Returns the parsed segments of the path, outermost first.
+    pub fn segments(&self) -> &[Segment<'a>] {
+        &self.segments
+    }
+
+    /// Parses a key written by the serializer, in which any brackets (or
+    /// dots, with `NestingStyle::Dots`) within a segment are encoded.
+    /// Numeric segments after the first are taken to be sequence indices.
+    pub(crate) fn from_encoded(key: &str, style: NestingStyle) -> KeyPath<'static> {
+        let (first, rest, separator) = match style {
+            NestingStyle::Brackets => match key.find('[') {
+                Some(i) => (&key[..i], key[i + 1..].trim_end_matches(']'), "]["),
+                None => (key, "", "]["),
+            },
+            NestingStyle::Dots => match key.find('.') {
+                Some(i) => (&key[..i], &key[i + 1..], "."),
+                None => (key, "", "."),
+            },
+        };
+        let decode =
+            |segment: &str| String::from_utf8_lossy(&decode_key(segment.as_bytes())).into_owned();
+        let mut path = KeyPath::new([decode(first)]);
+        for segment in rest.split(separator).filter(|_| !rest.is_empty()) {
+            match segment.parse() {
+                Ok(index) if segment == "0" || !segment.starts_with('0') => {
+                    path.push(Segment::Index(index))
+                }
+                _ => path.push(decode(segment)),
+            }
+        }
+        path
+    }
+
+    /// Renders the path as a querystring key, percent-encoding each segment
+    /// as the serializer does.
+    pub fn encode(&self) -> String {
+        let mut key = String::new();
+        for (i, segment) in self.segments.iter().enumerate() {
+            let segment = match segment {
+                Segment::Key(segment) => encode_key(segment),
+                Segment::Index(index) => Cow::Owned(index.to_string()),
+            };
+            if i == 0 {
+                key.push_str(&segment);
+            } else {
+                key.push('[');
+                key.push_str(&segment);
+                key.push(']');
+            }
+        }
+        key
+    }
+}
+
+impl fmt::Display for KeyPath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, segment) in self.segments.iter().enumerate() {
+            match (i, segment) {
+                (0, Segment::Key(key)) => f.write_str(key)?,
+                (0, Segment::Index(index)) => write!(f, "{}", index)?,
+                (_, Segment::Key(key)) => write!(f, "[{}]", key)?,
+                (_, Segment::Index(index)) => write!(f, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An iterator over the `(key path, value)` pairs of a parsed querystring.
+///
+/// This is created by [`Deserializer::iter_pairs`](struct.Deserializer.html#method.iter_pairs).
+pub struct Pairs<'a> {
+    /// The segments leading to the innermost level on the `stack`.
+    path: Vec<Segment<'a>>,
+    stack: Vec<Children<'a>>,
+}
+
+impl<'a> Pairs<'a> {
+    pub(super) fn new(map: &'a Map<'a>) -> Self {
+        Pairs {
+            path: Vec::new(),
+            stack: vec![Children::Nested(map.iter())],
+        }
+    }
+
+    fn path_to(&self, segment: Segment<'a>) -> KeyPath<'a> {
+        let mut segments = Vec::with_capacity(self.path.len() + 1);
+        segments.extend_from_slice(&self.path);
+        segments.push(segment);
+        KeyPath { segments }
+    }
+}
+
+impl<'a> Iterator for Pairs<'a> {
+    type Item = (KeyPath<'a>, Option<Cow<'a, str>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (segment, level) = match self.stack.last_mut()?.next() {
+                Some(child) => child,
+                None => {
+                    let _ = self.stack.pop();
+                    let _ = self.path.pop();
+                    continue;
+                }
+            };
+            let children = match level {
+                Level::Nested(map) => Children::Nested(map.iter()),
+                Level::OrderedSeq(map) => Children::OrderedSeq(map.iter()),
+                Level::Sequence(seq) => Children::Sequence(seq.iter().enumerate()),
+                Level::Flat(value) => {
+                    return Some((self.path_to(segment), Some(Cow::Borrowed(value))))
+                }
+                Level::NoValue => return Some((self.path_to(segment), Some(Cow::Borrowed("")))),
+                Level::Invalid(_) | Level::Uninitialised => {
+                    return Some((self.path_to(segment), None))
+                }
+            };
+            self.path.push(segment);
+            self.stack.push(children);
+        }
+    }
+}
+
+/// The remaining children of a level being traversed.
+enum Children<'a> {
+    Nested(map::Iter<'a, Key<'a>, Level<'a>>),
+    OrderedSeq(btree_map::Iter<'a, usize, Level<'a>>),
+    Sequence(Enumerate<slice::Iter<'a, Level<'a>>>),
+}
+
+impl<'a> Children<'a> {
+    fn next(&mut self) -> Option<(Segment<'a>, &'a Level<'a>)> {
+        match self {
+            Children::Nested(iter) => iter.next().map(|(k, v)| (Segment::Key(k.as_str()), v)),
+            Children::OrderedSeq(iter) => iter.next().map(|(i, v)| (Segment::Index(*i), v)),
+            Children::Sequence(iter) => iter.next().map(|(i, v)| (Segment::Index(i), v)),
+        }
+    }
+}
@@ -2,13 +2,19 @@ use crate::utils::{replace_space, QS_ENCODE_SET};
 
 use super::*;
 
+#[cfg(feature = "bumpalo")]
+use bumpalo::Bump;
 use percent_encoding::percent_encode;
 use serde::de;
 
 use std::borrow::Cow;
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::collections::HashMap;
 use std::iter::Iterator;
+use std::mem;
 use std::slice::Iter;
 use std::str;
+use std::sync::Arc;
 
 macro_rules! tu {
     ($x:expr) => {
@@ -22,87 +28,176 @@ macro_rules! tu {
 impl<'a> Level<'a> {
     /// If this `Level` value is indeed a map, then attempt to insert
     /// `value` for key `key`.
-    /// Returns error if `self` is not a map, or already has an entry for that
-    /// key.
-    fn insert_map_value(&mut self, key: Cow<'a, str>, value: Cow<'a, str>) {
+    /// Returns the conflict if `self` is not a map, or already has an entry
+    /// for that key. An entry with nested keys is resolved with `conflict`,
+    /// and one with values is extended if `repeated` is set.
+    fn insert_map_value(
+        &mut self,
+        key: Key<'a>,
+        value: Level<'a>,
+        conflict: Conflict,
+        repeated: bool,
+    ) -> Option<ConflictKind> {
         if let Level::Nested(ref mut map) = *self {
             match map.entry(key) {
-                Entry::Occupied(mut o) => {
-                    let key = o.key();
+                map::Entry::Occupied(mut o)
+                    if repeated
+                        && matches!(
+                            o.get(),
+                            Level::Flat(_)
+                                | Level::NoValue
+                                | Level::Sequence(_)
+                                | Level::OrderedSeq(_)
+                        ) =>
+                {
+                    let level = o.get_mut();
+                    if let Level::Flat(_) | Level::NoValue = level {
+                        let first = mem::replace(level, Level::Uninitialised);
+                        *level = Level::Sequence(vec![first]);
+                    }
+                    match level {
+                        Level::Sequence(seq) => seq.push(value),
+                        // After the indexed values, e.g. `a[0]=x&a=y`
+                        Level::OrderedSeq(seq) => {
+                            let index = seq.keys().next_back().map_or(0, |i| i + 1);
+                            let _ = seq.insert(index, value);
+                        }
+                        _ => {}
+                    }
+                }
+                map::Entry::Occupied(mut o) => {
+                    if let Some(kind) = o.get_mut().resolve_conflict(value, conflict) {
+                        return Some(kind);
+                    }
+                    let key = o.key().as_str();
                     let error = if key.contains('[') {
                         let newkey = percent_encode(key.as_bytes(), QS_ENCODE_SET)
                             .map(replace_space)
                             .collect::<String>();
-                        format!("Multiple values for one key: \"{}\"\nInvalid field contains an encoded bracket -- did you mean to use non-strict mode?\n  https://docs.rs/serde_qs/latest/serde_qs/#strict-vs-non-strict-modes", newkey)
+                        let error = de::Error::custom(format!(
+                            "Multiple values for one key: \"{}\"",
+                            newkey
+                        ));
+                        Error::encoded_bracket(newkey, error)
                     } else {
-                        format!("Multiple values for one key: \"{}\"", key)
+                        de::Error::custom(format!("Multiple values for one key: \"{}\"", key))
                     };
                     // Throw away old result; map is now invalid anyway.
                     let _ = o.insert(Level::Invalid(error));
+                    return Some(ConflictKind::DuplicateKey);
                 }
-                Entry::Vacant(vm) => {
+                map::Entry::Vacant(vm) => {
                     // Map is empty, result is None
-                    let _ = vm.insert(Level::Flat(value));
+                    let _ = vm.insert(value);
                 }
             }
         } else if let Level::Uninitialised = *self {
-            let mut map = BTreeMap::default();
-            let _ = map.insert(key, Level::Flat(value));
+            let mut map = Map::default();
+            let _ = map.insert(key, value);
             *self = Level::Nested(map);
         } else {
-            *self = Level::Invalid(
+            *self = Level::Invalid(de::Error::custom(
                 "Attempted to insert map value into \
-                 non-map structure"
-                    .to_string(),
-            );
+                 non-map structure",
+            ));
+            return Some(ConflictKind::MismatchedStructure);
         }
+        None
+    }
+
+    /// Resolves a `value` given for a key which already has nested keys,
+    /// keeping one or the other according to `conflict`.
+    fn resolve_conflict(&mut self, value: Level<'a>, conflict: Conflict) -> Option<ConflictKind> {
+        match self {
+            Level::Nested(_) | Level::OrderedSeq(_) | Level::Sequence(_) => match conflict {
+                Conflict::Error => None,
+                Conflict::PreferNested => Some(ConflictKind::DroppedValue),
+                Conflict::PreferFlat => {
+                    *self = value;
+                    Some(ConflictKind::DroppedValue)
+                }
+            },
+            _ => None,
+        }
+    }
+
+    /// If this `Level` value is an ordered seq, then convert it to a map
+    /// with the indices as keys, so that string keys can be added.
+    fn promote_seq_to_map(&mut self) -> Option<ConflictKind> {
+        if let Level::OrderedSeq(ref mut seq) = *self {
+            let map = std::mem::take(seq)
+                .into_iter()
+                .map(|(k, v)| (Key::Shared(k.to_string().into()), v))
+                .collect();
+            *self = Level::Nested(map);
+            return Some(ConflictKind::PromotedToMap);
+        }
+        None
     }
 
     /// If this `Level` value is indeed a seq, then push a new value
-    fn insert_ord_seq_value(&mut self, key: usize, value: Cow<'a, str>) {
+    fn insert_ord_seq_value(
+        &mut self,
+        key: usize,
+        value: Level<'a>,
+        conflict: Conflict,
+    ) -> Option<ConflictKind> {
         if let Level::OrderedSeq(ref mut map) = *self {
             match map.entry(key) {
                 Entry::Occupied(mut o) => {
+                    if let Some(kind) = o.get_mut().resolve_conflict(value, conflict) {
+                        return Some(kind);
+                    }
                     // Throw away old result; map is now invalid anyway.
-                    let _ = o.insert(Level::Invalid("Multiple values for one key".to_string()));
+                    let _ = o.insert(Level::Invalid(de::Error::custom(
+                        "Multiple values for one key",
+                    )));
+                    return Some(ConflictKind::DuplicateKey);
                 }
                 Entry::Vacant(vm) => {
                     // Map is empty, result is None
-                    let _ = vm.insert(Level::Flat(value));
+                    let _ = vm.insert(value);
                 }
             }
         } else if let Level::Uninitialised = *self {
             // To reach here, self is either an OrderedSeq or nothing.
             let mut map = BTreeMap::default();
-            let _ = map.insert(key, Level::Flat(value));
+            let _ = map.insert(key, value);
             *self = Level::OrderedSeq(map);
         } else {
-            *self = Level::Invalid(
+            *self = Level::Invalid(de::Error::custom(
                 "Attempted to insert seq value into \
-                 non-seq structure"
-                    .to_string(),
-            );
+                 non-seq structure",
+            ));
+            return Some(ConflictKind::MismatchedStructure);
         }
+        None
     }
 
     /// If this `Level` value is indeed a seq, then attempt to insert
     /// `value` for key `key`.
-    /// Returns error if `self` is not a seq, or already has an entry for that
-    /// key.
-    fn insert_seq_value(&mut self, value: Cow<'a, str>) {
+    /// Returns the conflict if `self` is not a seq, unless it is a single value
+    /// and `repeated` is set, in which case it becomes the first element.
+    fn insert_seq_value(&mut self, value: Level<'a>, repeated: bool) -> Option<ConflictKind> {
         // Reached the end of the key string
         if let Level::Sequence(ref mut seq) = *self {
-            seq.push(Level::Flat(value));
+            seq.push(value);
+        } else if let (true, Level::Flat(_) | Level::NoValue) = (repeated, &*self) {
+            let first = mem::replace(self, Level::Uninitialised);
+            *self = Level::Sequence(vec![first, value]);
         } else if let Level::Uninitialised = *self {
-            let seq = vec![Level::Flat(value)];
+            // Room for a few more elements, as one is rarely given alone.
+            let mut seq = Vec::with_capacity(4);
+            seq.push(value);
             *self = Level::Sequence(seq);
         } else {
-            *self = Level::Invalid(
+            *self = Level::Invalid(de::Error::custom(
                 "Attempted to insert seq value into \
-                 non-seq structure"
-                    .to_string(),
-            );
+                 non-seq structure",
+            ));
+            return Some(ConflictKind::MismatchedStructure);
         }
+        None
     }
 }
 
@@ -112,6 +207,8 @@ impl<'a> Level<'a> {
 /// The parser additionally supports peeking values, which allows them to be
 /// re-used (precisely once, unlike with `Peekable` from `std::iter`).
 pub struct Parser<'a> {
+    /// The input as it was given, before any trimming.
+    input: &'a [u8],
     inner: &'a [u8],
     iter: Iter<'a, u8>,
     index: usize,
@@ -119,11 +216,47 @@ pub struct Parser<'a> {
     peeked: Option<&'a u8>,
     depth: usize, // stores the current depth, for use in bounded-depth parsing
     strict: bool,
+    /// Whether encoded brackets in keys denote nested keys.
+    lenient_brackets: bool,
+    /// Whether dots in keys denote nested keys, see `NestingStyle::Dots`.
+    dots: bool,
+    /// Whether the input is a single key which is already decoded, see
+    /// `parse_pair`. `&` and `=` are then part of the key.
+    decoded: bool,
+    /// The value for the key, when `decoded`.
+    value: Option<Cow<'a, str>>,
+    max_seq_index: usize,
+    seq_index_overflow: SeqIndexOverflow,
+    /// See `Config::key_conflict_resolution`.
+    key_conflict: Conflict,
+    /// Collect repeated keys into a sequence, see
+    /// `Config::repeated_keys_as_seq`.
+    repeated_keys: bool,
+    preserve_leading_zero_keys: bool,
+    reject_control_chars: bool,
+    strict_percent_decoding: bool,
+    /// Keep values as they are given, see `Config::assume_preencoded_values`.
+    preencoded_values: bool,
+    on_conflict: Option<fn(ConflictEvent)>,
+    key_transcoder: Option<Transcoder>,
+    value_transcoder: Option<Transcoder>,
+    /// The start of the entry being parsed, for reporting conflicts.
+    pair_start: usize,
     state: ParsingState,
+    /// Options for deserializing the parsed map.
+    options: Options,
+    /// The expected number of top-level keys, see `Config::capacity_hint`.
+    capacity_hint: usize,
+    pub(crate) stats: ParseStats,
+    /// Keys which were transcoded, indexed by their raw bytes.
+    decoded_keys: HashMap<&'a [u8], Arc<str>>,
+    /// Where to decode values into, see `QsParserArena`.
+    #[cfg(feature = "bumpalo")]
+    arena: Option<&'a Bump>,
 }
 
 /// The parsing logic varies slightly based on whether it is a key or a value
-/// (determines how encoded brackets are parse in non-strict mode)
+/// (determines how encoded brackets are parsed with lenient bracket decoding)
 /// This tracks the state.
 enum ParsingState {
     Init,
@@ -137,7 +270,7 @@ impl<'a> Iterator for Parser<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         let preparse_brackets = match self.state {
             ParsingState::Value => false,
-            _ => !self.strict,
+            _ => self.lenient_brackets,
         };
         if preparse_brackets {
             // in non-strict mode, we will happily decode any bracket
@@ -200,7 +333,7 @@ impl<'a> Parser<'a> {
 
 /// Replace b'+' with b' '
 /// Copied from [`form_urlencoded`](https://github.com/servo/rust-url/blob/380be29859adb859e861c2d765897c22ec878e01/src/form_urlencoded.rs#L125).
-fn replace_plus(input: &[u8]) -> Cow<[u8]> {
+fn replace_plus(input: &[u8]) -> Cow<'_, [u8]> {
     match input.iter().position(|&b| b == b'+') {
         None => Cow::Borrowed(input),
         Some(first_position) => {
@@ -217,17 +350,129 @@ fn replace_plus(input: &[u8]) -> Cow<[u8]> {
     }
 }
 
+/// Replaces `+` with ` ` and percent-decodes `raw` into `arena`. Invalid
+/// UTF-8 is an error if `strict`, and is otherwise replaced, in which case
+/// the result is owned after all.
+#[cfg(feature = "bumpalo")]
+fn decode_in<'a>(raw: &[u8], arena: &'a Bump, strict: bool) -> Result<Cow<'a, str>> {
+    let mut decoded = bumpalo::collections::Vec::with_capacity_in(raw.len(), arena);
+    for (i, part) in raw.split(|&b| b == b'+').enumerate() {
+        if i > 0 {
+            decoded.push(b' ');
+        }
+        decoded.extend(percent_encoding::percent_decode(part));
+    }
+    let decoded = decoded.into_bump_slice();
+    match str::from_utf8(decoded) {
+        Ok(decoded) => Ok(Cow::Borrowed(decoded)),
+        Err(error) if strict => Err(error.into()),
+        Err(_) => Ok(String::from_utf8_lossy(decoded)),
+    }
+}
+
 impl<'a> Parser<'a> {
-    pub fn new(encoded: &'a [u8], depth: usize, strict: bool) -> Self {
+    pub fn new(encoded: &'a [u8], config: &Config) -> Self {
         Parser {
+            input: encoded,
             inner: encoded,
             iter: encoded.iter(),
             acc: (0, 0),
             index: 0,
             peeked: None,
-            depth,
-            strict,
+            depth: config.max_depth(),
+            strict: config.strict,
+            lenient_brackets: !config.strict || config.lenient_bracket_decoding,
+            dots: config.nesting_style == NestingStyle::Dots,
+            decoded: false,
+            value: None,
+            max_seq_index: config.max_seq_index,
+            seq_index_overflow: config.seq_index_overflow,
+            key_conflict: config.key_conflict_resolution,
+            repeated_keys: config.repeated_keys_as_seq,
+            preserve_leading_zero_keys: config.preserve_leading_zero_keys,
+            reject_control_chars: config.reject_control_chars,
+            strict_percent_decoding: config.strict_percent_decoding,
+            preencoded_values: config.assume_preencoded_values,
+            on_conflict: config.on_conflict,
+            key_transcoder: config.key_transcoder,
+            value_transcoder: config.value_transcoder,
+            pair_start: 0,
             state: ParsingState::Init,
+            options: config.into(),
+            capacity_hint: config.capacity_hint,
+            stats: ParseStats::default(),
+            decoded_keys: HashMap::new(),
+            #[cfg(feature = "bumpalo")]
+            arena: None,
+        }
+    }
+
+    /// Decodes values into `arena` rather than into owned strings.
+    #[cfg(feature = "bumpalo")]
+    pub(crate) fn with_arena(mut self, arena: &'a Bump) -> Self {
+        self.arena = Some(arena);
+        self
+    }
+
+    /// Interprets an integer key as a sequence index, or returns `None` when
+    /// it should be treated as a map key instead: either because `node` is
+    /// already a map, the key only starts with a digit (e.g. a UUID), or the
+    /// index is too large.
+    fn seq_index(&self, key: &str, node: &Level<'a>) -> Result<Option<usize>> {
+        if let Level::Nested(_) = *node {
+            return Ok(None);
+        }
+        if !key.bytes().all(|b| b.is_ascii_digit()) {
+            return Ok(None);
+        }
+        if self.preserve_leading_zero_keys && key.len() > 1 && key.starts_with('0') {
+            return Ok(None);
+        }
+        match key.parse::<usize>() {
+            Ok(index) if index <= self.max_seq_index => Ok(Some(index)),
+            _ => match self.seq_index_overflow {
+                SeqIndexOverflow::MapKey => Ok(None),
+                SeqIndexOverflow::Error => Err(Error::SequenceIndexTooLarge {
+                    index: key.to_owned(),
+                    max: self.max_seq_index,
+                }),
+            },
+        }
+    }
+
+    /// Skips a UTF-8 byte order mark and ASCII whitespace at the start of the
+    /// input, and drops ASCII whitespace at the end. Must be called before
+    /// parsing starts.
+    pub(crate) fn trim_input(&mut self) {
+        let end = self
+            .inner
+            .iter()
+            .rposition(|b| !b.is_ascii_whitespace())
+            .map_or(0, |i| i + 1);
+        self.inner = &self.inner[..end];
+        self.iter = self.inner.iter();
+
+        let bom = if self.inner.starts_with(b"\xEF\xBB\xBF") {
+            3
+        } else {
+            0
+        };
+        let whitespace = self.inner[bom..]
+            .iter()
+            .take_while(|b| b.is_ascii_whitespace())
+            .count();
+        for _ in 0..bom + whitespace {
+            let _ = self.next();
+        }
+        self.clear_acc();
+    }
+
+    /// Skips a single `?` at the start of the input, as left over from
+    /// slicing the querystring out of a URL.
+    pub(crate) fn skip_leading_question_mark(&mut self) {
+        if self.inner.get(self.index) == Some(&b'?') {
+            let _ = self.next();
+            self.clear_acc();
         }
     }
 
@@ -241,7 +486,22 @@ impl<'a> Parser<'a> {
     /// Avoids allocations when neither percent encoded, nor `'+'` values are
     /// present.
     fn collect_str(&mut self) -> Result<Cow<'a, str>> {
-        let replaced = replace_plus(&self.inner[self.acc.0..self.acc.1 - 1]);
+        let raw = &self.inner[self.acc.0..self.acc.1 - 1];
+        if self.strict_percent_decoding {
+            check_percent_encoding(raw, self.acc.0)?;
+        }
+        #[cfg(feature = "bumpalo")]
+        if let (Some(arena), None) = (self.arena, self.transcoder()) {
+            if raw.iter().any(|&b| b == b'%' || b == b'+') {
+                let ret = decode_in(raw, arena, self.strict);
+                self.clear_acc();
+                return ret;
+            }
+        }
+        let replaced = replace_plus(raw);
+        if let Some(transcoder) = self.transcoder() {
+            return self.transcode(replaced, transcoder);
+        }
         let decoder = percent_encoding::percent_decode(&replaced);
 
         let maybe_decoded = if self.strict {
@@ -268,22 +528,202 @@ impl<'a> Parser<'a> {
             Cow::Owned(owned) => Ok(Cow::Owned(owned)),
         };
         self.clear_acc();
-        ret.map_err(Error::from)
+        ret
+    }
+
+    /// Like `collect_str`, but without replacing `+` or percent-decoding.
+    fn collect_raw(&mut self) -> Result<Cow<'a, str>> {
+        let raw = &self.inner[self.acc.0..self.acc.1 - 1];
+        let ret = if self.strict {
+            Cow::Borrowed(str::from_utf8(raw)?)
+        } else {
+            String::from_utf8_lossy(raw)
+        };
+        self.clear_acc();
+        Ok(ret)
+    }
+
+    /// The transcoder for the string being collected, if there is one.
+    fn transcoder(&self) -> Option<Transcoder> {
+        match self.state {
+            ParsingState::Value => self.value_transcoder,
+            _ => self.key_transcoder,
+        }
+    }
+
+    /// Percent-decodes `replaced` and converts it to UTF-8 with `transcoder`.
+    fn transcode(
+        &mut self,
+        replaced: Cow<'a, [u8]>,
+        transcoder: Transcoder,
+    ) -> Result<Cow<'a, str>> {
+        let decoded: Cow<[u8]> = percent_encoding::percent_decode(&replaced).into();
+        let decoded = match decoded {
+            Cow::Owned(owned) => Cow::Owned(owned),
+            Cow::Borrowed(_) => replaced,
+        };
+        let res = transcoder(decoded).map_err(|error| Error::Transcode {
+            key: self.entry_key().1,
+            error,
+        });
+        self.clear_acc();
+        res
+    }
+
+    /// Like `collect_str`, but keys which need decoding are kept as they are,
+    /// to be decoded only if they are deserialized, see `Key`.
+    ///
+    /// Keys converted with a key transcoder are shared instead, so that keys
+    /// repeated across entries (e.g. `items[0][na%6De]`, `items[1][na%6De]`,
+    /// ...) are only transcoded once per parse.
+    fn collect_key(&mut self) -> Result<Key<'a>> {
+        let raw = &self.inner[self.acc.0..self.acc.1 - 1];
+        if self.decoded {
+            return self.collect_decoded_key(raw);
+        }
+        if self.reject_control_chars {
+            check_key_chars(raw, self.acc.0)?;
+        }
+        if self.key_transcoder.is_none() && !raw.iter().any(|&b| b == b'%' || b == b'+') {
+            self.stats.borrowed_keys += 1;
+            return self.collect_str().map(Key::from);
+        }
+        self.stats.owned_keys += 1;
+        if self.key_transcoder.is_none() {
+            // Decoded if and when the key is deserialized
+            if self.strict_percent_decoding {
+                check_percent_encoding(raw, self.acc.0)?;
+            }
+            self.clear_acc();
+            return Ok(Key::Encoded(Cow::Borrowed(raw)));
+        }
+        if let Some(key) = self.decoded_keys.get(raw) {
+            let key = Key::Shared(key.clone());
+            self.clear_acc();
+            return Ok(key);
+        }
+        let key = Key::from(self.collect_str()?);
+        if let Key::Shared(ref decoded) = key {
+            let _ = self.decoded_keys.insert(raw, decoded.clone());
+        }
+        Ok(key)
+    }
+
+    /// Borrows a key which is already decoded, see `parse_pair`.
+    fn collect_decoded_key(&mut self, raw: &'a [u8]) -> Result<Key<'a>> {
+        if self.reject_control_chars {
+            if let Some(i) = raw.iter().position(u8::is_ascii_control) {
+                return Err(Error::InvalidKeyCharacter {
+                    byte: raw[i],
+                    position: self.acc.0 + i,
+                });
+            }
+        }
+        self.stats.borrowed_keys += 1;
+        self.clear_acc();
+        Ok(Key::Borrowed(str::from_utf8(raw)?))
+    }
+
+    /// The value of a key which runs to the end of the input: none, or the
+    /// value given to `parse_pair`.
+    fn end_value(&mut self) -> Level<'a> {
+        self.value.take().map_or(Level::NoValue, Level::Flat)
+    }
+
+    /// Like `collect_str`, counting whether the value was borrowed.
+    fn collect_value(&mut self) -> Result<Cow<'a, str>> {
+        let value = if self.preencoded_values {
+            self.collect_raw()?
+        } else {
+            self.collect_str()?
+        };
+        match value {
+            Cow::Borrowed(_) => self.stats.borrowed_values += 1,
+            Cow::Owned(_) => self.stats.owned_values += 1,
+        }
+        Ok(value)
+    }
+
+    /// Counts a conflict in the entry being parsed, and passes it to the
+    /// `on_conflict` callback, if there is one.
+    fn report(&mut self, conflict: Option<ConflictKind>) {
+        match conflict {
+            Some(ConflictKind::PromotedToMap) => self.stats.promoted_sequences += 1,
+            Some(ConflictKind::DuplicateKey) => self.stats.duplicate_keys += 1,
+            Some(ConflictKind::DroppedValue) => self.stats.dropped_values += 1,
+            _ => {}
+        }
+        if let (Some(on_conflict), Some(kind)) = (self.on_conflict, conflict) {
+            let (position, key) = self.entry_key();
+            on_conflict(ConflictEvent {
+                kind,
+                key,
+                position,
+            });
+        }
+    }
+
+    /// The offset and key of the entry being parsed, as given in the input.
+    fn entry_key(&self) -> (usize, String) {
+        let start = self.inner[self.pair_start..]
+            .iter()
+            .position(|&b| b != b'&')
+            .map_or(self.inner.len(), |i| self.pair_start + i);
+        let entry = &self.inner[start..];
+        let end = entry
+            .iter()
+            .position(|&b| b == b'=' || b == b'&')
+            .unwrap_or(entry.len());
+        (start, String::from_utf8_lossy(&entry[..end]).into_owned())
     }
 
     /// In some ways the main way to use a `Parser`, this runs the parsing step
     /// and outputs a simple `Deserializer` over the parsed map.
     pub(crate) fn as_deserializer(&mut self) -> Result<QsDeserializer<'a>> {
-        let map = BTreeMap::default();
-        let mut root = Level::Nested(map);
+        // Each key takes at least a byte, which bounds a hint which is too
+        // large.
+        let capacity = self.capacity_hint.min(self.inner.len());
+        let mut root = Level::Nested(map_with_capacity(capacity));
 
         // Parses all top level nodes into the `root` map.
-        while self.parse(&mut root)? {}
-        let iter = match root {
-            Level::Nested(map) => map.into_iter(),
-            _ => BTreeMap::default().into_iter(),
+        loop {
+            self.pair_start = self.index;
+            let more = if self.depth == 0 {
+                self.parse_flat(&mut root)?
+            } else {
+                self.parse(&mut root)?
+            };
+            if !more {
+                break;
+            }
+        }
+        sort_keys(&mut root, self.options);
+        let map = match root {
+            Level::Nested(map) => map,
+            _ => Map::default(),
         };
-        Ok(QsDeserializer { iter, value: None })
+        Ok(QsDeserializer {
+            input: Some(Cow::Borrowed(self.input)),
+            ..QsDeserializer::with_map(map, self.options)
+        })
+    }
+
+    /// Parses the input as a single key which is already decoded, such as
+    /// from `url::Url::query_pairs`, and inserts `value` for it into `root`.
+    ///
+    /// Nothing is percent-decoded, so `%` and `+` are kept as they are, and
+    /// any brackets in the key denote nested keys.
+    pub(crate) fn parse_pair(&mut self, value: Cow<'a, str>, root: &mut Level<'a>) -> Result<()> {
+        self.decoded = true;
+        self.lenient_brackets = false;
+        self.value = Some(value);
+        if self.inner.is_empty() {
+            self.parse_map_value(Key::Borrowed(""), root)
+        } else if self.depth == 0 {
+            self.parse_flat(root).map(|_| ())
+        } else {
+            self.parse(root).map(|_| ())
+        }
     }
 
     /// This is the top level parsing function. It checks the first character to
@@ -293,12 +733,6 @@ impl<'a> Parser<'a> {
     /// Returns `Ok(false)` when there is no more string to parse.
     fn parse(&mut self, node: &mut Level<'a>) -> Result<bool> {
         // First character determines parsing type
-        if self.depth == 0 {
-            // Hit the maximum depth level, so parse everything as a key
-            let key = self.parse_key(b'=', false)?;
-            self.parse_map_value(key, node)?;
-            return Ok(true);
-        }
         match self.next() {
             Some(x) => {
                 match *x {
@@ -311,7 +745,11 @@ impl<'a> Parser<'a> {
                                 b'[' => {
                                     // If we're in strict mode, error, otherwise just ignore it.
                                     if self.strict {
-                                        return Err(super::Error::parse_err("found another opening bracket before the closed bracket", self.index));
+                                        return Err(Error::parse_err_in(
+                                            "found another opening bracket before the closed bracket",
+                                            self.index,
+                                            self.inner,
+                                        ));
                                     } else {
                                         let _ = self.next();
                                     }
@@ -327,8 +765,10 @@ impl<'a> Parser<'a> {
                                 // First character is an integer, attempt to parse it as an integer key
                                 b'0'..=b'9' => {
                                     let key = self.parse_key(b']', true)?;
-                                    let key = key.parse().map_err(Error::from)?;
-                                    self.parse_ord_seq_value(key, node)?;
+                                    match self.seq_index(&key.as_str(), node)? {
+                                        Some(index) => self.parse_ord_seq_value(index, node)?,
+                                        None => self.parse_map_value(key, node)?,
+                                    }
                                     return Ok(true);
                                 }
                                 // Key is "[a..=" so parse up to the closing "]"
@@ -339,12 +779,13 @@ impl<'a> Parser<'a> {
                                 }
                                 c => {
                                     if self.strict {
-                                        return Err(super::Error::parse_err(
+                                        return Err(Error::parse_err_in(
                                             format!(
                                                 "unexpected character: {}",
                                                 String::from_utf8_lossy(&[c])
                                             ),
                                             self.index,
+                                            self.inner,
                                         ));
                                     } else {
                                         let _ = self.next();
@@ -353,8 +794,20 @@ impl<'a> Parser<'a> {
                             }
                         }
                     }
+                    // Key is ".a..", so parse up to the next "." or "["
+                    b'.' if self.dots => {
+                        self.clear_acc();
+                        let key = self.parse_key(b'[', false)?;
+                        match self.seq_index(&key.as_str(), node)? {
+                            Some(index) if !key.as_str().is_empty() => {
+                                self.parse_ord_seq_value(index, node)?
+                            }
+                            _ => self.parse_map_value(key, node)?,
+                        }
+                        Ok(true)
+                    }
                     // Skip empty byte sequences (e.g. leading `&`, trailing `&`, `&&`, ...)
-                    b'&' => {
+                    b'&' if !self.decoded => {
                         self.clear_acc();
                         Ok(true)
                     }
@@ -375,6 +828,77 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses a top level entry when `max_depth` is zero, so the whole key,
+    /// brackets included, is a key of the root map.
+    ///
+    /// Returns `Ok(false)` when there is no more string to parse.
+    fn parse_flat(&mut self, node: &mut Level<'a>) -> Result<bool> {
+        match self.peek() {
+            // Skip empty byte sequences, as in `parse`
+            Some(b'&') if !self.decoded => {
+                let _ = self.next();
+                self.clear_acc();
+                Ok(true)
+            }
+            Some(_) => {
+                let key = self.parse_remaining_key()?;
+                self.parse_map_value(key, node)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Parses the next bracketed part of a key into `node`. Once `max_depth`
+    /// parts have been parsed, the rest of the key, brackets included, is
+    /// parsed as a single key instead.
+    fn parse_nested(&mut self, node: &mut Level<'a>) -> Result<()> {
+        if self.depth == 0 {
+            let key = self.parse_remaining_key()?;
+            return self.parse_map_value(key, node);
+        }
+        self.depth -= 1;
+        let res = self.parse(node);
+        self.depth += 1;
+        res.map(|_| ())
+    }
+
+    /// Parses the next part of a key nested under `node`. If `node` already
+    /// has a value, the conflict is resolved with `key_conflict` first.
+    fn parse_nested_under(&mut self, node: &mut Level<'a>) -> Result<()> {
+        if let Level::Flat(_) | Level::NoValue = *node {
+            match self.key_conflict {
+                Conflict::Error => {}
+                Conflict::PreferNested => {
+                    *node = Level::Uninitialised;
+                    self.report(Some(ConflictKind::DroppedValue));
+                }
+                Conflict::PreferFlat => {
+                    self.report(Some(ConflictKind::DroppedValue));
+                    return self.parse_nested(&mut Level::Uninitialised);
+                }
+            }
+        }
+        self.parse_nested(node)
+    }
+
+    /// Parses the rest of a key, brackets included, as a single key. Encoded
+    /// brackets are left to be decoded along with the rest of the key.
+    fn parse_remaining_key(&mut self) -> Result<Key<'a>> {
+        let lenient_brackets = mem::replace(&mut self.lenient_brackets, false);
+        // Catch the accumulator up, in case the last character read was an
+        // encoded bracket.
+        self.acc.1 = self.index;
+        let key = if self.decoded {
+            while self.next().is_some() {}
+            self.collect_key()
+        } else {
+            self.parse_key(b'=', false)
+        };
+        self.lenient_brackets = lenient_brackets;
+        key
+    }
+
     /// The iterator is currently pointing at a key, so parse up until the
     /// `end_on` value. This will either be `'['` when the key is the root key,
     /// or `']'` when the key is a nested key. In the former case, `'='` will
@@ -384,34 +908,52 @@ impl<'a> Parser<'a> {
     /// returned to the buffer to be peeked. This is important when
     /// parsing keys like `abc[def][ghi]` since the `'['` character is
     /// needed to for the next iteration of `parse`.
-    fn parse_key(&mut self, end_on: u8, consume: bool) -> Result<Cow<'a, str>> {
+    fn parse_key(&mut self, end_on: u8, consume: bool) -> Result<Key<'a>> {
         self.state = ParsingState::Key;
+        // Whether a bracketed segment is open, when parsing the rest of a
+        // key up to the `'='`, and if so whether it is closed in this pair.
+        // That is found at most once per segment, so that a key full of
+        // `'='` is not rescanned for each one.
+        let mut open = false;
+        let mut closed = None;
         loop {
+            let start = self.index;
             if let Some(x) = self.next() {
                 match *x {
-                    c if c == end_on => {
+                    b'[' if end_on == b'=' => {
+                        open = true;
+                        closed = None;
+                    }
+                    b']' if end_on == b'=' => open = false,
+                    // Within a segment, '=' is part of the key as long as
+                    // the segment is closed before the end of the pair
+                    b'=' if open && *closed.get_or_insert_with(|| self.closes_segment()) => {}
+                    c if c == end_on || (c == b'.' && end_on == b'[' && self.dots) => {
                         // Add this character back to the buffer for peek.
                         if !consume {
                             self.peeked = Some(x);
                         }
-                        return self.collect_str();
-                    }
-                    b'=' => {
-                        // Allow the '=' byte only when parsing keys within []
-                        if end_on != b']' {
-                            // Otherwise, we have reached the end of the key
-                            // Add this character back to the buffer for peek.
-                            self.peeked = Some(x);
-                            return self.collect_str();
+                        let key = self.collect_key();
+                        if !consume {
+                            // Keep the end character in the accumulator, in
+                            // case it starts a key at the maximum depth.
+                            self.acc.0 = start;
                         }
-
-                        // otherwise do nothing, so '=' is accumulated
+                        return key;
                     }
-                    b'&' => {
+                    // Allow the '=' byte only when parsing keys within [],
+                    // otherwise it is accumulated as part of the key
+                    b'=' if end_on != b']' && !self.decoded => {
+                        // Otherwise, we have reached the end of the key
+                        // Add this character back to the buffer for peek.
+                        self.peeked = Some(x);
+                        return self.collect_key();
+                    }
+                    b'&' if !self.decoded => {
                         // important to keep the `&` character so we know the
                         // key-value is of the form `key&..=` (i.e. no value)
                         self.peeked = Some(&b'&');
-                        return self.collect_str();
+                        return self.collect_key();
                     }
                     _ => {
                         // for any other character
@@ -420,55 +962,75 @@ impl<'a> Parser<'a> {
                 }
             } else {
                 // no more string to parse
-                return self.collect_str();
+                return self.collect_key();
             }
         }
     }
 
+    /// Whether a `']'` follows in the current pair.
+    fn closes_segment(&self) -> bool {
+        self.iter
+            .as_slice()
+            .iter()
+            .take_while(|b| **b != b'&')
+            .any(|b| *b == b']')
+    }
+
     /// The `(key,value)` pair is determined to be corresponding to a map entry,
     /// so parse it as such. The first part of the `key` has been parsed.
-    fn parse_map_value(&mut self, key: Cow<'a, str>, node: &mut Level<'a>) -> Result<()> {
+    fn parse_map_value(&mut self, key: Key<'a>, node: &mut Level<'a>) -> Result<()> {
         self.state = ParsingState::Key;
-        let res = loop {
+        self.report(node.promote_seq_to_map());
+        loop {
             if let Some(x) = self.peek() {
                 match *x {
-                    b'=' => {
+                    b'=' if !self.decoded => {
                         // Key is finished, parse up until the '&' as the value
                         self.clear_acc();
                         self.state = ParsingState::Value;
                         for _ in self.take_while(|b| *b != &b'&') {}
-                        let value: Cow<'a, str> = self.collect_str()?;
-                        node.insert_map_value(key, value);
+                        let value = Level::Flat(self.collect_value()?);
+                        self.report(node.insert_map_value(
+                            key,
+                            value,
+                            self.key_conflict,
+                            self.repeated_keys,
+                        ));
                         break Ok(());
                     }
-                    b'&' => {
+                    b'&' if !self.decoded => {
                         // No value
-                        node.insert_map_value(key, Cow::Borrowed(""));
+                        self.report(node.insert_map_value(
+                            key,
+                            Level::NoValue,
+                            self.key_conflict,
+                            self.repeated_keys,
+                        ));
                         break Ok(());
                     }
-                    b'[' => {
+                    c @ (b'[' | b'.') if c == b'[' || self.dots => {
                         // The key continues to another level of nested.
                         // Add a new unitialised level for this node and continue.
                         if let Level::Uninitialised = *node {
-                            *node = Level::Nested(BTreeMap::default());
+                            *node = Level::Nested(Map::default());
                         }
                         if let Level::Nested(ref mut map) = *node {
-                            // By parsing we drop down another level
-                            self.depth -= 1;
                             // Either take the existing entry, or add a new
                             // unitialised level
                             // Use this new node to keep parsing
-                            let _ = self.parse(map.entry(key).or_insert(Level::Uninitialised))?;
-                            break Ok(());
+                            break self.parse_nested_under(
+                                map.entry(key).or_insert(Level::Uninitialised),
+                            );
                         } else {
                             // We expected to parse into a map here.
-                            break Err(super::Error::parse_err(
+                            break Err(Error::parse_err_in(
                                 format!(
                                     "tried to insert a \
                                      new key into {:?}",
                                     node
                                 ),
                                 self.index,
+                                self.inner,
                             ));
                         }
                     }
@@ -476,12 +1038,13 @@ impl<'a> Parser<'a> {
                         // Anything else is unexpected since we just finished
                         // parsing a key.
                         if self.strict {
-                            break Err(super::Error::parse_err(
+                            break Err(Error::parse_err_in(
                                 format!(
                                     "Unexpected character: '{}' found when parsing",
                                     String::from_utf8_lossy(&[c])
                                 ),
                                 self.index,
+                                self.inner,
                             ));
                         } else {
                             let _ = self.next();
@@ -489,14 +1052,18 @@ impl<'a> Parser<'a> {
                     }
                 }
             } else {
-                // The string has ended, so the value is empty.
-                node.insert_map_value(key, Cow::Borrowed(""));
+                // The string has ended, so there is no value, unless one was
+                // given to `parse_pair`.
+                let value = self.end_value();
+                self.report(node.insert_map_value(
+                    key,
+                    value,
+                    self.key_conflict,
+                    self.repeated_keys,
+                ));
                 break Ok(());
             }
-        };
-        // We have finished parsing this level, so go back up a level.
-        self.depth += 1;
-        res
+        }
     }
 
     /// The `(key,value)` pair is determined to be corresponding to an
@@ -505,49 +1072,51 @@ impl<'a> Parser<'a> {
     /// Can potentially be merged?
     fn parse_ord_seq_value(&mut self, key: usize, node: &mut Level<'a>) -> Result<()> {
         self.state = ParsingState::Key;
-        let res = loop {
+        loop {
             if let Some(x) = self.peek() {
                 match *x {
-                    b'=' => {
+                    b'=' if !self.decoded => {
                         // Key is finished, parse up until the '&' as the value
                         self.clear_acc();
                         self.state = ParsingState::Value;
                         for _ in self.take_while(|b| *b != &b'&') {}
-                        let value = self.collect_str()?;
+                        let value = Level::Flat(self.collect_value()?);
                         // Reached the end of the key string
-                        node.insert_ord_seq_value(key, value);
+                        self.report(node.insert_ord_seq_value(key, value, self.key_conflict));
                         break Ok(());
                     }
-                    b'&' => {
+                    b'&' if !self.decoded => {
                         // No value
-                        node.insert_ord_seq_value(key, Cow::Borrowed(""));
+                        self.report(node.insert_ord_seq_value(
+                            key,
+                            Level::NoValue,
+                            self.key_conflict,
+                        ));
                         break Ok(());
                     }
-                    b'[' => {
+                    c @ (b'[' | b'.') if c == b'[' || self.dots => {
                         // The key continues to another level of nested.
                         // Add a new unitialised level for this node and continue.
                         if let Level::Uninitialised = *node {
                             *node = Level::OrderedSeq(BTreeMap::default());
                         }
                         if let Level::OrderedSeq(ref mut map) = *node {
-                            // By parsing we drop down another level
-                            self.depth -= 1;
-                            let _ = self.parse(
-                                // Either take the existing entry, or add a new
-                                // unitialised level
-                                // Use this new node to keep parsing
+                            // Either take the existing entry, or add a new
+                            // unitialised level
+                            // Use this new node to keep parsing
+                            break self.parse_nested_under(
                                 map.entry(key).or_insert(Level::Uninitialised),
-                            )?;
-                            break Ok(());
+                            );
                         } else {
                             // We expected to parse into a seq here.
-                            break Err(super::Error::parse_err(
+                            break Err(Error::parse_err_in(
                                 format!(
                                     "tried to insert a \
                                      new key into {:?}",
                                     node
                                 ),
                                 self.index,
+                                self.inner,
                             ));
                         }
                     }
@@ -555,9 +1124,10 @@ impl<'a> Parser<'a> {
                         // Anything else is unexpected since we just finished
                         // parsing a key.
                         if self.strict {
-                            break Err(super::Error::parse_err(
+                            break Err(Error::parse_err_in(
                                 format!("Unexpected character: {:?} found when parsing", c),
                                 self.index,
+                                self.inner,
                             ));
                         } else {
                             let _ = self.next();
@@ -565,14 +1135,13 @@ impl<'a> Parser<'a> {
                     }
                 }
             } else {
-                // The string has ended, so the value is empty.
-                node.insert_ord_seq_value(key, Cow::Borrowed(""));
+                // The string has ended, so there is no value, unless one was
+                // given to `parse_pair`.
+                let value = self.end_value();
+                self.report(node.insert_ord_seq_value(key, value, self.key_conflict));
                 break Ok(());
             }
-        };
-        // We have finished parsing this level, so go back up a level.
-        self.depth += 1;
-        res
+        }
     }
 
     /// The `(key,value)` pair is determined to be corresponding to an
@@ -580,38 +1149,85 @@ impl<'a> Parser<'a> {
     /// This must be the final level of nesting, so assume we have a value
     fn parse_seq_value(&mut self, node: &mut Level<'a>) -> Result<()> {
         self.state = ParsingState::Key;
-        let res = match self.peek() {
+        match self.peek() {
             Some(x) => {
                 match *x {
-                    b'=' => {
+                    b'=' if !self.decoded => {
                         // Key is finished, parse up until the '&' as the value
                         self.clear_acc();
                         self.state = ParsingState::Value;
                         for _ in self.take_while(|b| *b != &b'&') {}
-                        let value = self.collect_str()?;
-                        node.insert_seq_value(value);
+                        let value = Level::Flat(self.collect_value()?);
+                        self.report(node.insert_seq_value(value, self.repeated_keys));
                         Ok(())
                     }
-                    b'&' => {
+                    b'&' if !self.decoded => {
                         // key value is empty
-                        node.insert_seq_value(Cow::Borrowed(""));
+                        self.report(node.insert_seq_value(Level::NoValue, self.repeated_keys));
                         Ok(())
                     }
-                    _ => Err(super::Error::parse_err(
-                        "non-indexed sequence of \
-                         structs not supported",
-                        self.index,
-                    )),
+                    _ => {
+                        // Suggest indexing the sequence, e.g. `a[0][]` for
+                        // `a[][]`, if the brackets are as given in the input.
+                        let (_, key) = self.entry_key();
+                        let example = match key.find("[]") {
+                            Some(i) => format!(", e.g. `{}[0]{}`", &key[..i], &key[i + 2..]),
+                            None => String::new(),
+                        };
+                        Err(Error::parse_err_in(
+                            format!(
+                                "non-indexed sequence of structs or sequences not supported, \
+                                 index the outer sequence instead{}",
+                                example
+                            ),
+                            self.index,
+                            self.inner,
+                        ))
+                    }
                 }
             }
             None => {
-                // The string has ended, so the value is empty.
-                node.insert_seq_value(Cow::Borrowed(""));
+                // The string has ended, so there is no value, unless one was
+                // given to `parse_pair`.
+                let value = self.end_value();
+                self.report(node.insert_seq_value(value, self.repeated_keys));
                 Ok(())
             }
+        }
+    }
+}
+
+/// Checks that the raw key starting at `offset` in the input contains no
+/// control characters, either as-is or percent-encoded.
+fn check_key_chars(raw: &[u8], offset: usize) -> Result<()> {
+    let mut i = 0;
+    while i < raw.len() {
+        let hex = |i: usize| raw.get(i).and_then(|&b| (b as char).to_digit(16));
+        let (byte, len) = match (raw[i], hex(i + 1), hex(i + 2)) {
+            (b'%', Some(high), Some(low)) => ((high * 16 + low) as u8, 3),
+            (byte, _, _) => (byte, 1),
         };
-        // We have finished parsing this level, so go back up a level.
-        self.depth += 1;
-        res
+        if byte.is_ascii_control() {
+            return Err(Error::InvalidKeyCharacter {
+                byte,
+                position: offset + i,
+            });
+        }
+        i += len;
+    }
+    Ok(())
+}
+
+/// Checks that every `%` in the raw string starting at `offset` in the input
+/// is followed by two hex digits.
+fn check_percent_encoding(raw: &[u8], offset: usize) -> Result<()> {
+    for (i, _) in raw.iter().enumerate().filter(|(_, &b)| b == b'%') {
+        let hex = |i: usize| raw.get(i).map_or(false, u8::is_ascii_hexdigit);
+        if !hex(i + 1) || !hex(i + 2) {
+            return Err(Error::InvalidPercentEncoding {
+                position: offset + i,
+            });
+        }
     }
+    Ok(())
 }
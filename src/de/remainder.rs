@@ -0,0 +1,221 @@
+//! Collecting the keys which a struct does not consume.
+
+use super::{Key, Level, Map, Options, Pairs, QsDeserializer};
+use crate::error::{Error, Result};
+
+use serde::de;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Deserializes a struct `T` along with all the keys which it has no field
+/// for.
+///
+/// This is an alternative to a `#[serde(flatten)]` map for collecting extra
+/// parameters, such as `utm_source`, alongside a typed struct. Leftover keys
+/// which are nested are rendered as bracketed keys, e.g. `utm[source]`, and
+/// sequence indices are numbered from 0 in order.
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # extern crate serde_qs;
+/// use serde_qs::Remainder;
+///
+/// #[derive(Debug, Deserialize, PartialEq)]
+/// struct Search {
+///     q: String,
+///     page: u32,
+/// }
+///
+/// # fn main() {
+/// let Remainder(search, rest) =
+///     serde_qs::from_str::<Remainder<Search>>("q=rust&page=2&utm_source=mail").unwrap();
+/// assert_eq!(search, Search { q: "rust".to_owned(), page: 2 });
+/// assert_eq!(rest["utm_source"], "mail");
+/// # }
+/// ```
+///
+/// Only the top-level fields of `T` are considered: a leftover key inside a
+/// field of `T`, such as `a[unknown]` for a struct field `a`, is ignored as
+/// usual. If `T` is not a struct, for example a map or a struct containing a
+/// `#[serde(flatten)]` field, every key is given to `T` and there are no
+/// leftovers.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Remainder<T>(pub T, pub HashMap<String, String>);
+
+impl<T> Remainder<T> {
+    /// Unwrap into the inner T value and the leftover keys
+    pub fn into_parts(self) -> (T, HashMap<String, String>) {
+        (self.0, self.1)
+    }
+}
+
+impl<'de, T: de::Deserialize<'de>> de::Deserialize<'de> for Remainder<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let mut map = deserializer.deserialize_map(MapVisitor)?;
+
+        let mut fields = None;
+        let _ = T::deserialize(FieldProbe(&mut fields));
+        let rest = match fields {
+            Some(fields) => {
+                let (consumed, rest) = map
+                    .into_iter()
+                    .partition(|(key, _)| fields.contains(&&*key.as_str()));
+                map = consumed;
+                rest
+            }
+            None => Map::default(),
+        };
+
+        let value = T::deserialize(QsDeserializer::with_map(map, Options::default()))
+            .map_err(de::Error::custom)?;
+        let rest = Pairs::new(&rest)
+            .map(|(path, value)| {
+                let value = value.map(Cow::into_owned).unwrap_or_default();
+                (path.to_string(), value)
+            })
+            .collect();
+        Ok(Remainder(value, rest))
+    }
+}
+
+/// Records the fields of a struct, without deserializing anything.
+struct FieldProbe<'a>(&'a mut Option<&'static [&'static str]>);
+
+impl<'de> de::Deserializer<'de> for FieldProbe<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        Err(de::Error::custom("expected a struct"))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        *self.0 = Some(fields);
+        Err(de::Error::custom("struct fields recorded"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// Rebuilds the parsed map from any map, so that it can be deserialized
+/// again.
+struct MapVisitor;
+
+impl<'de> de::Visitor<'de> for MapVisitor {
+    type Value = Map<'de>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        let mut entries = Map::default();
+        while let Some((key, value)) = map.next_entry_seed(KeyVisitor, LevelVisitor)? {
+            let _ = entries.insert(key, value);
+        }
+        Ok(entries)
+    }
+}
+
+struct KeyVisitor;
+
+impl<'de> de::Visitor<'de> for KeyVisitor {
+    type Value = Key<'de>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string key")
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<Self::Value, E> {
+        Ok(Key::Borrowed(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Key::Shared(v.into()))
+    }
+}
+
+impl<'de> de::DeserializeSeed<'de> for KeyVisitor {
+    type Value = Key<'de>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_str(self)
+    }
+}
+
+struct LevelVisitor;
+
+impl<'de> de::Visitor<'de> for LevelVisitor {
+    type Value = Level<'de>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a querystring value")
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<Self::Value, E> {
+        Ok(Level::Flat(Cow::Borrowed(v)))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Level::Flat(Cow::Owned(v.to_owned())))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+        Ok(Level::Flat(Cow::Owned(v)))
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        MapVisitor.visit_map(map).map(Level::Nested)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        let mut elements = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(element) = seq.next_element_seed(LevelVisitor)? {
+            elements.push(element);
+        }
+        Ok(Level::Sequence(elements))
+    }
+}
+
+impl<'de> de::DeserializeSeed<'de> for LevelVisitor {
+    type Value = Level<'de>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
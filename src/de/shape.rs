@@ -0,0 +1,67 @@
+//! Classifying a querystring by whether its keys are nested.
+
+use super::{Config, NestingStyle};
+
+/// Whether the keys of a querystring are nested, see [`classify`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueryShape {
+    /// No key is nested, e.g. `a=1&b=[2]`.
+    Flat,
+    /// At least one key is nested, e.g. `a[b]=1`.
+    Nested,
+    /// No key is nested, but a key contains an encoded bracket which the
+    /// config does not decode, e.g. `a%5Bb%5D=1`, so a client may have
+    /// meant it to be nested.
+    Ambiguous,
+}
+
+/// Classifies a querystring by whether its keys are nested when parsed
+/// with `config`.
+///
+/// Only the keys are scanned, up to the first nested key, without decoding
+/// anything or building the parsed map. This is much cheaper than parsing,
+/// so it can be used to send flat querystrings down a faster path.
+/// Brackets in values do not count.
+///
+/// Encoded brackets are nested keys when `config` decodes them, which is in
+/// non-strict mode or with
+/// [`Config::lenient_bracket_decoding`](crate::Config::lenient_bracket_decoding),
+/// and otherwise make the querystring [`QueryShape::Ambiguous`]. With a
+/// `max_depth` of zero, every querystring is flat.
+///
+/// ```
+/// use serde_qs::{classify, Config, QueryShape};
+///
+/// let config = Config::default();
+/// assert_eq!(classify("q=[rust]&page=2", &config), QueryShape::Flat);
+/// assert_eq!(classify("q=rust&filter[lang]=en", &config), QueryShape::Nested);
+/// assert_eq!(classify("filter%5Blang%5D=en", &config), QueryShape::Ambiguous);
+///
+/// let config = config.lenient_bracket_decoding(true);
+/// assert_eq!(classify("filter%5Blang%5D=en", &config), QueryShape::Nested);
+/// ```
+pub fn classify(input: &str, config: &Config) -> QueryShape {
+    if config.max_depth() == 0 {
+        return QueryShape::Flat;
+    }
+    let dots = config.nesting_style == NestingStyle::Dots;
+    let decode_brackets = !config.strict || config.lenient_bracket_decoding;
+    let mut shape = QueryShape::Flat;
+    for pair in input.as_bytes().split(|&b| b == b'&') {
+        let key = &pair[..pair.iter().position(|&b| b == b'=').unwrap_or(pair.len())];
+        for (i, &b) in key.iter().enumerate() {
+            match b {
+                b'[' => return QueryShape::Nested,
+                b'.' if dots => return QueryShape::Nested,
+                b'%' => match key.get(i + 1..i + 3) {
+                    // the parser only decodes upper case brackets
+                    Some(b"5B") if decode_brackets => return QueryShape::Nested,
+                    Some(b"5B") | Some(b"5b") => shape = QueryShape::Ambiguous,
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+    }
+    shape
+}
@@ -0,0 +1,144 @@
+//! Capturing the whole querystring alongside the value deserialized from it.
+
+use super::QsDeserializer;
+use crate::error::Error;
+
+use serde::de::{self, IntoDeserializer};
+
+use std::borrow::Cow;
+use std::fmt;
+use std::marker::PhantomData;
+use std::str;
+
+/// The name `QsDeserializer::deserialize_newtype_struct` recognises to hand
+/// over the input it was created from.
+pub(super) const NAME: &str = "$serde_qs::WholeInput";
+
+/// Deserializes a value `T` along with the whole querystring it was
+/// deserialized from.
+///
+/// This is useful when the exact input is needed as well as the typed value,
+/// such as for verifying the signature of a webhook request. The querystring
+/// is given exactly as it was passed in, before any trimming or
+/// [`Config::allow_leading_question_mark`](crate::Config::allow_leading_question_mark)
+/// handling.
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # extern crate serde_qs;
+/// use serde_qs::WholeInput;
+///
+/// #[derive(Debug, Deserialize, PartialEq)]
+/// struct Hook {
+///     event: String,
+///     signature: String,
+/// }
+///
+/// # fn main() {
+/// let input = "event=push&signature=abc";
+/// let WholeInput(hook, raw) = serde_qs::from_str::<WholeInput<Hook>>(input).unwrap();
+/// assert_eq!(hook.event, "push");
+/// assert_eq!(raw, input);
+/// # }
+/// ```
+///
+/// `WholeInput` must be the top-level type being deserialized, and so cannot
+/// be a field or be wrapped in a [`Remainder`](crate::Remainder). The input
+/// is not available when deserializing from
+/// [`Deserializer::from_pairs`](crate::Deserializer::from_pairs), and must be
+/// valid UTF-8.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WholeInput<T>(pub T, pub String);
+
+impl<T> WholeInput<T> {
+    /// Unwrap into the inner T value and the whole querystring
+    pub fn into_parts(self) -> (T, String) {
+        (self.0, self.1)
+    }
+}
+
+impl<'de, T: de::Deserialize<'de>> de::Deserialize<'de> for WholeInput<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(NAME, WholeInputVisitor(PhantomData))
+    }
+}
+
+struct WholeInputVisitor<T>(PhantomData<T>);
+
+impl<'de, T: de::Deserialize<'de>> de::Visitor<'de> for WholeInputVisitor<T> {
+    type Value = WholeInput<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a whole querystring, as the top-level type")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        let input = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let value = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(WholeInput(value, input))
+    }
+}
+
+/// The error for a `WholeInput` which is not the top-level type.
+pub(super) fn not_top_level() -> Error {
+    de::Error::custom("`WholeInput` is only supported as the top-level type")
+}
+
+/// Gives the input and then the value deserialized from it to
+/// `WholeInputVisitor`.
+pub(super) struct WholeInputAccess<'a> {
+    input: Option<Cow<'a, str>>,
+    deserializer: Option<QsDeserializer<'a>>,
+}
+
+impl<'a> WholeInputAccess<'a> {
+    pub(super) fn new(
+        input: Cow<'a, [u8]>,
+        deserializer: QsDeserializer<'a>,
+    ) -> Result<Self, Error> {
+        let input = match input {
+            Cow::Borrowed(input) => Cow::Borrowed(str::from_utf8(input)?),
+            Cow::Owned(input) => Cow::Owned(String::from_utf8(input)?),
+        };
+        Ok(WholeInputAccess {
+            input: Some(input),
+            deserializer: Some(deserializer),
+        })
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for WholeInputAccess<'de> {
+    type Error = Error;
+
+    fn next_element_seed<S>(&mut self, seed: S) -> Result<Option<S::Value>, Error>
+    where
+        S: de::DeserializeSeed<'de>,
+    {
+        match self.input.take() {
+            Some(Cow::Borrowed(input)) => {
+                return seed
+                    .deserialize(de::value::BorrowedStrDeserializer::new(input))
+                    .map(Some)
+            }
+            Some(Cow::Owned(input)) => {
+                return seed.deserialize(input.into_deserializer()).map(Some)
+            }
+            None => {}
+        }
+        match self.deserializer.take() {
+            Some(deserializer) => seed.deserialize(deserializer).map(Some),
+            None => Ok(None),
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use serde::de;
 
+use std::convert::Infallible;
+use std::fmt;
 use std::fmt::Display;
 use std::io;
 use std::num;
@@ -13,16 +15,27 @@ pub enum Error {
     #[error("{0}")]
     Custom(String),
 
-    /// Parse error at a specified position in the query string
-    #[error("parsing failed with error: '{0}' at position: {1}")]
-    Parse(String, usize),
+    /// Parse error at a specified position in the query string, along with
+    /// the input around that position (empty if unknown)
+    #[error("parsing failed with error: '{0}' at position: {1}{}", near(.2))]
+    Parse(String, usize, String),
 
     /// Unsupported type that `serde_qs` can't serialize into a query string
     #[error("unsupported type for serialization")]
     Unsupported,
 
+    /// Map key that `serde_qs` can't serialize into a query string
+    #[error(
+        "unsupported map key: {kind}; map keys must serialize to a string, number, bool or unit variant"
+    )]
+    UnsupportedAsKey {
+        /// The kind of value the key serialized as, e.g. `"struct"`,
+        /// `"sequence"` or `"none"`
+        kind: &'static str,
+    },
+
     /// Error proessing UTF-8 for a `String`
-    #[error(transparent)]
+    #[error("{0}")]
     FromUtf8(#[from] string::FromUtf8Error),
 
     /// I/O error
@@ -30,12 +43,178 @@ pub enum Error {
     Io(#[from] io::Error),
 
     /// Error parsing a number
-    #[error(transparent)]
+    #[error("{0}")]
     ParseInt(#[from] num::ParseIntError),
 
     /// Error processing UTF-8 for a `str`
-    #[error(transparent)]
+    #[error("{0}")]
     Utf8(#[from] str::Utf8Error),
+
+    /// Integer key exceeding the configured maximum sequence index
+    #[error("sequence index {index} exceeds the maximum of {max}")]
+    SequenceIndexTooLarge {
+        /// The index, as given in the input
+        index: String,
+        /// The maximum allowed index
+        max: usize,
+    },
+
+    /// Key nested deeper than the configured `max_depth`, when serializing
+    #[error("key `{key}` is nested deeper than the maximum depth of {max_depth}")]
+    MaxDepthExceeded {
+        /// The key, as it would have been written
+        key: String,
+        /// The maximum allowed number of bracketed parts in a key
+        max_depth: usize,
+    },
+
+    /// Key segment containing a `.` or a bracket, which would be ambiguous
+    /// when serializing with
+    /// [`Config::serialize_flat_dotted`](crate::Config::serialize_flat_dotted)
+    #[error(
+        "key `{segment}`{} contains a `.` or a bracket, which is ambiguous in a flat dotted key",
+        under(.key)
+    )]
+    AmbiguousKeySegment {
+        /// The key the segment is nested under, as it would have been
+        /// written, or empty at the top level
+        key: String,
+        /// The segment
+        segment: String,
+    },
+
+    /// Value nested more than `limit` times when serializing, such as a
+    /// deeply recursive type
+    #[error("value is nested more than {limit} times")]
+    RecursionLimitExceeded {
+        /// The maximum number of nested values
+        limit: usize,
+    },
+
+    /// Key containing a control character, raw or percent-encoded, when
+    /// [`Config::reject_control_chars`](crate::Config::reject_control_chars)
+    /// is enabled
+    #[error("invalid character {byte:#04x} in key at position {position}")]
+    InvalidKeyCharacter {
+        /// The decoded character
+        byte: u8,
+        /// The offset of the character, or of the `%` encoding it, in the input
+        position: usize,
+    },
+
+    /// A `%` which is not followed by two hex digits, when
+    /// [`Config::strict_percent_decoding`](crate::Config::strict_percent_decoding)
+    /// is enabled
+    #[error("invalid percent-encoding at position {position}")]
+    InvalidPercentEncoding {
+        /// The offset of the `%` in the input
+        position: usize,
+    },
+
+    /// Error for a key containing an encoded bracket, which may have been
+    /// intended as a nested key
+    #[error("{error}\nInvalid field contains an encoded bracket -- {suggestion}")]
+    EncodedBracketInKey {
+        /// The key containing the encoded bracket
+        key: String,
+        /// The underlying error
+        #[source]
+        error: Box<Error>,
+        /// How the error might be fixed
+        suggestion: Suggestion,
+    },
+
+    /// Error converting a key or value to UTF-8 with a transcoder, see
+    /// [`Config::value_transcoder`](crate::Config::value_transcoder)
+    #[error("could not transcode `{key}`: {error}")]
+    Transcode {
+        /// The key of the entry, as given in the input
+        key: String,
+        /// The error from the transcoder
+        #[source]
+        error: TranscodeError,
+    },
+
+    /// Error validating a deserialized value, such as the
+    /// `validator::ValidationErrors` from the `ValidatedQsQuery` extractors
+    /// of the `validator` feature, which can be recovered with
+    /// `downcast_ref`
+    #[error("validation failed: {0}")]
+    Validation(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    /// Error deserializing a line of input, from
+    /// [`Config::deserialize_lines`](crate::Config::deserialize_lines)
+    #[error("line {line}: {source}")]
+    AtLine {
+        /// The line number, counting from one
+        line: usize,
+        /// The error deserializing the line
+        source: Box<Error>,
+    },
+
+    /// More than one key naming a variant of an externally tagged enum
+    #[error("expected a single enum variant, found {}", .found.join(", "))]
+    MultipleVariants {
+        /// The variants found, in the order they are declared
+        found: Vec<String>,
+    },
+
+    /// Error deserializing the value of a key
+    #[error("{}{error}", value_of(.key))]
+    InvalidValue {
+        /// The key path of the value, e.g. `user[ids][0]`, or empty if the
+        /// value is not under a key
+        key: String,
+        /// The underlying error
+        #[source]
+        error: Box<Error>,
+    },
+}
+
+/// Error returned by a transcoder, see
+/// [`Config::value_transcoder`](crate::Config::value_transcoder).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TranscodeError(String);
+
+impl TranscodeError {
+    /// Creates an error with the given message.
+    pub fn new(msg: impl Display) -> Self {
+        TranscodeError(msg.to_string())
+    }
+}
+
+impl Display for TranscodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for TranscodeError {}
+
+/// A suggestion for how to fix an [`Error`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Suggestion {
+    /// Use non-strict mode, so that encoded brackets denote nested keys.
+    UseNonStrictMode,
+    /// Use [`Config::lenient_bracket_decoding`](crate::Config::lenient_bracket_decoding),
+    /// so that encoded brackets denote nested keys.
+    UseLenientBracketDecoding,
+}
+
+impl Display for Suggestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Suggestion::UseNonStrictMode => f.write_str(
+                "did you mean to use non-strict mode?\n  \
+                 https://docs.rs/serde_qs/latest/serde_qs/#strict-vs-non-strict-modes",
+            ),
+            Suggestion::UseLenientBracketDecoding => f.write_str(
+                "did you mean to use `Config::lenient_bracket_decoding`?\n  \
+                 https://docs.rs/serde_qs/latest/serde_qs/#square-brackets",
+            ),
+        }
+    }
 }
 
 impl Error {
@@ -48,12 +227,154 @@ impl Error {
         ))
     }
 
+    /// Generate error to show top-level type cannot be deserialized, with an
+    /// example of a field to wrap it in and the shape the input parsed as.
+    pub(crate) fn top_level_shape(object: &str, field: &str, shape: &str) -> Self {
+        Error::Custom(format!(
+            "cannot deserialize {} at the top level: wrap it in a struct or map, \
+             e.g. `struct Q {{ {} }}`; input parsed as {}",
+            object, field, shape
+        ))
+    }
+
+    /// Wrap an error for a key containing an encoded bracket.
+    pub(crate) fn encoded_bracket(key: impl Into<String>, error: Error) -> Self {
+        Error::EncodedBracketInKey {
+            key: key.into(),
+            error: Box::new(error),
+            suggestion: Suggestion::UseLenientBracketDecoding,
+        }
+    }
+
+    /// Wrap an error for the value being deserialized, before its key is
+    /// known. The key is filled in by [`Error::invalid_value`].
+    pub(crate) fn unkeyed_value(error: Error) -> Self {
+        Error::InvalidValue {
+            key: String::new(),
+            error: Box::new(error),
+        }
+    }
+
+    /// Wrap an error for the value of `key`. If the error is already for a
+    /// nested value, `key` is prepended to its key path instead.
+    pub(crate) fn invalid_value(key: &str, error: Error) -> Self {
+        match error {
+            Error::InvalidValue { key: path, error } if path.is_empty() => Error::InvalidValue {
+                key: key.to_owned(),
+                error,
+            },
+            Error::InvalidValue { key: path, error } => {
+                let path = match path.find('[') {
+                    Some(i) => format!("{}[{}]{}", key, &path[..i], &path[i..]),
+                    None => format!("{}[{}]", key, path),
+                };
+                Error::InvalidValue { key: path, error }
+            }
+            error => Error::InvalidValue {
+                key: key.to_owned(),
+                error: Box::new(error),
+            },
+        }
+    }
+
+    /// Copies an error stored in a parsed map. These are built from
+    /// messages, so any other error is copied as its message.
+    pub(crate) fn duplicate(&self) -> Self {
+        match self {
+            Error::Custom(msg) => Error::Custom(msg.clone()),
+            Error::EncodedBracketInKey {
+                key,
+                error,
+                suggestion,
+            } => Error::EncodedBracketInKey {
+                key: key.clone(),
+                error: Box::new(error.duplicate()),
+                suggestion: *suggestion,
+            },
+            error => Error::Custom(error.to_string()),
+        }
+    }
+
     /// Generate a parsing error message with position.
     pub fn parse_err<T>(msg: T, position: usize) -> Self
     where
         T: Display,
     {
-        Error::Parse(msg.to_string(), position)
+        Error::Parse(msg.to_string(), position, String::new())
+    }
+
+    /// Generate a parsing error message with position, showing the input
+    /// around the byte before `position`.
+    pub(crate) fn parse_err_in<T>(msg: T, position: usize, input: &[u8]) -> Self
+    where
+        T: Display,
+    {
+        let context = parse_context(input, position.saturating_sub(1));
+        Error::Parse(msg.to_string(), position, context)
+    }
+}
+
+/// How many bytes of input to show either side of a parse error.
+const CONTEXT_WIDTH: usize = 20;
+
+/// Renders the input around `index`, with an arrow before the byte at
+/// `index` and unprintable bytes percent-encoded, e.g.
+/// `...user_ids[2]=3&us→er_ids[3]=x4...`.
+fn parse_context(input: &[u8], index: usize) -> String {
+    let index = index.min(input.len());
+    let start = index.saturating_sub(CONTEXT_WIDTH);
+    let end = input.len().min(index + CONTEXT_WIDTH);
+
+    let mut context = String::new();
+    if start > 0 {
+        context.push_str("...");
+    }
+    for (i, &b) in input[start..end].iter().enumerate() {
+        if start + i == index {
+            context.push('→');
+        }
+        if b.is_ascii_graphic() || b == b' ' {
+            context.push(b as char);
+        } else {
+            context.push_str(&format!("%{:02X}", b));
+        }
+    }
+    if end == index {
+        context.push('→');
+    }
+    if end < input.len() {
+        context.push_str("...");
+    }
+    context
+}
+
+fn near(context: &str) -> String {
+    if context.is_empty() {
+        String::new()
+    } else {
+        format!(", near `{}`", context)
+    }
+}
+
+fn under(key: &str) -> String {
+    if key.is_empty() {
+        String::new()
+    } else {
+        format!(" under `{}`", key)
+    }
+}
+
+fn value_of(key: &str) -> String {
+    if key.is_empty() {
+        String::new()
+    } else {
+        format!("invalid value for `{}`: ", key)
+    }
+}
+
+impl From<Infallible> for Error {
+    fn from(error: Infallible) -> Self {
+        match error {}
     }
 }
 
@@ -66,4 +387,15 @@ impl de::Error for Error {
     }
 }
 
+impl From<Error> for io::Error {
+    /// Unwraps an [`Error::Io`], and otherwise wraps the error as
+    /// `InvalidData`, keeping it as the source.
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Io(error) => error,
+            error => io::Error::new(io::ErrorKind::InvalidData, error),
+        }
+    }
+}
+
 pub type Result<T, E = Error> = core::result::Result<T, E>;
@@ -0,0 +1,946 @@
+//! Helpers for de/serializing delimited values, such as `a=1,2,3`, prefixed
+//! keys, such as `meta_a=1&meta_b=2`, and durations, such as `timeout=30s`.
+//!
+//! ## Delimited values
+//!
+//! Querystrings usually represent sequences using repeated or indexed keys
+//! (`a[0]=1&a[1]=2`), but many APIs (for example the OpenAPI `form` style
+//! with `explode=false`) instead pack all the values into a single delimited
+//! string. The modules here can be used with `#[serde(with = "...")]` to
+//! handle such fields.
+//!
+//! The value is split on the delimiter and each element is deserialized
+//! individually, so any type which deserializes from a sequence is supported:
+//! `Vec<T>`, tuples, fixed-size arrays, and structs of scalar fields (which
+//! are read positionally).
+//!
+//! ```
+//! # #[macro_use]
+//! # extern crate serde_derive;
+//! # extern crate serde_qs;
+//! #[derive(Debug, Deserialize, PartialEq, Serialize)]
+//! struct Query {
+//!     #[serde(with = "serde_qs::helpers::comma_separated")]
+//!     ids: Vec<u32>,
+//!     #[serde(with = "serde_qs::helpers::comma_separated")]
+//!     range: (u32, u32),
+//!     #[serde(with = "serde_qs::helpers::pipe_delimited")]
+//!     bbox: [f64; 4],
+//! }
+//!
+//! # fn main() {
+//! let q = Query {
+//!     ids: vec![1, 2, 3],
+//!     range: (1, 100),
+//!     bbox: [1.0, 2.0, 3.0, 4.5],
+//! };
+//! let s = "ids=1,2,3&range=1,100&bbox=1|2|3|4.5";
+//! assert_eq!(serde_qs::from_str::<Query>(s).unwrap(), q);
+//! assert_eq!(serde_qs::from_str::<Query>(&serde_qs::to_string(&q).unwrap()).unwrap(), q);
+//! # }
+//! ```
+//!
+//! Individual elements are not escaped, so serializing an element which
+//! contains the delimiter is an error.
+//!
+//! ## Prefixed keys
+//!
+//! See [`prefixed_map`].
+//!
+//! ## Durations
+//!
+//! See [`duration`] and [`duration_secs`].
+
+use crate::de::ParsableStringDeserializer;
+use crate::error::*;
+use crate::ser::StringSerializer;
+
+use serde::{de, ser};
+
+use std::borrow::Cow;
+use std::fmt;
+use std::time::Duration;
+
+/// De/serialize a value as a string of elements separated by `DELIM`.
+///
+/// Use with `#[serde(serialize_with = "...", deserialize_with = "...")]`:
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # extern crate serde_qs;
+/// use serde_qs::helpers::generic_delimiter;
+///
+/// #[derive(Debug, Deserialize, PartialEq, Serialize)]
+/// struct Query {
+///     #[serde(
+///         serialize_with = "generic_delimiter::serialize::<_, _, ';'>",
+///         deserialize_with = "generic_delimiter::deserialize::<_, _, ';'>"
+///     )]
+///     point: (i32, i32),
+/// }
+///
+/// # fn main() {
+/// let q: Query = serde_qs::from_str("point=-1;2").unwrap();
+/// assert_eq!(q, Query { point: (-1, 2) });
+/// # }
+/// ```
+pub mod generic_delimiter {
+    use super::*;
+
+    /// Serializes a sequence, tuple or struct of scalars as a single string
+    /// with the elements separated by `DELIM`.
+    pub fn serialize<S, T, const DELIM: char>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+        T: ?Sized + ser::Serialize,
+    {
+        let joined = value
+            .serialize(DelimitedSerializer { delimiter: DELIM })
+            .map_err(ser::Error::custom)?;
+        serializer.serialize_str(&joined)
+    }
+
+    /// Deserializes a string of elements separated by `DELIM` into a
+    /// sequence, tuple or struct of scalars.
+    ///
+    /// Fixed-length targets (tuples, arrays and structs) return an error if
+    /// the number of elements does not match.
+    pub fn deserialize<'de, D, T, const DELIM: char>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: de::Deserializer<'de>,
+        T: de::Deserialize<'de>,
+    {
+        let value = deserializer.deserialize_str(CowStrVisitor)?;
+        T::deserialize(DelimitedDeserializer::new(value, DELIM)).map_err(de::Error::custom)
+    }
+}
+
+macro_rules! delimiter_module {
+    ($(#[$attr:meta])* $name:ident, $delim:expr) => {
+        $(#[$attr])*
+        pub mod $name {
+            use super::*;
+
+            /// Serializes a sequence, tuple or struct of scalars as a
+            /// single delimited string.
+            pub fn serialize<S, T>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: ser::Serializer,
+                T: ?Sized + ser::Serialize,
+            {
+                generic_delimiter::serialize::<S, T, $delim>(value, serializer)
+            }
+
+            /// Deserializes a delimited string into a sequence, tuple or
+            /// struct of scalars.
+            pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+            where
+                D: de::Deserializer<'de>,
+                T: de::Deserialize<'de>,
+            {
+                generic_delimiter::deserialize::<D, T, $delim>(deserializer)
+            }
+        }
+    };
+}
+
+delimiter_module!(
+    /// De/serialize a value as comma-separated elements, e.g. `a=1,2,3`.
+    comma_separated,
+    ','
+);
+delimiter_module!(
+    /// De/serialize a value as pipe-delimited elements, e.g. `a=1|2|3`.
+    pipe_delimited,
+    '|'
+);
+delimiter_module!(
+    /// De/serialize a value as space-delimited elements, e.g. `a=1+2+3`.
+    space_delimited,
+    ' '
+);
+
+/// A prefix for the keys of a [`prefixed_map`].
+pub trait Prefix {
+    /// The prefix itself, e.g. `"meta_"`.
+    const PREFIX: &'static str;
+}
+
+/// De/serialize a map or struct as the top-level keys starting with a
+/// [`Prefix`], with the prefix removed.
+///
+/// This is intended for `#[serde(flatten)]` fields, where the remaining keys
+/// would otherwise be collected as untyped strings. Use with
+/// `#[serde(flatten, serialize_with = "...", deserialize_with = "...")]`:
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # extern crate serde_qs;
+/// use serde_qs::helpers::{prefixed_map, Prefix};
+/// use std::collections::BTreeMap;
+///
+/// struct Meta;
+///
+/// impl Prefix for Meta {
+///     const PREFIX: &'static str = "meta_";
+/// }
+///
+/// #[derive(Debug, Deserialize, PartialEq, Serialize)]
+/// struct Query {
+///     id: u32,
+///     #[serde(
+///         flatten,
+///         serialize_with = "prefixed_map::serialize::<_, _, Meta>",
+///         deserialize_with = "prefixed_map::deserialize::<_, _, Meta>"
+///     )]
+///     meta: BTreeMap<String, u32>,
+/// }
+///
+/// # fn main() {
+/// let q: Query = serde_qs::from_str("id=1&meta_a=2&meta_b=3&other=4").unwrap();
+/// assert_eq!(q.meta["a"], 2);
+/// assert_eq!(serde_qs::to_string(&q).unwrap(), "id=1&meta_a=2&meta_b=3");
+/// # }
+/// ```
+///
+/// The prefixed values must be flat, i.e. `meta_a[b]=1` is an error. Fields
+/// of the containing struct take precedence, so a field named `meta_count`
+/// is not also collected into `meta`. Keys without the prefix are ignored.
+pub mod prefixed_map {
+    use super::*;
+
+    /// Serializes a map or struct with `P::PREFIX` prepended to each key.
+    pub fn serialize<S, T, P>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+        T: ?Sized + ser::Serialize,
+        P: Prefix,
+    {
+        value.serialize(PrefixedSerializer {
+            prefix: P::PREFIX,
+            inner: serializer,
+        })
+    }
+
+    /// Deserializes a map or struct from the keys starting with `P::PREFIX`,
+    /// with the prefix removed.
+    pub fn deserialize<'de, D, T, P>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: de::Deserializer<'de>,
+        T: de::Deserialize<'de>,
+        P: Prefix,
+    {
+        let entries = deserializer.deserialize_map(PrefixedVisitor { prefix: P::PREFIX })?;
+        let entries = entries
+            .into_iter()
+            .map(|(k, v)| (ParsableStringDeserializer(k), ParsableStringDeserializer(v)));
+        T::deserialize(de::value::MapDeserializer::new(entries)).map_err(de::Error::custom)
+    }
+}
+
+/// De/serialize a [`Duration`] as a number with a unit suffix, e.g.
+/// `timeout=30s` or `ttl=1500ms`.
+///
+/// The supported suffixes are `ms`, `s`, `m` and `h`. A number without a
+/// suffix is read as seconds, and fractions such as `1.5s` are accepted.
+/// Durations are serialized using the largest unit which represents them
+/// exactly, so 90 seconds is written as `90s` and 2 minutes as `2m`.
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # extern crate serde_qs;
+/// use std::time::Duration;
+///
+/// #[derive(Debug, Deserialize, PartialEq, Serialize)]
+/// struct Query {
+///     #[serde(with = "serde_qs::helpers::duration")]
+///     timeout: Duration,
+///     #[serde(with = "serde_qs::helpers::duration")]
+///     ttl: Duration,
+/// }
+///
+/// # fn main() {
+/// let q: Query = serde_qs::from_str("timeout=1.5s&ttl=120").unwrap();
+/// assert_eq!(q.timeout, Duration::from_millis(1500));
+/// assert_eq!(serde_qs::to_string(&q).unwrap(), "timeout=1500ms&ttl=2m");
+/// # }
+/// ```
+pub mod duration {
+    use super::*;
+
+    /// Serializes a `Duration` in the largest exact unit.
+    pub fn serialize<S>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        serializer.serialize_str(&format_duration(*value))
+    }
+
+    /// Deserializes a `Duration` from a number with an optional unit suffix.
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let value = deserializer.deserialize_str(CowStrVisitor)?;
+        parse_duration(&value).map_err(de::Error::custom)
+    }
+}
+
+/// De/serialize a [`Duration`] as a whole number of seconds, e.g.
+/// `timeout=30`.
+///
+/// Serializing a duration with a fractional number of seconds is an error.
+pub mod duration_secs {
+    use super::*;
+
+    /// Serializes a `Duration` as an integer number of seconds.
+    pub fn serialize<S>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        if value.subsec_nanos() != 0 {
+            return Err(ser::Error::custom(format!(
+                "duration {:?} is not a whole number of seconds",
+                value
+            )));
+        }
+        serializer.serialize_u64(value.as_secs())
+    }
+
+    /// Deserializes a `Duration` from an integer number of seconds.
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        <u64 as de::Deserialize>::deserialize(deserializer).map(Duration::from_secs)
+    }
+}
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// The units of a [`duration`], largest first.
+const DURATION_UNITS: [(&str, u128); 4] = [
+    ("h", 3600 * NANOS_PER_SEC),
+    ("m", 60 * NANOS_PER_SEC),
+    ("s", NANOS_PER_SEC),
+    ("ms", NANOS_PER_SEC / 1000),
+];
+
+fn parse_duration(input: &str) -> std::result::Result<Duration, String> {
+    let invalid = || {
+        format!(
+            "invalid duration `{}`, expected a number followed by `ms`, `s`, `m` or `h`",
+            input
+        )
+    };
+    if input.starts_with('-') {
+        return Err(format!(
+            "invalid duration `{}`, must not be negative",
+            input
+        ));
+    }
+    let (number, unit) = DURATION_UNITS
+        .iter()
+        .rev()
+        .find_map(|&(suffix, unit)| input.strip_suffix(suffix).map(|n| (n, unit)))
+        .unwrap_or((input, NANOS_PER_SEC));
+    let (whole, fraction) = match number.split_once('.') {
+        Some((whole, fraction)) => (whole, fraction),
+        None => (number, ""),
+    };
+    let mut digits = whole.bytes().chain(fraction.bytes());
+    if whole.is_empty() || number.ends_with('.') || !digits.all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    let too_large = || format!("invalid duration `{}`, too large", input);
+    let whole: u64 = whole.parse().map_err(|_| too_large())?;
+    // Digits beyond the 18th are below a nanosecond for every unit.
+    let fraction = &fraction[..fraction.len().min(18)];
+    let fraction_nanos = match fraction.parse::<u128>() {
+        Ok(digits) => digits * unit / 10u128.pow(fraction.len() as u32),
+        Err(_) => 0,
+    };
+    let nanos = whole as u128 * unit + fraction_nanos;
+    let secs = nanos / NANOS_PER_SEC;
+    if secs > u64::MAX as u128 {
+        return Err(too_large());
+    }
+    Ok(Duration::new(secs as u64, (nanos % NANOS_PER_SEC) as u32))
+}
+
+fn format_duration(value: Duration) -> String {
+    let nanos = value.as_nanos();
+    if nanos == 0 {
+        return "0s".to_owned();
+    }
+    for &(suffix, unit) in DURATION_UNITS.iter() {
+        if nanos % unit == 0 {
+            return format!("{}{}", nanos / unit, suffix);
+        }
+    }
+    let fraction = format!("{:09}", value.subsec_nanos());
+    format!("{}.{}s", value.as_secs(), fraction.trim_end_matches('0'))
+}
+
+/// Collects the entries whose keys start with `prefix`, removing it.
+struct PrefixedVisitor {
+    prefix: &'static str,
+}
+
+impl<'de> de::Visitor<'de> for PrefixedVisitor {
+    type Value = Vec<(Cow<'de, str>, Cow<'de, str>)>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a map with keys prefixed by {:?}", self.prefix)
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        let mut entries = Vec::new();
+        while let Some(key) = map.next_key_seed(CowStrVisitor)? {
+            let key = match key {
+                Cow::Borrowed(key) => key.strip_prefix(self.prefix).map(Cow::Borrowed),
+                Cow::Owned(key) => key
+                    .strip_prefix(self.prefix)
+                    .map(|key| Cow::Owned(key.to_owned())),
+            };
+            match key {
+                Some(key) => entries.push((key, map.next_value_seed(CowStrVisitor)?)),
+                None => {
+                    let _ = map.next_value::<de::IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Serializes a map or struct with a prefix prepended to each key.
+struct PrefixedSerializer<S> {
+    prefix: &'static str,
+    inner: S,
+}
+
+macro_rules! unsupported_prefixed {
+    ($($meth:ident($($arg:ident: $ty:ty),*) -> $ret:ty,)*) => {
+        $(
+            /// Returns an error.
+            fn $meth(self, $(_: $ty),*) -> Result<$ret, S::Error> {
+                Err(ser::Error::custom("a prefixed map must be a map or struct"))
+            }
+        )*
+    };
+}
+
+impl<S: ser::Serializer> ser::Serializer for PrefixedSerializer<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = ser::Impossible<S::Ok, S::Error>;
+    type SerializeTuple = ser::Impossible<S::Ok, S::Error>;
+    type SerializeTupleStruct = ser::Impossible<S::Ok, S::Error>;
+    type SerializeTupleVariant = ser::Impossible<S::Ok, S::Error>;
+    type SerializeMap = PrefixedMap<S::SerializeMap>;
+    type SerializeStruct = PrefixedMap<S::SerializeMap>;
+    type SerializeStructVariant = ser::Impossible<S::Ok, S::Error>;
+
+    unsupported_prefixed! {
+        serialize_bool(v: bool) -> S::Ok,
+        serialize_i8(v: i8) -> S::Ok,
+        serialize_i16(v: i16) -> S::Ok,
+        serialize_i32(v: i32) -> S::Ok,
+        serialize_i64(v: i64) -> S::Ok,
+        serialize_i128(v: i128) -> S::Ok,
+        serialize_u8(v: u8) -> S::Ok,
+        serialize_u16(v: u16) -> S::Ok,
+        serialize_u32(v: u32) -> S::Ok,
+        serialize_u64(v: u64) -> S::Ok,
+        serialize_u128(v: u128) -> S::Ok,
+        serialize_f32(v: f32) -> S::Ok,
+        serialize_f64(v: f64) -> S::Ok,
+        serialize_char(v: char) -> S::Ok,
+        serialize_str(v: &str) -> S::Ok,
+        serialize_bytes(v: &[u8]) -> S::Ok,
+        serialize_unit() -> S::Ok,
+        serialize_unit_struct(name: &'static str) -> S::Ok,
+        serialize_unit_variant(name: &'static str, index: u32, variant: &'static str) -> S::Ok,
+        serialize_seq(len: Option<usize>) -> Self::SerializeSeq,
+        serialize_tuple(len: usize) -> Self::SerializeTuple,
+        serialize_tuple_struct(name: &'static str, len: usize) -> Self::SerializeTupleStruct,
+        serialize_tuple_variant(
+            name: &'static str,
+            index: u32,
+            variant: &'static str,
+            len: usize
+        ) -> Self::SerializeTupleVariant,
+        serialize_struct_variant(
+            name: &'static str,
+            index: u32,
+            variant: &'static str,
+            len: usize
+        ) -> Self::SerializeStructVariant,
+    }
+
+    /// Returns an error.
+    fn serialize_newtype_variant<T: ?Sized + ser::Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        Err(ser::Error::custom("a prefixed map must be a map or struct"))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + ser::Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        value.serialize(self)
+    }
+
+    /// `None` has no keys.
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        ser::SerializeMap::end(self.inner.serialize_map(Some(0))?)
+    }
+
+    fn serialize_some<T: ?Sized + ser::Serialize>(self, value: &T) -> Result<S::Ok, S::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        Ok(PrefixedMap {
+            prefix: self.prefix,
+            inner: self.inner.serialize_map(len)?,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, S::Error> {
+        self.serialize_map(Some(len))
+    }
+}
+
+struct PrefixedMap<M> {
+    prefix: &'static str,
+    inner: M,
+}
+
+impl<M: ser::SerializeMap> PrefixedMap<M> {
+    fn prefixed<T: ?Sized + ser::Serialize>(&self, key: &T) -> Result<String, M::Error> {
+        let key = key
+            .serialize(StringSerializer)
+            .map_err(ser::Error::custom)?;
+        Ok(format!("{}{}", self.prefix, key))
+    }
+}
+
+impl<M: ser::SerializeMap> ser::SerializeMap for PrefixedMap<M> {
+    type Ok = M::Ok;
+    type Error = M::Error;
+
+    fn serialize_key<T: ?Sized + ser::Serialize>(&mut self, key: &T) -> Result<(), M::Error> {
+        let key = self.prefixed(key)?;
+        self.inner.serialize_key(&key)
+    }
+
+    fn serialize_value<T: ?Sized + ser::Serialize>(&mut self, value: &T) -> Result<(), M::Error> {
+        self.inner.serialize_value(value)
+    }
+
+    fn end(self) -> Result<M::Ok, M::Error> {
+        self.inner.end()
+    }
+}
+
+impl<M: ser::SerializeMap> ser::SerializeStruct for PrefixedMap<M> {
+    type Ok = M::Ok;
+    type Error = M::Error;
+
+    fn serialize_field<T: ?Sized + ser::Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), M::Error> {
+        let key = self.prefixed(key)?;
+        self.inner.serialize_entry(&key, value)
+    }
+
+    fn end(self) -> Result<M::Ok, M::Error> {
+        self.inner.end()
+    }
+}
+
+/// Accepts a string, borrowing from the input where possible.
+struct CowStrVisitor;
+
+impl<'de> de::Visitor<'de> for CowStrVisitor {
+    type Value = Cow<'de, str>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string")
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<Self::Value, E> {
+        Ok(Cow::Borrowed(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Cow::Owned(v.to_owned()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+        Ok(Cow::Owned(v))
+    }
+}
+
+impl<'de> de::DeserializeSeed<'de> for CowStrVisitor {
+    type Value = Cow<'de, str>;
+
+    fn deserialize<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+/// Deserializes the elements of a delimited string as a sequence.
+struct DelimitedDeserializer<'a> {
+    parts: Vec<Cow<'a, str>>,
+}
+
+impl<'a> DelimitedDeserializer<'a> {
+    fn new(value: Cow<'a, str>, delimiter: char) -> Self {
+        let parts = match value {
+            // An empty string has no elements, rather than a single empty one.
+            ref v if v.is_empty() => Vec::new(),
+            Cow::Borrowed(v) => v.split(delimiter).map(Cow::Borrowed).collect(),
+            Cow::Owned(v) => v
+                .split(delimiter)
+                .map(|part| Cow::Owned(part.to_owned()))
+                .collect(),
+        };
+        DelimitedDeserializer { parts }
+    }
+
+    fn check_len(&self, expected: usize) -> Result<()> {
+        if self.parts.len() == expected {
+            Ok(())
+        } else {
+            Err(de::Error::custom(format!(
+                "expected {} delimited elements, found {}",
+                expected,
+                self.parts.len()
+            )))
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for DelimitedDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_seq(DelimitedSeq(self.parts.into_iter()))
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.check_len(len)?;
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool u8 u16 u32 u64 u128 i8 i16 i32 i64 i128 f32 f64 char str string bytes
+        byte_buf option unit unit_struct seq map enum identifier ignored_any
+    }
+}
+
+struct DelimitedSeq<'a>(std::vec::IntoIter<Cow<'a, str>>);
+
+impl<'de> de::SeqAccess<'de> for DelimitedSeq<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        match self.0.next() {
+            Some(part) => seed.deserialize(ParsableStringDeserializer(part)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+/// Serializes a sequence-like value into a single delimited string.
+struct DelimitedSerializer {
+    delimiter: char,
+}
+
+macro_rules! serialize_as_element {
+    ($($ty:ty => $meth:ident,)*) => {
+        $(
+            fn $meth(self, v: $ty) -> Result<Self::Ok> {
+                ser::Serializer::$meth(StringSerializer, v)
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for DelimitedSerializer {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = DelimitedElements;
+    type SerializeTuple = DelimitedElements;
+    type SerializeTupleStruct = DelimitedElements;
+    type SerializeTupleVariant = ser::Impossible<String, Error>;
+    type SerializeMap = ser::Impossible<String, Error>;
+    type SerializeStruct = DelimitedElements;
+    type SerializeStructVariant = ser::Impossible<String, Error>;
+
+    serialize_as_element! {
+        bool => serialize_bool,
+        u8  => serialize_u8,
+        u16 => serialize_u16,
+        u32 => serialize_u32,
+        u64 => serialize_u64,
+        u128 => serialize_u128,
+        i8  => serialize_i8,
+        i16 => serialize_i16,
+        i32 => serialize_i32,
+        i64 => serialize_i64,
+        i128 => serialize_i128,
+        f32 => serialize_f32,
+        f64 => serialize_f64,
+        char => serialize_char,
+        &str => serialize_str,
+        &[u8] => serialize_bytes,
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok> {
+        Ok(String::new())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> {
+        Ok(String::new())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok> {
+        ser::Serializer::serialize_unit_variant(StringSerializer, name, variant_index, variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + ser::Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok> {
+        value.serialize(self)
+    }
+
+    /// Returns an error.
+    fn serialize_newtype_variant<T: ?Sized + ser::Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok> {
+        Ok(String::new())
+    }
+
+    fn serialize_some<T: ?Sized + ser::Serialize>(self, value: &T) -> Result<Self::Ok> {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Ok(DelimitedElements::new(self.delimiter, len.unwrap_or(0)))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        Ok(DelimitedElements::new(self.delimiter, len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Ok(DelimitedElements::new(self.delimiter, len))
+    }
+
+    /// Returns an error.
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(Error::Unsupported)
+    }
+
+    /// Returns an error.
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(Error::Unsupported)
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
+        Ok(DelimitedElements::new(self.delimiter, len))
+    }
+
+    /// Returns an error.
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(Error::Unsupported)
+    }
+}
+
+struct DelimitedElements {
+    delimiter: char,
+    parts: Vec<String>,
+}
+
+impl DelimitedElements {
+    fn new(delimiter: char, len: usize) -> Self {
+        DelimitedElements {
+            delimiter,
+            parts: Vec::with_capacity(len),
+        }
+    }
+
+    fn push<T: ?Sized + ser::Serialize>(&mut self, value: &T) -> Result<()> {
+        let part = value.serialize(StringSerializer)?;
+        if part.contains(self.delimiter) {
+            return Err(ser::Error::custom(format!(
+                "element {:?} contains the delimiter {:?}",
+                part, self.delimiter
+            )));
+        }
+        self.parts.push(part);
+        Ok(())
+    }
+
+    fn join(self) -> String {
+        let mut buf = [0; 4];
+        self.parts.join(self.delimiter.encode_utf8(&mut buf))
+    }
+}
+
+impl ser::SerializeSeq for DelimitedElements {
+    type Ok = String;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + ser::Serialize>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        Ok(self.join())
+    }
+}
+
+impl ser::SerializeTuple for DelimitedElements {
+    type Ok = String;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + ser::Serialize>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        Ok(self.join())
+    }
+}
+
+impl ser::SerializeTupleStruct for DelimitedElements {
+    type Ok = String;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + ser::Serialize>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        Ok(self.join())
+    }
+}
+
+impl ser::SerializeStruct for DelimitedElements {
+    type Ok = String;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + ser::Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        Ok(self.join())
+    }
+}
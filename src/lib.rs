@@ -26,6 +26,41 @@
 //! the form `a[0]=1&a[1]=3` will deserialize to the ordered sequence `a =
 //! [1,3]`.
 //!
+//! ### Empty values
+//!
+//! An empty value is an empty sequence, and a sequence with an empty
+//! element is written with a bracketed key. An `Option` with an empty value
+//! is `None`, whatever it contains, since that is how `None` is serialized.
+//! So for a field `tags`:
+//!
+//! | Input                 | `Vec<String>` | `Option<Vec<String>>` | `Vec<Option<String>>` |
+//! |-----------------------|---------------|-----------------------|-----------------------|
+//! | (missing)             | error         | `None`                | error                 |
+//! | `tags` or `tags=`     | `[]`          | `None`                | `[]`                  |
+//! | `tags[]` or `tags[]=` | `[""]`        | `Some([""])`          | `[None]`              |
+//! | `tags[0]=`            | `[""]`        | `Some([""])`          | `[None]`              |
+//!
+//! Use `#[serde(default)]` for a missing sequence to be empty.
+//!
+//! ### Repeated keys
+//!
+//! A key given more than once without brackets, such as `tag=a&tag=b`, is an
+//! error by default. With [`Config::repeated_keys_as_seq`] its values are
+//! collected into a sequence, and a key given once is a sequence of one
+//! value, so any querystring of flat keys can be deserialized as a
+//! `HashMap<String, Vec<String>>`. Each time the key is given is one element,
+//! so unlike above, an empty value is an empty string:
+//!
+//! | Input                        | `Vec<String>`   |
+//! |------------------------------|-----------------|
+//! | `tag=a`                      | `["a"]`         |
+//! | `tag=a&tag=b`                | `["a", "b"]`    |
+//! | `tag` or `tag=`              | `[""]`          |
+//! | `tag&tag=` or `tag=&tag=`    | `["", ""]`      |
+//! | `tag=&tag=x`                 | `["", "x"]`     |
+//! | `tag=a&tag[]=b`              | `["a", "b"]`    |
+//! | `tag[0]=a&tag=b`             | `["a", "b"]`    |
+//!
 //! ## Usage
 //!
 //! See the examples folder for a more detailed introduction.
@@ -102,6 +137,11 @@
 //! using keys with square brackets in them, or unexpected things can
 //! happen.
 //!
+//! To decode encoded brackets without the rest of non-strict mode, use
+//! [`Config::lenient_bracket_decoding`](struct.Config.html#method.lenient_bracket_decoding).
+//! Since the serializer encodes brackets in keys, a field whose name contains
+//! brackets does not round trip when encoded brackets are decoded.
+//!
 //! ### Invalid UTF-8 Percent Encodings
 //!
 //! Sometimes querystrings may have percent-encoded data which does not decode
@@ -154,6 +194,38 @@
 //! }
 //! ```
 //!
+//! The same workaround applies to `#[serde(flatten)]` fields of type
+//! `Option<CommonParams>`. Note that `serde` makes such a field `None`
+//! whenever `CommonParams` fails to deserialize, so a query with only some of
+//! its required fields (or with an invalid value) gives `None` rather than an
+//! error. Make the fields themselves optional with `#[serde(default)]` to
+//! accept partial input.
+//!
+//! Internally tagged enums, `#[serde(tag = "type")]`, are buffered in the
+//! same way, so need the same workaround for fields which are not strings.
+//! The tag can be given anywhere in the querystring, e.g.
+//! `channel_id=C1&type=message`.
+//!
+//! ## Delimited values
+//!
+//! Some APIs pack multiple values into a single parameter, such as
+//! `ids=1,2,3` or `range=1,100`. The [`helpers`](helpers/index.html) module
+//! provides `#[serde(with = "...")]` modules for comma, pipe and space
+//! delimited values, which can be used for sequences, tuples, fixed-size
+//! arrays and structs of scalars.
+//!
+//! ## Key order
+//!
+//! Map keys are deserialized in sorted order, whatever order they were given
+//! in. With the `indexmap` feature, [`Config::preserve_key_order`] keeps them
+//! in the order they first appear in the querystring instead, so that
+//! deserializing into an `IndexMap` preserves it. The feature alone does not
+//! change the order, so enabling it for one crate does not affect others
+//! using `serde_qs` in the same build. Sequence elements are always in index
+//! order. When serializing, map
+//! entries are written in iteration order unless
+//! [`Config::stable_output`] is set.
+//!
 //! ## Use with `actix_web` extractors
 //!
 //! The `actix4`, `actix3` or `actix2` features enable the use of `serde_qs::actix::QsQuery`, which
@@ -182,6 +254,26 @@
 //!     .recover(serde_qs::warp::recover_fn);
 //! ```
 //!
+//! ## Use with `rocket` guards
+//!
+//! The `rocket` feature enables the use of `serde_qs::rocket::QsQuery` as a
+//! request guard and `serde_qs::rocket::QsForm` as a data guard, which use the
+//! `Config` managed by the application:
+//!
+//! ```ignore
+//! #[get("/")]
+//! fn index(info: QsQuery<Info>) -> String {
+//!     format!("Welcome {}!", info.username)
+//! }
+//!
+//! rocket::build()
+//!     .manage(Config::new(5, false))
+//!     .mount("/", routes![index]);
+//! ```
+//!
+
+// Malformed input is reported as an `Error`, never as a panic.
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
 
 #[macro_use]
 extern crate serde;
@@ -208,19 +300,42 @@ compile_error!(
 
 mod de;
 mod error;
+pub mod helpers;
+mod merge;
+mod query_string;
 mod ser;
 pub(crate) mod utils;
 
+#[cfg(feature = "bumpalo")]
 #[doc(inline)]
-pub use de::{from_bytes, from_str};
+pub use de::QsParserArena;
 #[doc(inline)]
-pub use de::{Config, QsDeserializer as Deserializer};
-pub use error::Error;
+pub use de::{classify, from_bytes, from_str};
 #[doc(inline)]
-pub use ser::{to_string, to_writer, Serializer};
+pub use de::{
+    Config, Conflict, ConflictEvent, ConflictKind, KeyPath, NestingStyle, Pairs, ParseStats,
+    Parsed, QsDeserializer as Deserializer, QueryShape, Remainder, Segment, SeqIndexOverflow,
+    WholeInput, MAX_DEPTH_LIMIT,
+};
+pub use error::{Error, Suggestion, TranscodeError};
+pub use merge::merge;
+pub use query_string::QueryString;
+#[doc(inline)]
+pub use ser::{to_string, to_string_with_prefix, to_writer, Redactable, Serializer, ValueFilter};
 
 #[cfg(feature = "axum")]
 pub mod axum;
 
+#[cfg(feature = "rocket")]
+pub mod rocket;
+
 #[cfg(feature = "warp")]
 pub mod warp;
+
+#[cfg(any(
+    feature = "actix4",
+    feature = "actix3",
+    feature = "warp",
+    feature = "axum"
+))]
+pub mod web;
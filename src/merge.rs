@@ -0,0 +1,143 @@
+//! Merging a value into an existing querystring.
+
+use crate::de::Config;
+use crate::error::{Error, Result};
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::fmt;
+
+/// Serializes `update` over the querystring `original`, keeping any keys of
+/// `original` which `update` does not set.
+///
+/// Maps and structs are merged recursively, while values and sequences in
+/// `update` replace those in `original`. Fields which serialize to nothing,
+/// such as `None`, leave the original value in place. It is an error for
+/// `update` to have a map where `original` has a value, or vice versa.
+///
+/// The keys of the output are sorted, and sequences are re-indexed from zero.
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # extern crate serde_qs;
+/// #[derive(Serialize)]
+/// struct Page {
+///     page: u32,
+/// }
+///
+/// # fn main() {
+/// let config = serde_qs::Config::default();
+/// let merged = serde_qs::merge("q=rust&page=1&sort=asc", &Page { page: 2 }, &config);
+/// assert_eq!(merged.unwrap(), "page=2&q=rust&sort=asc");
+/// # }
+/// ```
+pub fn merge<T: ?Sized + Serialize>(original: &str, update: &T, config: &Config) -> Result<String> {
+    let mut merged: BTreeMap<String, Value> = config.deserialize_str(original)?;
+    let update: BTreeMap<String, Value> =
+        config.deserialize_str(&config.serialize_string(update)?)?;
+    merge_maps(&mut merged, update)?;
+    config.serialize_string(&merged)
+}
+
+fn merge_maps(
+    original: &mut BTreeMap<String, Value>,
+    update: BTreeMap<String, Value>,
+) -> Result<()> {
+    for (key, value) in update {
+        match original.entry(key) {
+            Entry::Vacant(entry) => {
+                let _ = entry.insert(value);
+            }
+            Entry::Occupied(mut entry) => match (entry.get_mut(), value) {
+                (Value::Map(original), Value::Map(update)) => merge_maps(original, update)
+                    .map_err(|e| Error::invalid_value(entry.key(), e))?,
+                (Value::Map(_), _) => {
+                    return Err(Error::invalid_value(
+                        entry.key(),
+                        de::Error::custom("cannot replace a map with a value"),
+                    ))
+                }
+                (_, Value::Map(_)) => {
+                    return Err(Error::invalid_value(
+                        entry.key(),
+                        de::Error::custom("cannot replace a value with a map"),
+                    ))
+                }
+                (original, value) => *original = value,
+            },
+        }
+    }
+    Ok(())
+}
+
+/// The structure of a parsed querystring.
+enum Value {
+    Map(BTreeMap<String, Value>),
+    Seq(Vec<Value>),
+    Str(String),
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a querystring value")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::Str(v.to_owned()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Value, E> {
+        Ok(Value::Str(v))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut values = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(Value::Seq(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut values = BTreeMap::new();
+        while let Some((key, value)) = map.next_entry()? {
+            let _ = values.insert(key, value);
+        }
+        Ok(Value::Map(values))
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Map(map) => {
+                let mut state = serializer.serialize_map(Some(map.len()))?;
+                for (key, value) in map {
+                    state.serialize_entry(key, value)?;
+                }
+                state.end()
+            }
+            Value::Seq(seq) => {
+                let mut state = serializer.serialize_seq(Some(seq.len()))?;
+                for value in seq {
+                    state.serialize_element(value)?;
+                }
+                state.end()
+            }
+            Value::Str(s) => serializer.serialize_str(s),
+        }
+    }
+}
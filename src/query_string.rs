@@ -0,0 +1,126 @@
+//! A typed querystring, parsed with `FromStr` and written with `Display`.
+
+use crate::de::Config;
+use crate::error::{Error, Result};
+
+use serde::de::{Deserialize, DeserializeOwned, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
+
+/// A value of `T` which converts to and from a querystring.
+///
+/// `QueryString<T>` parses with [`FromStr`] and writes back out with
+/// [`Display`](fmt::Display), both using the default [`Config`], which
+/// makes it convenient for storing typed querystrings as text. Use
+/// [`QueryString::parse_with`] for a custom config.
+///
+/// It serializes and deserializes as `T` itself.
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # extern crate serde_qs;
+/// use serde_qs::QueryString;
+///
+/// #[derive(Deserialize, Serialize)]
+/// struct Params {
+///     a: u8,
+///     b: String,
+/// }
+///
+/// # fn main() {
+/// let params: QueryString<Params> = "a=1&b=two".parse().unwrap();
+/// assert_eq!(params.a, 1);
+/// assert_eq!(params.to_string(), "a=1&b=two");
+/// # }
+/// ```
+///
+/// ## Display errors
+///
+/// `Display` cannot fail, so if `T` cannot be serialized, for example a map
+/// whose keys are not strings or numbers, nothing is written. This is a bug
+/// in the caller, and so panics in debug builds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct QueryString<T>(pub T);
+
+impl<T> QueryString<T> {
+    /// Unwrap into the inner `T` value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: DeserializeOwned> QueryString<T> {
+    /// Parses a querystring with the given config.
+    ///
+    /// ```
+    /// # extern crate serde_qs;
+    /// use serde_qs::{Config, QueryString};
+    /// use std::collections::BTreeMap;
+    ///
+    /// let config = Config::new(0, false);
+    /// let map = QueryString::<BTreeMap<String, u8>>::parse_with("a[b]=1", &config).unwrap();
+    /// assert_eq!(map["a[b]"], 1);
+    /// ```
+    pub fn parse_with(input: &str, config: &Config) -> Result<Self> {
+        config.deserialize_str(input).map(QueryString)
+    }
+}
+
+impl<T: DeserializeOwned> FromStr for QueryString<T> {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        Self::parse_with(input, &Config::default())
+    }
+}
+
+impl<T: DeserializeOwned> TryFrom<&str> for QueryString<T> {
+    type Error = Error;
+
+    fn try_from(input: &str) -> Result<Self> {
+        input.parse()
+    }
+}
+
+impl<T: Serialize> fmt::Display for QueryString<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match crate::to_string(&self.0) {
+            Ok(s) => f.write_str(&s),
+            Err(e) => {
+                debug_assert!(false, "QueryString could not be serialized: {}", e);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<T> Deref for QueryString<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for QueryString<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Serialize> Serialize for QueryString<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for QueryString<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(QueryString)
+    }
+}
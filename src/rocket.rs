@@ -0,0 +1,183 @@
+//! Functionality for using `serde_qs` with `rocket`.
+//!
+//! Enable with the `rocket` feature.
+//!
+//! The guards here use the [`Config`](crate::Config) managed by the
+//! application, or the default config if there is none:
+//!
+//! ```rust
+//! # extern crate rocket_framework as rocket;
+//! let rocket = rocket::build().manage(serde_qs::Config::new(1, false));
+//! ```
+//!
+//! On failure, both guards fail with a `400 Bad Request` and the
+//! [`Error`](crate::Error). Use `Result<QsQuery<T>, serde_qs::Error>` as the
+//! guard to handle the error in the route instead.
+
+use rocket_framework as rocket;
+
+use std::ops::{Deref, DerefMut};
+
+use crate::de::Config as QsConfig;
+use crate::error::Error as QsError;
+
+use rocket::data::{self, Data, FromData, Limits};
+use rocket::http::{ContentType, Status};
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest, Request};
+use serde::de::DeserializeOwned;
+
+fn config(req: &Request<'_>) -> QsConfig {
+    req.rocket()
+        .state::<QsConfig>()
+        .copied()
+        .unwrap_or_default()
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+/// Extract typed information from the request's query.
+///
+/// A request without a query string is deserialized from an empty string.
+///
+/// ## Example
+///
+/// ```rust
+/// # extern crate rocket_framework as rocket;
+/// # #[macro_use] extern crate serde_derive;
+/// use rocket::get;
+/// use serde_qs::rocket::QsQuery;
+///
+/// #[derive(Deserialize)]
+/// pub struct UsersFilter {
+///    id: Vec<u64>,
+/// }
+///
+/// // The correct query for this route would be `/users?id[]=1124&id[]=88`.
+/// #[get("/users")]
+/// fn filter_users(info: QsQuery<UsersFilter>) -> String {
+///     info.id.iter().map(|i| i.to_string()).collect::<Vec<String>>().join(", ")
+/// }
+///
+/// fn main() {
+///     let rocket = rocket::build().mount("/", rocket::routes![filter_users]);
+/// }
+/// ```
+pub struct QsQuery<T>(pub T);
+
+impl<T> QsQuery<T> {
+    /// Unwrap into inner T value
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for QsQuery<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for QsQuery<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, T> FromRequest<'r> for QsQuery<T>
+where
+    T: DeserializeOwned,
+{
+    type Error = QsError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let query = req.uri().query().map(|query| query.as_str()).unwrap_or("");
+        match config(req).deserialize_str::<T>(query) {
+            Ok(value) => Outcome::Success(QsQuery(value)),
+            Err(err) => Outcome::Error((Status::BadRequest, err)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+/// Extract typed information from the request's form data.
+///
+/// Requests without a `Content-Type: application/x-www-form-urlencoded`
+/// header are forwarded. The body is limited by the `form` limit, as for
+/// Rocket's own `Form` guard.
+///
+/// ## Example
+///
+/// ```rust
+/// # extern crate rocket_framework as rocket;
+/// # #[macro_use] extern crate serde_derive;
+/// use rocket::post;
+/// use serde_qs::rocket::QsForm;
+///
+/// #[derive(Deserialize)]
+/// pub struct UsersFilter {
+///    id: Vec<u64>,
+/// }
+///
+/// // The correct request payload for this route would be `id[]=1124&id[]=88`.
+/// #[post("/users", data = "<info>")]
+/// fn filter_users(info: QsForm<UsersFilter>) -> String {
+///     info.id.iter().map(|i| i.to_string()).collect::<Vec<String>>().join(", ")
+/// }
+///
+/// fn main() {
+///     let rocket = rocket::build().mount("/", rocket::routes![filter_users]);
+/// }
+/// ```
+pub struct QsForm<T>(pub T);
+
+impl<T> QsForm<T> {
+    /// Unwrap into inner T value
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for QsForm<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for QsForm<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, T> FromData<'r> for QsForm<T>
+where
+    T: DeserializeOwned,
+{
+    type Error = QsError;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        if req.content_type() != Some(&ContentType::Form) {
+            return Outcome::Forward((data, Status::UnsupportedMediaType));
+        }
+
+        let limit = req.limits().get("form").unwrap_or(Limits::FORM);
+        let body = match data.open(limit).into_bytes().await {
+            Ok(body) if body.is_complete() => body.into_inner(),
+            Ok(_) => {
+                let err = QsError::Custom("form body exceeds the size limit".to_owned());
+                return Outcome::Error((Status::PayloadTooLarge, err));
+            }
+            Err(err) => return Outcome::Error((Status::BadRequest, err.into())),
+        };
+        match config(req).deserialize_bytes::<T>(&body) {
+            Ok(value) => Outcome::Success(QsForm(value)),
+            Err(err) => Outcome::Error((Status::BadRequest, err)),
+        }
+    }
+}
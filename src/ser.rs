@@ -3,6 +3,7 @@
 use percent_encoding::percent_encode;
 use serde::ser;
 
+use crate::de::{Config, KeyPath, NestingStyle, Segment};
 use crate::error::*;
 use crate::utils::*;
 
@@ -40,10 +41,39 @@ use std::sync::Arc;
 ///     "name=Alice&age=24&occupation=Student");
 /// # }
 /// ```
-pub fn to_string<T: ser::Serialize>(input: &T) -> Result<String> {
-    let mut buffer = Vec::new();
-    input.serialize(&mut Serializer::new(&mut buffer))?;
-    String::from_utf8(buffer).map_err(Error::from)
+pub fn to_string<T: ?Sized + ser::Serialize>(input: &T) -> Result<String> {
+    Config::default().serialize_string(input)
+}
+
+/// Serializes a value into a querystring, with every key nested under
+/// `prefix`.
+///
+/// This is useful for combining querystrings serialized separately, which
+/// can then be deserialized into a struct with a field named after each
+/// prefix.
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # extern crate serde_qs;
+/// #[derive(Deserialize, Serialize)]
+/// struct Page {
+///     number: u32,
+///     size: u32,
+/// }
+///
+/// # fn main(){
+/// let page = Page { number: 2, size: 50 };
+/// assert_eq!(
+///     serde_qs::to_string_with_prefix("page", &page).unwrap(),
+///     "page[number]=2&page[size]=50");
+/// # }
+/// ```
+pub fn to_string_with_prefix<T: ?Sized + ser::Serialize>(
+    prefix: &str,
+    input: &T,
+) -> Result<String> {
+    Config::default().serialize_string_with_prefix(prefix, input)
 }
 
 /// Serializes a value into a generic writer object.
@@ -67,30 +97,209 @@ pub fn to_string<T: ser::Serialize>(input: &T) -> Result<String> {
 /// };
 ///
 /// let mut buffer = Vec::new();
-/// serde_qs::to_writer(&q, &mut buffer).unwrap();
+/// let written = serde_qs::to_writer(&q, &mut buffer).unwrap();
+/// assert_eq!(written, buffer.len());
 /// assert_eq!(
 ///     String::from_utf8(buffer).unwrap(),
 ///     "name=Alice&age=24&occupation=Student");
 /// # }
 /// ```
-pub fn to_writer<T: ser::Serialize, W: Write>(input: &T, writer: &mut W) -> Result<()> {
-    input.serialize(&mut Serializer::new(writer))
+///
+/// Returns the number of bytes written.
+pub fn to_writer<T: ?Sized + ser::Serialize, W: Write>(input: &T, writer: &mut W) -> Result<usize> {
+    Config::default().serialize_to_writer(input, writer)
+}
+
+impl Config {
+    /// Serializes a value into a querystring using this `Config`.
+    pub fn serialize_string<T: ?Sized + ser::Serialize>(&self, input: &T) -> Result<String> {
+        let mut buffer = Vec::new();
+        self.serialize_to_writer(input, &mut buffer)?;
+        String::from_utf8(buffer).map_err(Error::from)
+    }
+
+    /// Serializes a value into a querystring using this `Config`, with every
+    /// key nested under `prefix`, see [`to_string_with_prefix`].
+    ///
+    /// A top-level value which is not a struct or map, such as a number or
+    /// sequence, is serialized as the value of `prefix` itself, e.g.
+    /// `prefix=1` or `prefix[0]=1`. An empty prefix is the same as
+    /// [`serialize_string`](#method.serialize_string).
+    pub fn serialize_string_with_prefix<T: ?Sized + ser::Serialize>(
+        &self,
+        prefix: &str,
+        input: &T,
+    ) -> Result<String> {
+        if prefix.is_empty() {
+            return self.serialize_string(input);
+        }
+        let mut buffer = Vec::new();
+        let mut serializer = QsSerializer {
+            writer: &mut buffer,
+            first: Arc::new(AtomicBool::new(true)),
+            key: None,
+            config: *self,
+            in_seq: false,
+            depth: 0,
+        };
+        serializer.extend_key(prefix)?;
+        input.serialize(serializer)?;
+        if !self.field_order.is_empty() {
+            let mut ordered = Vec::with_capacity(buffer.len());
+            self.write_in_field_order(&buffer, prefix, &mut ordered)?;
+            buffer = ordered;
+        }
+        String::from_utf8(buffer).map_err(Error::from)
+    }
+
+    /// Serializes a value as the value of `path`, encoding the key exactly
+    /// as the serializer does.
+    ///
+    /// This is useful for writing a single parameter, such as a modified
+    /// page number for a pagination link. A value which is not a scalar is
+    /// nested under `path`, as with
+    /// [`serialize_string_with_prefix`](#method.serialize_string_with_prefix).
+    ///
+    /// ```
+    /// use serde_qs::{Config, KeyPath};
+    ///
+    /// let path = KeyPath::new(["page", "number"]);
+    /// assert_eq!(
+    ///     Config::default().serialize_pair(&path, &3).unwrap(),
+    ///     "page[number]=3"
+    /// );
+    /// ```
+    pub fn serialize_pair<T: ?Sized + ser::Serialize>(
+        &self,
+        path: &KeyPath,
+        value: &T,
+    ) -> Result<String> {
+        let mut buffer = Vec::new();
+        let mut serializer = QsSerializer {
+            writer: &mut buffer,
+            first: Arc::new(AtomicBool::new(true)),
+            key: None,
+            config: *self,
+            in_seq: false,
+            depth: 0,
+        };
+        for segment in path.segments() {
+            match segment {
+                Segment::Key(key) => serializer.extend_key(key)?,
+                Segment::Index(index) => serializer.extend_key(&index.to_string())?,
+            }
+        }
+        value.serialize(serializer)?;
+        String::from_utf8(buffer).map_err(Error::from)
+    }
+
+    /// Serializes a value into a generic writer object using this `Config`,
+    /// returning the number of bytes written.
+    pub fn serialize_to_writer<T: ?Sized + ser::Serialize, W: Write>(
+        &self,
+        input: &T,
+        writer: &mut W,
+    ) -> Result<usize> {
+        // Serialize through `dyn Write`, so that `T::serialize` is only
+        // instantiated once rather than for every writer type.
+        let mut writer = CountingWriter { writer, written: 0 };
+        if self.field_order.is_empty() {
+            input.serialize(&mut Serializer::with_config(&mut writer, *self))?;
+        } else {
+            let mut buffer = Vec::new();
+            input.serialize(&mut Serializer::with_config(&mut buffer, *self))?;
+            self.write_in_field_order(&buffer, "", &mut writer)?;
+        }
+        Ok(writer.written)
+    }
+
+    /// Writes the pairs of a serialized querystring, moving those whose
+    /// top-level key (after `prefix`, if any) is in `field_order` to the
+    /// front in that order. Other pairs keep their order.
+    fn write_in_field_order(
+        &self,
+        output: &[u8],
+        prefix: &str,
+        writer: &mut dyn Write,
+    ) -> Result<()> {
+        let order: Vec<Cow<str>> = self
+            .field_order
+            .iter()
+            .map(|name| encode_key(name))
+            .collect();
+        let prefix = encode_key(prefix);
+        let dots = self.key_style() == NestingStyle::Dots;
+        let position = |pair: &&[u8]| {
+            let mut key = &pair[..pair.iter().position(|&b| b == b'=').unwrap_or(pair.len())];
+            if !prefix.is_empty() {
+                key = key.get(prefix.len() + 1..).unwrap_or_default();
+            }
+            let end = key
+                .iter()
+                .position(|&b| b == b'[' || b == b']' || (dots && b == b'.'))
+                .unwrap_or(key.len());
+            order
+                .iter()
+                .position(|name| name.as_bytes() == &key[..end])
+                .unwrap_or(order.len())
+        };
+
+        let mut pairs: Vec<&[u8]> = output
+            .split(|&b| b == b'&')
+            .filter(|p| !p.is_empty())
+            .collect();
+        pairs.sort_by_key(position);
+        for (i, pair) in pairs.into_iter().enumerate() {
+            if i > 0 {
+                writer.write_all(b"&")?;
+            }
+            writer.write_all(pair)?;
+        }
+        Ok(())
+    }
+}
+
+/// Counts the bytes written through it.
+struct CountingWriter<'a> {
+    writer: &'a mut dyn Write,
+    written: usize,
+}
+
+impl Write for CountingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.written += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
 }
 
 pub struct Serializer<W: Write> {
     writer: W,
+    config: Config,
 }
 
 impl<W: Write> Serializer<W> {
     pub fn new(writer: W) -> Self {
-        Self { writer }
+        Self::with_config(writer, Config::default())
+    }
+
+    /// Creates a new `Serializer` using the serialization options of `config`.
+    pub fn with_config(writer: W, config: Config) -> Self {
+        Self { writer, config }
     }
 
-    fn as_qs_serializer(&mut self) -> QsSerializer<W> {
+    fn as_qs_serializer(&mut self) -> QsSerializer<'_> {
         QsSerializer {
             writer: &mut self.writer,
             first: Arc::new(AtomicBool::new(true)),
             key: None,
+            config: self.config,
+            in_seq: false,
+            depth: 0,
         }
     }
 }
@@ -104,6 +313,21 @@ macro_rules! serialize_as_string {
             }
         )*
     };
+    (QsInteger $($ty:ty => $meth:ident,)*) => {
+        $(
+            fn $meth(mut self, v: $ty) -> Result<Self::Ok> {
+                let mut buffer = itoa::Buffer::new();
+                self.write_unencoded(buffer.format(v))
+            }
+        )*
+    };
+    (QsDisplay $($ty:ty => $meth:ident,)*) => {
+        $(
+            fn $meth(mut self, v: $ty) -> Result<Self::Ok> {
+                self.write_unencoded(v)
+            }
+        )*
+    };
     (Qs $($ty:ty => $meth:ident,)*) => {
         $(
             fn $meth(mut self, v: $ty) -> Result<Self::Ok> {
@@ -123,13 +347,13 @@ macro_rules! serialize_as_string {
 impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     type Ok = ();
     type Error = Error;
-    type SerializeSeq = QsSeq<'a, W>;
-    type SerializeTuple = QsSeq<'a, W>;
-    type SerializeTupleStruct = QsSeq<'a, W>;
-    type SerializeTupleVariant = QsSeq<'a, W>;
-    type SerializeMap = QsMap<'a, W>;
-    type SerializeStruct = QsSerializer<'a, W>;
-    type SerializeStructVariant = QsSerializer<'a, W>;
+    type SerializeSeq = QsSeq<'a>;
+    type SerializeTuple = QsSeq<'a>;
+    type SerializeTupleStruct = QsSeq<'a>;
+    type SerializeTupleVariant = QsSeq<'a>;
+    type SerializeMap = QsMap<'a>;
+    type SerializeStruct = QsSerializer<'a>;
+    type SerializeStructVariant = QsSerializer<'a>;
 
     serialize_as_string! {
         Serializer
@@ -138,10 +362,12 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
         u16 => serialize_u16,
         u32 => serialize_u32,
         u64 => serialize_u64,
+        u128 => serialize_u128,
         i8  => serialize_i8,
         i16 => serialize_i16,
         i32 => serialize_i32,
         i64 => serialize_i64,
+        i128 => serialize_i128,
         f32 => serialize_f32,
         f64 => serialize_f64,
         char => serialize_char,
@@ -254,48 +480,157 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
 ///
 /// * Newtype structs defer to their inner values.
 #[doc(hidden)]
-pub struct QsSerializer<'a, W: 'a + Write> {
+pub struct QsSerializer<'a> {
     key: Option<Cow<'static, str>>,
-    writer: &'a mut W,
+    writer: &'a mut dyn Write,
     first: Arc<AtomicBool>,
+    config: Config,
+    /// Whether this is within an element of a sequence, where empty values
+    /// are written even with `Config::skip_empty_leaves`, so that the
+    /// elements keep their indices.
+    in_seq: bool,
+    /// The number of values this one is nested in, see `RECURSION_LIMIT`.
+    depth: usize,
 }
 
-impl<'a, W: 'a + Write> QsSerializer<'a, W> {
-    fn extend_key(&mut self, newkey: &str) {
-        let newkey = percent_encode(newkey.as_bytes(), QS_ENCODE_SET)
-            .map(replace_space)
-            .collect::<String>();
+/// The most values which can be nested in each other when serializing.
+///
+/// The depth of keys is limited by `max_depth`, but newtypes and options
+/// nest without extending the key, so a recursive type could otherwise
+/// overflow the stack.
+const RECURSION_LIMIT: usize = 1024;
+
+impl<'a> QsSerializer<'a> {
+    /// Appends `newkey` to the key, erroring if this would nest it deeper
+    /// than `max_depth`. Brackets in keys are encoded, as are dots with
+    /// `NestingStyle::Dots`, so the depth of the key is the number of `[`
+    /// or `.` in it.
+    fn extend_key(&mut self, newkey: &str) -> Result<()> {
+        self.descend()?;
+        if self.config.serialize_flat_dotted && newkey.contains(&['.', '[', ']'][..]) {
+            return Err(Error::AmbiguousKeySegment {
+                key: self.key.as_deref().unwrap_or_default().to_owned(),
+                segment: newkey.to_owned(),
+            });
+        }
+        let dots = self.config.key_style() == NestingStyle::Dots;
+        let mut newkey = encode_key(newkey);
+        if dots && newkey.contains('.') {
+            newkey = Cow::Owned(newkey.replace('.', "%2E"));
+        }
         let key = if let Some(ref key) = self.key {
-            format!("{}[{}]", key, newkey)
+            let (key, separator) = if dots {
+                (format!("{}.{}", key, newkey), b'.')
+            } else {
+                (format!("{}[{}]", key, newkey), b'[')
+            };
+            let max_depth = self.config.max_depth();
+            if key.bytes().filter(|&b| b == separator).count() > max_depth {
+                return Err(Error::MaxDepthExceeded { key, max_depth });
+            }
+            key
         } else {
-            newkey
+            newkey.into_owned()
+        };
+        self.key = Some(Cow::Owned(key));
+        Ok(())
+    }
+
+    /// Runs `Config::value_filter`, if any, on the pair for `key`.
+    fn filter(&self, key: &str) -> Action {
+        let mut value = Redactable {
+            action: Action::Keep,
         };
-        self.key = Some(Cow::Owned(key))
+        if let Some(filter) = self.config.value_filter {
+            filter(
+                &KeyPath::from_encoded(key, self.config.key_style()),
+                &mut value,
+            );
+        }
+        value.action
+    }
+
+    /// Whether to leave out an empty value, see `Config::skip_empty_leaves`.
+    fn skip_empty(&self) -> bool {
+        self.config.skip_empty_leaves && !self.in_seq
+    }
+
+    /// Counts a level of nesting, erroring beyond `RECURSION_LIMIT`.
+    fn descend(&mut self) -> Result<()> {
+        self.depth += 1;
+        if self.depth > RECURSION_LIMIT {
+            return Err(Error::RecursionLimitExceeded {
+                limit: RECURSION_LIMIT,
+            });
+        }
+        Ok(())
     }
 
     fn write_value(&mut self, value: &[u8]) -> Result<()> {
         if let Some(ref key) = self.key {
+            let value = match self.filter(key) {
+                Action::Keep if value.is_empty() && self.skip_empty() => return Ok(()),
+                Action::Keep => value,
+                Action::Redact => REDACTED.as_bytes(),
+                Action::Skip => return Ok(()),
+            };
+            let amp = !self.first.swap(false, Ordering::Relaxed);
+            write!(self.writer, "{}{}=", if amp { "&" } else { "" }, key)?;
+            if self.config.assume_preencoded_values {
+                self.writer.write_all(value)?;
+                return Ok(());
+            }
+            // Write the encoded chunks directly, so values which need no
+            // escaping (numbers, UUIDs, ...) are written without allocating.
+            for chunk in percent_encode(value, QS_ENCODE_SET) {
+                self.writer.write_all(replace_space(chunk).as_bytes())?;
+            }
+            Ok(())
+        } else {
+            Err(Error::no_key())
+        }
+    }
+
+    /// Writes a value which is known to need no percent-encoding, such as a
+    /// formatted number, straight to the writer.
+    fn write_unencoded<T: Display>(&mut self, value: T) -> Result<()> {
+        if let Some(ref key) = self.key {
+            let value: &dyn Display = match self.filter(key) {
+                Action::Keep => &value,
+                Action::Redact => &REDACTED,
+                Action::Skip => return Ok(()),
+            };
             let amp = !self.first.swap(false, Ordering::Relaxed);
             write!(
                 self.writer,
                 "{}{}={}",
                 if amp { "&" } else { "" },
                 key,
-                percent_encode(value, QS_ENCODE_SET)
-                    .map(replace_space)
-                    .collect::<String>()
-            )
-            .map_err(Error::from)
+                value
+            )?;
+            Ok(())
         } else {
             Err(Error::no_key())
         }
     }
 
     fn write_unit(&mut self) -> Result<()> {
-        let amp = !self.first.swap(false, Ordering::Relaxed);
         if let Some(ref key) = self.key {
-            write!(self.writer, "{}{}=", if amp { "&" } else { "" }, key,).map_err(Error::from)
-        } else if amp {
+            let value = match self.filter(key) {
+                Action::Keep => "",
+                Action::Redact => REDACTED,
+                Action::Skip => return Ok(()),
+            };
+            let amp = !self.first.swap(false, Ordering::Relaxed);
+            write!(
+                self.writer,
+                "{}{}={}",
+                if amp { "&" } else { "" },
+                key,
+                value
+            )
+            .map_err(Error::from)
+        } else if !self.first.swap(false, Ordering::Relaxed) {
             write!(self.writer, "&").map_err(Error::from)
         } else {
             Ok(())
@@ -304,15 +639,52 @@ impl<'a, W: 'a + Write> QsSerializer<'a, W> {
 
     /// Creates a new `QsSerializer` with a distinct key, but `writer` and
     ///`first` referring to the original data.
-    fn new_from_ref<'b: 'a>(other: &'a mut QsSerializer<'b, W>) -> QsSerializer<'a, W> {
+    fn new_from_ref<'b: 'a>(other: &'a mut QsSerializer<'b>) -> QsSerializer<'a> {
         Self {
             key: other.key.clone(),
             writer: other.writer,
             first: other.first.clone(),
+            config: other.config,
+            in_seq: other.in_seq,
+            depth: other.depth,
         }
     }
 }
 
+/// A function which can redact or skip values as they are serialized, see
+/// [`Config::value_filter`].
+pub type ValueFilter = fn(&KeyPath, &mut Redactable);
+
+/// The value written in place of a redacted one.
+const REDACTED: &str = "***";
+
+/// What to do with a value which is about to be serialized, see
+/// [`Config::value_filter`]. The value is written as usual unless one of
+/// the methods is called.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Redactable {
+    action: Action,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Action {
+    Keep,
+    Redact,
+    Skip,
+}
+
+impl Redactable {
+    /// Writes `***` in place of the value.
+    pub fn redact(&mut self) {
+        self.action = Action::Redact;
+    }
+
+    /// Leaves the pair out of the output.
+    pub fn skip(&mut self) {
+        self.action = Action::Skip;
+    }
+}
+
 impl Error {
     fn no_key() -> Self {
         let msg = "tried to serialize a value before serializing key";
@@ -320,30 +692,42 @@ impl Error {
     }
 }
 
-impl<'a, W: Write> ser::Serializer for QsSerializer<'a, W> {
+impl<'a> ser::Serializer for QsSerializer<'a> {
     type Ok = ();
     type Error = Error;
-    type SerializeSeq = QsSeq<'a, W>;
-    type SerializeTuple = QsSeq<'a, W>;
-    type SerializeTupleStruct = QsSeq<'a, W>;
-    type SerializeTupleVariant = QsSeq<'a, W>;
-    type SerializeMap = QsMap<'a, W>;
+    type SerializeSeq = QsSeq<'a>;
+    type SerializeTuple = QsSeq<'a>;
+    type SerializeTupleStruct = QsSeq<'a>;
+    type SerializeTupleVariant = QsSeq<'a>;
+    type SerializeMap = QsMap<'a>;
     type SerializeStruct = Self;
     type SerializeStructVariant = Self;
 
     serialize_as_string! {
-        Qs
-        bool => serialize_bool,
+        QsInteger
         u8  => serialize_u8,
         u16 => serialize_u16,
         u32 => serialize_u32,
         u64 => serialize_u64,
+        u128 => serialize_u128,
         i8  => serialize_i8,
         i16 => serialize_i16,
         i32 => serialize_i32,
         i64 => serialize_i64,
+        i128 => serialize_i128,
+    }
+
+    // `Display` for these only writes letters, digits, `-` and `.`, so the
+    // output never needs encoding.
+    serialize_as_string! {
+        QsDisplay
+        bool => serialize_bool,
         f32 => serialize_f32,
         f64 => serialize_f64,
+    }
+
+    serialize_as_string! {
+        Qs
         char => serialize_char,
         &str => serialize_str,
     }
@@ -370,10 +754,11 @@ impl<'a, W: Write> ser::Serializer for QsSerializer<'a, W> {
     }
 
     fn serialize_newtype_struct<T: ?Sized + ser::Serialize>(
-        self,
+        mut self,
         _name: &'static str,
         value: &T,
     ) -> Result<Self::Ok> {
+        self.descend()?;
         value.serialize(self)
     }
 
@@ -384,15 +769,34 @@ impl<'a, W: Write> ser::Serializer for QsSerializer<'a, W> {
         variant: &'static str,
         value: &T,
     ) -> Result<Self::Ok> {
-        self.extend_key(variant);
-        value.serialize(self)
+        self.extend_key(variant)?;
+        // A value which writes nothing, such as an empty `Vec` or `None`,
+        // would lose the variant, so write `variant=` instead.
+        let mut buffer = Vec::new();
+        value.serialize(QsSerializer {
+            key: self.key.clone(),
+            writer: &mut buffer,
+            first: Arc::new(AtomicBool::new(true)),
+            config: self.config,
+            in_seq: self.in_seq,
+            depth: self.depth,
+        })?;
+        if buffer.is_empty() {
+            return self.write_unit();
+        }
+        if !self.first.swap(false, Ordering::Relaxed) {
+            self.writer.write_all(b"&")?;
+        }
+        self.writer.write_all(&buffer)?;
+        Ok(())
     }
 
     fn serialize_none(self) -> Result<Self::Ok> {
         Ok(())
     }
 
-    fn serialize_some<T: ?Sized + ser::Serialize>(self, value: &T) -> Result<Self::Ok> {
+    fn serialize_some<T: ?Sized + ser::Serialize>(mut self, value: &T) -> Result<Self::Ok> {
+        self.descend()?;
         value.serialize(self)
     }
 
@@ -419,12 +823,17 @@ impl<'a, W: Write> ser::Serializer for QsSerializer<'a, W> {
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        self.extend_key(variant);
+        self.extend_key(variant)?;
         Ok(QsSeq(self, 0))
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
-        Ok(QsMap(self, None))
+        let buffered = if self.config.stable_output {
+            Some(Vec::new())
+        } else {
+            None
+        };
+        Ok(QsMap(self, None, buffered))
     }
 
     fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
@@ -438,7 +847,7 @@ impl<'a, W: Write> ser::Serializer for QsSerializer<'a, W> {
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        self.extend_key(variant);
+        self.extend_key(variant)?;
         Ok(self)
     }
 }
@@ -453,23 +862,97 @@ impl ser::Error for Error {
 }
 
 #[doc(hidden)]
-pub struct QsSeq<'a, W: 'a + Write>(QsSerializer<'a, W>, usize);
+pub struct QsSeq<'a>(QsSerializer<'a>, usize);
+
+impl<'a> QsSeq<'a> {
+    /// Serializes the next element. An element which writes no pairs, such as
+    /// `None` or a struct with only `None` fields, is written as an empty
+    /// value instead, so that the following elements keep their indices.
+    fn serialize_next<T: ?Sized + ser::Serialize>(&mut self, value: &T) -> Result<()> {
+        let mut writer = WriteTracker {
+            writer: &mut *self.0.writer,
+            written: false,
+        };
+        let mut serializer = QsSerializer {
+            key: self.0.key.clone(),
+            writer: &mut writer,
+            first: self.0.first.clone(),
+            config: self.0.config,
+            in_seq: true,
+            depth: self.0.depth,
+        };
+        serializer.extend_key(&self.1.to_string())?;
+        self.1 += 1;
+        let key = serializer.key.clone();
+        value.serialize(serializer)?;
+        if writer.written {
+            return Ok(());
+        }
+        let mut placeholder = QsSerializer::new_from_ref(&mut self.0);
+        placeholder.key = key;
+        placeholder.write_unit()
+    }
+}
+
+/// Forwards to `writer`, noting whether anything was written.
+struct WriteTracker<'a> {
+    writer: &'a mut dyn Write,
+    written: bool,
+}
+
+impl<'a> Write for WriteTracker<'a> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.written |= !buf.is_empty();
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
 
+/// The third field buffers the serialized entries when `stable_output` is set,
+/// so that they can be sorted by key before writing.
 #[doc(hidden)]
-pub struct QsMap<'a, W: 'a + Write>(QsSerializer<'a, W>, Option<Cow<'a, str>>);
+pub struct QsMap<'a>(
+    QsSerializer<'a>,
+    Option<Cow<'a, str>>,
+    Option<Vec<(String, Vec<u8>)>>,
+);
+
+impl<'a> QsMap<'a> {
+    fn serialize_entry_value<T: ?Sized + ser::Serialize>(
+        &mut self,
+        key: &str,
+        value: &T,
+    ) -> Result<()> {
+        if let Some(ref mut entries) = self.2 {
+            let mut buffer = Vec::new();
+            let mut serializer = QsSerializer {
+                key: self.0.key.clone(),
+                writer: &mut buffer,
+                first: Arc::new(AtomicBool::new(true)),
+                config: self.0.config,
+                in_seq: self.0.in_seq,
+                depth: self.0.depth,
+            };
+            serializer.extend_key(key)?;
+            value.serialize(serializer)?;
+            entries.push((key.to_owned(), buffer));
+            Ok(())
+        } else {
+            let mut serializer = QsSerializer::new_from_ref(&mut self.0);
+            serializer.extend_key(key)?;
+            value.serialize(serializer)
+        }
+    }
+}
 
-impl<'a, W: Write> ser::SerializeTuple for QsSeq<'a, W> {
+impl<'a> ser::SerializeTuple for QsSeq<'a> {
     type Ok = ();
     type Error = Error;
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
-    where
-        T: ser::Serialize,
-    {
-        let key = self.1.to_string();
-        self.1 += 1;
-        let mut serializer = QsSerializer::new_from_ref(&mut self.0);
-        serializer.extend_key(&key);
-        value.serialize(serializer)
+    fn serialize_element<T: ?Sized + ser::Serialize>(&mut self, value: &T) -> Result<()> {
+        self.serialize_next(value)
     }
 
     fn end(self) -> Result<Self::Ok> {
@@ -477,32 +960,27 @@ impl<'a, W: Write> ser::SerializeTuple for QsSeq<'a, W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeSeq for QsSeq<'a, W> {
+impl<'a> ser::SerializeSeq for QsSeq<'a> {
     type Ok = ();
     type Error = Error;
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
-    where
-        T: ser::Serialize,
-    {
-        let mut serializer = QsSerializer::new_from_ref(&mut self.0);
-        serializer.extend_key(&self.1.to_string());
-        self.1 += 1;
-        value.serialize(serializer)
+    fn serialize_element<T: ?Sized + ser::Serialize>(&mut self, value: &T) -> Result<()> {
+        self.serialize_next(value)
     }
     fn end(self) -> Result<Self::Ok> {
         Ok(())
     }
 }
 
-impl<'a, W: Write> ser::SerializeStruct for QsSerializer<'a, W> {
+impl<'a> ser::SerializeStruct for QsSerializer<'a> {
     type Ok = ();
     type Error = Error;
-    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
-    where
-        T: ser::Serialize,
-    {
+    fn serialize_field<T: ?Sized + ser::Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
         let mut serializer = QsSerializer::new_from_ref(self);
-        serializer.extend_key(key);
+        serializer.extend_key(key)?;
         value.serialize(serializer)
     }
     fn end(self) -> Result<Self::Ok> {
@@ -510,16 +988,17 @@ impl<'a, W: Write> ser::SerializeStruct for QsSerializer<'a, W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeStructVariant for QsSerializer<'a, W> {
+impl<'a> ser::SerializeStructVariant for QsSerializer<'a> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
-    where
-        T: ser::Serialize,
-    {
+    fn serialize_field<T: ?Sized + ser::Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
         let mut serializer = QsSerializer::new_from_ref(self);
-        serializer.extend_key(key);
+        serializer.extend_key(key)?;
         value.serialize(serializer)
     }
 
@@ -528,18 +1007,12 @@ impl<'a, W: Write> ser::SerializeStructVariant for QsSerializer<'a, W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeTupleVariant for QsSeq<'a, W> {
+impl<'a> ser::SerializeTupleVariant for QsSeq<'a> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<()>
-    where
-        T: ser::Serialize,
-    {
-        let mut serializer = QsSerializer::new_from_ref(&mut self.0);
-        serializer.extend_key(&self.1.to_string());
-        self.1 += 1;
-        value.serialize(serializer)
+    fn serialize_field<T: ?Sized + ser::Serialize>(&mut self, value: &T) -> Result<()> {
+        self.serialize_next(value)
     }
 
     fn end(self) -> Result<Self::Ok> {
@@ -547,18 +1020,12 @@ impl<'a, W: Write> ser::SerializeTupleVariant for QsSeq<'a, W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeTupleStruct for QsSeq<'a, W> {
+impl<'a> ser::SerializeTupleStruct for QsSeq<'a> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<()>
-    where
-        T: ser::Serialize,
-    {
-        let mut serializer = QsSerializer::new_from_ref(&mut self.0);
-        serializer.extend_key(&self.1.to_string());
-        self.1 += 1;
-        value.serialize(serializer)
+    fn serialize_field<T: ?Sized + ser::Serialize>(&mut self, value: &T) -> Result<()> {
+        self.serialize_next(value)
     }
 
     fn end(self) -> Result<Self::Ok> {
@@ -566,48 +1033,47 @@ impl<'a, W: Write> ser::SerializeTupleStruct for QsSeq<'a, W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeMap for QsMap<'a, W> {
+impl<'a> ser::SerializeMap for QsMap<'a> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<()>
-    where
-        T: ser::Serialize,
-    {
-        self.1 = Some(Cow::from(key.serialize(StringSerializer)?));
+    fn serialize_key<T: ?Sized + ser::Serialize>(&mut self, key: &T) -> Result<()> {
+        self.1 = Some(Cow::from(key.serialize(KeySerializer)?));
         Ok(())
     }
 
-    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<()>
-    where
-        T: ser::Serialize,
-    {
-        let mut serializer = QsSerializer::new_from_ref(&mut self.0);
-        if let Some(ref key) = self.1 {
-            serializer.extend_key(key);
+    fn serialize_value<T: ?Sized + ser::Serialize>(&mut self, value: &T) -> Result<()> {
+        if let Some(key) = self.1.take() {
+            self.serialize_entry_value(&key, value)
         } else {
-            return Err(Error::no_key());
+            Err(Error::no_key())
         }
-        self.1 = None;
-        value.serialize(serializer)
     }
 
     fn end(self) -> Result<Self::Ok> {
+        if let Some(mut entries) = self.2 {
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            for (_, entry) in entries.into_iter().filter(|(_, e)| !e.is_empty()) {
+                if !self.0.first.swap(false, Ordering::Relaxed) {
+                    self.0.writer.write_all(b"&")?;
+                }
+                self.0.writer.write_all(&entry)?;
+            }
+        }
         Ok(())
     }
 
-    fn serialize_entry<K: ?Sized, V: ?Sized>(&mut self, key: &K, value: &V) -> Result<()>
-    where
-        K: ser::Serialize,
-        V: ser::Serialize,
-    {
-        let mut serializer = QsSerializer::new_from_ref(&mut self.0);
-        serializer.extend_key(&key.serialize(StringSerializer)?);
-        value.serialize(serializer)
+    fn serialize_entry<K: ?Sized + ser::Serialize, V: ?Sized + ser::Serialize>(
+        &mut self,
+        key: &K,
+        value: &V,
+    ) -> Result<()> {
+        let key = key.serialize(KeySerializer)?;
+        self.serialize_entry_value(&key, value)
     }
 }
 
-struct StringSerializer;
+pub(crate) struct StringSerializer;
 
 impl ser::Serializer for StringSerializer {
     type Ok = String;
@@ -626,10 +1092,12 @@ impl ser::Serializer for StringSerializer {
         u16 => serialize_u16,
         u32 => serialize_u32,
         u64 => serialize_u64,
+        u128 => serialize_u128,
         i8  => serialize_i8,
         i16 => serialize_i16,
         i32 => serialize_i32,
         i64 => serialize_i64,
+        i128 => serialize_i128,
         f32 => serialize_f32,
         f64 => serialize_f64,
         char => serialize_char,
@@ -735,3 +1203,182 @@ impl ser::Serializer for StringSerializer {
         Err(Error::Unsupported)
     }
 }
+
+/// Serializes map keys, as [`StringSerializer`] does, except that floats are
+/// written so that they parse back to the same value and do not collide with
+/// integer keys, e.g. `1.0` rather than `1`. Non-finite floats are rejected,
+/// since they could not be parsed back as keys, as are values which are not
+/// a string, number, bool or unit variant.
+struct KeySerializer;
+
+macro_rules! serialize_float_key {
+    ($($ty:ty => $meth:ident,)*) => {
+        $(
+            fn $meth(self, v: $ty) -> Result<Self::Ok> {
+                if v.is_finite() {
+                    Ok(format!("{:?}", v))
+                } else {
+                    Err(Error::UnsupportedAsKey {
+                        kind: "non-finite float",
+                    })
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! forward_to_string_serializer {
+    ($($ty:ty => $meth:ident,)*) => {
+        $(
+            fn $meth(self, v: $ty) -> Result<Self::Ok> {
+                StringSerializer.$meth(v)
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = ser::Impossible<String, Error>;
+    type SerializeTuple = ser::Impossible<String, Error>;
+    type SerializeTupleStruct = ser::Impossible<String, Error>;
+    type SerializeTupleVariant = ser::Impossible<String, Error>;
+    type SerializeMap = ser::Impossible<String, Error>;
+    type SerializeStruct = ser::Impossible<String, Error>;
+    type SerializeStructVariant = ser::Impossible<String, Error>;
+
+    serialize_float_key! {
+        f32 => serialize_f32,
+        f64 => serialize_f64,
+    }
+
+    forward_to_string_serializer! {
+        bool => serialize_bool,
+        u8  => serialize_u8,
+        u16 => serialize_u16,
+        u32 => serialize_u32,
+        u64 => serialize_u64,
+        u128 => serialize_u128,
+        i8  => serialize_i8,
+        i16 => serialize_i16,
+        i32 => serialize_i32,
+        i64 => serialize_i64,
+        i128 => serialize_i128,
+        char => serialize_char,
+        &str => serialize_str,
+        &[u8] => serialize_bytes,
+    }
+
+    /// Returns an error.
+    fn serialize_unit(self) -> Result<Self::Ok> {
+        Err(Error::UnsupportedAsKey { kind: "unit" })
+    }
+
+    /// Returns an error.
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> {
+        Err(Error::UnsupportedAsKey {
+            kind: "unit struct",
+        })
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok> {
+        StringSerializer.serialize_unit_variant(name, variant_index, variant)
+    }
+
+    /// Returns an error.
+    fn serialize_newtype_struct<T: ?Sized + ser::Serialize>(
+        self,
+        _name: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok> {
+        Err(Error::UnsupportedAsKey {
+            kind: "newtype struct",
+        })
+    }
+
+    /// Returns an error.
+    fn serialize_newtype_variant<T: ?Sized + ser::Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok> {
+        Err(Error::UnsupportedAsKey {
+            kind: "newtype variant",
+        })
+    }
+
+    /// Returns an error.
+    fn serialize_none(self) -> Result<Self::Ok> {
+        Err(Error::UnsupportedAsKey { kind: "none" })
+    }
+
+    /// Returns an error.
+    fn serialize_some<T: ?Sized + ser::Serialize>(self, _value: &T) -> Result<Self::Ok> {
+        Err(Error::UnsupportedAsKey { kind: "some" })
+    }
+
+    /// Returns an error.
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(Error::UnsupportedAsKey { kind: "sequence" })
+    }
+
+    /// Returns an error.
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(Error::UnsupportedAsKey { kind: "tuple" })
+    }
+
+    /// Returns an error.
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(Error::UnsupportedAsKey {
+            kind: "tuple struct",
+        })
+    }
+
+    /// Returns an error.
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(Error::UnsupportedAsKey {
+            kind: "tuple variant",
+        })
+    }
+
+    /// Returns an error.
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(Error::UnsupportedAsKey { kind: "map" })
+    }
+
+    /// Returns an error.
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(Error::UnsupportedAsKey { kind: "struct" })
+    }
+
+    /// Returns an error.
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(Error::UnsupportedAsKey {
+            kind: "struct variant",
+        })
+    }
+}
@@ -1,4 +1,4 @@
-use percent_encoding::{AsciiSet, NON_ALPHANUMERIC};
+use percent_encoding::{percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use std::borrow::Cow;
 
 pub const QS_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
@@ -6,20 +6,34 @@ pub const QS_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
     .remove(b'*')
     .remove(b'-')
     .remove(b'.')
-    .remove(b'_');
+    .remove(b'_')
+    .remove(b'~');
 
-pub fn replace_space(input: &str) -> Cow<str> {
-    match input.as_bytes().iter().position(|&b| b == b' ') {
-        None => Cow::Borrowed(input),
-        Some(first_position) => {
-            let mut replaced = input.as_bytes().to_owned();
-            replaced[first_position] = b'+';
-            for byte in &mut replaced[first_position + 1..] {
-                if *byte == b' ' {
-                    *byte = b'+';
-                }
-            }
-            Cow::Owned(String::from_utf8(replaced).expect("replacing ' ' with '+' cannot panic"))
-        }
+/// Whether `byte` is written as-is by `QS_ENCODE_SET`.
+///
+/// This is every RFC 3986 unreserved character (`ALPHA / DIGIT / "-" / "." /
+/// "_" / "~"`), plus `*`, which `application/x-www-form-urlencoded` leaves
+/// bare.
+pub fn is_unencoded(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'*' | b'-' | b'.' | b'_' | b'~')
+}
+
+pub fn replace_space(input: &str) -> Cow<'_, str> {
+    if input.contains(' ') {
+        Cow::Owned(input.replace(' ', "+"))
+    } else {
+        Cow::Borrowed(input)
+    }
+}
+
+/// Encodes a single segment of a key, e.g. `a b` as `a+b`.
+pub fn encode_key(key: &str) -> Cow<'_, str> {
+    if key.bytes().all(is_unencoded) {
+        Cow::Borrowed(key)
+    } else {
+        percent_encode(key.as_bytes(), QS_ENCODE_SET)
+            .map(replace_space)
+            .collect::<String>()
+            .into()
     }
 }
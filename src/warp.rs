@@ -4,9 +4,9 @@
 
 extern crate warp_framework as warp;
 
+use crate::web::{ErrorResponse, PROBLEM_JSON};
 use crate::{de::Config as QsConfig, error};
 use serde::de;
-use std::sync::Arc;
 use warp::{http::StatusCode, reject::Reject, Filter, Rejection, Reply};
 
 impl Reject for error::Error {}
@@ -40,8 +40,6 @@ pub fn query<T>(config: QsConfig) -> impl Filter<Extract = (T,), Error = Rejecti
 where
     T: de::DeserializeOwned + Send + 'static,
 {
-    let config = Arc::new(config);
-
     warp::query::raw()
         .or_else(|_| async {
             tracing::debug!("route was called without a query string, defaulting to empty");
@@ -49,8 +47,7 @@ where
             Ok::<_, Rejection>((String::new(),))
         })
         .and_then(move |query: String| {
-            let config = Arc::clone(&config);
-
+            // `Config` is `Copy`, so each request gets its own copy.
             async move {
                 config.deserialize_str(query.as_str()).map_err(|err| {
                     tracing::debug!("failed to decode query string '{}': {:?}", query, err);
@@ -61,6 +58,33 @@ where
         })
 }
 
+/// Like [`query`], using the default [`Config`](crate::Config).
+///
+/// ## Example
+///
+/// ```rust
+/// # extern crate warp_framework as warp;
+/// # #[macro_use] extern crate serde_derive;
+/// use warp::Filter;
+///
+/// #[derive(Deserialize)]
+/// pub struct UsersFilter {
+///    id: Vec<u64>,
+/// }
+///
+/// fn main() {
+///     let filter = serde_qs::warp::query_with::<UsersFilter>()
+///         .map(|info: UsersFilter| info.id.len().to_string())
+///         .recover(serde_qs::warp::recover_fn);
+/// }
+/// ```
+pub fn query_with<T>() -> impl Filter<Extract = (T,), Error = Rejection> + Clone
+where
+    T: de::DeserializeOwned + Send + 'static,
+{
+    query(QsConfig::default())
+}
+
 /// Use this as the function for a `.recover()` after assembled filter
 ///
 /// This is not strictly required but changes the response from a
@@ -75,3 +99,17 @@ pub async fn recover_fn(rejection: Rejection) -> Result<impl Reply, Rejection> {
         Err(rejection)
     }
 }
+
+/// Like [`recover_fn`], but responds with an RFC 7807 problem details body
+/// rather than plain text, see [`ErrorResponse`](crate::web::ErrorResponse).
+pub async fn recover_problem_fn(rejection: Rejection) -> Result<impl Reply, Rejection> {
+    if let Some(err) = rejection.find::<error::Error>() {
+        let problem = ErrorResponse::from_qs_error(err);
+        Ok(warp::reply::with_status(
+            warp::reply::with_header(problem.to_json(), "content-type", PROBLEM_JSON),
+            StatusCode::BAD_REQUEST,
+        ))
+    } else {
+        Err(rejection)
+    }
+}
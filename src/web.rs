@@ -0,0 +1,122 @@
+//! Error responses shared by the web framework integrations.
+//!
+//! Enabled by any of the `actix4`, `actix3`, `warp` or `axum` features.
+
+use crate::error::Error as QsError;
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+/// The media type of a problem details body.
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// An [RFC 7807](https://www.rfc-editor.org/rfc/rfc7807) problem details
+/// description of a rejected query string.
+///
+/// By default the framework integrations respond to errors with a plain text
+/// body. They can be configured to respond with this type instead, serialized
+/// as `application/problem+json`:
+///
+/// ```json
+/// {
+///   "type": "about:blank",
+///   "title": "Bad Request",
+///   "status": 400,
+///   "detail": "invalid value for `id`: invalid digit found in string",
+///   "invalid-params": [{ "name": "id", "reason": "invalid digit found in string" }]
+/// }
+/// ```
+///
+/// The `invalid-params` member is omitted when no parameters are known.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErrorResponse {
+    /// A URI reference identifying the problem type.
+    pub problem_type: String,
+    /// A short, human-readable summary of the problem type.
+    pub title: String,
+    /// The HTTP status code.
+    pub status: u16,
+    /// A human-readable explanation specific to this occurrence.
+    pub detail: String,
+    /// The parameters which caused the problem, if known.
+    pub invalid_params: Vec<InvalidParam>,
+}
+
+/// A single entry of the `invalid-params` extension member.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidParam {
+    /// The key path of the parameter, e.g. `user[ids][0]`.
+    pub name: String,
+    /// Why the parameter was rejected.
+    pub reason: String,
+}
+
+impl ErrorResponse {
+    /// Create a "400 Bad Request" problem from a `serde_qs` error.
+    pub fn from_qs_error(error: &QsError) -> Self {
+        let problem = ErrorResponse {
+            problem_type: "about:blank".to_string(),
+            title: "Bad Request".to_string(),
+            status: 400,
+            detail: error.to_string(),
+            invalid_params: Vec::new(),
+        };
+        match error {
+            QsError::EncodedBracketInKey { key, error, .. }
+            | QsError::InvalidValue { key, error } => {
+                problem.invalid_param(key.as_str(), error.to_string())
+            }
+            _ => problem,
+        }
+    }
+
+    /// Set the HTTP status code.
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Add an entry to the `invalid-params` extension member.
+    pub fn invalid_param<N, R>(mut self, name: N, reason: R) -> Self
+    where
+        N: Into<String>,
+        R: Into<String>,
+    {
+        self.invalid_params.push(InvalidParam {
+            name: name.into(),
+            reason: reason.into(),
+        });
+        self
+    }
+
+    /// Serialize this problem as JSON.
+    pub fn to_json(&self) -> String {
+        // Only strings and numbers are written, which JSON can always
+        // represent, whatever the request contained.
+        #[allow(clippy::expect_used)]
+        serde_json::to_string(self).expect("serializing an ErrorResponse cannot fail")
+    }
+}
+
+impl Serialize for ErrorResponse {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len = if self.invalid_params.is_empty() { 4 } else { 5 };
+        let mut map = serializer.serialize_map(Some(len))?;
+        map.serialize_entry("type", &self.problem_type)?;
+        map.serialize_entry("title", &self.title)?;
+        map.serialize_entry("status", &self.status)?;
+        map.serialize_entry("detail", &self.detail)?;
+        if !self.invalid_params.is_empty() {
+            map.serialize_entry("invalid-params", &self.invalid_params)?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for InvalidParam {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("name", &self.name)?;
+        map.serialize_entry("reason", &self.reason)?;
+        map.end()
+    }
+}
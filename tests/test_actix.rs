@@ -16,7 +16,9 @@ use actix_web::error::InternalError;
 use actix_web::http::StatusCode;
 use actix_web::test::TestRequest;
 use actix_web::{FromRequest, HttpResponse};
-use qs::actix::{QsForm, QsQuery, QsQueryConfig};
+use qs::actix::{
+    FlatQsQuery, OptionalQsQuery, QsForm, QsFormConfig, QsQuery, QsQueryConfig, QsQueryOrDefault,
+};
 use qs::Config as QsConfig;
 use serde::de::Error;
 
@@ -165,3 +167,334 @@ fn test_form_extractor() {
         assert_eq!(s.into_inner(), test_data);
     })
 }
+
+/// A form request with the given content type, if any.
+fn form_request(content_type: Option<&str>, body: &str) -> TestRequest {
+    let req = TestRequest::with_uri("/test").set_payload(body.to_owned());
+    match content_type {
+        #[cfg(feature = "actix4")]
+        Some(content_type) => req.insert_header(("content-type", content_type)),
+        #[cfg(feature = "actix3")]
+        Some(content_type) => req.header("content-type", content_type),
+        None => req,
+    }
+}
+
+#[test]
+fn test_form_extractor_content_type() {
+    futures::executor::block_on(async {
+        let body = "foo=1&bars[0]=0&limit=100&offset=50&remaining=true";
+
+        for content_type in [
+            None,
+            Some("application/x-www-form-urlencoded"),
+            Some("application/x-www-form-urlencoded; charset=UTF-8"),
+            Some("Application/X-WWW-Form-Urlencoded;charset=utf-8"),
+        ] {
+            let (req, mut pl) = form_request(content_type, body)
+                .to_srv_request()
+                .into_parts();
+            let s = QsForm::<Query>::from_request(&req, &mut pl).await.unwrap();
+            assert_eq!(s.foo, 1);
+        }
+
+        let (req, mut pl) = form_request(Some("application/json"), r#"{"foo":1}"#)
+            .to_srv_request()
+            .into_parts();
+        let e = QsForm::<Query>::from_request(&req, &mut pl)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.as_response_error().error_response().status(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+
+        // a missing content type is only rejected when configured
+        let (req, mut pl) = form_request(None, body)
+            .app_data(QsFormConfig::default().require_content_type(true))
+            .to_srv_request()
+            .into_parts();
+        let e = QsForm::<Query>::from_request(&req, &mut pl)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.as_response_error().error_response().status(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+
+        let (req, mut pl) = form_request(Some("text/plain"), body)
+            .app_data(QsFormConfig::default().allowed_content_types(&["text/plain"]))
+            .to_srv_request()
+            .into_parts();
+        let s = QsForm::<Query>::from_request(&req, &mut pl).await.unwrap();
+        assert_eq!(s.foo, 1);
+    })
+}
+
+#[test]
+fn test_form_extractor_limit() {
+    futures::executor::block_on(async {
+        let body = "foo=1&bars[0]=0&limit=100&offset=50&remaining=true";
+
+        let (req, mut pl) = form_request(None, body)
+            .app_data(QsFormConfig::default().limit(body.len()))
+            .to_srv_request()
+            .into_parts();
+        let s = QsForm::<Query>::from_request(&req, &mut pl).await.unwrap();
+        assert_eq!(s.foo, 1);
+
+        let (req, mut pl) = form_request(None, body)
+            .app_data(QsFormConfig::default().limit(body.len() - 1))
+            .to_srv_request()
+            .into_parts();
+        let e = QsForm::<Query>::from_request(&req, &mut pl)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.as_response_error().error_response().status(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    })
+}
+
+#[test]
+#[cfg(feature = "actix4")]
+fn test_form_extractor_limit_without_length() {
+    use actix_web::dev::Payload;
+    use actix_web::web::Bytes;
+
+    futures::executor::block_on(async {
+        let (req, _) = TestRequest::with_uri("/test")
+            .app_data(QsFormConfig::default().limit(20))
+            .to_srv_request()
+            .into_parts();
+        let chunks: Vec<Result<Bytes, actix_web::error::PayloadError>> = vec![
+            Ok(Bytes::from_static(b"foo=1&bars[0]=0")),
+            Ok(Bytes::from_static(b"&limit=100")),
+        ];
+        let mut pl: Payload = Payload::Stream {
+            payload: Box::pin(futures::stream::iter(chunks)),
+        };
+
+        let e = QsForm::<Query>::from_request(&req, &mut pl)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.as_response_error().error_response().status(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    })
+}
+
+#[test]
+#[cfg(feature = "actix4")]
+fn test_form_extractor_disconnect() {
+    use actix_web::dev::Payload;
+    use actix_web::error::PayloadError;
+    use actix_web::web::Bytes;
+
+    futures::executor::block_on(async {
+        let (req, _) = TestRequest::with_uri("/test").to_srv_request().into_parts();
+        let chunks = vec![
+            Ok(Bytes::from_static(b"foo=1&bars[0]=0")),
+            Err(PayloadError::Incomplete(None)),
+        ];
+        let mut pl: Payload = Payload::Stream {
+            payload: Box::pin(futures::stream::iter(chunks)),
+        };
+
+        let err = QsForm::<Query>::from_request(&req, &mut pl)
+            .await
+            .unwrap_err();
+        assert!(err.as_error::<PayloadError>().is_some());
+    })
+}
+
+#[test]
+fn test_problem_details_error_response() {
+    futures::executor::block_on(async {
+        let req = TestRequest::with_uri("/test?foo=abc")
+            .app_data(QsQueryConfig::default().problem_details(true))
+            .to_srv_request();
+        let (req, mut pl) = req.into_parts();
+
+        let e = QsQuery::<Query>::from_request(&req, &mut pl)
+            .await
+            .unwrap_err();
+        let resp = e.as_response_error().error_response();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            resp.headers()
+                .get(actix_web::http::header::CONTENT_TYPE)
+                .unwrap(),
+            "application/problem+json"
+        );
+
+        #[cfg(feature = "actix4")]
+        {
+            let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+            let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(problem["type"], "about:blank");
+            assert_eq!(problem["title"], "Bad Request");
+            assert_eq!(problem["status"], 400);
+            assert_eq!(
+                problem["detail"],
+                "invalid value for `foo`: invalid digit found in string"
+            );
+            assert_eq!(problem["invalid-params"][0]["name"], "foo");
+        }
+    })
+}
+
+#[test]
+fn test_optional_querystring_extractor() {
+    futures::executor::block_on(async {
+        let req = TestRequest::with_uri("/test").to_srv_request();
+        let (req, mut pl) = req.into_parts();
+        let s = OptionalQsQuery::<Query>::from_request(&req, &mut pl)
+            .await
+            .unwrap();
+        assert!(s.is_none());
+
+        let req = TestRequest::with_uri(
+            "/test?foo=1&bars[]=0&bars[]=1&limit=100&offset=50&remaining=true",
+        )
+        .to_srv_request();
+        let (req, mut pl) = req.into_parts();
+        let s = OptionalQsQuery::<Query>::from_request(&req, &mut pl)
+            .await
+            .unwrap()
+            .into_inner()
+            .unwrap();
+        assert_eq!(s.foo, 1);
+        assert_eq!(s.bars, vec![0, 1]);
+
+        let req = TestRequest::with_uri("/test?foo=abc")
+            .app_data(QsQueryConfig::default().error_handler(|e, _| {
+                let resp = HttpResponse::UnprocessableEntity().finish();
+                InternalError::from_response(e, resp).into()
+            }))
+            .to_srv_request();
+        let (req, mut pl) = req.into_parts();
+        let e = OptionalQsQuery::<Query>::from_request(&req, &mut pl)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.as_response_error().error_response().status(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+    })
+}
+
+#[test]
+fn test_default_querystring_extractor() {
+    #[derive(Deserialize, Debug, Default, PartialEq)]
+    struct Pagination {
+        page: u32,
+        per_page: u32,
+    }
+
+    futures::executor::block_on(async {
+        let req = TestRequest::with_uri("/test").to_srv_request();
+        let (req, mut pl) = req.into_parts();
+        let s = QsQueryOrDefault::<Pagination>::from_request(&req, &mut pl)
+            .await
+            .unwrap();
+        assert_eq!(s.into_inner(), Pagination::default());
+
+        let req = TestRequest::with_uri("/test?page=2&per_page=20").to_srv_request();
+        let (req, mut pl) = req.into_parts();
+        let s = QsQueryOrDefault::<Pagination>::from_request(&req, &mut pl)
+            .await
+            .unwrap();
+        assert_eq!(
+            s.into_inner(),
+            Pagination {
+                page: 2,
+                per_page: 20
+            }
+        );
+
+        let req = TestRequest::with_uri("/test?page=two").to_srv_request();
+        let (req, mut pl) = req.into_parts();
+        let e = QsQueryOrDefault::<Pagination>::from_request(&req, &mut pl)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.as_response_error().error_response().status(),
+            StatusCode::BAD_REQUEST
+        );
+    })
+}
+
+#[test]
+fn test_flat_querystring_extractor() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Search {
+        q: String,
+        page: u32,
+    }
+
+    futures::executor::block_on(async {
+        let req = TestRequest::with_uri("/test?q=rust&page=2").to_srv_request();
+        let (req, mut pl) = req.into_parts();
+        let s = FlatQsQuery::<Search>::from_request(&req, &mut pl)
+            .await
+            .unwrap();
+        assert_eq!(
+            s.into_inner(),
+            Search {
+                q: "rust".to_string(),
+                page: 2
+            }
+        );
+
+        let req = TestRequest::with_uri("/test?q[]=rust&page=2").to_srv_request();
+        let (req, mut pl) = req.into_parts();
+        let e = FlatQsQuery::<Search>::from_request(&req, &mut pl)
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("nested key `q[]`"));
+        assert_eq!(
+            e.as_response_error().error_response().status(),
+            StatusCode::BAD_REQUEST
+        );
+    })
+}
+
+#[cfg(feature = "validator")]
+#[test]
+fn test_validated_querystring_extractor() {
+    use qs::actix::ValidatedQsQuery;
+    use validator::Validate;
+
+    #[derive(Deserialize, Validate, Debug)]
+    struct Page {
+        #[validate(range(min = 1, max = 100))]
+        size: u32,
+    }
+
+    futures::executor::block_on(async {
+        let req = TestRequest::with_uri("/test?size=10").to_srv_request();
+        let (req, mut pl) = req.into_parts();
+        let page = ValidatedQsQuery::<Page>::from_request(&req, &mut pl)
+            .await
+            .unwrap();
+        assert_eq!(page.size, 10);
+
+        let req = TestRequest::with_uri("/test?size=1000")
+            .app_data(QsQueryConfig::default().error_handler(|e, _| {
+                assert!(matches!(e, qs::Error::Validation(_)));
+                InternalError::from_response(e, HttpResponse::BadRequest().finish()).into()
+            }))
+            .to_srv_request();
+        let (req, mut pl) = req.into_parts();
+        let e = ValidatedQsQuery::<Page>::from_request(&req, &mut pl)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.as_response_error().error_response().status(),
+            StatusCode::BAD_REQUEST
+        );
+    })
+}
@@ -0,0 +1,94 @@
+#![cfg(feature = "bumpalo")]
+
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_qs as qs;
+
+use qs::QsParserArena;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+type Json = HashMap<String, serde_json::Value>;
+
+/// Deserializes `input` with and without an arena, which must agree.
+fn check(config: &qs::Config, arena: &mut QsParserArena, input: &str) {
+    let expected = config.deserialize_str::<Json>(input);
+    let actual = arena.deserialize_str::<Json>(config, input);
+    match (expected, actual) {
+        (Ok(expected), Ok(actual)) => assert_eq!(expected, actual, "{}", input),
+        (Err(expected), Err(actual)) => {
+            assert_eq!(expected.to_string(), actual.to_string(), "{}", input)
+        }
+        (expected, actual) => panic!("{}: {:?} != {:?}", input, expected, actual),
+    }
+}
+
+#[test]
+fn arena_matches_default_parser() {
+    /// Decodes Latin-1, always returning an owned string.
+    fn latin1(bytes: Cow<[u8]>) -> Result<Cow<str>, qs::TranscodeError> {
+        Ok(Cow::Owned(bytes.iter().map(|&b| b as char).collect()))
+    }
+
+    let inputs = [
+        "",
+        "a=1&b=two",
+        "a=hello+world&b=%2B1&c=100%25",
+        "a=%2&b=%zz&c=%",
+        "a=caf%C3%A9&b=%E2%9C%93+ok",
+        "a=%FF&b=x",
+        "user[name]=Ada+Lovelace&user[address][city]=London&page=%32",
+        "ids[0]=%31&ids[1]=2&tags[]=a+b&tags[]=%63",
+        "a%5Bb%5D=1&a[c]=%32",
+        "a[b]=1&a=2",
+        "flag&flag2=&v=+",
+    ];
+    let mut arena = QsParserArena::new();
+    for config in [
+        qs::Config::default(),
+        qs::Config::new(5, false),
+        qs::Config::default().value_transcoder(latin1),
+        qs::Config::default().assume_preencoded_values(true),
+        qs::Config::default().strict_percent_decoding(true),
+    ] {
+        for input in inputs {
+            check(&config, &mut arena, input);
+        }
+    }
+}
+
+#[test]
+fn borrow_decoded_values() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Query<'a> {
+        name: &'a str,
+        tags: Vec<&'a str>,
+        page: u32,
+    }
+
+    let config = qs::Config::default();
+    let input = "name=Ada+Lovelace&tags[]=a%26b&tags[]=plain&page=%32";
+    // decoded values cannot be borrowed from the input
+    assert!(config.deserialize_str::<Query>(input).is_err());
+
+    let mut arena = QsParserArena::new();
+    let query: Query = arena.deserialize_str(&config, input).unwrap();
+    assert_eq!(
+        query,
+        Query {
+            name: "Ada Lovelace",
+            tags: vec!["a&b", "plain"],
+            page: 2,
+        }
+    );
+
+    // the arena is reset for each querystring, including after an error
+    let error = arena
+        .deserialize_str::<Query>(&config, "name=Grace%20Hopper&page=1")
+        .unwrap_err();
+    assert!(error.to_string().contains("tags"), "{}", error);
+    let query: Query = arena
+        .deserialize_str(&config, "name=Grace%20Hopper&tags[]=x&page=1")
+        .unwrap();
+    assert_eq!(query.name, "Grace Hopper");
+}
@@ -7,9 +7,16 @@ extern crate serde_derive;
 extern crate axum_framework as axum;
 extern crate serde_qs as qs;
 
-use axum::{extract::FromRequestParts, http::StatusCode, response::IntoResponse};
-use qs::axum::{QsQuery, QsQueryConfig, QsQueryRejection};
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use qs::axum::{
+    FlatQsQuery, ParseCounter, QsQuery, QsQueryConfig, QsQueryRejection, QsQueryWithState,
+};
 use serde::de::Error;
+use std::sync::atomic::Ordering;
 
 fn from_str<'de, D, S>(deserializer: D) -> Result<S, D::Error>
 where
@@ -96,6 +103,127 @@ fn test_composite_querystring_extractor() {
     })
 }
 
+#[test]
+fn test_whole_input_extractor() {
+    futures::executor::block_on(async {
+        let query = "foo=1&bars[]=0&limit=100&offset=50&remaining=true&sig=abc";
+        let req = axum::http::Request::builder()
+            .uri(format!("/test?{}", query))
+            .body(())
+            .unwrap();
+        let (mut req_parts, _) = req.into_parts();
+        let QsQuery(qs::WholeInput(s, raw)) =
+            QsQuery::<qs::WholeInput<Query>>::from_request_parts(&mut req_parts, &())
+                .await
+                .unwrap();
+        assert_eq!(s.foo, 1);
+        assert_eq!(s.bars, vec![0]);
+        assert_eq!(s.common.limit, 100);
+        assert_eq!(raw, query);
+    })
+}
+
+#[test]
+fn test_parsed_query_reused_across_extractors() {
+    #[derive(Deserialize)]
+    struct Paging {
+        limit: u64,
+    }
+
+    futures::executor::block_on(async {
+        let req = axum::http::Request::builder()
+            .uri("/test?foo=1&bars[]=0&bars[]=1&limit=100&offset=50&remaining=true")
+            .body(())
+            .unwrap();
+        let (mut req_parts, _) = req.into_parts();
+        let parses = ParseCounter::default();
+        let _ = req_parts.extensions.insert(parses.clone());
+        let s = QsQuery::<Query>::from_request_parts(&mut req_parts, &())
+            .await
+            .unwrap();
+        assert_eq!(s.bars, vec![0, 1]);
+        assert_eq!(parses.0.load(Ordering::Relaxed), 1);
+
+        // a second extractor reuses the parsed query
+        let paging = QsQuery::<Paging>::from_request_parts(&mut req_parts, &())
+            .await
+            .unwrap();
+        assert_eq!(paging.limit, 100);
+        assert_eq!(parses.0.load(Ordering::Relaxed), 1);
+        let QsQuery(qs::WholeInput(s, raw)) =
+            QsQuery::<qs::WholeInput<Query>>::from_request_parts(&mut req_parts, &())
+                .await
+                .unwrap();
+        assert_eq!(s.foo, 1);
+        assert_eq!(
+            raw,
+            "foo=1&bars[]=0&bars[]=1&limit=100&offset=50&remaining=true"
+        );
+        assert_eq!(parses.0.load(Ordering::Relaxed), 1);
+
+        // a changed query is parsed again
+        req_parts.uri = "/test?foo=2&bars[]=3&limit=1&offset=0&remaining=false"
+            .parse()
+            .unwrap();
+        let s = QsQuery::<Query>::from_request_parts(&mut req_parts, &())
+            .await
+            .unwrap();
+        assert_eq!(s.foo, 2);
+        assert_eq!(s.bars, vec![3]);
+        assert_eq!(parses.0.load(Ordering::Relaxed), 2);
+    })
+}
+
+#[test]
+fn test_parsed_query_not_reused_with_other_config() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Outer {
+        a: Inner,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Inner {
+        b: u8,
+    }
+
+    #[derive(Clone)]
+    struct State {
+        config: QsQueryConfig,
+    }
+
+    impl FromRef<State> for QsQueryConfig {
+        fn from_ref(state: &State) -> Self {
+            state.config.clone()
+        }
+    }
+
+    futures::executor::block_on(async {
+        let req = axum::http::Request::builder()
+            .uri("/test?a%5Bb%5D=1")
+            .body(())
+            .unwrap();
+        let (mut req_parts, _) = req.into_parts();
+
+        // strict by default, so the encoded brackets are part of the key
+        let map = QsQuery::<std::collections::HashMap<String, u8>>::from_request_parts(
+            &mut req_parts,
+            &(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(map["a[b]"], 1);
+
+        let state = State {
+            config: QsQueryConfig::new(5, false),
+        };
+        let QsQueryWithState(outer) =
+            QsQueryWithState::<Outer>::from_request_parts(&mut req_parts, &state)
+                .await
+                .unwrap();
+        assert_eq!(outer, Outer { a: Inner { b: 1 } });
+    })
+}
+
 #[test]
 fn test_default_qs_config() {
     futures::executor::block_on(async {
@@ -132,3 +260,178 @@ fn test_custom_qs_config() {
         assert!(s.common.remaining);
     })
 }
+
+#[test]
+fn test_problem_details_error_response() {
+    futures::executor::block_on(async {
+        let req = axum::http::Request::builder()
+            .uri("/test?foo=abc")
+            .extension(QsQueryConfig::default().problem_details(true))
+            .body(())
+            .unwrap();
+        let (mut req_parts, _) = req.into_parts();
+        let e = QsQuery::<Query>::from_request_parts(&mut req_parts, &())
+            .await
+            .unwrap_err();
+
+        let res = e.into_response();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            res.headers()[axum::http::header::CONTENT_TYPE],
+            "application/problem+json"
+        );
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["type"], "about:blank");
+        assert_eq!(problem["title"], "Bad Request");
+        assert_eq!(problem["status"], 400);
+        assert_eq!(
+            problem["detail"],
+            "invalid value for `foo`: invalid digit found in string"
+        );
+        assert_eq!(problem["invalid-params"][0]["name"], "foo");
+    })
+}
+
+#[test]
+fn test_plain_text_error_response() {
+    futures::executor::block_on(async {
+        let req = axum::http::Request::builder()
+            .uri("/test?foo=abc")
+            .body(())
+            .unwrap();
+        let (mut req_parts, _) = req.into_parts();
+        let e = QsQuery::<Query>::from_request_parts(&mut req_parts, &())
+            .await
+            .unwrap_err();
+
+        let res = e.into_response();
+        assert_eq!(
+            res.headers()[axum::http::header::CONTENT_TYPE],
+            "text/plain; charset=utf-8"
+        );
+    })
+}
+
+#[test]
+fn test_config_from_state() {
+    #[derive(Clone)]
+    struct AppState {
+        qs_config: QsQueryConfig,
+    }
+
+    impl FromRef<AppState> for QsQueryConfig {
+        fn from_ref(state: &AppState) -> Self {
+            state.qs_config.clone()
+        }
+    }
+
+    futures::executor::block_on(async {
+        let state = AppState {
+            qs_config: QsQueryConfig::new(0, true),
+        };
+        let req = axum::http::Request::builder()
+            .uri("/test?a[b]=1")
+            .body(())
+            .unwrap();
+        let (mut req_parts, _) = req.into_parts();
+
+        // With a max depth of 0, the nested key is kept as is.
+        let QsQueryWithState(map) =
+            QsQueryWithState::<std::collections::HashMap<String, String>>::from_request_parts(
+                &mut req_parts,
+                &state,
+            )
+            .await
+            .unwrap();
+        assert_eq!(map["a[b]"], "1");
+
+        // `QsQuery` does not look at the state, so uses the default config.
+        let e = QsQuery::<std::collections::HashMap<String, String>>::from_request_parts(
+            &mut req_parts,
+            &state,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(e.into_response().status(), StatusCode::BAD_REQUEST);
+    })
+}
+
+#[test]
+fn test_flat_querystring_extractor() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Search {
+        q: String,
+        page: u32,
+    }
+
+    futures::executor::block_on(async {
+        let req = axum::http::Request::builder()
+            .uri("/test?q=rust&page=2")
+            .body(())
+            .unwrap();
+        let (mut req_parts, _) = req.into_parts();
+        let FlatQsQuery(s) = FlatQsQuery::<Search>::from_request_parts(&mut req_parts, &())
+            .await
+            .unwrap();
+        assert_eq!(
+            s,
+            Search {
+                q: "rust".to_string(),
+                page: 2
+            }
+        );
+
+        let req = axum::http::Request::builder()
+            .uri("/test?q=rust&page[x]=2")
+            .body(())
+            .unwrap();
+        let (mut req_parts, _) = req.into_parts();
+        let e = FlatQsQuery::<Search>::from_request_parts(&mut req_parts, &())
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("nested key `page[x]`"));
+        assert_eq!(e.into_response().status(), StatusCode::BAD_REQUEST);
+    })
+}
+
+#[cfg(feature = "validator")]
+#[test]
+fn test_validated_querystring_extractor() {
+    use qs::axum::ValidatedQsQuery;
+    use validator::Validate;
+
+    #[derive(Deserialize, Validate, Debug)]
+    struct Page {
+        #[validate(range(min = 1, max = 100))]
+        size: u32,
+    }
+
+    futures::executor::block_on(async {
+        let req = axum::http::Request::builder()
+            .uri("/test?size=10")
+            .body(())
+            .unwrap();
+        let (mut req_parts, _) = req.into_parts();
+        let page = ValidatedQsQuery::<Page>::from_request_parts(&mut req_parts, &())
+            .await
+            .unwrap();
+        assert_eq!(page.size, 10);
+
+        let req = axum::http::Request::builder()
+            .uri("/test?size=1000")
+            .extension(QsQueryConfig::default().error_handler(|err| {
+                assert!(matches!(err, qs::Error::Validation(_)));
+                QsQueryRejection::new(err, StatusCode::BAD_REQUEST)
+            }))
+            .body(())
+            .unwrap();
+        let (mut req_parts, _) = req.into_parts();
+        let e = ValidatedQsQuery::<Page>::from_request_parts(&mut req_parts, &())
+            .await
+            .unwrap_err();
+        assert_eq!(e.into_response().status(), StatusCode::BAD_REQUEST);
+    })
+}
@@ -3,7 +3,7 @@ extern crate serde;
 extern crate serde_derive;
 extern crate serde_qs as qs;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 struct Address {
@@ -157,7 +157,13 @@ fn qs_test_simple() {
     map_test!("foo=bar", "foo"["bar"]);
 
     // st.deepEqual(qs.parse(' foo = bar = baz '), { ' foo ': ' bar = baz ' });
-    map_test!(" foo = bar = baz ", " foo "[" bar = baz "]);
+    // Unlike qs, whitespace around the whole input is trimmed by default.
+    map_test!(" foo = bar = baz ", "foo "[" bar = baz"]);
+    let map: HashMap<String, String> = qs::Config::default()
+        .trim_input(false)
+        .deserialize_str(" foo = bar = baz ")
+        .unwrap();
+    assert_eq!(map[" foo "], " bar = baz ");
 
     // st.deepEqual(qs.parse('foo=bar=baz'), { foo: 'bar=baz' });
     map_test!("foo=bar=baz", "foo"["bar=baz"]);
@@ -220,10 +226,9 @@ fn qs_nesting() {
     //     { a: { b: { c: { d: { e: { f: { '[g][h]': 'i' } } } } } } },
     //     'defaults to a depth of 5'
     // );
-    // This looks like depth 6 to me? Tweaked test to make it 5.
     map_test!(
         "a[b][c][d][e][f][g][h]=i",
-        "a"["b"["c"["d"["e"["[f][g][h]"["i"]]]]]]
+        "a"["b"["c"["d"["e"["f"["[g][h]"["i"]]]]]]]
     );
 }
 
@@ -347,6 +352,174 @@ fn deserialize_enum_adjacently() {
     );
 }
 
+#[test]
+fn deserialize_enum_adjacently_out_of_order() {
+    // The content key sorts before the tag key.
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(tag = "t", content = "c")]
+    enum V {
+        V1 { x: u8, y: u16 },
+        V2(bool),
+        V3(Vec<u8>),
+        V4,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Query {
+        v: V,
+    }
+
+    let cases = [
+        ("v[t]=V1&v[c][x]=12&v[c][y]=300", V::V1 { x: 12, y: 300 }),
+        ("v[c][y]=300&v[c][x]=12&v[t]=V1", V::V1 { x: 12, y: 300 }),
+        ("v[c]=true&v[t]=V2", V::V2(true)),
+        ("v[c][1]=2&v[c][0]=1&v[t]=V3", V::V3(vec![1, 2])),
+        ("v[t]=V4", V::V4),
+    ];
+    for (input, expected) in cases {
+        let query: Query = qs::from_str(input).unwrap();
+        assert_eq!(query.v, expected, "{}", input);
+    }
+
+    // At the top level too.
+    let v: V = qs::from_str("c[x]=12&c[y]=300&t=V1").unwrap();
+    assert_eq!(v, V::V1 { x: 12, y: 300 });
+}
+
+#[test]
+fn deserialize_enum_internally_tagged_top_level() {
+    use serde_with::{serde_as, DisplayFromStr};
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct User {
+        id: String,
+        name: String,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Action {
+        action_id: String,
+        value: String,
+    }
+
+    #[serde_as]
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    enum Payload {
+        Message {
+            channel_id: String,
+            ts: String,
+        },
+        BlockActions {
+            trigger_id: String,
+            user: User,
+            actions: Vec<Action>,
+        },
+        // The content is buffered as strings, so numbers need parsing.
+        Retry {
+            #[serde_as(as = "DisplayFromStr")]
+            attempt: u32,
+        },
+    }
+
+    let message = Payload::Message {
+        channel_id: "C1".to_owned(),
+        ts: "123.45".to_owned(),
+    };
+    for input in [
+        "type=message&channel_id=C1&ts=123.45",
+        "channel_id=C1&ts=123.45&type=message",
+        "ts=123.45&type=message&channel_id=C1",
+    ] {
+        assert_eq!(
+            qs::from_str::<Payload>(input).unwrap(),
+            message,
+            "{}",
+            input
+        );
+    }
+
+    let block_actions = Payload::BlockActions {
+        trigger_id: "T1".to_owned(),
+        user: User {
+            id: "U1".to_owned(),
+            name: "alice".to_owned(),
+        },
+        actions: vec![
+            Action {
+                action_id: "approve".to_owned(),
+                value: "yes".to_owned(),
+            },
+            Action {
+                action_id: "comment".to_owned(),
+                value: "looks good".to_owned(),
+            },
+        ],
+    };
+    let input = "actions[0][action_id]=approve&actions[0][value]=yes&\
+                 actions[1][action_id]=comment&actions[1][value]=looks+good&\
+                 trigger_id=T1&type=block_actions&user[id]=U1&user[name]=alice";
+    assert_eq!(qs::from_str::<Payload>(input).unwrap(), block_actions);
+
+    assert_eq!(
+        qs::from_str::<Payload>("attempt=2&type=retry").unwrap(),
+        Payload::Retry { attempt: 2 }
+    );
+
+    assert_eq!(
+        qs::from_str::<Payload>("channel_id=C1&ts=1")
+            .unwrap_err()
+            .to_string(),
+        "missing field `type`"
+    );
+    assert!(qs::from_str::<Payload>("type=unknown&channel_id=C1").is_err());
+}
+
+#[test]
+fn deserialize_enum_with_multiple_variants() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum E {
+        B(u8),
+        S(String),
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Query {
+        e: E,
+    }
+
+    match qs::from_str::<Query>("e[B]=1&e[S]=x") {
+        Err(qs::Error::MultipleVariants { found }) => assert_eq!(found, ["B", "S"]),
+        res => panic!("unexpected result: {:?}", res),
+    }
+
+    let err = qs::from_str::<E>("S=x&B=1").unwrap_err();
+    assert!(matches!(err, qs::Error::MultipleVariants { ref found } if found == &["B", "S"]));
+    assert_eq!(
+        err.to_string(),
+        "expected a single enum variant, found B, S"
+    );
+
+    // siblings which are not variants are still ignored
+    assert_eq!(qs::from_str::<E>("a=1&S=x").unwrap(), E::S("x".to_owned()));
+    assert!(qs::from_str::<Query>("e=").is_err());
+
+    // adjacently tagged enums have a tag and content key, so are unaffected
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(tag = "B", content = "S")]
+    enum Tagged {
+        V(u8),
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct TaggedQuery {
+        e: Tagged,
+    }
+
+    let query: TaggedQuery = qs::from_str("e[B]=V&e[S]=1").unwrap();
+    assert_eq!(query.e, Tagged::V(1));
+}
+
 #[test]
 fn deserialize_enum() {
     #[derive(Deserialize, Debug, PartialEq)]
@@ -402,6 +575,51 @@ fn deserialize_enum() {
     assert_eq!(rec_params, E::S("Hello World".to_string()));
 }
 
+#[test]
+fn newtype_variant_round_trip() {
+    #[derive(Deserialize, Serialize, Debug, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum E {
+        Ids(Vec<u8>),
+        Map(BTreeMap<String, u8>),
+        Opt(Option<u8>),
+    }
+
+    #[derive(Deserialize, Serialize, Debug, PartialEq)]
+    struct Query {
+        a: u8,
+        e: E,
+    }
+
+    let map = vec![("k".to_owned(), 1)].into_iter().collect();
+    let values = vec![
+        (
+            E::Ids(vec![1, 2]),
+            "ids[0]=1&ids[1]=2",
+            "e[ids][0]=1&e[ids][1]=2",
+        ),
+        (E::Ids(vec![]), "ids=", "e[ids]="),
+        (E::Map(map), "map[k]=1", "e[map][k]=1"),
+        (E::Map(BTreeMap::new()), "map=", "e[map]="),
+        (E::Opt(Some(3)), "opt=3", "e[opt]=3"),
+        (E::Opt(None), "opt=", "e[opt]="),
+    ];
+    for (value, top_level, nested) in values {
+        let encoded = qs::to_string(&value).unwrap();
+        assert_eq!(encoded, top_level);
+        assert_eq!(qs::from_str::<E>(&encoded).unwrap(), value);
+
+        let query = Query { a: 1, e: value };
+        let encoded = qs::to_string(&query).unwrap();
+        assert_eq!(encoded, format!("a=1&{}", nested));
+        assert_eq!(qs::from_str::<Query>(&encoded).unwrap(), query);
+    }
+
+    // the variant is found even when a sibling key sorts before it
+    let rec: E = qs::from_str("a=1&ids[1]=2&ids[0]=1").unwrap();
+    assert_eq!(rec, E::Ids(vec![1, 2]));
+}
+
 #[test]
 fn seq_of_struct() {
     #[derive(Deserialize, Debug, PartialEq)]
@@ -642,6 +860,48 @@ fn deserialize_flatten_workaround() {
     assert_eq!(rec_query.unwrap(), query);
 }
 
+#[test]
+fn deserialize_optional_flatten() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Query {
+        a: u8,
+        #[serde(flatten)]
+        common: Option<CommonParams>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct CommonParams {
+        #[serde(deserialize_with = "from_str")]
+        limit: u64,
+        #[serde(deserialize_with = "from_str")]
+        offset: u64,
+    }
+
+    // `serde` deserializes a flattened `Option` as `None` whenever the inner
+    // struct fails to deserialize, so a partial set of fields is `None`
+    // rather than an error.
+    let rec_query: Query = qs::from_str("a=1").unwrap();
+    assert_eq!(rec_query, Query { a: 1, common: None });
+
+    let rec_query: Query = qs::from_str("a=1&limit=100").unwrap();
+    assert_eq!(rec_query, Query { a: 1, common: None });
+
+    let rec_query: Query = qs::from_str("a=1&limit=100&offset=abc").unwrap();
+    assert_eq!(rec_query, Query { a: 1, common: None });
+
+    let rec_query: Query = qs::from_str("a=1&limit=100&offset=50").unwrap();
+    assert_eq!(
+        rec_query,
+        Query {
+            a: 1,
+            common: Some(CommonParams {
+                limit: 100,
+                offset: 50,
+            }),
+        }
+    );
+}
+
 use serde::de::Error;
 
 fn from_str<'de, D, S>(deserializer: D) -> Result<S, D::Error>
@@ -727,6 +987,43 @@ fn deserialize_unit_types() {
     assert_eq!(test, B { t: (), a: "test" });
 }
 
+#[test]
+fn unit_types_roundtrip() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Unit;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct ContainsUnits {
+        unit: (),
+        unit_struct: Unit,
+        tuple_with_unit: (String, (), i32),
+        units: Vec<()>,
+        unit_structs: Vec<Unit>,
+    }
+
+    let value = ContainsUnits {
+        unit: (),
+        unit_struct: Unit,
+        tuple_with_unit: ("test".to_owned(), (), 42),
+        units: vec![(), ()],
+        unit_structs: vec![Unit],
+    };
+    // units are written as empty values, and count as sequence elements
+    let serialized = qs::to_string(&value).unwrap();
+    assert_eq!(
+        serialized,
+        "unit=&unit_struct=&tuple_with_unit[0]=test&tuple_with_unit[1]=&tuple_with_unit[2]=42\
+         &units[0]=&units[1]=&unit_structs[0]="
+    );
+    assert_eq!(qs::from_str::<ContainsUnits>(&serialized).unwrap(), value);
+
+    // a tuple with a unit in the wrong place is rejected
+    assert!(qs::from_str::<ContainsUnits>(
+        "unit=&unit_struct=&tuple_with_unit[0]=test&tuple_with_unit[1]=x&tuple_with_unit[2]=42"
+    )
+    .is_err());
+}
+
 #[test]
 fn serialization_roundtrip() {
     #[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
@@ -742,3 +1039,2632 @@ fn serialization_roundtrip() {
     let deserialized = serde_qs::from_str::<Data>(&serialized).unwrap();
     assert_eq!(deserialized, data);
 }
+
+/// Asserts that `value` deserializes from its serialized form, with and
+/// without `stable_output`.
+fn assert_roundtrip<T>(value: &T)
+where
+    T: std::fmt::Debug + PartialEq + serde::Serialize + serde::de::DeserializeOwned,
+{
+    for config in [
+        qs::Config::default(),
+        qs::Config::default().stable_output(true),
+        qs::Config::default().nesting_style(qs::NestingStyle::Dots),
+    ] {
+        let serialized = config.serialize_string(value).unwrap();
+        let deserialized: T = config
+            .deserialize_str(&serialized)
+            .unwrap_or_else(|e| panic!("failed to deserialize `{}`: {}", serialized, e));
+        assert_eq!(&deserialized, value, "from `{}`", serialized);
+    }
+}
+
+#[test]
+fn numeric_field_names_roundtrip() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Pair {
+        #[serde(rename = "0")]
+        first: String,
+        #[serde(rename = "1")]
+        second: String,
+    }
+
+    // declared out of index order, with a leading zero, and mixed with
+    // other names
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Mixed {
+        #[serde(rename = "2")]
+        two: u8,
+        name: String,
+        #[serde(rename = "01")]
+        one: u8,
+        #[serde(rename = "0")]
+        zero: Vec<u8>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Query {
+        pair: Pair,
+        pairs: Vec<Pair>,
+        mixed: Mixed,
+        reversed: Reversed,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Reversed {
+        #[serde(rename = "1")]
+        one: String,
+        #[serde(rename = "0")]
+        zero: String,
+    }
+
+    let pair = Pair {
+        first: "x".to_owned(),
+        second: "y".to_owned(),
+    };
+    assert_eq!(qs::to_string(&pair).unwrap(), "0=x&1=y");
+    assert_roundtrip(&pair);
+    assert_roundtrip(&Query {
+        pair: Pair {
+            first: "a".to_owned(),
+            second: "b".to_owned(),
+        },
+        pairs: vec![pair],
+        mixed: Mixed {
+            two: 2,
+            name: "n".to_owned(),
+            one: 1,
+            zero: vec![0],
+        },
+        reversed: Reversed {
+            one: "one".to_owned(),
+            zero: "zero".to_owned(),
+        },
+    });
+
+    // integer keys are matched to fields by name, not by position
+    let reversed: Reversed = qs::from_str("0=zero&1=one").unwrap();
+    assert_eq!(reversed.one, "one");
+    let query: HashMap<String, Reversed> = qs::from_str("r[0]=zero&r[1]=one").unwrap();
+    assert_eq!(query["r"].one, "one");
+}
+
+#[test]
+fn signed_integer_keys_roundtrip() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Query<K: Ord + std::hash::Hash> {
+        btree: BTreeMap<K, String>,
+        hash: HashMap<K, u32>,
+    }
+
+    let btree: BTreeMap<i32, String> = vec![(-10, "a"), (-1, "b"), (0, "c"), (2, "d"), (10, "e")]
+        .into_iter()
+        .map(|(k, v)| (k, v.to_owned()))
+        .collect();
+    let hash: HashMap<i32, u32> = vec![(-5, 1), (3, 2), (i32::MIN, 3), (i32::MAX, 4)]
+        .into_iter()
+        .collect();
+    assert_roundtrip(&btree);
+    assert_roundtrip(&hash);
+    assert_roundtrip(&Query { btree, hash });
+
+    let btree: BTreeMap<i8, String> = vec![(-128, "min"), (-1, "neg"), (127, "max")]
+        .into_iter()
+        .map(|(k, v)| (k, v.to_owned()))
+        .collect();
+    let hash: HashMap<i8, u32> = vec![(-1, 1), (0, 2)].into_iter().collect();
+    assert_roundtrip(&Query { btree, hash });
+    let hash: HashMap<i64, u32> = vec![(i64::MIN, 1), (-1, 2)].into_iter().collect();
+    assert_roundtrip(&hash);
+
+    // keys are ordered as integers, not strings
+    let map: BTreeMap<i16, u8> = qs::from_str("10=1&-1=2&2=3&-10=4").unwrap();
+    assert_eq!(map.keys().collect::<Vec<_>>(), [&-10, &-1, &2, &10]);
+
+    // a negative key mixed with indices is a map, not a sequence
+    let query: Query<i8> = qs::from_str("btree[0]=a&btree[-1]=b&hash[-1]=1&hash[1]=2").unwrap();
+    assert_eq!(query.btree[&-1], "b");
+    assert_eq!(query.hash[&1], 2);
+    assert!(qs::from_str::<HashMap<i8, u8>>("-129=1").is_err());
+    assert!(qs::from_str::<HashMap<u8, u8>>("-1=1").is_err());
+}
+#[test]
+fn int128_roundtrip() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Query {
+        unsigned: u128,
+        signed: i128,
+        keys: BTreeMap<i128, u128>,
+        list: Vec<u128>,
+        #[serde(with = "qs::helpers::comma_separated")]
+        delimited: Vec<i128>,
+    }
+
+    let mut keys = BTreeMap::new();
+    keys.insert(i128::MIN, u128::MAX);
+    keys.insert(i128::MAX, 0);
+    let query = Query {
+        unsigned: u128::MAX,
+        signed: i128::MIN,
+        keys,
+        list: vec![u128::MAX, 1],
+        delimited: vec![i128::MIN, i128::MAX],
+    };
+    assert_roundtrip(&query);
+
+    let serialized = qs::to_string(&query).unwrap();
+    assert!(serialized.starts_with(&format!("unsigned={}&signed={}&", u128::MAX, i128::MIN)));
+}
+
+#[test]
+fn leading_question_mark() {
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Query {
+        a: u8,
+    }
+
+    map_test!("?a=1", "a"["1"]);
+    map_test!("?a[b]=1", "a"["b"["1"]]);
+
+    // only a single `?` is skipped
+    map_test!("??a=1", "?a"["1"]);
+
+    // an encoded `?` is always part of the key
+    map_test!("%3Fa=1", "?a"["1"]);
+
+    let rec_query: Query = qs::from_str("?a=1").unwrap();
+    assert_eq!(rec_query, Query { a: 1 });
+
+    let map: HashMap<String, String> = qs::from_str("?").unwrap();
+    assert!(map.is_empty());
+
+    let config = qs::Config::default().allow_leading_question_mark(false);
+    let map: HashMap<String, String> = config.deserialize_str("?a=1").unwrap();
+    assert_eq!(map.get("?a").unwrap(), "1");
+    assert!(config.deserialize_str::<Query>("?a=1").is_err());
+}
+
+#[test]
+fn deserialize_seq_of_unit_enums() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "kebab-case")]
+    enum Status {
+        Active,
+        TemporarilyDisabled,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Query {
+        status: Vec<Status>,
+    }
+
+    let expected = Query {
+        status: vec![Status::Active, Status::TemporarilyDisabled],
+    };
+
+    let rec_query: Query = qs::from_str("status[]=active&status[]=temporarily-disabled").unwrap();
+    assert_eq!(rec_query, expected);
+
+    let rec_query: Query = qs::from_str("status[1]=temporarily-disabled&status[0]=active").unwrap();
+    assert_eq!(rec_query, expected);
+
+    let err = qs::from_str::<Query>("status[]=active&status[]=active&status[]=Disabled")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("unknown variant `Disabled`"), "{}", err);
+    assert!(err.contains("`status[2]`"), "{}", err);
+
+    let err = qs::from_str::<Query>("status[]=active&status[]")
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        err,
+        "invalid value for `status[1]`: missing value for enum Status"
+    );
+
+    let err = qs::from_str::<Query>("status[0]=active&status[3]=oops")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("`status[3]`"), "{}", err);
+}
+
+#[test]
+fn encoded_bracket_error() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    struct Inner {
+        b: u8,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    struct Query {
+        a: Inner,
+    }
+
+    let strict_config = qs::Config::default();
+    let loose_config = qs::Config::new(5, false);
+
+    // top-level key
+    let err = strict_config
+        .deserialize_str::<Query>("a%5Bb%5D=1")
+        .unwrap_err();
+    match err {
+        qs::Error::EncodedBracketInKey {
+            ref key,
+            suggestion,
+            ..
+        } => {
+            assert_eq!(key, "a[b]");
+            assert_eq!(suggestion, qs::Suggestion::UseLenientBracketDecoding);
+        }
+        ref e => panic!("unexpected error: {:?}", e),
+    }
+    assert!(err
+        .to_string()
+        .contains("Invalid field contains an encoded bracket"));
+
+    // nested key
+    let err = strict_config
+        .deserialize_str::<Query>("a[b%5Bc%5D]=1")
+        .unwrap_err();
+    match err {
+        qs::Error::EncodedBracketInKey { ref key, .. } => assert_eq!(key, "b[c]"),
+        ref e => panic!("unexpected error: {:?}", e),
+    }
+
+    // repeated keys
+    let err = strict_config
+        .deserialize_str::<HashMap<String, String>>("a%5B%5D=1&a%5B%5D=2")
+        .unwrap_err();
+    match err {
+        qs::Error::EncodedBracketInKey { ref key, .. } => assert_eq!(key, "a%5B%5D"),
+        ref e => panic!("unexpected error: {:?}", e),
+    }
+
+    // non-strict mode decodes the brackets instead
+    let query: Query = loose_config.deserialize_str("a%5Bb%5D=1").unwrap();
+    assert_eq!(query, Query { a: Inner { b: 1 } });
+    let err = loose_config
+        .deserialize_str::<Query>("a%5Bc%5D=1")
+        .unwrap_err();
+    assert!(!matches!(err, qs::Error::EncodedBracketInKey { .. }));
+}
+
+#[test]
+fn lenient_bracket_decoding() {
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Inner {
+        b: u8,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Query {
+        #[serde(rename = "x[y]")]
+        xy: u8,
+        a: Inner,
+    }
+
+    let query = Query {
+        xy: 1,
+        a: Inner { b: 2 },
+    };
+    let encoded = qs::to_string(&query).unwrap();
+    assert_eq!(encoded, "x%5By%5D=1&a[b]=2");
+
+    for &(strict, lenient) in &[(true, false), (true, true), (false, false), (false, true)] {
+        let config = qs::Config::new(5, strict).lenient_bracket_decoding(lenient);
+        let rec_query = config.deserialize_str::<Query>(&encoded);
+        if strict && !lenient {
+            // the encoded brackets are part of the field name
+            assert_eq!(rec_query.unwrap(), query);
+        } else {
+            // the encoded brackets denote a nested key `x[y]`
+            assert_eq!(
+                rec_query.unwrap_err().to_string(),
+                "missing field `x[y]`",
+                "strict={} lenient={}",
+                strict,
+                lenient
+            );
+        }
+
+        let rec_inner = config.deserialize_str::<HashMap<String, Inner>>("a%5Bb%5D=2");
+        assert_eq!(rec_inner.is_ok(), !strict || lenient);
+
+        // lenient bracket decoding keeps strict UTF-8 decoding
+        let rec_invalid = config.deserialize_str::<HashMap<String, String>>("a=%E9");
+        assert_eq!(rec_invalid.is_ok(), !strict);
+    }
+}
+
+#[test]
+fn max_seq_index() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Query {
+        a: Vec<u8>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct MapQuery {
+        a: HashMap<u32, u8>,
+    }
+
+    let rec_query: Query = qs::from_str("a[10000]=1&a[0]=2").unwrap();
+    assert_eq!(rec_query, Query { a: vec![2, 1] });
+
+    // by default, larger indices are treated as map keys
+    assert!(qs::from_str::<Query>("a[4294967294]=1").is_err());
+    assert!(qs::from_str::<Query>("a[0]=1&a[4294967294]=1").is_err());
+    assert!(qs::from_str::<Query>("a[99999999999999999999999]=1").is_err());
+
+    let rec_query: MapQuery = qs::from_str("a[4294967294]=1&a[3]=2").unwrap();
+    assert_eq!(
+        rec_query.a,
+        vec![(4294967294, 1), (3, 2)].into_iter().collect()
+    );
+    let rec_query: MapQuery = qs::from_str("a[3]=2&a[4294967294]=1").unwrap();
+    assert_eq!(
+        rec_query.a,
+        vec![(4294967294, 1), (3, 2)].into_iter().collect()
+    );
+
+    let config = qs::Config::default()
+        .max_seq_index(10)
+        .seq_index_overflow(qs::SeqIndexOverflow::Error);
+    match config.deserialize_str::<MapQuery>("a[11]=1").unwrap_err() {
+        qs::Error::SequenceIndexTooLarge { index, max } => {
+            assert_eq!(index, "11");
+            assert_eq!(max, 10);
+        }
+        e => panic!("unexpected error: {:?}", e),
+    }
+    assert!(config.deserialize_str::<Query>("a[10]=1").is_ok());
+}
+
+#[test]
+fn seq_index_u32_boundary() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Query {
+        a: Vec<u8>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct MapQuery {
+        a: HashMap<u64, u8>,
+    }
+
+    let max = u32::MAX as usize;
+    let config = qs::Config::default()
+        .max_seq_index(max)
+        .seq_index_overflow(qs::SeqIndexOverflow::Error);
+
+    let rec_query: Query = config.deserialize_str("a[4294967295]=1&a[0]=2").unwrap();
+    assert_eq!(rec_query, Query { a: vec![2, 1] });
+
+    for index in &["4294967296", "99999999999999999999999"] {
+        match config
+            .deserialize_str::<Query>(&format!("a[{}]=1", index))
+            .unwrap_err()
+        {
+            qs::Error::SequenceIndexTooLarge { index: i, max: m } => {
+                assert_eq!(i, *index);
+                assert_eq!(m, max);
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    // large integer keys are still map keys when overflow is allowed
+    let config = config.seq_index_overflow(qs::SeqIndexOverflow::MapKey);
+    let rec_query: MapQuery = config
+        .deserialize_str("a[4294967296]=1&a[18446744073709551615]=2")
+        .unwrap();
+    assert_eq!(
+        rec_query.a,
+        vec![(4294967296, 1), (u64::MAX, 2)].into_iter().collect()
+    );
+}
+
+#[test]
+fn seq_size_hint() {
+    use serde::de::{Deserializer, SeqAccess, Visitor};
+    use std::fmt;
+
+    #[derive(Debug, PartialEq)]
+    struct SizeHint(Option<usize>);
+
+    impl<'de> serde::Deserialize<'de> for SizeHint {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct SizeHintVisitor;
+
+            impl<'de> Visitor<'de> for SizeHintVisitor {
+                type Value = SizeHint;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a sequence")
+                }
+
+                fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<SizeHint, A::Error> {
+                    let hint = seq.size_hint();
+                    while seq.next_element::<serde::de::IgnoredAny>()?.is_some() {}
+                    Ok(SizeHint(hint))
+                }
+            }
+
+            deserializer.deserialize_seq(SizeHintVisitor)
+        }
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Query {
+        a: SizeHint,
+    }
+
+    let rec_query: Query = qs::from_str("a[0]=1&a[1]=2&a[2]=3").unwrap();
+    assert_eq!(rec_query.a, SizeHint(Some(3)));
+
+    let rec_query: Query = qs::from_str("a[0]=1&a[9999]=2").unwrap();
+    assert_eq!(rec_query.a, SizeHint(Some(2)));
+
+    let rec_query: Query = qs::from_str("a[]=1&a[]=2&a[]=3").unwrap();
+    assert_eq!(rec_query.a, SizeHint(Some(3)));
+
+    // String keys make a map, which is not a sequence at all.
+    assert!(qs::from_str::<Query>("a[0]=1&a[x]=2").is_err());
+}
+
+#[test]
+fn map_size_hint() {
+    use serde::de::{Deserializer, MapAccess, Visitor};
+    use std::fmt;
+
+    #[derive(Debug, PartialEq)]
+    struct SizeHint(Option<usize>);
+
+    impl<'de> serde::Deserialize<'de> for SizeHint {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct SizeHintVisitor;
+
+            impl<'de> Visitor<'de> for SizeHintVisitor {
+                type Value = SizeHint;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a map")
+                }
+
+                fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<SizeHint, A::Error> {
+                    let hint = map.size_hint();
+                    while map
+                        .next_entry::<serde::de::IgnoredAny, serde::de::IgnoredAny>()?
+                        .is_some()
+                    {}
+                    Ok(SizeHint(hint))
+                }
+            }
+
+            deserializer.deserialize_map(SizeHintVisitor)
+        }
+    }
+
+    let top: SizeHint = qs::from_str("a=1&b[x]=2&b[y]=3&c[]=4").unwrap();
+    assert_eq!(top, SizeHint(Some(3)));
+
+    let nested: HashMap<String, SizeHint> = qs::from_str("a[x]=1&a[y]=2&b[0]=1").unwrap();
+    assert_eq!(nested["a"], SizeHint(Some(2)));
+    assert_eq!(nested["b"], SizeHint(Some(1)));
+
+    // The hint is correct for values like `serde_json::Value`, which take
+    // the map with `deserialize_any`.
+    let json: HashMap<String, serde_json::Value> =
+        qs::from_str("a[x]=1&a[y][]=2&a[y][]=3&b=4").unwrap();
+    assert_eq!(json["a"], serde_json::json!({"x": "1", "y": ["2", "3"]}));
+}
+
+#[test]
+fn struct_size_hint() {
+    use serde::de::{Deserializer, MapAccess, Visitor};
+    use std::fmt;
+
+    /// The hint before each entry is visited, and after the last.
+    #[derive(Debug, PartialEq)]
+    struct SizeHints(Vec<Option<usize>>);
+
+    impl<'de> serde::Deserialize<'de> for SizeHints {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct SizeHintVisitor;
+
+            impl<'de> Visitor<'de> for SizeHintVisitor {
+                type Value = SizeHints;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a struct")
+                }
+
+                fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<SizeHints, A::Error> {
+                    let mut hints = vec![map.size_hint()];
+                    while map
+                        .next_entry::<serde::de::IgnoredAny, serde::de::IgnoredAny>()?
+                        .is_some()
+                    {
+                        hints.push(map.size_hint());
+                    }
+                    Ok(SizeHints(hints))
+                }
+            }
+
+            deserializer.deserialize_struct("SizeHints", &["a", "b", "c"], SizeHintVisitor)
+        }
+    }
+
+    // Absent fields are not counted
+    let hints: SizeHints = qs::from_str("b=1").unwrap();
+    assert_eq!(hints, SizeHints(vec![Some(1), Some(0)]));
+    let hints: SizeHints = qs::from_str("").unwrap();
+    assert_eq!(hints, SizeHints(vec![Some(0)]));
+
+    // but unknown ones are, as they are visited too
+    let hints: SizeHints = qs::from_str("a=1&x=2&y[z]=3").unwrap();
+    assert_eq!(hints, SizeHints(vec![Some(3), Some(2), Some(1), Some(0)]));
+
+    let nested: HashMap<String, SizeHints> = qs::from_str("s[c]=1&s[d]=2").unwrap();
+    assert_eq!(nested["s"], SizeHints(vec![Some(2), Some(1), Some(0)]));
+}
+
+#[test]
+fn repeated_encoded_keys() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Item {
+        first_name: String,
+        count: u8,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Query {
+        items: Vec<Item>,
+        counts: HashMap<u8, String>,
+    }
+
+    // The same key encoded in different ways refers to the same field.
+    let rec_query: Query = qs::from_str(
+        "items[0][first%5Fname]=a&items[0][c%6Funt]=1\
+         &items[1][first_name]=b&items[1][count]=2\
+         &items[2][first%5Fname]=c&items[2][co%75nt]=3\
+         &counts[%31]=one&counts[%32]=two",
+    )
+    .unwrap();
+    assert_eq!(
+        rec_query.items,
+        vec![
+            Item {
+                first_name: "a".to_string(),
+                count: 1
+            },
+            Item {
+                first_name: "b".to_string(),
+                count: 2
+            },
+            Item {
+                first_name: "c".to_string(),
+                count: 3
+            },
+        ]
+    );
+    assert_eq!(rec_query.counts[&1], "one");
+    assert_eq!(rec_query.counts[&2], "two");
+
+    // ... so they still collide within a single entry.
+    let err = qs::from_str::<Item>("first%5Fname=a&first_name=b&count=1").unwrap_err();
+    assert!(err.to_string().contains("Multiple values for one key"));
+}
+
+#[test]
+fn deserialize_net_addrs() {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Query {
+        ip: IpAddr,
+        v6: Ipv6Addr,
+        addr: SocketAddr,
+    }
+
+    let expected = Query {
+        ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+        v6: Ipv6Addr::LOCALHOST,
+        addr: "[::1]:80".parse().unwrap(),
+    };
+    let strict = qs::Config::new(5, true);
+    for input in [
+        "ip=127.0.0.1&v6=::1&addr=[::1]:80",
+        "ip=127.0.0.1&v6=%3A%3A1&addr=%5B::1%5D:80",
+    ] {
+        assert_eq!(strict.deserialize_str::<Query>(input).unwrap(), expected);
+    }
+
+    // In strict mode, encoded brackets in keys are not nested keys.
+    let rec_map: HashMap<SocketAddr, u8> = strict
+        .deserialize_str("%5B::1%5D:80=1&127.0.0.1:8080=2")
+        .unwrap();
+    assert_eq!(rec_map[&"[::1]:80".parse().unwrap()], 1);
+    assert_eq!(rec_map[&"127.0.0.1:8080".parse().unwrap()], 2);
+
+    let rec_map: HashMap<IpAddr, u8> = qs::from_str("::1=1&127.0.0.1=2").unwrap();
+    assert_eq!(rec_map[&IpAddr::V6(Ipv6Addr::LOCALHOST)], 1);
+    assert_eq!(rec_map[&IpAddr::V4(Ipv4Addr::LOCALHOST)], 2);
+}
+
+#[test]
+fn invalid_value_key_path() {
+    use std::net::SocketAddr;
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Server {
+        addr: SocketAddr,
+        ports: Vec<u16>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Query {
+        servers: Vec<Server>,
+    }
+
+    let err = qs::from_str::<Query>(
+        "servers[0][addr]=127.0.0.1:80&servers[0][ports][0]=80&servers[1][addr]=nope",
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `servers[1][addr]`: invalid socket address syntax"
+    );
+
+    let err = qs::from_str::<Query>(
+        "servers[0][addr]=127.0.0.1:80&servers[0][ports][]=80&servers[0][ports][]=http",
+    )
+    .unwrap_err();
+    match err {
+        qs::Error::InvalidValue { key, error } => {
+            assert_eq!(key, "servers[0][ports][1]");
+            assert_eq!(error.to_string(), "invalid digit found in string");
+        }
+        err => panic!("unexpected error: {:?}", err),
+    }
+}
+
+#[test]
+fn parse_error_context() {
+    let config = qs::Config::new(5, true);
+    let parse_err = |input: &[u8]| {
+        config
+            .deserialize_bytes::<HashMap<String, String>>(input)
+            .unwrap_err()
+            .to_string()
+    };
+
+    assert_eq!(
+        parse_err(b"[::1]:80=a"),
+        "parsing failed with error: 'Unexpected character: ':' found when parsing' \
+         at position: 6, near `[::1]\u{2192}:80=a`"
+    );
+    assert_eq!(
+        parse_err(
+            b"user_ids[0]=1&user_ids[1]=2&user_ids[2]=3&user_ids[3]x4&user_ids[4]=5&user_ids[5]=6"
+        ),
+        "parsing failed with error: 'Unexpected character: 120 found when parsing' \
+         at position: 54, near `...ids[2]=3&user_ids[3]\u{2192}x4&user_ids[4]=5&use...`"
+    );
+    assert_eq!(
+        parse_err(b"user_ids[0]=1&user_ids[1]=2&user_ids[2]=3&user_ids[3]x"),
+        "parsing failed with error: 'Unexpected character: 120 found when parsing' \
+         at position: 54, near `...ids[2]=3&user_ids[3]\u{2192}x`"
+    );
+
+    // Non-UTF8 and unprintable bytes are percent-encoded.
+    assert!(parse_err(b"a[b]\xff\nx=1").ends_with("near `a[b]\u{2192}%FF%0Ax=1`"));
+}
+
+#[test]
+fn array_length_errors_name_the_key() {
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize)]
+    struct Query {
+        small_array: [u8; 3],
+        #[serde(default)]
+        nested: Vec<[u8; 2]>,
+    }
+
+    let err = qs::from_str::<Query>("small_array[0]=1&small_array[2]=3").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `small_array`: invalid length 2, expected an array of length 3; missing index 1"
+    );
+    let err = qs::from_str::<Query>("small_array[0]=1&small_array[1]=2").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `small_array`: invalid length 2, expected an array of length 3; missing index 2"
+    );
+    let err = qs::from_str::<Query>("small_array[4]=1").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `small_array`: invalid length 1, expected an array of length 3; missing indices 0, 1, 2"
+    );
+    match err {
+        qs::Error::InvalidValue { key, .. } => assert_eq!(key, "small_array"),
+        err => panic!("unexpected error: {:?}", err),
+    }
+
+    let err = qs::from_str::<Query>(
+        "small_array[0]=1&small_array[1]=2&small_array[2]=3&small_array[3]=4&small_array[4]=5",
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `small_array`: invalid length 5, expected an array of length 3; unexpected indices 3, 4"
+    );
+    let err =
+        qs::from_str::<Query>("small_array[]=1&small_array[]=2&small_array[]=3&small_array[]=4")
+            .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `small_array`: invalid length 4, expected an array of length 3; unexpected index 3"
+    );
+
+    let err =
+        qs::from_str::<Query>("small_array[]=1&small_array[]=2&small_array[]=3&nested[1][0]=1")
+            .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `nested[1]`: invalid length 1, expected an array of length 2; missing index 1"
+    );
+}
+
+#[test]
+fn tuple_errors_name_the_type_and_key() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Point(u8, u8);
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Pair((u8, u8));
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Wrap(Vec<(u8, u8)>);
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Query {
+        point: Option<Point>,
+        pair: Option<Pair>,
+        wrap: Option<Wrap>,
+        nested: Option<Vec<((u8, u8), u8)>>,
+    }
+
+    let error = |input| qs::from_str::<Query>(input).unwrap_err().to_string();
+    assert_eq!(
+        error("point[]=1"),
+        "invalid value for `point`: invalid length 1, expected tuple struct Point of size 2; \
+         missing index 1"
+    );
+    // newtypes defer to their inner values
+    assert_eq!(
+        error("pair[]=1"),
+        "invalid value for `pair`: invalid length 1, expected a tuple of size 2; missing index 1"
+    );
+    assert_eq!(
+        error("wrap[0][0]=1&wrap[0][1]=2&wrap[1][0]=3"),
+        "invalid value for `wrap[1]`: invalid length 1, expected a tuple of size 2; \
+         missing index 1"
+    );
+    assert_eq!(
+        error("nested[0][0][0]=1&nested[0][1]=2"),
+        "invalid value for `nested[0][0]`: invalid length 1, expected a tuple of size 2; \
+         missing index 1"
+    );
+    assert_eq!(
+        error("nested[0][0][0]=1&nested[0][0][1]=2&nested[0][1]=3&nested[0][2]=4"),
+        "invalid value for `nested[0]`: invalid length 3, expected a tuple of size 2; \
+         unexpected index 2"
+    );
+
+    assert_roundtrip(&Query {
+        point: Some(Point(1, 2)),
+        pair: Some(Pair((3, 4))),
+        wrap: Some(Wrap(vec![(5, 6), (7, 8)])),
+        nested: Some(vec![((9, 10), 11)]),
+    });
+}
+
+#[test]
+fn tuple_length() {
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Query {
+        pair: (u8, u8),
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Point(u8, u8);
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct PointQuery {
+        point: Point,
+    }
+
+    let q: Query = qs::from_str("pair[]=1&pair[]=2").unwrap();
+    assert_eq!(q.pair, (1, 2));
+    let q: Query = qs::from_str("pair[1]=2&pair[0]=1").unwrap();
+    assert_eq!(q.pair, (1, 2));
+    let q: PointQuery = qs::from_str("point[]=1&point[]=2").unwrap();
+    assert_eq!(q.point, Point(1, 2));
+
+    // Trailing elements are not silently dropped.
+    let err = qs::from_str::<Query>("pair[]=1&pair[]=2&pair[]=3").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `pair`: invalid length 3, expected a tuple of size 2; unexpected index 2"
+    );
+    assert!(qs::from_str::<Query>("pair[0]=1&pair[1]=2&pair[2]=3").is_err());
+    assert!(qs::from_str::<PointQuery>("point[]=1&point[]=2&point[]=3").is_err());
+    assert!(qs::from_str::<Query>("pair[]=1").is_err());
+
+    // Empty values still count towards the length.
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct OptionQuery {
+        pair: (Option<u8>, u8),
+    }
+    let q: OptionQuery = qs::from_str("pair[]=&pair[]=2").unwrap();
+    assert_eq!(q.pair, (None, 2));
+}
+
+#[test]
+fn iter_pairs() {
+    use qs::Segment;
+
+    let pairs = |config: qs::Config, input: &[u8]| -> Vec<(String, Option<String>)> {
+        qs::Deserializer::with_config(&config, input)
+            .unwrap()
+            .iter_pairs()
+            .map(|(path, value)| (path.to_string(), value.map(|v| v.into_owned())))
+            .collect()
+    };
+    let some = |path: &str, value: &str| (path.to_owned(), Some(value.to_owned()));
+
+    // Nested maps and sequences, visited depth-first in sorted order.
+    assert_eq!(
+        pairs(
+            qs::Config::default(),
+            b"user[name]=Acme&user[ids][1]=2&user[ids][0]=1&tags[]=a&tags[]=b&q=x%20y"
+        ),
+        vec![
+            some("q", "x y"),
+            some("tags[0]", "a"),
+            some("tags[1]", "b"),
+            some("user[ids][0]", "1"),
+            some("user[ids][1]", "2"),
+            some("user[name]", "Acme"),
+        ]
+    );
+
+    // Missing and empty values are both empty strings, while conflicting
+    // values have no value at all.
+    assert_eq!(
+        pairs(qs::Config::default(), b"a&b=&c=1&c=2"),
+        vec![some("a", ""), some("b", ""), ("c".to_owned(), None)]
+    );
+
+    // Non-UTF8 values are replaced lossily in non-strict mode, and rejected
+    // in strict mode.
+    assert_eq!(
+        pairs(qs::Config::new(5, false), b"a=%FFb"),
+        vec![some("a", "\u{FFFD}b")]
+    );
+    assert!(qs::Deserializer::with_config(&qs::Config::new(5, true), b"a=%FFb").is_err());
+
+    let qs = qs::Deserializer::new(b"a[b%5Dc][0]=1").unwrap();
+    let (path, _) = qs.iter_pairs().next().unwrap();
+    assert_eq!(
+        path.segments(),
+        &[
+            Segment::Key("a".into()),
+            Segment::Key("b]c".into()),
+            Segment::Index(0)
+        ]
+    );
+}
+
+#[test]
+fn keys_with_spaces() {
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Name {
+        #[serde(rename = "first name")]
+        first: String,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Form {
+        #[serde(rename = "first name")]
+        first: String,
+        user: Name,
+    }
+
+    let expected = Form {
+        first: "J".to_owned(),
+        user: Name {
+            first: "A".to_owned(),
+        },
+    };
+
+    // Spaces may be written as `+`, `%20` or left as-is, at any depth.
+    for input in [
+        "first+name=J&user[first+name]=A",
+        "first%20name=J&user[first%20name]=A",
+        "first name=J&user[first name]=A",
+        "first+name=J&user[first%20name]=A",
+    ] {
+        assert_eq!(qs::from_str::<Form>(input).unwrap(), expected, "{}", input);
+    }
+
+    // HTML forms encode the brackets too, which needs non-strict mode.
+    let config = qs::Config::new(5, false);
+    for input in [
+        "first+name=J&user%5Bfirst+name%5D=A",
+        "first%20name=J&user%5Bfirst%20name%5D=A",
+    ] {
+        assert_eq!(
+            config.deserialize_str::<Form>(input).unwrap(),
+            expected,
+            "{}",
+            input
+        );
+    }
+
+    // An encoded `+` is a literal plus sign.
+    let map: HashMap<String, String> = qs::from_str("c%2B%2B+user=a%2Bb").unwrap();
+    assert_eq!(map["c++ user"], "a+b");
+}
+
+#[test]
+fn shared_and_borrowed_strings() {
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Query<'a> {
+        boxed: Box<str>,
+        rc: Rc<str>,
+        arc: Arc<str>,
+        bytes: Box<[u8]>,
+        #[serde(borrow)]
+        borrowed: &'a str,
+        #[serde(borrow)]
+        borrowed_bytes: &'a [u8],
+        #[serde(borrow)]
+        list: Vec<&'a str>,
+    }
+
+    let q: Query = qs::from_str(
+        "boxed=a&rc=b&arc=c&bytes[0]=1&bytes[1]=2&borrowed=d&borrowed_bytes=e&list[]=f",
+    )
+    .unwrap();
+    assert_eq!(
+        q,
+        Query {
+            boxed: "a".into(),
+            rc: "b".into(),
+            arc: "c".into(),
+            bytes: vec![1, 2].into(),
+            borrowed: "d",
+            borrowed_bytes: b"e",
+            list: vec!["f"],
+        }
+    );
+
+    // Decoded values are owned, so can only be used for owned types.
+    let q: HashMap<&str, Arc<str>> = qs::from_str("a=x%20y").unwrap();
+    assert_eq!(&*q["a"], "x y");
+    assert!(qs::from_str::<HashMap<&str, &str>>("a=x%20y").is_err());
+
+    // Map keys, and values split by the `helpers` modules, borrow too.
+    let q: HashMap<&str, HashMap<&str, &str>> = qs::from_str("a[b]=c").unwrap();
+    assert_eq!(q["a"]["b"], "c");
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Delimited<'a> {
+        #[serde(borrow, with = "qs::helpers::comma_separated")]
+        ids: Vec<&'a str>,
+    }
+    let q: Delimited = qs::from_str("ids=a,b").unwrap();
+    assert_eq!(q.ids, ["a", "b"]);
+}
+
+#[test]
+fn borrowed_cow_strings() {
+    use std::borrow::Cow;
+
+    #[derive(Debug, Deserialize)]
+    struct Inner<'a> {
+        #[serde(borrow)]
+        plain: Cow<'a, str>,
+        #[serde(borrow)]
+        escaped: Cow<'a, str>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Query<'a> {
+        #[serde(borrow)]
+        plain: Cow<'a, str>,
+        #[serde(borrow)]
+        escaped: Cow<'a, str>,
+        #[serde(borrow)]
+        inner: Inner<'a>,
+        #[serde(borrow)]
+        single: Cow<'a, str>,
+    }
+
+    // Values are borrowed from the input unless they had to be decoded, in
+    // which case the decoded string is handed over rather than copied.
+    let config = qs::Config::default().scalar_from_single_element_seq(true);
+    let q: Query = config
+        .deserialize_str("plain=a&escaped=b+c&inner[plain]=d&inner[escaped]=%C3%A9&single[]=e")
+        .unwrap();
+    assert!(matches!(q.plain, Cow::Borrowed("a")));
+    assert!(matches!(q.escaped, Cow::Owned(ref s) if s == "b c"));
+    assert!(matches!(q.inner.plain, Cow::Borrowed("d")));
+    assert!(matches!(q.inner.escaped, Cow::Owned(ref s) if s == "é"));
+    assert!(matches!(q.single, Cow::Borrowed("e")));
+
+    // Encoded keys are decoded on the stack when short, and otherwise
+    // decoded once.
+    let long = "k%20".repeat(20);
+    let input = format!("a%20b=1&{}=2&c%FF=3", long);
+    let map: HashMap<String, u8> = qs::Config::new(5, false).deserialize_str(&input).unwrap();
+    assert_eq!(map["a b"], 1);
+    assert_eq!(map[&"k ".repeat(20)], 2);
+    assert_eq!(map["c\u{FFFD}"], 3);
+}
+
+#[test]
+fn preserve_leading_zero_keys() {
+    type Map = HashMap<String, HashMap<String, String>>;
+
+    // By default padded keys are sequence indices, so conflict with unpadded
+    // ones.
+    let input = "a[001]=x&a[1]=y";
+    assert!(qs::from_str::<Map>(input).is_err());
+    let seq: HashMap<String, Vec<String>> = qs::from_str("a[001]=x&a[0]=y").unwrap();
+    assert_eq!(seq["a"], ["y", "x"]);
+
+    let config = qs::Config::default().preserve_leading_zero_keys(true);
+    for input in ["a[001]=x&a[1]=y", "a[1]=y&a[001]=x"] {
+        let map: Map = config.deserialize_str(input).unwrap();
+        assert_eq!(map["a"].len(), 2, "{}", input);
+        assert_eq!(map["a"]["001"], "x");
+        assert_eq!(map["a"]["1"], "y");
+    }
+
+    // `0` itself is not padded.
+    let seq: HashMap<String, Vec<String>> = config.deserialize_str("a[0]=y&a[1]=x").unwrap();
+    assert_eq!(seq["a"], ["y", "x"]);
+    assert!(config
+        .deserialize_str::<HashMap<String, Vec<String>>>("a[00]=y")
+        .is_err());
+}
+
+#[test]
+fn reject_control_chars() {
+    type Map = HashMap<String, HashMap<String, String>>;
+
+    let invalid_char = |config: qs::Config, input: &[u8]| match config
+        .deserialize_bytes::<Map>(input)
+        .unwrap_err()
+    {
+        qs::Error::InvalidKeyCharacter { byte, position } => (byte, position),
+        err => panic!("unexpected error for {:?}: {}", input, err),
+    };
+
+    for strict in [true, false] {
+        // Allowed by default.
+        let config = qs::Config::new(5, strict);
+        let map: Map = config.deserialize_bytes(b"a[b%00]=1").unwrap();
+        assert_eq!(map["a"]["b\0"], "1");
+
+        let config = config.reject_control_chars(true);
+        assert_eq!(invalid_char(config, b"a\x00[b]=1"), (0, 1));
+        assert_eq!(invalid_char(config, b"a%00[b]=1"), (0, 1));
+        assert_eq!(invalid_char(config, b"x[y]=1&a[b%1F]=1"), (0x1f, 10));
+        assert_eq!(invalid_char(config, b"a[b\x7f]=1"), (0x7f, 3));
+        assert_eq!(invalid_char(config, b"a[b][%7F]=1"), (0x7f, 5));
+        assert_eq!(invalid_char(config, b"a[b\tc]=1"), (b'\t', 3));
+
+        // Values, and `%` not followed by two hex digits, are fine.
+        let map: Map = config.deserialize_bytes(b"a[b%2]=%00%1F\x7f").unwrap();
+        assert_eq!(map["a"]["b%2"], "\0\x1f\x7f");
+    }
+
+    let err = qs::Config::default()
+        .reject_control_chars(true)
+        .deserialize_str::<Map>("a[%00]=1")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid character 0x00 in key at position 2"
+    );
+}
+
+#[test]
+fn remainder() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Search {
+        q: String,
+        page: u32,
+        filter: Option<Address>,
+    }
+
+    let qs::Remainder(search, rest) = qs::from_str::<qs::Remainder<Search>>(
+        "q=rust&utm_source=mail&page=2&utm_campaign=launch+day",
+    )
+    .unwrap();
+    assert_eq!(
+        search,
+        Search {
+            q: "rust".to_owned(),
+            page: 2,
+            filter: None,
+        }
+    );
+    let expected: HashMap<_, _> = vec![
+        ("utm_source".to_owned(), "mail".to_owned()),
+        ("utm_campaign".to_owned(), "launch day".to_owned()),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(rest, expected);
+
+    // nested leftovers are rendered as bracketed keys, while unknown keys
+    // inside a field are ignored
+    let (search, rest) = qs::from_str::<qs::Remainder<Search>>(
+        "q=rust&page=1&filter[city]=Berlin&filter[postcode]=10115&filter[x]=1&\
+         track[ids][]=3&track[ids][]=4&track[src]=ad",
+    )
+    .unwrap()
+    .into_parts();
+    assert_eq!(search.filter.unwrap().city, "Berlin");
+    let expected: HashMap<_, _> = vec![
+        ("track[ids][0]".to_owned(), "3".to_owned()),
+        ("track[ids][1]".to_owned(), "4".to_owned()),
+        ("track[src]".to_owned(), "ad".to_owned()),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(rest, expected);
+
+    let rec: qs::Remainder<Search> = qs::from_str("q=rust&page=1").unwrap();
+    assert!(rec.1.is_empty());
+
+    let err = qs::from_str::<qs::Remainder<Search>>("q=rust&page=x&utm_source=mail").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `page`: invalid digit found in string"
+    );
+}
+
+thread_local! {
+    static CONFLICTS: std::cell::RefCell<Vec<qs::ConflictEvent>> = Default::default();
+}
+
+fn record_conflict(event: qs::ConflictEvent) {
+    CONFLICTS.with(|events| events.borrow_mut().push(event));
+}
+
+fn conflicts(input: &str) -> Vec<(qs::ConflictKind, String, usize)> {
+    let config = qs::Config::default().on_conflict(record_conflict);
+    let _ = config.deserialize_str::<HashMap<String, serde_json::Value>>(input);
+    CONFLICTS.with(|events| {
+        events
+            .borrow_mut()
+            .drain(..)
+            .map(|e| (e.kind, e.key, e.position))
+            .collect()
+    })
+}
+
+#[test]
+fn on_conflict() {
+    use qs::ConflictKind::*;
+
+    assert_eq!(conflicts("a=1&b[c]=2&d[]=3&d[]=4"), vec![]);
+
+    assert_eq!(
+        conflicts("a=1&b=2&a=3&&a=4"),
+        vec![
+            (DuplicateKey, "a".to_owned(), 8),
+            (DuplicateKey, "a".to_owned(), 13)
+        ]
+    );
+    assert_eq!(
+        conflicts("first%5Fname=a&first_name=b"),
+        vec![(DuplicateKey, "first_name".to_owned(), 15)]
+    );
+    assert_eq!(
+        conflicts("m[1]=2&m[1]=4"),
+        vec![(DuplicateKey, "m[1]".to_owned(), 7)]
+    );
+    assert_eq!(
+        conflicts("a=1&a[b]=2&s[]=1&s[0]=2"),
+        vec![
+            (MismatchedStructure, "a[b]".to_owned(), 4),
+            (MismatchedStructure, "s[0]".to_owned(), 17),
+        ]
+    );
+    assert_eq!(
+        conflicts("a[0]=1&a[x]=2&a[y]=3"),
+        vec![(PromotedToMap, "a[x]".to_owned(), 7)]
+    );
+
+    // promoted sequences still deserialize
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Query {
+        a: HashMap<String, u8>,
+    }
+    let config = qs::Config::default().on_conflict(record_conflict);
+    let query: Query = config.deserialize_str("a[0]=1&a[x]=2").unwrap();
+    assert_eq!(query.a["0"], 1);
+    assert_eq!(
+        CONFLICTS.with(|events| events.borrow_mut().drain(..).count()),
+        1
+    );
+}
+
+#[test]
+fn key_conflict_resolution() {
+    use qs::Conflict;
+    use serde_json::json;
+
+    let resolve = |conflict: Conflict, input: &str| {
+        let config = qs::Config::default()
+            .key_conflict_resolution(conflict)
+            .on_conflict(record_conflict);
+        let value = config.deserialize_str::<serde_json::Value>(input);
+        let events: Vec<_> = CONFLICTS.with(|events| {
+            events
+                .borrow_mut()
+                .drain(..)
+                .map(|e| (e.kind, e.key))
+                .collect()
+        });
+        (value.ok(), events)
+    };
+    let dropped = |key: &str| vec![(qs::ConflictKind::DroppedValue, key.to_owned())];
+
+    // flat first, then nested, and the other way around
+    for (input, nested, flat) in [
+        ("a=1&a[b]=2", json!({"a": {"b": "2"}}), json!({"a": "1"})),
+        ("a[b]=2&a=1", json!({"a": {"b": "2"}}), json!({"a": "1"})),
+        (
+            "a[b]=1&a[b][c]=2",
+            json!({"a": {"b": {"c": "2"}}}),
+            json!({"a": {"b": "1"}}),
+        ),
+        (
+            "a[b][c]=2&a[b]=1",
+            json!({"a": {"b": {"c": "2"}}}),
+            json!({"a": {"b": "1"}}),
+        ),
+        (
+            "a[0][b]=1&a[0]=2",
+            json!({"a": [{"b": "1"}]}),
+            json!({"a": ["2"]}),
+        ),
+    ] {
+        let second = input.split('&').nth(1).unwrap();
+        let key = &second[..second.find('=').unwrap()];
+        assert_eq!(
+            resolve(Conflict::PreferNested, input),
+            (Some(nested), dropped(key)),
+            "{}",
+            input
+        );
+        assert_eq!(
+            resolve(Conflict::PreferFlat, input),
+            (Some(flat), dropped(key)),
+            "{}",
+            input
+        );
+        assert_eq!(resolve(Conflict::Error, input).0, None, "{}", input);
+    }
+
+    // the dropped keys stay dropped, and other keys are kept
+    assert_eq!(
+        resolve(Conflict::PreferFlat, "a=1&a[b][c]=2&a[d]=3&e=4").0,
+        Some(json!({"a": "1", "e": "4"}))
+    );
+
+    // duplicate values are still an error
+    assert_eq!(resolve(Conflict::PreferFlat, "a=1&a=2").0, None);
+    assert_eq!(resolve(Conflict::PreferNested, "a[b]=1&a[b]=2").0, None);
+
+    let config = qs::Config::default().key_conflict_resolution(Conflict::PreferNested);
+    let (_, stats) = config
+        .deserialize_str_with_stats::<serde_json::Value>("a=1&a[b]=2&c[d]=3&c=4")
+        .unwrap();
+    assert_eq!(stats.dropped_values, 2);
+}
+
+#[test]
+fn sets_from_bare_keys() {
+    use std::collections::{BTreeSet, HashSet};
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Query {
+        flags: HashSet<String>,
+        ids: BTreeSet<u32>,
+    }
+
+    let query: Query = qs::from_str("flags[a]&flags[b]&ids[5]&ids[3]&flags[c]").unwrap();
+    assert_eq!(
+        query.flags,
+        vec!["a", "b", "c"].into_iter().map(String::from).collect()
+    );
+    assert_eq!(query.ids, vec![3, 5].into_iter().collect());
+
+    // a map of units still works
+    let map: HashMap<String, HashMap<String, ()>> = qs::from_str("flags[a]&flags[b]").unwrap();
+    assert_eq!(map["flags"].len(), 2);
+
+    // keys with values are not a set
+    assert!(qs::from_str::<Query>("flags[a]=1&flags[b]&ids[3]").is_err());
+    assert!(qs::from_str::<Query>("flags[a]&ids[3]=&ids[5]").is_err());
+
+    // empty values given with `=` are still elements
+    let map: HashMap<String, Vec<String>> = qs::from_str("a[0]=&a[1]=").unwrap();
+    assert_eq!(map["a"], vec!["", ""]);
+}
+
+#[test]
+fn error_source_chain() {
+    use std::error::Error as _;
+
+    fn assert_send_sync<T: Send + Sync + 'static>() {}
+    assert_send_sync::<qs::Error>();
+
+    #[derive(Debug, Deserialize)]
+    struct Query {
+        #[allow(dead_code)]
+        page: u32,
+    }
+
+    let error = qs::from_str::<Query>("page=x").unwrap_err();
+    let boxed: Box<dyn std::error::Error + Send + Sync> = Box::new(error);
+    let error = boxed.downcast_ref::<qs::Error>().unwrap();
+    assert!(matches!(error, qs::Error::InvalidValue { .. }));
+
+    // the wrapped errors are sources, down to the `ParseIntError`
+    let mut chain = Vec::new();
+    let mut source = error.source();
+    while let Some(error) = source {
+        chain.push(error);
+        source = error.source();
+    }
+    assert_eq!(chain.len(), 2, "{:?}", chain);
+    assert!(matches!(
+        chain[0]
+            .downcast_ref::<Box<qs::Error>>()
+            .map(|error| &**error),
+        Some(qs::Error::ParseInt(_))
+    ));
+    let root = chain[1].downcast_ref::<std::num::ParseIntError>().unwrap();
+    assert_eq!(root.to_string(), "invalid digit found in string");
+
+    let error = qs::Error::AtLine {
+        line: 2,
+        source: Box::new(qs::Error::Validation(Box::new(root.clone()))),
+    };
+    assert_eq!(
+        error.to_string(),
+        "line 2: validation failed: invalid digit found in string"
+    );
+    let source = error.source().unwrap().source().unwrap();
+    assert!(source.downcast_ref::<std::num::ParseIntError>().is_some());
+
+    let error = qs::from_str::<Query>("page=x").unwrap_err();
+    let io_error = std::io::Error::from(error);
+    assert_eq!(io_error.kind(), std::io::ErrorKind::InvalidData);
+    let inner = io_error.get_ref().unwrap();
+    assert!(inner.downcast_ref::<qs::Error>().is_some());
+}
+
+#[test]
+fn scalar_from_single_element_seq() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Query {
+        id: u64,
+        #[serde(default)]
+        name: String,
+    }
+
+    assert!(qs::from_str::<Query>("id[]=5").is_err());
+
+    let config = qs::Config::default().scalar_from_single_element_seq(true);
+    let query: Query = config.deserialize_str("id[]=5&name[]=abc").unwrap();
+    assert_eq!(
+        query,
+        Query {
+            id: 5,
+            name: "abc".to_owned()
+        }
+    );
+    let query: Query = config.deserialize_str("id[0]=5").unwrap();
+    assert_eq!(query.id, 5);
+
+    let error = config
+        .deserialize_str::<Query>("id[]=5&id[]=6")
+        .unwrap_err();
+    assert!(error.to_string().contains("2 elements"), "{}", error);
+    let error = config
+        .deserialize_str::<Query>("id=5&name[]=a&name[]=b")
+        .unwrap_err();
+    assert!(error.to_string().contains("2 elements"), "{}", error);
+
+    // sequences are unaffected
+    let map: HashMap<String, Vec<u64>> = config.deserialize_str("id[]=5").unwrap();
+    assert_eq!(map["id"], vec![5]);
+}
+
+#[test]
+fn repeated_keys_as_seq() {
+    type MultiMap = HashMap<String, Vec<String>>;
+
+    let error = qs::from_str::<MultiMap>("tag=a&tag=b").unwrap_err();
+    assert!(error.to_string().contains("Multiple values"), "{}", error);
+    assert!(qs::from_str::<MultiMap>("color=red").is_err());
+
+    let config = qs::Config::default().repeated_keys_as_seq(true);
+    let map: MultiMap = config
+        .deserialize_str("tag=a&color=red&tag=b&tag[]=c")
+        .unwrap();
+    assert_eq!(map["tag"], ["a", "b", "c"]);
+    assert_eq!(map["color"], ["red"]);
+
+    // in either order
+    let map: MultiMap = config.deserialize_str("tag=a&tag[]=b").unwrap();
+    assert_eq!(map["tag"], ["a", "b"]);
+    let map: MultiMap = config.deserialize_str("tag&tag[]=b").unwrap();
+    assert_eq!(map["tag"], ["", "b"]);
+
+    // and after indexed values
+    let map: MultiMap = config.deserialize_str("a[0]=x&a=1").unwrap();
+    assert_eq!(map["a"], ["x", "1"]);
+    let map: MultiMap = config.deserialize_str("a[3]=x&a[1]=w&a=1&a").unwrap();
+    assert_eq!(map["a"], ["w", "x", "1", ""]);
+
+    // each time a key is given is one element, even without a value
+    for (input, expected) in [
+        ("flag", vec![""]),
+        ("flag=", vec![""]),
+        ("flag[]", vec![""]),
+        ("flag&flag=", vec!["", ""]),
+        ("flag=&flag=", vec!["", ""]),
+        ("flag=&flag=x", vec!["", "x"]),
+    ] {
+        let map: MultiMap = config.deserialize_str(input).unwrap();
+        assert_eq!(map["flag"], expected, "{}", input);
+    }
+
+    // and nested keys
+    let map: HashMap<String, MultiMap> = config.deserialize_str("m[t]=1&m[u]=2&m[t]=3").unwrap();
+    assert_eq!(map["m"]["t"], ["1", "3"]);
+    assert_eq!(map["m"]["u"], ["2"]);
+
+    // a repeated key is still an error for a scalar, or mixed with a map
+    assert!(config
+        .deserialize_str::<HashMap<String, String>>("a=1&a=2")
+        .is_err());
+    assert!(config.deserialize_str::<MultiMap>("a[b]=1&a=2").is_err());
+}
+
+#[test]
+fn deserialize_str_with_stats() {
+    use std::borrow::Cow;
+
+    #[derive(Debug, Deserialize)]
+    struct Query<'a> {
+        #[serde(borrow)]
+        q: Cow<'a, str>,
+        tags: Vec<String>,
+    }
+
+    let config = qs::Config::default();
+    let (query, stats) = config
+        .deserialize_str_with_stats::<Query>("q=rust&tags[0]=a&tags[1]=b")
+        .unwrap();
+    assert!(matches!(query.q, Cow::Borrowed("rust")));
+    assert_eq!(stats.borrowed_keys, 5);
+    assert_eq!(stats.borrowed_values, 3);
+    assert_eq!(stats.owned_keys + stats.owned_values, 0);
+
+    let (query, stats) = config
+        .deserialize_str_with_stats::<Query>("q=hello+world&t%61gs[0]=%61&t%61gs[1]=b")
+        .unwrap();
+    assert_eq!(query.q, "hello world");
+    assert_eq!(query.tags, vec!["a", "b"]);
+    assert_eq!((stats.borrowed_keys, stats.owned_keys), (3, 2));
+    assert_eq!((stats.borrowed_values, stats.owned_values), (1, 2));
+
+    let (_, stats) = config
+        .deserialize_str_with_stats::<HashMap<String, HashMap<String, String>>>("a[0]=1&a[x]=2")
+        .unwrap();
+    assert_eq!(stats.promoted_sequences, 1);
+    assert_eq!(stats.duplicate_keys, 0);
+}
+
+#[test]
+fn capacity_hint() {
+    let input = "b=1&a[x]=2&c[]=3&c[]=4";
+    let expected: HashMap<String, serde_json::Value> = qs::from_str(input).unwrap();
+    for hint in [0, 1, 3, 10_000, usize::MAX] {
+        let config = qs::Config::default().capacity_hint(hint);
+        let map: HashMap<String, serde_json::Value> = config.deserialize_str(input).unwrap();
+        assert_eq!(map, expected, "{}", hint);
+        let map: HashMap<String, String> = config.deserialize_str("").unwrap();
+        assert!(map.is_empty());
+    }
+}
+
+#[test]
+fn top_level_shape_errors() {
+    let input = "filter[q]=x&items[0][a]=1&items[1][a]=2&page=2&tags[]=a";
+    let shape = "input parsed as a map with keys \
+                 [filter: map, items: sequence, page: value, tags: sequence]";
+
+    let err = qs::from_str::<Vec<u8>>(input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "cannot deserialize a sequence at the top level: wrap it in a struct or map, \
+             e.g. `struct Q {{ items: Vec<T> }}`; {}",
+            shape
+        )
+    );
+
+    let err = qs::from_str::<(u8, u8)>(input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "cannot deserialize a tuple at the top level: wrap it in a struct or map, \
+             e.g. `struct Q {{ items: (A, B) }}`; {}",
+            shape
+        )
+    );
+
+    let err = qs::from_str::<String>(input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "cannot deserialize a string at the top level: wrap it in a struct or map, \
+             e.g. `struct Q {{ value: T }}`; {}",
+            shape
+        )
+    );
+
+    let err = qs::from_str::<u32>("a=1&b=2&c=3&d=4&e=5&f=6").unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with("cannot deserialize an integer at the top level: wrap it in a struct or map"));
+    assert!(err.to_string().ends_with(
+        "input parsed as a map with keys [a: value, b: value, c: value, d: value, e: value, ...]"
+    ));
+
+    let err = qs::from_str::<u32>("").unwrap_err();
+    assert!(err
+        .to_string()
+        .ends_with("input parsed as an empty querystring"));
+}
+
+#[test]
+fn collect_unmatched_keys() {
+    #[derive(Debug, Deserialize)]
+    struct Exact {
+        page: u32,
+        filter: HashMap<String, String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Flattened {
+        page: u32,
+        #[serde(flatten)]
+        rest: HashMap<String, String>,
+    }
+
+    let config = qs::Config::default().collect_unmatched_keys(true);
+    let input = "page=2&filter[a]=1&filter[b]=2";
+    let (query, stats) = config.deserialize_str_with_stats::<Exact>(input).unwrap();
+    assert_eq!((query.page, query.filter.len()), (2, 2));
+    assert!(stats.unmatched_keys.is_empty());
+
+    // only top-level keys are listed
+    let (_, stats) = config
+        .deserialize_str_with_stats::<Exact>("page=2&filter[a]=1&sort=asc&x[y]=1")
+        .unwrap();
+    assert_eq!(stats.unmatched_keys, ["sort", "x"]);
+
+    // every key is listed, as a struct with a flattened field is
+    // deserialized as a map
+    let (query, stats) = config
+        .deserialize_str_with_stats::<Flattened>("page=2&sort=asc")
+        .unwrap();
+    assert_eq!(query.page, 2);
+    assert_eq!(query.rest["sort"], "asc");
+    assert_eq!(stats.unmatched_keys, ["page", "sort"]);
+
+    let (_, stats) = config
+        .deserialize_str_with_stats::<HashMap<String, String>>("b=1&a=2")
+        .unwrap();
+    assert_eq!(stats.unmatched_keys.len(), 2);
+
+    // nothing is collected unless asked for
+    let (_, stats) = qs::Config::default()
+        .deserialize_str_with_stats::<Exact>("page=2&filter[a]=1&sort=asc")
+        .unwrap();
+    assert!(stats.unmatched_keys.is_empty());
+}
+
+#[test]
+fn trim_input() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    struct Query {
+        a: String,
+        phone: String,
+    }
+
+    let expected = Query {
+        a: "x".to_owned(),
+        phone: "123".to_owned(),
+    };
+    for input in &[
+        "a=x&phone=123\n",
+        "a=x&phone=123\r\n",
+        "\u{feff}a=x&phone=123",
+        "\u{feff}?a=x&phone=123\n",
+        "  \ta=x&phone=123 ",
+    ] {
+        assert_eq!(
+            qs::from_str::<Query>(input).unwrap(),
+            expected,
+            "{:?}",
+            input
+        );
+    }
+
+    // encoded and embedded whitespace is kept
+    let query: Query = qs::from_str("a=x%20&phone=1 2+3\n").unwrap();
+    assert_eq!(query.a, "x ");
+    assert_eq!(query.phone, "1 2 3");
+
+    let config = qs::Config::default().trim_input(false);
+    assert!(config.deserialize_str::<Query>("a=x&phone=123\n").is_ok());
+    let error = config
+        .deserialize_str::<Query>("\na=x&phone=123")
+        .unwrap_err();
+    assert!(error.to_string().contains("unknown field"), "{}", error);
+}
+
+#[test]
+fn deserialize_other_variant() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Kind {
+        Known,
+        Sized(u32),
+        #[serde(other)]
+        Unknown,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Query {
+        kind: Kind,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Kinds {
+        kinds: Vec<Kind>,
+    }
+
+    let query: Query = qs::from_str("kind=new").unwrap();
+    assert_eq!(query.kind, Kind::Unknown);
+    let query: Query = qs::from_str("kind[sized]=3").unwrap();
+    assert_eq!(query.kind, Kind::Sized(3));
+
+    // the content of an unknown variant is discarded
+    let query: Query = qs::from_str("kind[new]=3").unwrap();
+    assert_eq!(query.kind, Kind::Unknown);
+    let query: Query = qs::from_str("kind[new][a]=1&kind[new][b]=2").unwrap();
+    assert_eq!(query.kind, Kind::Unknown);
+    let kind: Kind = qs::from_str("new=3").unwrap();
+    assert_eq!(kind, Kind::Unknown);
+
+    let kinds: Kinds = qs::from_str("kinds[0]=known&kinds[1]=new&kinds[2][new]=1").unwrap();
+    assert_eq!(kinds.kinds, vec![Kind::Known, Kind::Unknown, Kind::Unknown]);
+}
+
+#[test]
+fn value_transcoder() {
+    use std::borrow::Cow;
+
+    /// Decodes the Shift JIS encoding of `あ`, and ASCII.
+    fn fake_shift_jis(bytes: Cow<[u8]>) -> Result<Cow<str>, qs::TranscodeError> {
+        let mut out = String::new();
+        let mut iter = bytes.iter();
+        while let Some(&b) = iter.next() {
+            match b {
+                0x82 if iter.next() == Some(&0xA0) => out.push('あ'),
+                b if b.is_ascii() => out.push(b as char),
+                b => return Err(qs::TranscodeError::new(format!("invalid byte {:#x}", b))),
+            }
+        }
+        Ok(Cow::Owned(out))
+    }
+
+    let config = qs::Config::default().value_transcoder(fake_shift_jis);
+    let map: HashMap<String, String> = config.deserialize_str("a=%82%A0x&b=c+d").unwrap();
+    assert_eq!(map["a"], "あx");
+    assert_eq!(map["b"], "c d");
+
+    // keys are still UTF-8 unless a key transcoder is given
+    assert!(config
+        .deserialize_str::<HashMap<String, String>>("%82%A0=1")
+        .is_err());
+    let config = config.key_transcoder(fake_shift_jis);
+    let map: HashMap<String, HashMap<String, String>> =
+        config.deserialize_str("a[%82%A0]=%82%A0").unwrap();
+    assert_eq!(map["a"]["あ"], "あ");
+
+    let error = config
+        .deserialize_str::<HashMap<String, HashMap<String, String>>>("a[b]=%FF")
+        .unwrap_err();
+    match error {
+        qs::Error::Transcode { ref key, ref error } => {
+            assert_eq!(key, "a[b]");
+            assert_eq!(error.to_string(), "invalid byte 0xff");
+        }
+        ref error => panic!("unexpected error: {:?}", error),
+    }
+    assert_eq!(
+        error.to_string(),
+        "could not transcode `a[b]`: invalid byte 0xff"
+    );
+
+    // the default remains strict UTF-8
+    assert!(qs::from_str::<HashMap<String, String>>("a=%82%A0").is_err());
+}
+
+#[test]
+fn transcoded_empty_values() {
+    use std::borrow::Cow;
+
+    /// Decodes Latin-1, always returning an owned string.
+    fn latin1(bytes: Cow<[u8]>) -> Result<Cow<str>, qs::TranscodeError> {
+        Ok(Cow::Owned(bytes.iter().map(|&b| b as char).collect()))
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Flags {
+        #[serde(default)]
+        flag: bool,
+    }
+
+    for config in [
+        qs::Config::default(),
+        qs::Config::default().value_transcoder(latin1),
+    ] {
+        // empty values are values, not keys without one
+        let map: HashMap<String, Vec<String>> = config.deserialize_str("a[0]=&a[1]=").unwrap();
+        assert_eq!(map["a"], ["", ""]);
+        let map: HashMap<String, Vec<String>> = config.deserialize_str("a[0]&a[1]").unwrap();
+        assert_eq!(map["a"], ["0", "1"]);
+
+        let config = config.bare_key_as_true(true);
+        let flags: Flags = config.deserialize_str("flag").unwrap();
+        assert!(flags.flag);
+        assert!(config.deserialize_str::<Flags>("flag=").is_err());
+    }
+}
+
+#[test]
+fn invalid_percent_encoding() {
+    // by default, invalid escapes are kept as literal text
+    for (input, expected) in &[
+        ("a=%G1", "%G1"),
+        ("a=%", "%"),
+        ("a=50%&b=1", "50%"),
+        ("a=%u0041", "%u0041"),
+        ("a=x%2", "x%2"),
+    ] {
+        let map: HashMap<String, String> = qs::from_str(input).unwrap();
+        assert_eq!(map["a"], *expected, "{:?}", input);
+    }
+    let map: HashMap<String, String> = qs::from_str("%u0041=1").unwrap();
+    assert_eq!(map["%u0041"], "1");
+
+    let config = qs::Config::default().strict_percent_decoding(true);
+    for (input, position) in &[
+        ("a=%G1", 2),
+        ("a=%", 2),
+        ("b=1&a=50%&c=1", 8),
+        ("a=%u0041", 2),
+        ("a=x%2", 3),
+        ("a=%41&b[%u0041]=1", 8),
+    ] {
+        match config.deserialize_str::<HashMap<String, String>>(input) {
+            Err(qs::Error::InvalidPercentEncoding { position: p }) => {
+                assert_eq!(p, *position, "{:?}", input)
+            }
+            res => panic!("{:?} gave {:?}", input, res),
+        }
+    }
+    let map: HashMap<String, String> = config.deserialize_str("a=%41%2f+b").unwrap();
+    assert_eq!(map["a"], "A/ b");
+}
+
+#[test]
+fn max_depth_keeps_rest_of_key() {
+    type Map = HashMap<String, HashMap<String, HashMap<String, String>>>;
+
+    // the depth is the same for every entry, however many come before it
+    let config = qs::Config::new(1, true);
+    let map: Map = config.deserialize_str("x[y][z]=0&a[b][c][d]=1").unwrap();
+    assert_eq!(map["x"]["y"]["[z]"], "0");
+    assert_eq!(map["a"]["b"]["[c][d]"], "1");
+
+    // encoded brackets in the rest of the key are decoded with it
+    let config = config.lenient_bracket_decoding(true);
+    let map: Map = config.deserialize_str("a%5Bb%5D%5Bc%5D%5Bd%5D=1").unwrap();
+    assert_eq!(map["a"]["b"]["[c][d]"], "1");
+
+    let config = qs::Config::new(0, true).lenient_bracket_decoding(true);
+    let map: HashMap<String, String> = config.deserialize_str("a=0&a%5Bb%5D=1").unwrap();
+    assert_eq!(map["a[b]"], "1");
+}
+
+#[test]
+fn empty_segments_at_max_depth() {
+    type Map = HashMap<String, HashMap<String, HashMap<String, String>>>;
+
+    // empty and unclosed segments at or past the maximum depth are either
+    // kept in the rest of the key or reported as errors, never a panic
+    let config = qs::Config::new(1, true);
+    let map: Map = config.deserialize_str("a[b][]=1&c[d][e][]=2").unwrap();
+    assert_eq!(map["a"]["b"]["[]"], "1");
+    assert_eq!(map["c"]["d"]["[e][]"], "2");
+    let map: Map = config.deserialize_str("a[b][=1").unwrap();
+    assert_eq!(map["a"]["b"]["["], "1");
+
+    for input in ["a[][]=1", "[][]=1"] {
+        for depth in 1..3 {
+            let config = qs::Config::new(depth, true);
+            assert!(config.deserialize_str::<Map>(input).is_err(), "{}", input);
+        }
+        let config = qs::Config::new(0, true);
+        let map: HashMap<String, String> = config.deserialize_str(input).unwrap();
+        assert_eq!(map[input.trim_end_matches("=1")], "1");
+    }
+}
+
+#[test]
+fn equals_inside_bracketed_segments() {
+    type Map = HashMap<String, HashMap<String, HashMap<String, String>>>;
+
+    map_test!("a[x=y]=1", "a"["x=y"[1]]);
+    map_test!("a[b][<=>]==2", "a"["b"["<=>"["=2"]]]);
+    map_test!("a[b]==2", "a"["b"["=2"]]);
+
+    // the rest of a key past the maximum depth is split the same way
+    let config = qs::Config::new(1, true);
+    let map: Map = config.deserialize_str("a[b][x=y]=1&c[d][<=>]==2").unwrap();
+    assert_eq!(map["a"]["b"]["[x=y]"], "1");
+    assert_eq!(map["c"]["d"]["[<=>]"], "=2");
+
+    // an unclosed segment still ends at the '='
+    let map: Map = config.deserialize_str("a[b][x=y").unwrap();
+    assert_eq!(map["a"]["b"]["[x"], "y");
+
+    let config = qs::Config::new(0, true);
+    let map: HashMap<String, String> = config.deserialize_str("a[b][x=y]=1").unwrap();
+    assert_eq!(map["a[b][x=y]"], "1");
+}
+
+#[test]
+fn equals_filled_keys_parse_in_linear_time() {
+    type Map = HashMap<String, HashMap<String, String>>;
+
+    // each segment is scanned for its closing bracket once, rather than
+    // once for each '=' in it, which took seconds for inputs like these
+    let equals = "=".repeat(100_000);
+    let start = std::time::Instant::now();
+    let map: Map = qs::from_str(&format!("a[{}]=1", equals)).unwrap();
+    assert_eq!(map["a"][&equals], "1");
+    let input = format!("a[b][c][d][e][f][g{}]=1", equals);
+    assert!(qs::from_str::<HashMap<String, serde_json::Value>>(&input).is_ok());
+    assert!(
+        start.elapsed() < std::time::Duration::from_secs(2),
+        "{:?}",
+        start.elapsed()
+    );
+}
+
+#[test]
+fn encoded_keys_are_decoded_lazily() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Query {
+        name: String,
+        #[serde(rename = "a b")]
+        spaced: u8,
+    }
+
+    // keys are compared after decoding
+    let query: Query = qs::from_str("na%6De=x&a+b=1&ot%68er[%FF]=2").unwrap();
+    assert_eq!(
+        query,
+        Query {
+            name: "x".to_owned(),
+            spaced: 1
+        }
+    );
+    assert!(qs::from_str::<Query>("na%6De=x&name=y&a%20b=1").is_err());
+
+    // in strict mode, an encoded key must be valid UTF-8 when it is used
+    let map: Result<HashMap<String, u8>, _> = qs::from_str("a%FF=1");
+    assert!(map.is_err());
+    let map: HashMap<String, u8> = qs::Config::new(5, false).deserialize_str("a%FF=1").unwrap();
+    assert_eq!(map["a\u{FFFD}"], 1);
+
+    let map: BTreeMap<String, u8> = qs::from_str("a%5F2=2&a%5F1=1&a_0=0").unwrap();
+    assert_eq!(map.keys().collect::<Vec<_>>(), ["a_0", "a_1", "a_2"]);
+}
+
+#[test]
+fn deserialize_parsed_more_than_once() {
+    let parsed = qs::Config::default()
+        .parse_str("id=42&name=Acme&address[city]=Carrot+City&address[postcode]=12345&phone=12345&user_ids[0]=1&user_ids[1]=2")
+        .unwrap();
+
+    let map: HashMap<String, serde_json::Value> = parsed.deserialize().unwrap();
+    assert_eq!(map["name"], "Acme");
+    assert_eq!(map["user_ids"], serde_json::json!(["1", "2"]));
+
+    let params: QueryParams = parsed.deserialize().unwrap();
+    assert_eq!(params.address.city, "Carrot City");
+    assert_eq!(params.user_ids, vec![1, 2]);
+
+    let json: serde_json::Value = parsed.deserialize().unwrap();
+    assert_eq!(json["address"]["postcode"], "12345");
+
+    // errors for conflicting values are kept in the parsed map
+    let parsed = qs::Config::default().parse_str("a=1&a=2").unwrap();
+    for _ in 0..2 {
+        let err = parsed.deserialize::<HashMap<String, u8>>().unwrap_err();
+        assert!(
+            err.to_string().contains("Multiple values for one key"),
+            "{}",
+            err
+        );
+    }
+}
+
+#[test]
+fn bare_key_as_true() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Filters {
+        archived: bool,
+        #[serde(default)]
+        starred: bool,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Query {
+        verbose: bool,
+        #[serde(default)]
+        quiet: bool,
+        all: Option<bool>,
+        color: Option<bool>,
+        filters: Filters,
+    }
+
+    let config = qs::Config::default().bare_key_as_true(true);
+    let query: Query = config
+        .deserialize_str("verbose&all&color=&filters[archived]")
+        .unwrap();
+    assert_eq!(
+        query,
+        Query {
+            verbose: true,
+            quiet: false,
+            all: Some(true),
+            color: None,
+            filters: Filters {
+                archived: true,
+                starred: false,
+            },
+        }
+    );
+    let query: Query = config
+        .deserialize_str("verbose=false&quiet=true&all=false&filters[archived]&filters[starred]")
+        .unwrap();
+    assert!(!query.verbose && query.quiet);
+    assert_eq!(query.all, Some(false));
+    assert!(query.filters.starred);
+
+    // an empty value is an error, unless it is also true
+    assert!(config
+        .deserialize_str::<Query>("verbose=&filters[archived]")
+        .is_err());
+    let query: Query = config
+        .empty_value_as_true(true)
+        .deserialize_str("verbose=&all=&filters[archived]=")
+        .unwrap();
+    assert!(query.verbose && query.filters.archived);
+    assert_eq!(query.all, None);
+
+    // off by default
+    assert!(qs::from_str::<Query>("verbose&filters[archived]").is_err());
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Optional {
+        all: Option<bool>,
+    }
+    let query: Optional = qs::from_str("all").unwrap();
+    assert_eq!(query.all, None);
+}
+
+#[test]
+fn field_identifiers() {
+    #[derive(Debug, Deserialize, PartialEq, Eq, Hash)]
+    #[serde(field_identifier, rename_all = "lowercase")]
+    enum Field {
+        Name,
+        #[serde(alias = "years")]
+        Age,
+    }
+
+    let map: HashMap<Field, String> = qs::from_str("name=Acme&years=3").unwrap();
+    assert_eq!(map[&Field::Name], "Acme");
+    assert_eq!(map[&Field::Age], "3");
+    assert!(qs::from_str::<HashMap<Field, String>>("height=3").is_err());
+
+    // the indices of a sequence identify fields by position
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Query {
+        person: HashMap<Field, String>,
+    }
+    let query: Query = qs::from_str("person[1]=3&person[0]=Acme").unwrap();
+    assert_eq!(query.person[&Field::Name], "Acme");
+    assert_eq!(query.person[&Field::Age], "3");
+    assert!(qs::from_str::<Query>("person[2]=x").is_err());
+
+    // numeric keys of a map are still strings
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Numbered {
+        #[serde(rename = "1")]
+        one: u8,
+        #[serde(rename = "0")]
+        zero: u8,
+    }
+    let numbered: Numbered = qs::from_str("0=0&1=1").unwrap();
+    assert_eq!(numbered, Numbered { one: 1, zero: 0 });
+}
+
+#[test]
+fn unit_struct_errors() {
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Unit;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Query {
+        #[serde(default)]
+        units: Vec<Unit>,
+        #[serde(default)]
+        maybe: Option<Unit>,
+        #[serde(default)]
+        named: BTreeMap<String, Unit>,
+    }
+
+    // empty values and keys without values are both units
+    let query: Query = qs::from_str("units[0]=&units[1]=&maybe=&named[a]=&named[b]").unwrap();
+    assert_eq!(query.units, vec![Unit, Unit]);
+    assert_eq!(query.maybe, None);
+    assert_eq!(query.named.len(), 2);
+    let query: Query = qs::from_str("units[]=").unwrap();
+    assert_eq!(query.units, vec![Unit]);
+
+    let err = qs::from_str::<Query>("units[0]=&units[1]=1").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `units[1]`: invalid type: string \"1\", expected unit struct Unit"
+    );
+    let err = qs::from_str::<Query>("maybe=yes").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `maybe`: invalid type: string \"yes\", expected unit struct Unit"
+    );
+    let err = qs::from_str::<Query>("named[a]=&named[b]=x").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `named[b]`: invalid type: string \"x\", expected unit struct Unit"
+    );
+    let err = qs::from_str::<Query>("units[0][a]=1").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `units[0]`: invalid type: map, expected unit struct Unit"
+    );
+    let err = qs::from_str::<HashMap<String, ()>>("a[]=1").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `a`: invalid type: sequence, expected unit"
+    );
+}
+
+#[test]
+fn deeply_nested_keys() {
+    #[derive(Debug, Deserialize)]
+    struct Deep {
+        a: Option<Box<Deep>>,
+        #[serde(default)]
+        v: Option<u8>,
+    }
+
+    // `max_depth` is at most `MAX_DEPTH_LIMIT`, so parsing does not
+    // recurse once per bracket
+    let config = qs::Config::new(100_000, true);
+    let input = format!("a{}=1", "[a]".repeat(10_000));
+    let parsed = config.parse_str(&input).unwrap();
+    let (path, value) = parsed.iter_pairs().next().unwrap();
+    // `a`, the nested parts, then the rest of the key
+    assert_eq!(path.segments().len(), qs::MAX_DEPTH_LIMIT + 2);
+    assert_eq!(value.as_deref(), Some("1"));
+    // the rest of the key is an unknown field of the innermost value
+    assert!(parsed.deserialize::<Deep>().is_ok());
+
+    let input = format!("a{}[v]=1", "[a]".repeat(100));
+    let mut deep = config.deserialize_str::<Deep>(&input).unwrap().a.unwrap();
+    for _ in 0..100 {
+        deep = deep.a.unwrap();
+    }
+    assert_eq!(deep.v, Some(1));
+}
+
+#[test]
+fn empty_sequence_values() {
+    #[derive(Debug, Deserialize)]
+    struct Query<T> {
+        tags: T,
+    }
+
+    fn check<T>(input: &str, expected: Option<T>)
+    where
+        T: std::fmt::Debug + PartialEq + serde::de::DeserializeOwned,
+    {
+        let result = qs::from_str::<Query<T>>(input).map(|q| q.tags);
+        match expected {
+            Some(expected) => assert_eq!(result.unwrap(), expected, "from `{}`", input),
+            None => assert!(result.is_err(), "from `{}`: {:?}", input, result),
+        }
+    }
+
+    let empty: Vec<String> = vec![];
+    let one_empty = vec![String::new()];
+    for input in ["tags", "tags="] {
+        check(input, Some(empty.clone()));
+        check::<Option<Vec<String>>>(input, Some(None));
+        check(input, Some(Vec::<u8>::new()));
+        check(input, Some(Vec::<Option<String>>::new()));
+    }
+    for input in ["tags[]", "tags[]=", "tags[0]="] {
+        check(input, Some(one_empty.clone()));
+        check(input, Some(Some(one_empty.clone())));
+        check::<Vec<u8>>(input, None);
+        check(input, Some(vec![None::<String>]));
+    }
+    check::<Vec<String>>("", None);
+    check::<Vec<u8>>("", None);
+    check::<Vec<Option<String>>>("", None);
+    check::<Option<Vec<String>>>("", Some(None));
+    check(
+        "tags[0]=&tags[1]=",
+        Some(vec![String::new(), String::new()]),
+    );
+    check("tags[0]=&tags[1]=a", Some(vec![None, Some("a".to_owned())]));
+}
+
+#[test]
+fn dot_nesting_style() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Query {
+        address: Address,
+        ids: Vec<u8>,
+        tags: BTreeMap<String, Vec<String>>,
+    }
+
+    let mut tags = BTreeMap::new();
+    tags.insert("file.name".to_owned(), vec!["a.txt".to_owned()]);
+    tags.insert("a[b]".to_owned(), vec!["x".to_owned()]);
+    tags.insert("c".to_owned(), vec!["1".to_owned(), "2".to_owned()]);
+    let query = Query {
+        address: Address {
+            city: "Carrot City".to_owned(),
+            postcode: "12345".to_owned(),
+        },
+        ids: vec![7, 8],
+        tags,
+    };
+    assert_roundtrip(&query);
+
+    let config = qs::Config::default().nesting_style(qs::NestingStyle::Dots);
+    assert_eq!(
+        config.serialize_string(&query).unwrap(),
+        "address.city=Carrot+City&address.postcode=12345&ids.0=7&ids.1=8\
+         &tags.a%5Bb%5D.0=x&tags.c.0=1&tags.c.1=2&tags.file%2Ename.0=a.txt"
+    );
+
+    // brackets are still accepted, and can be mixed with dots
+    let rec: Query = config
+        .deserialize_str(
+            "address[city]=Carrot+City&address.postcode=12345&ids[0]=7&ids.1=8&tags[c].0=1",
+        )
+        .unwrap();
+    assert_eq!(rec.address, query.address);
+    assert_eq!(rec.ids, vec![7, 8]);
+    assert_eq!(rec.tags["c"], vec!["1"]);
+
+    // literal dots are only kept when encoded
+    let map: HashMap<String, HashMap<String, String>> =
+        config.deserialize_str("a.b%2Ec=1&a.d=2").unwrap();
+    assert_eq!(map["a"]["b.c"], "1");
+    assert_eq!(map["a"]["d"], "2");
+    let map: HashMap<String, String> = qs::from_str("a.b=1").unwrap();
+    assert_eq!(map["a.b"], "1");
+
+    // the rest of a key deeper than `max_depth` is kept as it is
+    let config = qs::Config::new(1, true).nesting_style(qs::NestingStyle::Dots);
+    let map: HashMap<String, HashMap<String, HashMap<String, String>>> =
+        config.deserialize_str("a.b.c.d=1").unwrap();
+    assert_eq!(map["a"]["b"][".c.d"], "1");
+    match config.serialize_string(&map) {
+        Err(qs::Error::MaxDepthExceeded { key, max_depth }) => {
+            assert_eq!(key, "a.b.%2Ec%2Ed");
+            assert_eq!(max_depth, 1);
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+#[test]
+fn deserialize_from_pairs() {
+    use serde::Deserialize;
+
+    let params = QueryParams {
+        id: 42,
+        name: "Acme".to_string(),
+        phone: 12345,
+        address: Address {
+            city: "Carrot City".to_string(),
+            postcode: "12345".to_string(),
+        },
+        user_ids: vec![1, 2, 3, 4],
+    };
+
+    // brackets decoded from `%5B` and `%5D` nest as well
+    let url = url::Url::parse(
+        "https://example.com/?name=Acme&id=42&phone=12345&address%5Bpostcode%5D=12345&\
+         address[city]=Carrot+City&user_ids[]=1&user_ids[]=2&user_ids%5B%5D=3&user_ids[]=4",
+    )
+    .unwrap();
+    for config in [qs::Config::new(5, true), qs::Config::new(5, false)] {
+        let de = qs::Deserializer::from_pairs(url.query_pairs(), &config).unwrap();
+        assert_eq!(QueryParams::deserialize(de).unwrap(), params);
+    }
+
+    // keys and values are not decoded again
+    let config = qs::Config::default();
+    let pairs = vec![("a[b%20c]", "1+1"), ("a[d=e&f]", "")];
+    let de = qs::Deserializer::from_pairs(pairs, &config).unwrap();
+    let map = HashMap::<String, HashMap<String, String>>::deserialize(de).unwrap();
+    assert_eq!(map["a"]["b%20c"], "1+1");
+    assert_eq!(map["a"]["d=e&f"], "");
+
+    // empty keys, and duplicates, are as in a querystring
+    let pairs = vec![("", "1"), ("a", "2"), ("a", "3")];
+    let de = qs::Deserializer::from_pairs(pairs, &config).unwrap();
+    let err = BTreeMap::<String, String>::deserialize(de).unwrap_err();
+    assert!(err.to_string().contains("Multiple values for one key"));
+    let de = qs::Deserializer::from_pairs(vec![("", "1")], &config).unwrap();
+    let map = BTreeMap::<String, String>::deserialize(de).unwrap();
+    assert_eq!(map[""], "1");
+
+    // and so is `max_depth`
+    let de =
+        qs::Deserializer::from_pairs(vec![("a[b][c]", "1")], &qs::Config::new(1, true)).unwrap();
+    let map = HashMap::<String, HashMap<String, HashMap<String, String>>>::deserialize(de).unwrap();
+    assert_eq!(map["a"]["b"]["[c]"], "1");
+}
+
+#[test]
+fn deserialize_lines() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Record {
+        id: u32,
+        tags: Vec<String>,
+    }
+
+    let config = qs::Config::default();
+    let records: Vec<Record> = config
+        .deserialize_lines("id=1&tags[0]=a\n\nid=2&tags[0]=b&tags[1]=c\r\nid=3&tags[]=d\n")
+        .unwrap();
+    assert_eq!(
+        records.iter().map(|r| r.id).collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+    assert_eq!(records[1].tags, vec!["b", "c"]);
+
+    let input = "id=1&tags[0]=a\nid=x&tags[0]=b\nid=3&tags[0]=c";
+    match config.deserialize_lines::<Record>(input) {
+        Err(qs::Error::AtLine { line: 2, source }) => {
+            assert!(matches!(*source, qs::Error::InvalidValue { .. }))
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+
+    let results: Vec<_> = config.deserialize_each_line::<Record>(input).collect();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].as_ref().unwrap().id, 1);
+    let err = results[1].as_ref().unwrap_err();
+    assert!(matches!(err, qs::Error::AtLine { line: 2, .. }));
+    assert!(err.to_string().starts_with("line 2: "));
+    assert_eq!(results[2].as_ref().unwrap().id, 3);
+}
+
+#[test]
+fn deserialize_lines_stops_at_first_error() {
+    use std::cell::Cell;
+
+    thread_local! {
+        static PARSED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Counts the lines its integer is deserialized from.
+    #[derive(Debug)]
+    struct Counted;
+
+    impl<'de> serde::Deserialize<'de> for Counted {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            PARSED.with(|parsed| parsed.set(parsed.get() + 1));
+            u32::deserialize(deserializer).map(|_| Counted)
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Record {
+        #[allow(dead_code)]
+        id: Counted,
+    }
+
+    let config = qs::Config::default();
+    let input = "id=1\nid=x\nid=3\nid=y";
+    match config.deserialize_lines::<Record>(input) {
+        Err(qs::Error::AtLine { line: 2, .. }) => {}
+        res => panic!("unexpected result: {:?}", res),
+    }
+    assert_eq!(PARSED.with(Cell::get), 2);
+
+    // lines are only deserialized as they are asked for
+    PARSED.with(|parsed| parsed.set(0));
+    let mut records = config.deserialize_each_line::<Record>(input);
+    assert!(records.next().unwrap().is_ok());
+    assert_eq!(PARSED.with(Cell::get), 1);
+    assert_eq!(records.count(), 3);
+    assert_eq!(PARSED.with(Cell::get), 4);
+}
+
+#[test]
+fn classify_query_shape() {
+    use qs::QueryShape::*;
+
+    let strict = qs::Config::default();
+    let lenient = qs::Config::default().lenient_bracket_decoding(true);
+    let non_strict = qs::Config::new(5, false);
+    let dots = strict.nesting_style(qs::NestingStyle::Dots);
+    let cases = [
+        ("a=1&b=2", [Flat, Flat, Flat, Flat]),
+        ("q=[rust]&r=a[b]=c&s=%5B", [Flat, Flat, Flat, Flat]),
+        ("a=1&b[c]=2", [Nested, Nested, Nested, Nested]),
+        ("a[]=1", [Nested, Nested, Nested, Nested]),
+        ("a%5Bb%5D=1", [Ambiguous, Nested, Nested, Ambiguous]),
+        ("a%5bb%5d=1", [Ambiguous, Ambiguous, Ambiguous, Ambiguous]),
+        ("a%5Bb%5D=1&c[d]=2", [Nested, Nested, Nested, Nested]),
+        ("a.b=1&c=2.5", [Flat, Flat, Flat, Nested]),
+        ("", [Flat, Flat, Flat, Flat]),
+    ];
+    for (input, shapes) in cases {
+        for (config, shape) in [strict, lenient, non_strict, dots].iter().zip(shapes) {
+            assert_eq!(qs::classify(input, config), shape, "{}", input);
+
+            // a flat querystring parses into a flat map, and only then
+            let flat = config.deserialize_str::<HashMap<String, String>>(input);
+            assert_eq!(flat.is_ok(), shape != Nested, "{}: {:?}", input, flat);
+        }
+    }
+
+    // with a depth of zero, keys are never nested
+    let config = qs::Config::new(0, true);
+    assert_eq!(qs::classify("a[b]=1", &config), Flat);
+}
+
+#[test]
+fn nested_sequences() {
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Grid {
+        matrix: Vec<Vec<u8>>,
+    }
+
+    let grid = Grid {
+        matrix: vec![vec![1, 2], vec![3]],
+    };
+    let encoded = qs::to_string(&grid).unwrap();
+    assert_eq!(qs::from_str::<Grid>(&encoded).unwrap(), grid);
+
+    // the outer sequence indexed, the inner ones not, in any order
+    for input in [
+        "matrix[0][0]=1&matrix[0][1]=2&matrix[1][0]=3",
+        "matrix[0][]=1&matrix[0][]=2&matrix[1][]=3",
+        "matrix[0][]=1&matrix[1][]=3&matrix[0][]=2",
+    ] {
+        assert_eq!(qs::from_str::<Grid>(input).unwrap(), grid, "{}", input);
+    }
+
+    // without an outer index, values cannot be grouped into inner sequences
+    let err = qs::from_str::<Grid>("matrix[][]=1&matrix[][]=2").unwrap_err();
+    assert!(
+        err.to_string().contains("e.g. `matrix[0][]`"),
+        "unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn whole_input() {
+    use qs::WholeInput;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Hook {
+        event: String,
+        ids: Vec<u8>,
+    }
+
+    let input = "event=push&ids[1]=2&ids[0]=1&sig=abc";
+    let WholeInput(hook, raw) = qs::from_str::<WholeInput<Hook>>(input).unwrap();
+    assert_eq!(
+        hook,
+        Hook {
+            event: "push".to_owned(),
+            ids: vec![1, 2]
+        }
+    );
+    assert_eq!(raw, input);
+
+    // the input is given as it was passed in, before it is trimmed
+    let config = qs::Config::default()
+        .trim_input(true)
+        .allow_leading_question_mark(true);
+    let input = " ?event=push&ids[]=1\n";
+    let (hook, raw) = config
+        .deserialize_str::<WholeInput<Hook>>(input)
+        .unwrap()
+        .into_parts();
+    assert_eq!(hook.ids, [1]);
+    assert_eq!(raw, input);
+
+    let parsed = qs::Config::default()
+        .parse_str("event=pull&ids[]=3")
+        .unwrap();
+    let WholeInput(hook, raw) = parsed.deserialize::<WholeInput<Hook>>().unwrap();
+    assert_eq!(hook.event, "pull");
+    assert_eq!(raw, "event=pull&ids[]=3");
+
+    // only the top-level type can capture the input
+    #[derive(Debug, Deserialize)]
+    struct Outer {
+        #[allow(dead_code)]
+        hook: WholeInput<Hook>,
+    }
+    let err = qs::from_str::<Outer>("hook[event]=push&hook[ids][]=1").unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("only supported as the top-level type"));
+
+    let de = qs::Deserializer::from_pairs(vec![("event", "push")], &qs::Config::default()).unwrap();
+    let result: Result<WholeInput<Hook>, _> = serde::Deserialize::deserialize(de);
+    assert!(result.is_err());
+
+    assert!(qs::from_bytes::<WholeInput<Hook>>(b"event=push&x=%FF\xff").is_err());
+}
+
+#[test]
+fn nested_value_errors_name_their_key() {
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Row {
+        a: u8,
+        b: u8,
+    }
+
+    fn key_of(error: qs::Error) -> String {
+        match error {
+            qs::Error::InvalidValue { key, error } => {
+                assert_eq!(error.to_string(), "missing field `b`");
+                key
+            }
+            error => panic!("unexpected error {:?}", error),
+        }
+    }
+
+    let err = qs::from_str::<HashMap<String, Row>>("x[a]=1&x[b]=2&y[a]=1").unwrap_err();
+    assert_eq!(err.to_string(), "invalid value for `y`: missing field `b`");
+    assert_eq!(key_of(err), "y");
+
+    type Nested = HashMap<String, HashMap<String, HashMap<String, Row>>>;
+    let err = qs::from_str::<Nested>("m[x][y][a]=1").unwrap_err();
+    assert_eq!(key_of(err), "m[x][y]");
+
+    // sequence elements are named by their index
+    type Rows = HashMap<String, Vec<Row>>;
+    let err = qs::from_str::<Rows>("rows[0][a]=1&rows[0][b]=2&rows[1][a]=1").unwrap_err();
+    assert_eq!(key_of(err), "rows[1]");
+    let err = qs::from_str::<HashMap<String, Vec<Vec<Row>>>>("rows[1][0][a]=1").unwrap_err();
+    assert_eq!(key_of(err), "rows[1][0]");
+}
+
+#[test]
+fn fixed_arrays_of_structs() {
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Point {
+        a: u8,
+        b: u8,
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Query {
+        arr: [Point; 2],
+    }
+
+    let query = Query {
+        arr: [Point { a: 1, b: 2 }, Point { a: 3, b: 4 }],
+    };
+    let encoded = qs::to_string(&query).unwrap();
+    assert_eq!(encoded, "arr[0][a]=1&arr[0][b]=2&arr[1][a]=3&arr[1][b]=4");
+    assert_eq!(qs::from_str::<Query>(&encoded).unwrap(), query);
+    let input = "arr[1][b]=4&arr[0][a]=1&arr[1][a]=3&arr[0][b]=2";
+    assert_eq!(qs::from_str::<Query>(input).unwrap(), query);
+
+    // an element is one element however its brackets are encoded
+    let input = "arr%5B0%5D[a]=1&arr[0]%5Bb%5D=2&arr[1][a]=3&arr%5B1%5D%5Bb%5D=4";
+    for config in [
+        qs::Config::default().lenient_bracket_decoding(true),
+        qs::Config::new(5, false),
+    ] {
+        assert_eq!(config.deserialize_str::<Query>(input).unwrap(), query);
+    }
+
+    let err = qs::from_str::<Query>("arr[0][a]=1&arr[0][b]=2").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `arr`: invalid length 1, expected an array of length 2; missing index 1"
+    );
+    let err = qs::from_str::<Query>("arr[0][a]=1&arr[0][b]=2&arr[2][a]=3&arr[2][b]=4&arr[5][a]=5")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `arr`: invalid length 3, expected an array of length 2; unexpected index 5"
+    );
+    let config = qs::Config::default().lenient_bracket_decoding(true);
+    let err = config
+        .deserialize_str::<Query>("arr%5B0%5D[a]=1&arr[0]%5Bb%5D=2")
+        .unwrap_err();
+    assert!(err.to_string().contains("missing index 1"), "{}", err);
+}
@@ -0,0 +1,358 @@
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_qs as qs;
+
+use qs::helpers::{
+    comma_separated, duration, generic_delimiter, pipe_delimited, prefixed_map, space_delimited,
+    Prefix,
+};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[test]
+fn delimited_vec() {
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Query {
+        #[serde(with = "comma_separated")]
+        ids: Vec<u32>,
+        #[serde(with = "space_delimited")]
+        names: Vec<String>,
+    }
+
+    let query = Query {
+        ids: vec![1, 2, 3],
+        names: vec!["alice".to_string(), "bob".to_string()],
+    };
+    let rec_query: Query = qs::from_str("ids=1,2,3&names=alice+bob").unwrap();
+    assert_eq!(rec_query, query);
+
+    let s = qs::to_string(&query).unwrap();
+    assert_eq!(s, "ids=1%2C2%2C3&names=alice+bob");
+    assert_eq!(qs::from_str::<Query>(&s).unwrap(), query);
+}
+
+#[test]
+fn delimited_tuple() {
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Query {
+        #[serde(with = "comma_separated")]
+        range: (u32, u32),
+    }
+
+    let query = Query { range: (1, 100) };
+    let rec_query: Query = qs::from_str("range=1,100").unwrap();
+    assert_eq!(rec_query, query);
+
+    let s = qs::to_string(&query).unwrap();
+    assert_eq!(qs::from_str::<Query>(&s).unwrap(), query);
+}
+
+#[test]
+fn delimited_array() {
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Query {
+        #[serde(with = "pipe_delimited")]
+        bbox: [f64; 4],
+    }
+
+    let query = Query {
+        bbox: [1.0, 2.0, 3.0, 4.5],
+    };
+    let rec_query: Query = qs::from_str("bbox=1.0|2.0|3.0|4.5").unwrap();
+    assert_eq!(rec_query, query);
+
+    let s = qs::to_string(&query).unwrap();
+    assert_eq!(qs::from_str::<Query>(&s).unwrap(), query);
+}
+
+#[test]
+fn delimited_heterogeneous() {
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Point {
+        x: i32,
+        y: i32,
+        label: String,
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Query {
+        #[serde(
+            serialize_with = "generic_delimiter::serialize::<_, _, ';'>",
+            deserialize_with = "generic_delimiter::deserialize::<_, _, ';'>"
+        )]
+        pair: (u8, String),
+        #[serde(with = "comma_separated")]
+        point: Point,
+    }
+
+    let query = Query {
+        pair: (7, "seven".to_string()),
+        point: Point {
+            x: -1,
+            y: 2,
+            label: "origin".to_string(),
+        },
+    };
+    let rec_query: Query = qs::from_str("pair=7;seven&point=-1,2,origin").unwrap();
+    assert_eq!(rec_query, query);
+
+    let s = qs::to_string(&query).unwrap();
+    assert_eq!(qs::from_str::<Query>(&s).unwrap(), query);
+}
+
+#[test]
+fn delimited_wrong_arity() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Query {
+        #[serde(with = "comma_separated")]
+        range: (u32, u32),
+    }
+
+    let err = qs::from_str::<Query>("range=1,2,3").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `range`: expected 2 delimited elements, found 3"
+    );
+
+    let err = qs::from_str::<Query>("range=1").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `range`: expected 2 delimited elements, found 1"
+    );
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct BoundingBox {
+        #[serde(with = "comma_separated")]
+        bbox: [f64; 4],
+    }
+
+    let err = qs::from_str::<BoundingBox>("bbox=1.0,2.0").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `bbox`: expected 4 delimited elements, found 2"
+    );
+}
+
+#[test]
+fn delimited_empty_string() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Query {
+        #[serde(with = "comma_separated")]
+        ids: Vec<u32>,
+    }
+
+    let rec_query: Query = qs::from_str("ids=").unwrap();
+    assert_eq!(rec_query, Query { ids: vec![] });
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Range {
+        #[serde(with = "comma_separated")]
+        range: (u32, u32),
+    }
+
+    let err = qs::from_str::<Range>("range=").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `range`: expected 2 delimited elements, found 0"
+    );
+}
+
+#[test]
+fn delimited_element_errors() {
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Query {
+        #[serde(with = "comma_separated")]
+        names: Vec<String>,
+    }
+
+    assert!(qs::from_str::<Query>("names=a,b").is_ok());
+
+    let query = Query {
+        names: vec!["a,b".to_string()],
+    };
+    assert!(qs::to_string(&query).is_err());
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Numbers {
+        #[serde(with = "comma_separated")]
+        ids: Vec<u32>,
+    }
+    assert!(qs::from_str::<Numbers>("ids=1,x").is_err());
+}
+
+struct Meta;
+
+impl Prefix for Meta {
+    const PREFIX: &'static str = "meta_";
+}
+
+#[test]
+fn prefixed_map_round_trip() {
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Query {
+        id: u32,
+        // Also starts with the prefix, but is not collected into `meta`.
+        meta_count: u32,
+        #[serde(
+            flatten,
+            serialize_with = "prefixed_map::serialize::<_, _, Meta>",
+            deserialize_with = "prefixed_map::deserialize::<_, _, Meta>"
+        )]
+        meta: BTreeMap<String, u32>,
+    }
+
+    let rec_query: Query =
+        qs::from_str("id=1&meta_count=2&meta_a=3&other=4&meta_b=5&meta%5Fc=6").unwrap();
+    let mut meta = BTreeMap::new();
+    meta.insert("a".to_string(), 3);
+    meta.insert("b".to_string(), 5);
+    meta.insert("c".to_string(), 6);
+    assert_eq!(
+        rec_query,
+        Query {
+            id: 1,
+            meta_count: 2,
+            meta
+        }
+    );
+    assert_eq!(
+        qs::to_string(&rec_query).unwrap(),
+        "id=1&meta_count=2&meta_a=3&meta_b=5&meta_c=6"
+    );
+
+    let err = qs::from_str::<Query>("id=1&meta_count=2&meta_a=x").unwrap_err();
+    assert!(err.to_string().contains("invalid digit"), "{}", err);
+}
+
+#[test]
+fn prefixed_struct() {
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Paging {
+        page: u32,
+        size: Option<u32>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Query {
+        q: String,
+        #[serde(
+            flatten,
+            serialize_with = "prefixed_map::serialize::<_, _, Meta>",
+            deserialize_with = "prefixed_map::deserialize::<_, _, Meta>"
+        )]
+        paging: Paging,
+    }
+
+    let query = Query {
+        q: "rust".to_string(),
+        paging: Paging {
+            page: 2,
+            size: Some(10),
+        },
+    };
+    let s = qs::to_string(&query).unwrap();
+    assert_eq!(s, "q=rust&meta_page=2&meta_size=10");
+    assert_eq!(qs::from_str::<Query>(&s).unwrap(), query);
+
+    let rec_query: Query = qs::from_str("q=rust&meta_page=1&meta_size=").unwrap();
+    assert_eq!(
+        rec_query.paging,
+        Paging {
+            page: 1,
+            size: None
+        }
+    );
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct Timeout {
+    #[serde(with = "duration")]
+    timeout: Duration,
+}
+
+#[test]
+fn duration_suffixes() {
+    let cases = [
+        ("1500ms", Duration::from_millis(1500)),
+        ("30s", Duration::from_secs(30)),
+        ("5m", Duration::from_secs(300)),
+        ("2h", Duration::from_secs(7200)),
+        ("45", Duration::from_secs(45)),
+        ("1.5s", Duration::from_millis(1500)),
+        ("0.25m", Duration::from_secs(15)),
+        ("1.5ms", Duration::from_micros(1500)),
+        ("0", Duration::from_secs(0)),
+    ];
+    for &(input, expected) in cases.iter() {
+        let rec: Timeout = qs::from_str(&format!("timeout={}", input)).unwrap();
+        assert_eq!(rec.timeout, expected, "{}", input);
+    }
+}
+
+#[test]
+fn duration_serialize_largest_exact_unit() {
+    let cases = [
+        (Duration::from_secs(90), "90s"),
+        (Duration::from_secs(120), "2m"),
+        (Duration::from_secs(7200), "2h"),
+        (Duration::from_millis(1500), "1500ms"),
+        (Duration::from_micros(1500), "0.0015s"),
+        (Duration::from_secs(0), "0s"),
+    ];
+    for &(timeout, expected) in cases.iter() {
+        let s = qs::to_string(&Timeout { timeout }).unwrap();
+        assert_eq!(s, format!("timeout={}", expected));
+        assert_eq!(qs::from_str::<Timeout>(&s).unwrap().timeout, timeout);
+    }
+}
+
+#[test]
+fn duration_errors() {
+    let err = qs::from_str::<Timeout>("timeout=-5s").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `timeout`: invalid duration `-5s`, must not be negative"
+    );
+
+    let err = qs::from_str::<Timeout>("timeout=5d").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `timeout`: invalid duration `5d`, \
+         expected a number followed by `ms`, `s`, `m` or `h`"
+    );
+
+    for input in &["", "s", ".5s", "1.s", "1e3s", "1 s"] {
+        assert!(qs::from_str::<Timeout>(&format!("timeout={}", input)).is_err());
+    }
+
+    // u64::MAX seconds is representable, but not in minutes
+    let max = qs::from_str::<Timeout>("timeout=18446744073709551615").unwrap();
+    assert_eq!(max.timeout, Duration::from_secs(u64::MAX));
+    let err = qs::from_str::<Timeout>("timeout=18446744073709551615m").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `timeout`: invalid duration `18446744073709551615m`, too large"
+    );
+    assert!(qs::from_str::<Timeout>("timeout=18446744073709551616").is_err());
+}
+
+#[test]
+fn duration_secs() {
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Query {
+        #[serde(with = "qs::helpers::duration_secs")]
+        ttl: Duration,
+    }
+
+    let query: Query = qs::from_str("ttl=90").unwrap();
+    assert_eq!(query.ttl, Duration::from_secs(90));
+    assert_eq!(qs::to_string(&query).unwrap(), "ttl=90");
+
+    assert!(qs::from_str::<Query>("ttl=-1").is_err());
+    assert!(qs::from_str::<Query>("ttl=1s").is_err());
+    let query = Query {
+        ttl: Duration::from_millis(1500),
+    };
+    assert!(qs::to_string(&query).is_err());
+}
@@ -0,0 +1,141 @@
+#![cfg(feature = "indexmap")]
+
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_qs as qs;
+
+use indexmap::IndexMap;
+
+fn keys<K: ToString, V>(map: &IndexMap<K, V>) -> Vec<String> {
+    map.keys().map(ToString::to_string).collect()
+}
+
+fn ordered() -> qs::Config {
+    qs::Config::default().preserve_key_order(true)
+}
+
+#[test]
+fn deserialize_sorted_by_default() {
+    // enabling the feature alone does not change the order
+    let input = "z[y]=1&a[c]=2&z[b]=3&m[x]=4&a[a]=5";
+    let map: IndexMap<String, IndexMap<String, String>> = qs::from_str(input).unwrap();
+    assert_eq!(keys(&map), ["a", "m", "z"]);
+    assert_eq!(keys(&map["z"]), ["b", "y"]);
+
+    let parsed = qs::Config::default().parse_str(input).unwrap();
+    let paths: Vec<String> = parsed
+        .iter_pairs()
+        .map(|(path, _)| path.to_string())
+        .collect();
+    assert_eq!(paths, ["a[a]", "a[c]", "m[x]", "z[b]", "z[y]"]);
+
+    // including for integer keys, which are sorted as strings
+    let map: IndexMap<String, IndexMap<String, String>> =
+        qs::from_str("m[10]=a&m[2]=b&m[x]=c").unwrap();
+    assert_eq!(keys(&map["m"]), ["10", "2", "x"]);
+    let map: IndexMap<String, IndexMap<String, String>> = qs::from_str("m[2]=b&m[10]=a").unwrap();
+    assert_eq!(keys(&map["m"]), ["10", "2"]);
+
+    let pairs = [("b[y]", "1"), ("a", "2"), ("b[x]", "3")];
+    let de = qs::Deserializer::from_pairs(pairs, &qs::Config::default()).unwrap();
+    let map: IndexMap<String, serde_json::Value> = serde::Deserialize::deserialize(de).unwrap();
+    assert_eq!(keys(&map), ["a", "b"]);
+    assert_eq!(map["b"], serde_json::json!({"x": "3", "y": "1"}));
+}
+
+#[test]
+fn deserialize_nested_maps_in_order() {
+    let map: IndexMap<String, IndexMap<String, String>> = ordered()
+        .deserialize_str("z[y]=1&a[c]=2&z[b]=3&m[x]=4&a[a]=5")
+        .unwrap();
+    assert_eq!(keys(&map), ["z", "a", "m"]);
+    assert_eq!(keys(&map["z"]), ["y", "b"]);
+    assert_eq!(keys(&map["a"]), ["c", "a"]);
+
+    // an encoded key is the same key as its decoded form
+    let map: IndexMap<String, IndexMap<String, String>> = qs::Config::new(5, false)
+        .preserve_key_order(true)
+        .deserialize_str("b[x]=1&a[z]=2&%62[y]=3")
+        .unwrap();
+    assert_eq!(keys(&map), ["b", "a"]);
+    assert_eq!(keys(&map["b"]), ["x", "y"]);
+
+    // and so are its spellings with encoded brackets
+    let config = ordered().lenient_bracket_decoding(true);
+    let map: IndexMap<String, IndexMap<String, String>> =
+        config.deserialize_str("c%5Bz%5D=1&a[y]=2&c[x]=3").unwrap();
+    assert_eq!(keys(&map), ["c", "a"]);
+    assert_eq!(keys(&map["c"]), ["z", "x"]);
+}
+
+#[test]
+fn deserialize_interleaved_sequences() {
+    let map: IndexMap<String, IndexMap<String, Vec<String>>> = ordered()
+        .deserialize_str("m[b][]=2&m[a][]=1&m[b][]=3&m[a][]=4")
+        .unwrap();
+    assert_eq!(keys(&map["m"]), ["b", "a"]);
+    assert_eq!(map["m"]["b"], ["2", "3"]);
+    assert_eq!(map["m"]["a"], ["1", "4"]);
+
+    // indexed elements are in index order, whatever order they are given in
+    let map: IndexMap<String, Vec<String>> =
+        ordered().deserialize_str("b[1]=x&a[0]=y&b[0]=z").unwrap();
+    assert_eq!(keys(&map), ["b", "a"]);
+    assert_eq!(map["b"], ["z", "x"]);
+}
+
+#[test]
+fn deserialize_enums_in_order() {
+    #[derive(Debug, Deserialize, PartialEq, Eq, Hash)]
+    #[serde(rename_all = "lowercase")]
+    enum Kind {
+        Red,
+        Green,
+        Blue,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Shape {
+        Circle { r: u8 },
+        Square(u8),
+    }
+
+    let map: IndexMap<Kind, u8> = ordered().deserialize_str("green=1&blue=2&red=3").unwrap();
+    assert_eq!(
+        map.keys().collect::<Vec<_>>(),
+        [&Kind::Green, &Kind::Blue, &Kind::Red]
+    );
+
+    let map: IndexMap<String, Shape> = ordered()
+        .deserialize_str("s[Square]=2&c[Circle][r]=1")
+        .unwrap();
+    assert_eq!(keys(&map), ["s", "c"]);
+    assert_eq!(map["s"], Shape::Square(2));
+    assert_eq!(map["c"], Shape::Circle { r: 1 });
+}
+
+#[test]
+fn serialize_in_iteration_order() {
+    let mut inner = IndexMap::new();
+    let _ = inner.insert("y", 1);
+    let _ = inner.insert("b", 2);
+    let mut map = IndexMap::new();
+    let _ = map.insert("z", inner.clone());
+    let _ = map.insert("a", inner);
+
+    let encoded = qs::to_string(&map).unwrap();
+    assert_eq!(encoded, "z[y]=1&z[b]=2&a[y]=1&a[b]=2");
+
+    // and back again, in the same order
+    let decoded: IndexMap<String, IndexMap<String, u8>> =
+        ordered().deserialize_str(&encoded).unwrap();
+    assert_eq!(keys(&decoded), ["z", "a"]);
+    assert_eq!(keys(&decoded["z"]), ["y", "b"]);
+
+    // unless sorted output is asked for
+    let config = qs::Config::default().stable_output(true);
+    assert_eq!(
+        config.serialize_string(&map).unwrap(),
+        "a[b]=2&a[y]=1&z[b]=2&z[y]=1"
+    );
+}
@@ -0,0 +1,113 @@
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_qs as qs;
+
+#[test]
+fn merge_adds_to_unknown_keys() {
+    #[derive(Serialize)]
+    struct Pagination {
+        page: u32,
+        per_page: Option<u32>,
+    }
+
+    let config = qs::Config::default();
+    let original = "q=rust&filter[lang]=en&filter[tags][0]=web&page=1&per_page=50";
+    let merged = qs::merge(
+        original,
+        &Pagination {
+            page: 2,
+            per_page: None,
+        },
+        &config,
+    )
+    .unwrap();
+    assert_eq!(
+        merged,
+        "filter[lang]=en&filter[tags][0]=web&page=2&per_page=50&q=rust"
+    );
+
+    let merged = qs::merge(
+        "",
+        &Pagination {
+            page: 1,
+            per_page: Some(10),
+        },
+        &config,
+    )
+    .unwrap();
+    assert_eq!(merged, "page=1&per_page=10");
+}
+
+#[test]
+fn merge_nested_fields() {
+    #[derive(Serialize)]
+    struct Filter {
+        tags: Vec<&'static str>,
+    }
+
+    #[derive(Serialize)]
+    struct Query {
+        filter: Filter,
+    }
+
+    let config = qs::Config::default();
+    let original = "q=rust&filter[lang]=en&filter[tags][0]=web&filter[tags][1]=cli";
+    let merged = qs::merge(
+        original,
+        &Query {
+            filter: Filter {
+                tags: vec!["async"],
+            },
+        },
+        &config,
+    )
+    .unwrap();
+    assert_eq!(merged, "filter[lang]=en&filter[tags][0]=async&q=rust");
+}
+
+#[test]
+fn merge_conflicts() {
+    #[derive(Serialize)]
+    struct Lang {
+        lang: &'static str,
+    }
+
+    #[derive(Serialize)]
+    struct Query {
+        filter: Lang,
+    }
+
+    let config = qs::Config::default();
+    let err = qs::merge(
+        "filter=all",
+        &Query {
+            filter: Lang { lang: "en" },
+        },
+        &config,
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `filter`: cannot replace a value with a map"
+    );
+
+    #[derive(Serialize)]
+    struct Nested {
+        options: Query,
+    }
+
+    let err = qs::merge(
+        "options[filter][lang][code]=en",
+        &Nested {
+            options: Query {
+                filter: Lang { lang: "en" },
+            },
+        },
+        &config,
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value for `options[filter][lang]`: cannot replace a map with a value"
+    );
+}
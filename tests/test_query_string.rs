@@ -0,0 +1,84 @@
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_qs as qs;
+
+use qs::QueryString;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+struct Params {
+    a: u8,
+    b: Vec<String>,
+}
+
+#[test]
+fn parse_and_display() {
+    let params: QueryString<Params> = "a=1&b[0]=x&b[1]=y".parse().unwrap();
+    assert_eq!(params.a, 1);
+    assert_eq!(params.b, vec!["x", "y"]);
+    assert_eq!(params.to_string(), "a=1&b[0]=x&b[1]=y");
+
+    let params = QueryString::<Params>::try_from("a=2&b[0]=z").unwrap();
+    assert_eq!(
+        params.into_inner(),
+        Params {
+            a: 2,
+            b: vec!["z".to_owned()]
+        }
+    );
+
+    assert!("a=x".parse::<QueryString<Params>>().is_err());
+
+    let config = qs::Config::new(0, true);
+    let map = QueryString::<BTreeMap<String, u8>>::parse_with("a[b]=1", &config).unwrap();
+    assert_eq!(map["a[b]"], 1);
+}
+
+#[test]
+fn infallible_conversions() {
+    // generic over conversions which fail with an `Error`, or never fail
+    fn convert<T, U>(value: T) -> Result<U, qs::Error>
+    where
+        U: TryFrom<T>,
+        qs::Error: From<U::Error>,
+    {
+        Ok(U::try_from(value)?)
+    }
+
+    let params: QueryString<Params> = convert("a=1&b[0]=x").unwrap();
+    let params: QueryString<Params> = convert(params).unwrap();
+    assert_eq!(params.a, 1);
+}
+
+#[test]
+fn serializes_as_inner_value() {
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Stored {
+        query: QueryString<Params>,
+    }
+
+    let stored: Stored = serde_json::from_str(r#"{"query":{"a":1,"b":["x"]}}"#).unwrap();
+    assert_eq!(
+        stored.query.0,
+        Params {
+            a: 1,
+            b: vec!["x".to_owned()]
+        }
+    );
+    assert_eq!(
+        serde_json::to_string(&stored).unwrap(),
+        r#"{"query":{"a":1,"b":["x"]}}"#
+    );
+}
+
+#[test]
+#[cfg_attr(debug_assertions, should_panic(expected = "could not be serialized"))]
+fn display_writes_nothing_on_error() {
+    // a tuple is not a valid key
+    let mut map = BTreeMap::new();
+    map.insert((1, 2), 3);
+    let query = QueryString(map);
+    assert!(qs::to_string(&query).is_err());
+    assert_eq!(query.to_string(), "");
+}
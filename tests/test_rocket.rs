@@ -0,0 +1,144 @@
+#![cfg(feature = "rocket")]
+
+#[macro_use]
+extern crate serde_derive;
+extern crate rocket_framework as rocket;
+extern crate serde_qs as qs;
+
+use qs::rocket::{QsForm, QsQuery};
+use rocket::http::{ContentType, Status};
+use rocket::local::blocking::Client;
+use rocket::{get, post, routes};
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+struct UserInfo {
+    username: String,
+    address: Address,
+    ids: Vec<u8>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+struct Address {
+    city: String,
+    postcode: String,
+}
+
+fn describe(info: &UserInfo) -> String {
+    format!(
+        "{} lives in {} ({}), ids: {:?}",
+        info.username, info.address.city, info.address.postcode, info.ids
+    )
+}
+
+#[get("/query")]
+fn query(info: QsQuery<UserInfo>) -> String {
+    describe(&info)
+}
+
+#[get("/query_result")]
+fn query_result(info: Result<QsQuery<UserInfo>, qs::Error>) -> (Status, String) {
+    match info {
+        Ok(info) => (Status::Ok, describe(&info)),
+        Err(err) => (Status::BadRequest, err.to_string()),
+    }
+}
+
+#[post("/form", data = "<info>")]
+fn form(info: QsForm<UserInfo>) -> String {
+    describe(&info)
+}
+
+fn local_client(rocket: rocket::Rocket<rocket::Build>) -> Client {
+    Client::untracked(rocket.mount("/", routes![query, query_result, form])).unwrap()
+}
+
+const QUERY: &str = "username=bob&address[city]=Berlin&address[postcode]=10115&ids[0]=1&ids[1]=2";
+const EXPECTED: &str = "bob lives in Berlin (10115), ids: [1, 2]";
+
+#[test]
+fn test_query() {
+    let client = local_client(rocket::build());
+
+    let response = client.get(format!("/query?{}", QUERY)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), EXPECTED);
+
+    // Encoded brackets are rejected in strict mode.
+    let response = client
+        .get("/query?username=bob&address%5Bcity%5D=Berlin&address%5Bpostcode%5D=10115&ids[0]=1")
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[test]
+fn test_query_error() {
+    let client = local_client(rocket::build());
+
+    let response = client.get("/query?username=bob").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client.get("/query_result?username=bob").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(response.into_string().unwrap(), "missing field `address`");
+
+    let response = client.get("/query_result").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(response.into_string().unwrap(), "missing field `username`");
+}
+
+#[test]
+fn test_managed_config() {
+    // Non-strict mode accepts encoded brackets.
+    let client = local_client(rocket::build().manage(qs::Config::new(5, false)));
+    let response = client
+        .get(
+            "/query?username=bob&address%5Bcity%5D=Berlin&address%5Bpostcode%5D=10115\
+             &ids%5B0%5D=1&ids%5B1%5D=2",
+        )
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), EXPECTED);
+
+    // With a depth of 1, `address[city]` is still nested, and the rest of
+    // `address[city][name]` is kept as a single key under `city`.
+    let client = local_client(rocket::build().manage(qs::Config::new(1, true)));
+    let response = client.get(format!("/query?{}", QUERY)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get("/query_result?address[city][name]=Berlin&address[postcode]=10115&username=bob")
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(
+        response.into_string().unwrap(),
+        "invalid value for `address[city]`: invalid type: map, expected a string"
+    );
+}
+
+#[test]
+fn test_form() {
+    let client = local_client(rocket::build());
+
+    let response = client
+        .post("/form")
+        .header(ContentType::Form)
+        .body(QUERY)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), EXPECTED);
+
+    let response = client
+        .post("/form")
+        .header(ContentType::Form)
+        .body("username=bob&ids[0]=x")
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    // Other content types are forwarded, and so not found here.
+    let response = client
+        .post("/form")
+        .header(ContentType::JSON)
+        .body(QUERY)
+        .dispatch();
+    assert_eq!(response.status(), Status::UnsupportedMediaType);
+}
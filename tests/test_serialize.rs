@@ -157,6 +157,60 @@ fn serialize_map_with_unit_enum_keys() {
     assert!(query == "point[Lt]=321&point[Gt]=123" || query == "point[Gt]=123&point[Lt]=321");
 }
 
+#[test]
+fn enum_map_keys_roundtrip() {
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+    #[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+    enum Operator {
+        NotEqual,
+        #[serde(rename = "greater than")]
+        GreaterThan,
+        #[serde(rename = "a+b")]
+        Plus,
+        #[serde(rename = "größer")]
+        Larger,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Query {
+        counts: BTreeMap<Operator, u64>,
+    }
+
+    let query = Query {
+        counts: vec![
+            (Operator::NotEqual, 1),
+            (Operator::GreaterThan, 2),
+            (Operator::Plus, 3),
+            (Operator::Larger, 4),
+        ]
+        .into_iter()
+        .collect(),
+    };
+    let encoded = qs::to_string(&query).unwrap();
+    assert_eq!(
+        encoded,
+        "counts[NOT-EQUAL]=1&counts[greater+than]=2&counts[a%2Bb]=3&counts[gr%C3%B6%C3%9Fer]=4"
+    );
+    assert_eq!(qs::from_str::<Query>(&encoded).unwrap(), query);
+
+    for config in [
+        qs::Config::new(5, false),
+        qs::Config::default().nesting_style(qs::NestingStyle::Dots),
+    ] {
+        let encoded = config.serialize_string(&query).unwrap();
+        assert_eq!(config.deserialize_str::<Query>(&encoded).unwrap(), query);
+    }
+
+    // and at the top level
+    let encoded = qs::to_string(&query.counts).unwrap();
+    assert_eq!(
+        qs::from_str::<BTreeMap<Operator, u64>>(&encoded).unwrap(),
+        query.counts
+    );
+}
+
 #[test]
 fn serialize_bytes() {
     struct Bytes(&'static [u8]);
@@ -276,3 +330,1013 @@ fn test_serializer_unit() {
 
     assert_eq!(writer, b"t=", "we are testing B{{t: ()}}");
 }
+
+#[test]
+fn serialize_stable_output() {
+    use std::collections::HashMap;
+
+    #[derive(Serialize)]
+    struct Query {
+        z: u8,
+        maps: Vec<HashMap<&'static str, u8>>,
+        nested: HashMap<&'static str, HashMap<&'static str, u8>>,
+    }
+
+    let first: HashMap<_, _> = vec![("d", 4), ("b", 2), ("c", 3), ("a", 1)]
+        .into_iter()
+        .collect();
+    let second: HashMap<_, _> = vec![("b", 6), ("a", 5)].into_iter().collect();
+    let query = Query {
+        z: 0,
+        maps: vec![first, second],
+        nested: vec![
+            ("y", vec![("b", 8), ("a", 7)].into_iter().collect()),
+            ("x", vec![("d", 10), ("c", 9)].into_iter().collect()),
+        ]
+        .into_iter()
+        .collect(),
+    };
+
+    let config = qs::Config::default().stable_output(true);
+    assert_eq!(
+        config.serialize_string(&query).unwrap(),
+        "\
+         z=0&\
+         maps[0][a]=1&maps[0][b]=2&maps[0][c]=3&maps[0][d]=4&\
+         maps[1][a]=5&maps[1][b]=6&\
+         nested[x][c]=9&nested[x][d]=10&nested[y][a]=7&nested[y][b]=8"
+    );
+
+    // top-level maps are sorted too, skipping entries which produce no output
+    let map: HashMap<_, _> = vec![("b", Some(2)), ("c", None), ("a", Some(1))]
+        .into_iter()
+        .collect();
+    assert_eq!(config.serialize_string(&map).unwrap(), "a=1&b=2");
+
+    let mut writer = Vec::new();
+    config.serialize_to_writer(&map, &mut writer).unwrap();
+    assert_eq!(writer, b"a=1&b=2");
+}
+
+#[test]
+fn serialize_keys_with_spaces() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Name {
+        #[serde(rename = "first name")]
+        first: String,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Form {
+        #[serde(rename = "first name")]
+        first: String,
+        #[serde(rename = "c++ user")]
+        user: Name,
+    }
+
+    let form = Form {
+        first: "Jane Doe".to_owned(),
+        user: Name {
+            first: "A+B".to_owned(),
+        },
+    };
+    let s = qs::to_string(&form).unwrap();
+    assert_eq!(s, "first+name=Jane+Doe&c%2B%2B+user[first+name]=A%2BB");
+    assert_eq!(qs::from_str::<Form>(&s).unwrap(), form);
+}
+
+#[test]
+fn serialize_numbers() {
+    #[derive(Serialize)]
+    struct Numbers {
+        min: i64,
+        max: u64,
+        small: i8,
+        whole: f64,
+        big: f64,
+        tiny: f32,
+        nan: f64,
+        neg_inf: f64,
+        flag: bool,
+    }
+
+    let numbers = Numbers {
+        min: i64::MIN,
+        max: u64::MAX,
+        small: -1,
+        whole: 1.0,
+        big: 1e21,
+        tiny: -1.5e-7,
+        nan: f64::NAN,
+        neg_inf: f64::NEG_INFINITY,
+        flag: true,
+    };
+    assert_eq!(
+        qs::to_string(&numbers).unwrap(),
+        "min=-9223372036854775808&max=18446744073709551615&small=-1&whole=1\
+         &big=1000000000000000000000&tiny=-0.00000015&nan=NaN&neg_inf=-inf&flag=true"
+    );
+}
+
+#[test]
+fn serialize_with_prefix() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Filter {
+        name: String,
+        address: Address,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Page {
+        number: u32,
+        size: u32,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Query {
+        filter: Filter,
+        page: Page,
+        ids: Vec<u8>,
+    }
+
+    let filter = Filter {
+        name: "Acme Corp".to_owned(),
+        address: Address {
+            city: "Carrot City".to_owned(),
+            street: "Special-Street* No. 11".to_owned(),
+            postcode: "12345".to_owned(),
+        },
+    };
+    let page = Page {
+        number: 2,
+        size: 50,
+    };
+    let ids = vec![1u8, 2];
+
+    let filter_qs = qs::to_string_with_prefix("filter", &filter).unwrap();
+    assert_eq!(
+        filter_qs,
+        "filter[name]=Acme+Corp&filter[address][city]=Carrot+City\
+         &filter[address][street]=Special-Street*+No.+11&filter[address][postcode]=12345"
+    );
+    let page_qs = qs::to_string_with_prefix("page", &page).unwrap();
+    assert_eq!(page_qs, "page[number]=2&page[size]=50");
+    let ids_qs = qs::to_string_with_prefix("ids", &ids).unwrap();
+    assert_eq!(ids_qs, "ids[0]=1&ids[1]=2");
+
+    // Top-level values, prefixes needing encoding, and empty prefixes.
+    assert_eq!(qs::to_string_with_prefix("q", &"a b").unwrap(), "q=a+b");
+    assert_eq!(qs::to_string_with_prefix("a b", &1).unwrap(), "a+b=1");
+    assert_eq!(
+        qs::to_string_with_prefix("", &page).unwrap(),
+        qs::to_string(&page).unwrap()
+    );
+    assert!(qs::to_string_with_prefix("", &1).is_err());
+
+    let config = qs::Config::default();
+    assert_eq!(
+        config.serialize_string_with_prefix("page", &page).unwrap(),
+        "page[number]=2&page[size]=50"
+    );
+
+    let query: Query = qs::from_str(&[filter_qs, page_qs, ids_qs].join("&")).unwrap();
+    assert_eq!(query, Query { filter, page, ids });
+}
+
+#[test]
+fn encode_matrix() {
+    use std::collections::HashMap;
+
+    struct Bytes(Vec<u8>);
+
+    impl serde::Serialize for Bytes {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+
+    fn expected(byte: u8) -> String {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'*' => {
+                (byte as char).to_string()
+            }
+            b' ' => "+".to_string(),
+            _ => format!("%{:02X}", byte),
+        }
+    }
+
+    for byte in 0..=255u8 {
+        let mut map = HashMap::new();
+        map.insert("v", Bytes(vec![byte]));
+        assert_eq!(
+            qs::to_string(&map).unwrap(),
+            format!("v={}", expected(byte)),
+            "value byte {:#04x}",
+            byte
+        );
+
+        if byte.is_ascii() {
+            let key = (byte as char).to_string();
+            let mut map = HashMap::new();
+            map.insert(key.as_str(), 1);
+            let encoded = qs::to_string(&map).unwrap();
+            assert_eq!(
+                encoded,
+                format!("{}=1", expected(byte)),
+                "key byte {:#04x}",
+                byte
+            );
+
+            let mut map = HashMap::new();
+            map.insert("v", key.clone());
+            let encoded = qs::to_string(&map).unwrap();
+            let decoded: HashMap<String, String> = qs::from_str(&encoded).unwrap();
+            assert_eq!(decoded["v"], key, "round trip of {:?}", encoded);
+        }
+    }
+
+    // every byte from 0x80 is encoded, and round trips as part of UTF-8
+    let latin1: String = (0x80..=0xffu8).map(char::from).collect();
+    let mut map = HashMap::new();
+    map.insert("v", latin1.clone());
+    let encoded = qs::to_string(&map).unwrap();
+    assert!(encoded[2..].bytes().all(|b| b.is_ascii()));
+    let decoded: HashMap<String, String> = qs::from_str(&encoded).unwrap();
+    assert_eq!(decoded["v"], latin1);
+}
+
+#[test]
+fn serialize_field_order() {
+    #[derive(Serialize)]
+    struct Auth {
+        key: String,
+        signature: String,
+    }
+
+    #[derive(Serialize)]
+    struct Page {
+        number: u32,
+        size: u32,
+    }
+
+    #[derive(Serialize)]
+    struct Query {
+        q: String,
+        page: Page,
+        tags: Vec<String>,
+        #[serde(flatten)]
+        auth: Auth,
+    }
+
+    let query = Query {
+        q: "rust".to_owned(),
+        page: Page {
+            number: 2,
+            size: 50,
+        },
+        tags: vec!["a".to_owned(), "b".to_owned()],
+        auth: Auth {
+            key: "k".to_owned(),
+            signature: "s".to_owned(),
+        },
+    };
+
+    let config = qs::Config::default().field_order(&["signature", "key", "missing", "page"]);
+    assert_eq!(
+        config.serialize_string(&query).unwrap(),
+        "signature=s&key=k&page[number]=2&page[size]=50&q=rust&tags[0]=a&tags[1]=b"
+    );
+
+    let mut buffer = Vec::new();
+    config.serialize_to_writer(&query, &mut buffer).unwrap();
+    assert_eq!(
+        String::from_utf8(buffer).unwrap(),
+        config.serialize_string(&query).unwrap()
+    );
+
+    assert_eq!(
+        config.serialize_string_with_prefix("f", &query).unwrap(),
+        "f[signature]=s&f[key]=k&f[page][number]=2&f[page][size]=50&f[q]=rust&f[tags][0]=a&f[tags][1]=b"
+    );
+}
+
+#[test]
+fn serialize_pair_matches_serializer() {
+    use qs::{KeyPath, Segment};
+    use std::collections::BTreeMap;
+
+    let config = qs::Config::default();
+    for segment in &["a[b]", "x&y=z", "with space", "ünï©ødé", "1", "plain"] {
+        let path = KeyPath::new(["filter", *segment]);
+        let mut inner = BTreeMap::new();
+        inner.insert(*segment, "v&w x");
+        let mut map = BTreeMap::new();
+        map.insert("filter", inner);
+
+        let expected = qs::to_string(&map).unwrap();
+        assert_eq!(config.serialize_pair(&path, "v&w x").unwrap(), expected);
+        assert_eq!(
+            format!("{}=v%26w+x", path.encode()),
+            expected,
+            "{:?}",
+            segment
+        );
+    }
+
+    // indices match sequence elements
+    let mut path = KeyPath::new(vec![Segment::Key("ids".into())]);
+    path.push(1);
+    let mut map = BTreeMap::new();
+    map.insert("ids", vec![5, 6]);
+    assert_eq!(
+        config.serialize_pair(&path, &6).unwrap(),
+        qs::to_string(&map).unwrap().split('&').nth(1).unwrap()
+    );
+    assert_eq!(path.encode(), "ids[1]");
+
+    // non-scalar values are nested under the path
+    let path = KeyPath::new(["page"]);
+    assert_eq!(
+        config.serialize_pair(&path, &map).unwrap(),
+        "page[ids][0]=5&page[ids][1]=6"
+    );
+}
+
+#[test]
+fn vec_of_options_roundtrip() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Query {
+        strings: Vec<Option<String>>,
+        numbers: Vec<Option<u8>>,
+        tuple: (Option<u8>, Option<u8>),
+        missing: Option<u8>,
+    }
+
+    let query = Query {
+        strings: vec![None, Some("a".to_owned()), None, Some("c".to_owned()), None],
+        numbers: vec![Some(1), None, Some(3)],
+        tuple: (None, Some(2)),
+        missing: None,
+    };
+    // `None` elements keep their index, but `None` fields are still omitted
+    let serialized = qs::to_string(&query).unwrap();
+    assert_eq!(
+        serialized,
+        "strings[0]=&strings[1]=a&strings[2]=&strings[3]=c&strings[4]=\
+         &numbers[0]=1&numbers[1]=&numbers[2]=3&tuple[0]=&tuple[1]=2"
+    );
+    assert_eq!(qs::from_str::<Query>(&serialized).unwrap(), query);
+}
+
+#[test]
+fn vec_of_empty_elements_roundtrip() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Inner {
+        x: Option<u8>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Query {
+        v: Vec<Option<Inner>>,
+        lists: Vec<Option<Vec<u8>>>,
+    }
+
+    // elements which write nothing keep their index, but read back as `None`
+    let query = Query {
+        v: vec![
+            Some(Inner { x: None }),
+            None,
+            Some(Inner { x: Some(2) }),
+            Some(Inner { x: None }),
+        ],
+        lists: vec![Some(vec![]), Some(vec![1])],
+    };
+    let serialized = qs::to_string(&query).unwrap();
+    assert_eq!(
+        serialized,
+        "v[0]=&v[1]=&v[2][x]=2&v[3]=&lists[0]=&lists[1][0]=1"
+    );
+    assert_eq!(
+        qs::from_str::<Query>(&serialized).unwrap(),
+        Query {
+            v: vec![None, None, Some(Inner { x: Some(2) }), None],
+            lists: vec![None, Some(vec![1])],
+        }
+    );
+}
+
+#[test]
+fn max_depth_roundtrip() {
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+    use std::fmt::Debug;
+
+    // Serializes `value`, whose keys have `nesting` bracketed parts, with
+    // every `max_depth` from 0 to 4.
+    fn check<T>(value: T, nesting: usize)
+    where
+        T: Serialize + DeserializeOwned + PartialEq + Debug,
+    {
+        let serialized = qs::Config::new(nesting, true)
+            .serialize_string(&value)
+            .unwrap();
+        for max_depth in 0..=4 {
+            let config = qs::Config::new(max_depth, true);
+            if nesting <= max_depth {
+                assert_eq!(config.serialize_string(&value).unwrap(), serialized);
+                assert_eq!(config.deserialize_str::<T>(&serialized).unwrap(), value);
+            } else {
+                match config.serialize_string(&value) {
+                    Err(qs::Error::MaxDepthExceeded { max_depth: max, .. }) => {
+                        assert_eq!(max, max_depth)
+                    }
+                    res => panic!("expected MaxDepthExceeded, got {:?}", res),
+                }
+                assert!(config.deserialize_str::<T>(&serialized).is_err());
+            }
+        }
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Query<T> {
+        a: T,
+    }
+
+    check(Query { a: 1 }, 0);
+    check(Query { a: vec![1, 2] }, 1);
+    check(Query { a: Query { a: 1 } }, 1);
+    check(
+        Query {
+            a: vec![vec![1], vec![2, 3]],
+        },
+        2,
+    );
+    check(
+        Query {
+            a: vec![Query { a: 1 }],
+        },
+        2,
+    );
+
+    let mut map = BTreeMap::new();
+    map.insert("b".to_owned(), vec![vec![1, 2]]);
+    check(Query { a: map }, 3);
+    check(
+        Query {
+            a: vec![vec![vec![vec![1]]]],
+        },
+        4,
+    );
+}
+
+#[test]
+fn value_filter() {
+    use qs::{KeyPath, Redactable, Segment};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Credentials {
+        user: String,
+        api_key: String,
+        pin: u16,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Request {
+        q: String,
+        auth: Credentials,
+        #[serde(default)]
+        debug: Option<Vec<u8>>,
+    }
+
+    fn redact(path: &KeyPath, value: &mut Redactable) {
+        match path.segments() {
+            [Segment::Key(auth), Segment::Key(field)]
+                if auth == "auth" && (field == "api_key" || field == "pin") =>
+            {
+                value.redact()
+            }
+            [Segment::Key(debug), Segment::Index(1)] if debug == "debug" => value.skip(),
+            _ => {}
+        }
+    }
+
+    let request = Request {
+        q: "a b".to_owned(),
+        auth: Credentials {
+            user: "Acme".to_owned(),
+            api_key: "secret".to_owned(),
+            pin: 1234,
+        },
+        debug: Some(vec![1, 2, 3]),
+    };
+    let config = qs::Config::default().value_filter(redact);
+    let output = config.serialize_string(&request).unwrap();
+    assert_eq!(
+        output,
+        "q=a+b&auth[user]=Acme&auth[api_key]=***&auth[pin]=***&debug[0]=1&debug[2]=3"
+    );
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Logged {
+        auth: std::collections::HashMap<String, String>,
+    }
+    let logged: Logged = qs::from_str(&output).unwrap();
+    assert_eq!(logged.auth["api_key"], "***");
+    assert_eq!(logged.auth["user"], "Acme");
+
+    // skipping the first pair does not leave a leading `&`
+    fn skip_query(path: &KeyPath, value: &mut Redactable) {
+        if path.segments() == [Segment::from("q")] {
+            value.skip();
+        }
+    }
+    let output = qs::Config::default()
+        .value_filter(skip_query)
+        .serialize_string(&request)
+        .unwrap();
+    assert!(output.starts_with("auth[user]=Acme&"), "{}", output);
+
+    // the path is decoded, and includes any prefix
+    fn redact_encoded(path: &KeyPath, value: &mut Redactable) {
+        if path.to_string() == "p[a&b][c d]" {
+            value.redact();
+        }
+    }
+    let mut map = std::collections::BTreeMap::new();
+    map.insert(
+        "a&b",
+        std::collections::BTreeMap::from([("c d", "x"), ("e", "y")]),
+    );
+    assert_eq!(
+        qs::Config::default()
+            .value_filter(redact_encoded)
+            .serialize_string_with_prefix("p", &map)
+            .unwrap(),
+        "p[a%26b][c+d]=***&p[a%26b][e]=y"
+    );
+}
+
+#[test]
+fn to_writer_returns_bytes_written() {
+    #[derive(Serialize)]
+    struct Query<'a> {
+        name: &'a str,
+        tags: Vec<&'a str>,
+        nested: std::collections::BTreeMap<&'a str, u32>,
+    }
+
+    let fixtures = [
+        Query {
+            name: "Alice",
+            tags: vec![],
+            nested: Default::default(),
+        },
+        Query {
+            name: "Zoë & Søren",
+            tags: vec!["日本語", "a b", "100%"],
+            nested: vec![("k[ey]", 1), ("ключ", 2)].into_iter().collect(),
+        },
+    ];
+    for query in &fixtures {
+        let expected = qs::to_string(query).unwrap();
+        let mut buffer = Vec::new();
+        let written = qs::to_writer(query, &mut buffer).unwrap();
+        assert_eq!(written, expected.len());
+        assert_eq!(buffer, expected.as_bytes());
+
+        // also when the pairs are reordered
+        let config = qs::Config::default().field_order(&["nested"]);
+        let mut buffer = Vec::new();
+        let written = config.serialize_to_writer(query, &mut buffer).unwrap();
+        assert_eq!(written, buffer.len());
+        assert_eq!(written, expected.len());
+    }
+
+    let mut buffer = Vec::new();
+    let written = qs::Config::default()
+        .serialize_to_writer(
+            &std::collections::BTreeMap::<String, u8>::new(),
+            &mut buffer,
+        )
+        .unwrap();
+    assert_eq!(written, 0);
+}
+
+#[test]
+fn deeply_nested_values() {
+    #[derive(Debug, Serialize)]
+    struct Chain(Option<Box<Chain>>);
+
+    #[derive(Debug, Serialize)]
+    struct Deep {
+        a: Option<Box<Deep>>,
+        v: u8,
+    }
+
+    #[derive(Debug, Serialize)]
+    struct Query<T> {
+        value: T,
+    }
+
+    fn chain(depth: usize) -> Chain {
+        (0..depth).fold(Chain(None), |c, _| Chain(Some(Box::new(c))))
+    }
+    fn deep(depth: usize) -> Deep {
+        (0..depth).fold(Deep { a: None, v: 1 }, |d, _| Deep {
+            a: Some(Box::new(d)),
+            v: 1,
+        })
+    }
+
+    let config = qs::Config::new(100_000, true);
+
+    // newtypes and options nest without extending the key
+    let query = Query {
+        value: chain(10_000),
+    };
+    match config.serialize_string(&query) {
+        Err(qs::Error::RecursionLimitExceeded { limit }) => assert_eq!(limit, 1024),
+        result => panic!("unexpected result: {:?}", result),
+    }
+    let query = Query { value: chain(100) };
+    assert_eq!(config.serialize_string(&query).unwrap(), "");
+
+    // keys are limited by `max_depth`, which is at most `MAX_DEPTH_LIMIT`
+    let query = Query {
+        value: deep(10_000),
+    };
+    match config.serialize_string(&query) {
+        Err(qs::Error::MaxDepthExceeded { max_depth, .. }) => {
+            assert_eq!(max_depth, qs::MAX_DEPTH_LIMIT)
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+    let query = Query { value: deep(100) };
+    assert!(config.serialize_string(&query).is_ok());
+}
+
+#[test]
+fn preencoded_values_fixed_point() {
+    fn cycle(config: qs::Config, input: &str) -> String {
+        let mut encoded = input.to_owned();
+        for _ in 0..3 {
+            let map: std::collections::BTreeMap<String, String> =
+                config.deserialize_str(&encoded).unwrap();
+            encoded = config.serialize_string(&map).unwrap();
+        }
+        encoded
+    }
+
+    // encoded brackets stay encoded, so the key is never nested
+    let input = "a%5Bb%5D=1&c=x+y";
+    assert_eq!(cycle(qs::Config::default(), input), input);
+
+    // values are passed through as they are
+    let config = qs::Config::default().assume_preencoded_values(true);
+    let input = "a%5Bb%5D=50%25&c=x+y%2By&d=%E2%82%AC";
+    assert_eq!(cycle(config, input), input);
+    let map: std::collections::BTreeMap<String, String> = config.deserialize_str(input).unwrap();
+    assert_eq!(map["a[b]"], "50%25");
+    assert_eq!(map["c"], "x+y%2By");
+
+    // whereas by default, a value which was already encoded is encoded again
+    let map: std::collections::BTreeMap<String, String> =
+        std::iter::once(("a".to_owned(), "50%25".to_owned())).collect();
+    assert_eq!(qs::to_string(&map).unwrap(), "a=50%2525");
+    assert_eq!(config.serialize_string(&map).unwrap(), "a=50%25");
+}
+
+#[test]
+fn float_map_keys() {
+    use std::collections::HashMap;
+    use std::hash::{Hash, Hasher};
+
+    // like `ordered_float::OrderedFloat`, serialized as the bare float
+    #[derive(Clone, Copy, Debug)]
+    struct FloatKey(f64);
+
+    impl PartialEq for FloatKey {
+        fn eq(&self, other: &Self) -> bool {
+            self.0.to_bits() == other.0.to_bits()
+        }
+    }
+
+    impl Eq for FloatKey {}
+
+    impl Hash for FloatKey {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.0.to_bits().hash(state)
+        }
+    }
+
+    impl serde::Serialize for FloatKey {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_f64(self.0)
+        }
+    }
+
+    impl<'de> serde::Deserialize<'de> for FloatKey {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            f64::deserialize(deserializer).map(FloatKey)
+        }
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Prices {
+        prices: HashMap<FloatKey, u32>,
+    }
+
+    let keys = [0.1, 1.0, -2.5, 1e20, 1e-7, 0.30000000000000004];
+    let prices = Prices {
+        prices: keys
+            .iter()
+            .enumerate()
+            .map(|(i, k)| (FloatKey(*k), i as u32))
+            .collect(),
+    };
+    for style in [qs::NestingStyle::Brackets, qs::NestingStyle::Dots] {
+        let config = qs::Config::default().nesting_style(style);
+        let encoded = config.serialize_string(&prices).unwrap();
+        let decoded: Prices = config.deserialize_str(&encoded).unwrap();
+        assert_eq!(decoded, prices, "{}", encoded);
+    }
+
+    // integer-valued floats keep their decimal point, so they do not collide
+    // with integer keys
+    let mut floats = HashMap::new();
+    floats.insert(FloatKey(1.0), 1);
+    assert_eq!(qs::to_string(&floats).unwrap(), "1.0=1");
+    let mut floats = HashMap::new();
+    floats.insert(FloatKey(0.1), 1);
+    assert_eq!(qs::to_string(&floats).unwrap(), "0.1=1");
+
+    for key in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+        let mut floats = HashMap::new();
+        floats.insert(FloatKey(key), 1);
+        match qs::to_string(&floats) {
+            Err(qs::Error::UnsupportedAsKey { kind }) => assert_eq!(kind, "non-finite float"),
+            res => panic!("{}: {:?}", key, res),
+        }
+    }
+}
+
+#[test]
+fn unsupported_map_keys() {
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+    struct Point {
+        x: u8,
+        y: u8,
+    }
+
+    #[derive(Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+    enum Inner {
+        A,
+    }
+
+    #[derive(Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+    enum Outer {
+        Wrapped(Inner),
+    }
+
+    fn kind_of<K: serde::Serialize + Ord>(key: K) -> &'static str {
+        let map = BTreeMap::from([(key, 1)]);
+        let error = qs::to_string(&map).unwrap_err();
+        assert!(error.to_string().contains("unsupported"), "{}", error);
+        assert!(error.to_string().contains("unit variant"), "{}", error);
+        match error {
+            qs::Error::UnsupportedAsKey { kind } => kind,
+            error => panic!("{:?}", error),
+        }
+    }
+
+    assert_eq!(kind_of(Point { x: 1, y: 2 }), "struct");
+    assert_eq!(kind_of((1, 2)), "tuple");
+    assert_eq!(kind_of(None::<u8>), "none");
+    assert_eq!(kind_of(Some(1)), "some");
+    assert_eq!(kind_of(Outer::Wrapped(Inner::A)), "newtype variant");
+    assert_eq!(kind_of(vec![1]), "sequence");
+    assert_eq!(kind_of(BTreeMap::from([(1, 2)])), "map");
+
+    // a unit variant is a plain key
+    let map = BTreeMap::from([(Inner::A, 1)]);
+    assert_eq!(qs::to_string(&map).unwrap(), "A=1");
+}
+
+#[test]
+fn serialize_flat_dotted() {
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct Metrics {
+        route: Route,
+        hits: Vec<Hit>,
+    }
+
+    #[derive(Serialize)]
+    struct Route {
+        method: &'static str,
+        path: &'static str,
+    }
+
+    #[derive(Serialize)]
+    struct Hit {
+        status: u16,
+        tags: BTreeMap<&'static str, &'static str>,
+    }
+
+    let mut tags = BTreeMap::new();
+    tags.insert("region", "eu west");
+    let metrics = Metrics {
+        route: Route {
+            method: "GET",
+            path: "/a?b",
+        },
+        hits: vec![
+            Hit {
+                status: 200,
+                tags: tags.clone(),
+            },
+            Hit {
+                status: 404,
+                tags: BTreeMap::new(),
+            },
+        ],
+    };
+    let config = qs::Config::default().serialize_flat_dotted(true);
+    assert_eq!(
+        config.serialize_string(&metrics).unwrap(),
+        "route.method=GET&route.path=%2Fa%3Fb&hits.0.status=200&hits.0.tags.region=eu+west&hits.1.status=404"
+    );
+
+    // the dotted output is not affected by the nesting style
+    let dots = config.nesting_style(qs::NestingStyle::Dots);
+    assert_eq!(
+        dots.serialize_string(&metrics).unwrap(),
+        config.serialize_string(&metrics).unwrap()
+    );
+
+    // segments which would be ambiguous are errors, rather than encoded
+    for key in ["file.name", "a[b]", "c]"] {
+        let mut tags = BTreeMap::new();
+        tags.insert(key, "x");
+        let metrics = Metrics {
+            route: Route {
+                method: "GET",
+                path: "/",
+            },
+            hits: vec![Hit { status: 200, tags }],
+        };
+        match config.serialize_string(&metrics) {
+            Err(qs::Error::AmbiguousKeySegment { key: path, segment }) => {
+                assert_eq!(path, "hits.0.tags");
+                assert_eq!(segment, key);
+            }
+            res => panic!("{}: {:?}", key, res),
+        }
+    }
+    let mut map = BTreeMap::new();
+    map.insert("a.b", 1);
+    let err = config.serialize_string(&map).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "key `a.b` contains a `.` or a bracket, which is ambiguous in a flat dotted key"
+    );
+}
+
+#[test]
+fn serialize_unsized_values() {
+    use std::collections::BTreeMap;
+
+    let mut map = BTreeMap::new();
+    map.insert("a", 1);
+    let plugins: Vec<Box<dyn erased_serde::Serialize>> = vec![
+        Box::new(map),
+        Box::new(Address {
+            city: "Carrot City".to_string(),
+            street: "Special-Street* No. 11".to_string(),
+            postcode: "12345".to_string(),
+        }),
+    ];
+    let encoded: Vec<String> = plugins
+        .iter()
+        .map(|p| qs::to_string(&**p).unwrap())
+        .collect();
+    assert_eq!(
+        encoded,
+        [
+            "a=1",
+            "city=Carrot+City&street=Special-Street*+No.+11&postcode=12345"
+        ]
+    );
+
+    let config = qs::Config::default();
+    let plugin: &dyn erased_serde::Serialize = &*plugins[0];
+    assert_eq!(config.serialize_string(plugin).unwrap(), "a=1");
+    assert_eq!(
+        config.serialize_string_with_prefix("p", plugin).unwrap(),
+        "p[a]=1"
+    );
+    let mut buffer = Vec::new();
+    qs::to_writer(plugin, &mut buffer).unwrap();
+    assert_eq!(buffer, b"a=1");
+
+    // unsized values at the top level give the usual errors
+    let s: &str = "abc";
+    assert_eq!(
+        qs::to_string(s).unwrap_err().to_string(),
+        "tried to serialize a value before serializing key"
+    );
+    let slice: &[u8] = &[1, 2];
+    assert_eq!(qs::to_string(slice).unwrap(), "0=1&1=2");
+}
+
+#[test]
+fn skip_empty_leaves() {
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Address {
+        #[serde(default)]
+        city: String,
+        #[serde(default)]
+        postcode: String,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Query {
+        name: String,
+        address: Option<Address>,
+        nickname: Option<String>,
+        #[serde(default)]
+        tags: Vec<String>,
+    }
+
+    let config = qs::Config::default().skip_empty_leaves(true);
+    let query = Query {
+        name: "Acme".to_owned(),
+        address: Some(Address {
+            city: "Carrot City".to_owned(),
+            postcode: String::new(),
+        }),
+        nickname: None,
+        tags: vec!["".to_owned(), "b".to_owned()],
+    };
+    assert_eq!(
+        qs::to_string(&query).unwrap(),
+        "name=Acme&address[city]=Carrot+City&address[postcode]=&tags[0]=&tags[1]=b"
+    );
+    // sequence elements keep their indices
+    let encoded = config.serialize_string(&query).unwrap();
+    assert_eq!(
+        encoded,
+        "name=Acme&address[city]=Carrot+City&tags[0]=&tags[1]=b"
+    );
+    assert_eq!(qs::from_str::<Query>(&encoded).unwrap(), query);
+
+    // a struct with only empty values is left out, key and all, so an
+    // optional one comes back as `None`
+    let query = Query {
+        address: Some(Address::default()),
+        nickname: Some(String::new()),
+        tags: vec![],
+        ..query
+    };
+    let encoded = config.serialize_string(&query).unwrap();
+    assert_eq!(encoded, "name=Acme");
+    let decoded = qs::from_str::<Query>(&encoded).unwrap();
+    assert!(decoded.address.is_none());
+    assert!(decoded.nickname.is_none());
+
+    // and a required empty value is missing
+    let query = Query {
+        name: String::new(),
+        ..decoded
+    };
+    let encoded = config.serialize_string(&query).unwrap();
+    assert_eq!(encoded, "");
+    assert_eq!(
+        qs::from_str::<Query>(&encoded).unwrap_err().to_string(),
+        "missing field `name`"
+    );
+
+    // structs in sequences are written in full
+    let mut rows = std::collections::BTreeMap::new();
+    let _ = rows.insert("rows", vec![Address::default(), Address::default()]);
+    let encoded = config.serialize_string(&rows).unwrap();
+    assert_eq!(
+        encoded,
+        "rows[0][city]=&rows[0][postcode]=&rows[1][city]=&rows[1][postcode]="
+    );
+
+    // a redacted empty value is still written
+    fn redact(_: &qs::KeyPath, value: &mut qs::Redactable) {
+        value.redact();
+    }
+    let address = Address::default();
+    let encoded = config
+        .value_filter(redact)
+        .serialize_string(&address)
+        .unwrap();
+    assert_eq!(encoded, "city=***&postcode=***");
+}
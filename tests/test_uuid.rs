@@ -0,0 +1,101 @@
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_qs as qs;
+
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+#[test]
+fn test_uuid_field() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Params {
+        id: Uuid,
+        ids: Vec<Uuid>,
+    }
+
+    let params = Params {
+        id: Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap(),
+        ids: vec![Uuid::nil(), Uuid::max()],
+    };
+
+    let s = qs::to_string(&params).unwrap();
+    assert_eq!(
+        s,
+        "id=67e55044-10b1-426f-9247-bb680e5fe0c8\
+         &ids[0]=00000000-0000-0000-0000-000000000000\
+         &ids[1]=ffffffff-ffff-ffff-ffff-ffffffffffff"
+    );
+
+    let data: Params = qs::from_str(&s).unwrap();
+    assert_eq!(data, params);
+}
+
+#[test]
+fn test_uuid_map_keys() {
+    let mut map = BTreeMap::new();
+    map.insert(Uuid::nil(), 1u8);
+    map.insert(
+        Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap(),
+        2,
+    );
+    map.insert(Uuid::max(), 3);
+
+    let s = qs::to_string(&map).unwrap();
+    assert_eq!(
+        s,
+        "00000000-0000-0000-0000-000000000000=1\
+         &67e55044-10b1-426f-9247-bb680e5fe0c8=2\
+         &ffffffff-ffff-ffff-ffff-ffffffffffff=3"
+    );
+
+    let data: BTreeMap<Uuid, u8> = qs::from_str(&s).unwrap();
+    assert_eq!(data, map);
+
+    // Nested under a struct field.
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Params {
+        scores: BTreeMap<Uuid, u8>,
+    }
+    let data: Params =
+        qs::from_str("scores[67e55044-10b1-426f-9247-bb680e5fe0c8]=2&scores[00000000-0000-0000-0000-000000000000]=1")
+            .unwrap();
+    assert_eq!(data.scores.len(), 2);
+    assert_eq!(data.scores[&Uuid::nil()], 1);
+}
+
+#[test]
+fn test_invalid_uuid() {
+    #[derive(Debug, Deserialize)]
+    struct Params {
+        #[allow(dead_code)]
+        id: Uuid,
+    }
+
+    match qs::from_str::<Params>("id=not-a-uuid") {
+        Err(qs::Error::InvalidValue { key, error }) => {
+            assert_eq!(key, "id");
+            assert!(
+                error.to_string().contains("UUID parsing failed"),
+                "{}",
+                error
+            );
+        }
+        res => panic!("{:?}", res),
+    }
+
+    // an invalid map key is reported under the map it is in
+    match qs::from_str::<BTreeMap<String, BTreeMap<Uuid, u8>>>("scores[not-a-uuid]=1") {
+        Err(qs::Error::InvalidValue { key, error }) => {
+            assert_eq!(key, "scores");
+            assert!(
+                error.to_string().contains("UUID parsing failed"),
+                "{}",
+                error
+            );
+        }
+        res => panic!("{:?}", res),
+    }
+
+    let err = qs::from_str::<BTreeMap<Uuid, u8>>("not-a-uuid=1").unwrap_err();
+    assert!(err.to_string().contains("UUID parsing failed"), "{}", err);
+}
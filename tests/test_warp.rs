@@ -102,3 +102,84 @@ fn test_custom_qs_config() {
         assert!(s.common.remaining);
     })
 }
+
+#[test]
+fn test_problem_details_error_response() {
+    futures::executor::block_on(async {
+        let filter = qs::warp::query::<Query>(QsConfig::default())
+            .map(|_| "")
+            .recover(qs::warp::recover_problem_fn);
+
+        let resp = warp::test::request()
+            .path("/test?foo=abc")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(resp.headers()["content-type"], "application/problem+json");
+        let problem: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(problem["type"], "about:blank");
+        assert_eq!(problem["title"], "Bad Request");
+        assert_eq!(problem["status"], 400);
+        assert_eq!(
+            problem["detail"],
+            "invalid value for `foo`: invalid digit found in string"
+        );
+        assert_eq!(problem["invalid-params"][0]["name"], "foo");
+    })
+}
+
+#[test]
+fn test_query_with_default_config() {
+    futures::executor::block_on(async {
+        let filter = qs::warp::query_with::<Query>();
+        let s = warp::test::request()
+            .path("/test?foo=1&bars[0]=2&limit=100&offset=50&remaining=true")
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_eq!(s.bars, vec![2]);
+
+        // The default config is strict, so rejects encoded brackets.
+        let filter = filter.map(|_| "").recover(qs::warp::recover_fn);
+        let resp = warp::test::request()
+            .path("/test?foo=1&bars%5B0%5D=2&limit=100&offset=50&remaining=true")
+            .reply(&filter)
+            .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    })
+}
+
+#[test]
+fn test_builder_qs_config() {
+    #[derive(Deserialize)]
+    struct Vector {
+        vec: Vec<u8>,
+    }
+
+    futures::executor::block_on(async {
+        let config = QsConfig::new(5, false)
+            .max_seq_index(10)
+            .preserve_leading_zero_keys(true);
+        let filter = qs::warp::query::<Vector>(config);
+
+        // The filter can be used for several requests.
+        for path in ["/test?vec%5B0%5D=1&vec%5B1%5D=2", "/test?vec[1]=2&vec[0]=1"] {
+            let s = warp::test::request()
+                .path(path)
+                .filter(&filter)
+                .await
+                .unwrap();
+            assert_eq!(s.vec, vec![1, 2], "{}", path);
+        }
+
+        // Options other than `strict` apply too.
+        for path in ["/test?vec%5B11%5D=1", "/test?vec%5B01%5D=1"] {
+            assert!(warp::test::request()
+                .path(path)
+                .filter(&filter)
+                .await
+                .is_err());
+        }
+    })
+}
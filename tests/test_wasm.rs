@@ -0,0 +1,71 @@
+//! Smoke tests for `wasm32-unknown-unknown`, run with
+//! `wasm-pack test --node -- --test test_wasm`.
+#![cfg(target_arch = "wasm32")]
+
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_qs as qs;
+
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Address {
+    city: String,
+    street: String,
+    postcode: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct QueryParams {
+    id: u8,
+    name: String,
+    phone: u32,
+    address: Address,
+    user_ids: Vec<u8>,
+}
+
+fn params() -> QueryParams {
+    QueryParams {
+        id: 42,
+        name: "Acme".to_string(),
+        phone: 12345,
+        address: Address {
+            city: "Carrot City".to_string(),
+            street: "Special-Street* No. 11".to_string(),
+            postcode: "12345".to_string(),
+        },
+        user_ids: vec![1, 2, 3, 4],
+    }
+}
+
+const ENCODED: &str = "\
+    id=42&name=Acme&phone=12345&address[city]=Carrot+City&\
+    address[street]=Special-Street*+No.+11&\
+    address[postcode]=12345&user_ids[0]=1&user_ids[1]=2&\
+    user_ids[2]=3&user_ids[3]=4";
+
+#[wasm_bindgen_test]
+fn serialize_nested_struct() {
+    assert_eq!(qs::to_string(&params()).unwrap(), ENCODED);
+
+    let mut writer = Vec::new();
+    qs::to_writer(&params(), &mut writer).unwrap();
+    assert_eq!(writer, ENCODED.as_bytes());
+}
+
+#[wasm_bindgen_test]
+fn deserialize_nested_struct() {
+    assert_eq!(qs::from_str::<QueryParams>(ENCODED).unwrap(), params());
+
+    let input = "id=42&name=Acme&phone=12345&address[city]=Carrot+City&\
+                 address[street]=Special-Street*+No.+11&address[postcode]=12345&\
+                 user_ids[]=1&user_ids[]=2&user_ids[]=3&user_ids[]=4";
+    assert_eq!(qs::from_str::<QueryParams>(input).unwrap(), params());
+
+    let config = qs::Config::default().collect_unmatched_keys(true);
+    let (query, stats) = config
+        .deserialize_str_with_stats::<QueryParams>(&format!("{}&extra=1", ENCODED))
+        .unwrap();
+    assert_eq!(query, params());
+    assert_eq!(stats.unmatched_keys, ["extra"]);
+}